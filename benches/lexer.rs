@@ -0,0 +1,73 @@
+//! Throughput of the lexer, on a large generated source file. Run with `cargo bench`.
+
+use chant::parser::scan;
+use chant::tokenizer::{lex, tokenize};
+use std::time::Instant;
+
+const FUNCTION: &str = "
+fn distance_between(first_point: Point, second_point: Point) -> Real {
+    horizontal_distance := first_point.x - second_point.x;
+    vertical_distance := first_point.y - second_point.y;
+    (horizontal_distance ^ 2 + vertical_distance ^ 2) ^ 0.5
+}
+";
+
+const NUMBERS: &str = "
+matrix := [[1024, -7, 3.25], [65536, 0.125, -42], [1000000007, 299792458, 6.02214076]];
+";
+
+/// Runs `f` on `src` a few times, and prints the best throughput, and the number of `what` that
+/// `f` counted.
+fn bench(name: &str, what: &str, src: &str, f: impl Fn(&str) -> usize) {
+    let mut best = f64::INFINITY;
+    let mut result = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        result = f(src);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    let mb = src.len() as f64 / 1e6;
+    println!("{name}: {:.0} MB/s ({result} {what})", mb / best);
+}
+
+fn main() {
+    let src = FUNCTION.repeat(100_000);
+    bench("tokenize", "tokens", &src, |src| {
+        tokenize(src).unwrap().len()
+    });
+    bench("lex", "tokens", &src, |src| lex(src).unwrap().len());
+    let numbers = NUMBERS.repeat(100_000);
+    bench("numbers", "tokens", &numbers, |src| {
+        tokenize(src).unwrap().len()
+    });
+    // Just the scanning loops, without making tokens.
+    bench("scan", "runs", &src, |src| {
+        let (bytes, mut n, mut runs) = (src.as_bytes(), 0, 0);
+        while n < bytes.len() {
+            n += match scan::run(&bytes[n..], scan::WHITESPACE | scan::SYMBOL) {
+                0 => 1,
+                len => len,
+            };
+            runs += 1;
+        }
+        runs
+    });
+    // Long runs, of deep indentation and long names, which the scanning loops do a word at a time.
+    let long = format!(
+        "{}{}\n",
+        " ".repeat(32),
+        "a_long_descriptive_name_".repeat(4)
+    )
+    .repeat(100_000);
+    bench("long runs", "runs", &long, |src| {
+        let (bytes, mut n, mut runs) = (src.as_bytes(), 0, 0);
+        while n < bytes.len() {
+            n += match scan::run(&bytes[n..], scan::WHITESPACE) {
+                0 => scan::run(&bytes[n..], scan::SYMBOL),
+                len => len,
+            };
+            runs += 1;
+        }
+        runs
+    });
+}
@@ -0,0 +1,34 @@
+/*
+ * Evaluates the program given as the first argument, and prints its value. Built with
+ *
+ *     cargo build -p chant-capi --release
+ *     cc capi/examples/embed.c -Icapi/include target/release/libchant_capi.a -lm -o embed
+ */
+
+#include <stdio.h>
+
+#include "chant.h"
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <source>\n", argv[0]);
+        return 2;
+    }
+    Chant *chant = chant_new();
+    ChantValue *value = chant_eval(chant, argv[1]);
+    int status = 0;
+    double x;
+    if (!value) {
+        fprintf(stderr, "%s\n", chant_last_error());
+        status = 1;
+    } else if (chant_value_as_double(value, &x)) {
+        printf("%g\n", x);
+    } else {
+        char *s = chant_value_to_string(value);
+        printf("%s\n", s);
+        chant_string_free(s);
+    }
+    chant_value_free(value);
+    chant_free(chant);
+    return status;
+}
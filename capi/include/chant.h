@@ -0,0 +1,80 @@
+/*
+ * The C interface to the chant interpreter, from the chant-capi crate. The functions are
+ * documented in capi/src/lib.rs. This header is written by hand, not generated, and a test in
+ * capi/src/lib.rs checks that it declares each of them with the prototype of its Rust signature.
+ *
+ * Functions that fail return NULL or false, and chant_last_error() is then the message of the
+ * error, which is kept per thread. That includes a panic in the interpreter, which doesn't
+ * unwind into the host.
+ */
+
+#ifndef CHANT_H
+#define CHANT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An interpreter. */
+typedef struct Chant Chant;
+
+/* A value a program returned. */
+typedef struct ChantValue ChantValue;
+
+/* Makes an interpreter, which is freed with chant_free. */
+Chant *chant_new(void);
+
+/* Frees an interpreter, or does nothing with NULL. */
+void chant_free(Chant *chant);
+
+/*
+ * Sets the most calls that can be in progress at once, in programs chant evaluates, or does
+ * nothing with NULL.
+ */
+void chant_set_max_depth(Chant *chant, size_t max_depth);
+
+/*
+ * Sets the most bytes of the heap that programs chant evaluates can use, where 0 is no limit, or
+ * does nothing with NULL. A program that would use more fails to evaluate, with an out of memory
+ * error, rather than aborting the host.
+ */
+void chant_set_max_heap(Chant *chant, size_t max_heap);
+
+/*
+ * Compiles the UTF-8 source src, and returns the value of its main, which is freed with
+ * chant_value_free, or NULL if that fails.
+ */
+ChantValue *chant_eval(Chant *chant, const char *src);
+
+/* Frees a value, or does nothing with NULL. */
+void chant_value_free(ChantValue *value);
+
+/*
+ * Writes value as a double to out, if it's a number that isn't complex, and returns whether
+ * it was. A NULL value fails, like the ones that aren't numbers.
+ */
+bool chant_value_as_double(const ChantValue *value, double *out);
+
+/*
+ * Prints value like `chantrs --run` does, into a string freed with chant_string_free, or
+ * returns NULL if value is NULL.
+ */
+char *chant_value_to_string(const ChantValue *value);
+
+/* Frees a string from chant_value_to_string, or does nothing with NULL. */
+void chant_string_free(char *s);
+
+/*
+ * The message of the last error in this thread, or NULL if nothing has failed. It's valid
+ * until the next call that fails.
+ */
+const char *chant_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,368 @@
+//! # chant-capi
+//! A C interface to the chant interpreter, for embedding it in hosts that aren't written in
+//! Rust, like C, C++ and Python through `ctypes`. It's declared in `include/chant.h`.
+//!
+//! ```c
+//! #include "chant.h"
+//!
+//! Chant *chant = chant_new();
+//! ChantValue *value = chant_eval(chant, "fn main() { 1.5 * 2 }");
+//! double result;
+//! if (chant_value_as_double(value, &result)) {
+//!     printf("%g\n", result);
+//! } else {
+//!     fprintf(stderr, "%s\n", chant_last_error());
+//! }
+//! chant_value_free(value);
+//! chant_free(chant);
+//! ```
+//!
+//! Functions that fail return `NULL` or `false`, and the message of the error is then
+//! [`chant_last_error`], which is kept per thread, like `errno`. A panic in the interpreter
+//! doesn't unwind into the host, it fails the call like an error does.
+
+use chant::eval::{heap, Limits};
+use chant::prelude::*;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Counts the heap each interpreter uses, so that [`chant_set_max_heap`] can limit it.
+#[global_allocator]
+static ALLOCATOR: heap::Counting = heap::Counting;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Makes `error` the last error of this thread.
+fn fail(error: impl Display) {
+    // A message with a nul in it can't be a C string, so it's cut there.
+    let mut message = error.to_string().into_bytes();
+    message.truncate(
+        message
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(message.len()),
+    );
+    let message = CString::new(message).expect("the message has no nul");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Calls `f`, or makes a panic in it the last error and returns `failed`, since unwinding out
+/// of an `extern "C"` function aborts the host.
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(s) => s,
+            None => payload.downcast_ref::<String>().map_or("", |s| s),
+        };
+        fail(format!("the interpreter panicked: {message}"));
+        failed
+    })
+}
+
+/// An interpreter, with the limits the programs it evaluates run with.
+pub struct Chant {
+    limits: Limits,
+}
+
+/// A value a program returned.
+pub struct ChantValue(Value);
+
+/// Makes an interpreter, which is freed with [`chant_free`].
+#[no_mangle]
+pub extern "C" fn chant_new() -> *mut Chant {
+    Box::into_raw(Box::new(Chant {
+        limits: Limits::default(),
+    }))
+}
+
+/// Frees an interpreter.
+///
+/// # Safety
+/// `chant` has to be from [`chant_new`], and not freed already, or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_free(chant: *mut Chant) {
+    if !chant.is_null() {
+        drop(Box::from_raw(chant));
+    }
+}
+
+/// Sets the most calls that can be in progress at once, in programs `chant` evaluates, or does
+/// nothing if `chant` is `NULL`.
+///
+/// # Safety
+/// `chant` has to be from [`chant_new`], or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_set_max_depth(chant: *mut Chant, max_depth: usize) {
+    if let Some(chant) = chant.as_mut() {
+        chant.limits.max_depth = max_depth;
+    }
+}
+
+/// Sets the most bytes of the heap that programs `chant` evaluates can use, where 0 is no limit,
+/// or does nothing if `chant` is `NULL`. A program that would use more fails to evaluate, with
+/// an out of memory error, rather than aborting the host.
+///
+/// # Safety
+/// `chant` has to be from [`chant_new`], or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_set_max_heap(chant: *mut Chant, max_heap: usize) {
+    if let Some(chant) = chant.as_mut() {
+        chant.limits.max_heap = (max_heap > 0).then_some(max_heap);
+    }
+}
+
+/// Compiles the nul-terminated UTF-8 source `src`, and returns the value of its `main`, which is
+/// freed with [`chant_value_free`], or `NULL` if that fails.
+///
+/// # Safety
+/// `chant` has to be from [`chant_new`], and `src` a nul-terminated string, or each of them
+/// `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_eval(chant: *mut Chant, src: *const c_char) -> *mut ChantValue {
+    let Some(chant) = chant.as_ref() else {
+        fail("the interpreter is NULL");
+        return ptr::null_mut();
+    };
+    if src.is_null() {
+        fail("the source is NULL");
+        return ptr::null_mut();
+    }
+    let src = match CStr::from_ptr(src).to_str() {
+        Ok(src) => src,
+        Err(e) => {
+            fail(format!("the source isn't UTF-8: {e}"));
+            return ptr::null_mut();
+        }
+    };
+    guard(ptr::null_mut(), || {
+        let result = Compiler::new()
+            .limits(chant.limits.clone())
+            .compile(src)
+            .and_then(|session| session.run());
+        match result {
+            Ok(value) => Box::into_raw(Box::new(ChantValue(value))),
+            Err(e) => {
+                fail(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees a value.
+///
+/// # Safety
+/// `value` has to be from [`chant_eval`], and not freed already, or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_value_free(value: *mut ChantValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Writes `value` as a double to `out`, if it's a number that isn't complex, and returns whether
+/// it was. A `NULL` value fails, like the ones that aren't numbers.
+///
+/// # Safety
+/// `value` has to be from [`chant_eval`], or `NULL`, and `out` has to be writable.
+#[no_mangle]
+pub unsafe extern "C" fn chant_value_as_double(value: *const ChantValue, out: *mut f64) -> bool {
+    let Some(value) = value.as_ref() else {
+        fail("the value is NULL");
+        return false;
+    };
+    guard(false, || match f64::from_chant(&value.0) {
+        Ok(x) => {
+            *out = x;
+            true
+        }
+        Err(e) => {
+            fail(e);
+            false
+        }
+    })
+}
+
+/// Prints `value` like `chantrs --run` prints it, into a string freed with
+/// [`chant_string_free`], or returns `NULL` if `value` is `NULL`.
+///
+/// # Safety
+/// `value` has to be from [`chant_eval`], or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_value_to_string(value: *const ChantValue) -> *mut c_char {
+    let Some(value) = value.as_ref() else {
+        fail("the value is NULL");
+        return ptr::null_mut();
+    };
+    guard(ptr::null_mut(), || {
+        let mut s = value.0.to_string().into_bytes();
+        s.retain(|&b| b != 0);
+        CString::new(s).expect("the nuls are removed").into_raw()
+    })
+}
+
+/// Frees a string from [`chant_value_to_string`].
+///
+/// # Safety
+/// `s` has to be from [`chant_value_to_string`], and not freed already, or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chant_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// The message of the last error in this thread, or `NULL` if nothing has failed. It's valid
+/// until the next call that fails.
+#[no_mangle]
+pub extern "C" fn chant_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(chant_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn eval() {
+        unsafe {
+            let chant = chant_new();
+            let value = chant_eval(chant, c"fn main() { 1.5 * 2 }".as_ptr());
+            let mut x = 0.0;
+            assert!(chant_value_as_double(value, &mut x));
+            assert_eq!(x, 3.0);
+            let s = chant_value_to_string(value);
+            assert_eq!(CStr::from_ptr(s).to_str(), Ok("3"));
+            chant_string_free(s);
+            chant_value_free(value);
+
+            let value = chant_eval(chant, c"fn main() { true }".as_ptr());
+            assert!(!chant_value_as_double(value, &mut x));
+            assert_eq!(last_error(), "expected Real, found Bool");
+            chant_value_free(value);
+
+            assert!(chant_eval(chant, c"fn main() { y }".as_ptr()).is_null());
+            assert_eq!(last_error(), "compiling failed: `y` isn't defined");
+            chant_set_max_depth(chant, 10);
+            assert!(chant_eval(chant, c"fn main() { main() }".as_ptr()).is_null());
+            assert!(last_error().contains("stack overflow"), "{}", last_error());
+            assert!(chant_eval(chant, ptr::null()).is_null());
+            chant_set_max_heap(chant, 1 << 20);
+            assert!(chant_eval(chant, c"fn main() { [0; 1000000] }".as_ptr()).is_null());
+            assert_eq!(
+                last_error(),
+                "running failed: in `main`: out of memory, from using more than 1048576 bytes"
+            );
+            let value = chant_eval(chant, c"fn main() { [2; 1000][999] }".as_ptr());
+            assert!(chant_value_as_double(value, &mut x));
+            chant_value_free(value);
+            chant_set_max_heap(chant, 0);
+            let value = chant_eval(chant, c"fn main() { [3; 1000000][999999] }".as_ptr());
+            assert!(chant_value_as_double(value, &mut x));
+            assert_eq!(x, 3.0);
+            chant_value_free(value);
+            chant_free(chant);
+
+            assert!(chant_eval(ptr::null_mut(), c"".as_ptr()).is_null());
+            assert_eq!(last_error(), "the interpreter is NULL");
+            assert!(!chant_value_as_double(ptr::null(), &mut x));
+            assert_eq!(last_error(), "the value is NULL");
+            assert!(chant_value_to_string(ptr::null()).is_null());
+            chant_set_max_depth(ptr::null_mut(), 10);
+            chant_set_max_heap(ptr::null_mut(), 10);
+        }
+    }
+
+    #[test]
+    fn panics() {
+        assert!(!guard(false, || panic!("at {}", 1)));
+        assert_eq!(last_error(), "the interpreter panicked: at 1");
+        assert_eq!(guard(1, || panic!("with a &str")), 1);
+        assert_eq!(last_error(), "the interpreter panicked: with a &str");
+        assert!(guard(true, || true));
+    }
+
+    /// The C type of the Rust type `ty`, from the types the functions take and return.
+    fn c_type(ty: &str) -> String {
+        if let Some(ty) = ty.strip_prefix("*mut ") {
+            return format!("{} *", c_type(ty));
+        }
+        if let Some(ty) = ty.strip_prefix("*const ") {
+            return format!("const {} *", c_type(ty));
+        }
+        match ty {
+            "c_char" => "char",
+            "f64" => "double",
+            "usize" => "size_t",
+            "bool" | "Chant" | "ChantValue" => ty,
+            _ => panic!("no C type for {ty}"),
+        }
+        .to_string()
+    }
+
+    /// `ty` followed by `name`, like `Chant *chant` or `size_t max_depth`.
+    fn declare(ty: &str, name: &str) -> String {
+        match ty.ends_with('*') {
+            true => format!("{ty}{name}"),
+            false => format!("{ty} {name}"),
+        }
+    }
+
+    /// The header declares every function that's exported, with the prototype of its Rust
+    /// signature, and nothing else.
+    #[test]
+    fn header() {
+        let header = include_str!("../include/chant.h");
+        let lib = include_str!("lib.rs");
+        let prototypes: Vec<_> = lib
+            .split("extern \"C\" fn ")
+            .skip(1)
+            .filter_map(|f| {
+                let sig = f[..f.find('{')?].split_whitespace().collect::<Vec<_>>();
+                let sig = sig.join(" ");
+                let (name, rest) = sig.split_once('(')?;
+                let (params, ret) = rest.split_once(')')?;
+                let ret = ret
+                    .trim()
+                    .strip_prefix("-> ")
+                    .map_or("void".to_string(), c_type);
+                let params: Vec<_> = params
+                    .split(", ")
+                    .filter(|p| !p.is_empty())
+                    .map(|p| {
+                        let (name, ty) = p.split_once(": ").unwrap();
+                        declare(&c_type(ty), name)
+                    })
+                    .collect();
+                let params = match params.is_empty() {
+                    true => "void".to_string(),
+                    false => params.join(", "),
+                };
+                Some(format!("{}({params});", declare(&ret, name)))
+            })
+            .collect();
+        assert!(prototypes.len() > 5, "{prototypes:?}");
+        for prototype in &prototypes {
+            assert!(
+                header.lines().any(|line| line == prototype),
+                "chant.h doesn't declare {prototype}"
+            );
+        }
+        let declared = header
+            .lines()
+            .filter(|line| line.ends_with(");") && !line.starts_with(' '));
+        assert_eq!(declared.count(), prototypes.len(), "chant.h declares more");
+    }
+}
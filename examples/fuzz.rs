@@ -0,0 +1,152 @@
+//! Fuzzes the lexer and the parser without libFuzzer, by mutating a few snippets of chant at
+//! random, and running the checks of `chant::fuzz` on them. Run with
+//!
+//! ```text
+//! cargo run --release --example fuzz -- [runs] [seed]
+//! ```
+//!
+//! Each run also prints a random syntax tree and parses it back, see `chant::fuzz::round_trip`,
+//! and runs a random expression, see `chant::fuzz::differential`.
+//! An input that panics, or that takes longer than a second, is printed, and the fuzzer exits
+//! with an error.
+
+use chant::fuzz::{self, arbitrary::Gen};
+use std::panic;
+use std::process::exit;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const SEEDS: &[&str] = &[
+    "fn main() { x := [1, 2.5, 4i]; for i in 0..x.len() { print(x[i]) } }",
+    "struct Point<T: Numeric> { x: T, y: T } impl Add for Point { fn +(a, b) { a } }",
+    "enum E { A, B(Int), C { x: &'a Real } } fn f(e: E) { match e { E::A | _ => 1.5f } }",
+    "co fn gen() { yield 1; region 'r { t := task { fetch(a) }; } \"{t.0.1} \\{\" }",
+    "mod m { use a.b; const N: Natural = 3 ^ 2 ^ -1; } trait T { fn f(x: Self) -> Self; }",
+];
+
+/// Fragments that mutations insert, to get past the lexer more often than random bytes do.
+const FRAGMENTS: &[&str] = &[
+    "(",
+    ")",
+    "{",
+    "}",
+    "[",
+    "]",
+    ",",
+    ";",
+    ".",
+    "..",
+    ":=",
+    "=>",
+    "|",
+    "||",
+    "'",
+    "\"",
+    "{x}",
+    "fn ",
+    "if ",
+    "else ",
+    "match ",
+    "0",
+    "9999999999999999999",
+    "1.",
+    ".5",
+    "é",
+    " ",
+    "\n",
+];
+
+/// A xorshift generator, which is all the randomness fuzzing needs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn mutate(rng: &mut Rng, input: &mut Vec<u8>) {
+    let at = rng.below(input.len() + 1);
+    match rng.below(4) {
+        0 if at < input.len() => input[at] ^= 1 << rng.below(8),
+        1 if at < input.len() => {
+            let len = rng.below(input.len() - at).min(8) + 1;
+            input.drain(at..at + len);
+        }
+        2 => {
+            let seed = SEEDS[rng.below(SEEDS.len())].as_bytes();
+            let start = rng.below(seed.len());
+            let end = start + rng.below(seed.len() - start) + 1;
+            input.splice(at..at, seed[start..end].iter().copied());
+        }
+        _ => {
+            let fragment = FRAGMENTS[rng.below(FRAGMENTS.len())].as_bytes();
+            input.splice(at..at, fragment.iter().copied());
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let runs = args
+        .first()
+        .map_or(100_000, |n| n.parse().expect("runs is a number"));
+    let seed = args.get(1).map_or_else(
+        || {
+            (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH))
+                .unwrap()
+                .as_nanos() as u64
+        },
+        |n| n.parse().expect("seed is a number"),
+    );
+    println!("fuzzing {runs} inputs, from seed {seed}");
+    let mut rng = Rng(seed | 1);
+    // The checks run on a thread of their own, so an input that never finishes can be caught.
+    let (inputs, received) = mpsc::channel::<(Vec<u8>, u64)>();
+    let (done, results) = mpsc::channel();
+    thread::spawn(move || {
+        panic::set_hook(Box::new(|_| {}));
+        for (input, tree) in received {
+            let result = panic::catch_unwind(|| {
+                fuzz::tokenize(&input);
+                fuzz::parse(&input);
+                fuzz::round_trip(&Gen::new(tree, 5).program());
+                fuzz::differential(&Gen::new(tree, 5).expr());
+            });
+            done.send(result.map_err(|e| {
+                e.downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default()
+            }))
+            .unwrap();
+        }
+    });
+    for run in 0..runs {
+        let mut input = SEEDS[rng.below(SEEDS.len())].as_bytes().to_vec();
+        for _ in 0..rng.below(8) + 1 {
+            mutate(&mut rng, &mut input);
+        }
+        let tree = rng.next();
+        inputs.send((input.clone(), tree)).unwrap();
+        let failure = match results.recv_timeout(Duration::from_secs(1)) {
+            Ok(Ok(())) => continue,
+            Ok(Err(panic)) => format!("panicked: {panic}"),
+            Err(_) => "took more than a second".to_string(),
+        };
+        eprintln!(
+            "input {run} {failure}:\n{:?}\nor the tree of seed {tree}",
+            String::from_utf8_lossy(&input)
+        );
+        exit(1);
+    }
+    println!("no failures");
+}
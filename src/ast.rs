@@ -0,0 +1,582 @@
+//! Abstract syntax tree for the chant programming language
+
+pub mod json;
+pub mod pretty;
+pub mod rewrite;
+pub mod visit;
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::Range;
+
+/// An expression. Subexpressions are behind `Arc`s, so trees can share them, see [`rewrite`].
+#[derive(PartialEq, Clone, Debug)]
+pub enum Expr {
+    Integer(i64),
+    /// A number with a fractional part, like `1.5`.
+    Real(f64),
+    /// An imaginary number, like `4i`.
+    Imaginary(f64),
+    /// A fast float, like `1.5f`.
+    FReal(f64),
+    String(String),
+    /// `"text {expr} text"`
+    Interpolate {
+        parts: Vec<StrPart>,
+    },
+    Symbol(String),
+    Unary(UnaryOp, Arc<Expr>),
+    Binary(Arc<Expr>, BinaryOp, Arc<Expr>),
+    /// `a && b` or `a || b`, where `b` is only evaluated if `a` doesn't decide the result.
+    Logical(Arc<Expr>, LogicalOp, Arc<Expr>),
+    Call(Arc<Expr>, Vec<Expr>),
+    Block(Block),
+    /// `if cond { ... } else { ... }`, where `otherwise` is either another `If` (for `else if`)
+    /// or a `Block`.
+    If {
+        cond: Arc<Expr>,
+        then: Block,
+        otherwise: Option<Arc<Expr>>,
+    },
+    /// `a..b` (half-open) or `a...b` (inclusive). Both ends are optional for half-open ranges.
+    Range {
+        start: Option<Arc<Expr>>,
+        end: Option<Arc<Expr>>,
+        inclusive: bool,
+    },
+    /// `Name { field: value, ... }`
+    Struct {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+    /// `Enum::Variant`, `Enum::Variant(a, b)` or `Enum::Variant { x: a, y: b }`
+    Variant {
+        ty: String,
+        name: String,
+        args: Fields<Expr>,
+    },
+    /// `value.field`
+    Field(Arc<Expr>, String),
+    /// `receiver.method(args)`, which is distinct from calling a field, `(value.field)(args)`.
+    MethodCall {
+        receiver: Arc<Expr>,
+        method: String,
+        args: Vec<Expr>,
+    },
+    /// `(a, b, ...)`. `(a)` is just `a`, so one element tuples are written `(a,)`.
+    Tuple(Vec<Expr>),
+    /// `tuple.0`
+    TupleIndex(Arc<Expr>, usize),
+    /// `[a, b, ...]`
+    Array(Vec<Expr>),
+    /// `[value; count]`
+    Repeat {
+        value: Arc<Expr>,
+        count: Arc<Expr>,
+    },
+    /// `array[index]`
+    Index(Arc<Expr>, Arc<Expr>),
+    /// `value : Type`
+    Ascribe(Arc<Expr>, TypeExpr),
+    /// `|a, b| body`. `captures` lists the variables from the surrounding scope used in `body`,
+    /// and is empty until names have been resolved.
+    Lambda {
+        params: Vec<String>,
+        body: Arc<Expr>,
+        captures: Vec<String>,
+    },
+    /// `match scrutinee { pattern => body, ... }`
+    Match {
+        scrutinee: Arc<Expr>,
+        arms: Vec<Arm>,
+    },
+    /// `yield value` or a bare `yield`, suspending the surrounding `co fn`.
+    Yield(Option<Arc<Expr>>),
+    /// `region 'r { ... }` or `region { ... }`. Everything allocated in the region is freed at
+    /// once when the block ends, so references into it, of type `&'r T`, can't outlive it.
+    Region {
+        lifetime: Option<String>,
+        body: Block,
+    },
+    /// `task { ... }`. Coroutines started in the block are its child tasks, which the block waits
+    /// for before it ends. The first child to fail cancels the others.
+    Task(Block),
+}
+
+impl Expr {
+    /// Expressions ending in a block, which don't need a `;` to be used as a statement.
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            Expr::Block(_)
+                | Expr::If { .. }
+                | Expr::Match { .. }
+                | Expr::Region { .. }
+                | Expr::Task(_)
+        )
+    }
+
+    /// Expressions that can be assigned to, like `x`, `p.x`, `t.0` and `a[i]`.
+    pub fn is_place(&self) -> bool {
+        match self {
+            Expr::Symbol(_) => true,
+            Expr::Field(e, _) | Expr::TupleIndex(e, _) | Expr::Index(e, _) => e.is_place(),
+            _ => false,
+        }
+    }
+}
+
+/// A part of an interpolated string.
+#[derive(PartialEq, Clone, Debug)]
+pub enum StrPart {
+    Text(String),
+    Expr(Expr),
+}
+
+/// `pattern => body`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// A name, binding whatever is matched.
+    Binding(String),
+    /// A literal, like `1`, `-1` or `"one"`, matched by equality.
+    Literal(Expr),
+    /// `(a, b, ...)`
+    Tuple(Vec<Pattern>),
+    /// `a | b | ...`
+    Or(Vec<Pattern>),
+    /// `Enum::Variant`, `Enum::Variant(a, b)` or `Enum::Variant { x: a, y: b }`
+    Variant {
+        ty: String,
+        name: String,
+        args: Fields<Pattern>,
+    },
+}
+
+/// The fields of an enum variant, in a declaration, a construction or a pattern.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Fields<T> {
+    Unit,
+    Tuple(Vec<T>),
+    Named(Vec<(String, T)>),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    BitOr,
+    /// `+%`, which wraps around instead of overflowing, like the other operators ending in `%`.
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+}
+
+impl BinaryOp {
+    pub fn from_symbol(op: &str) -> Option<Self> {
+        use BinaryOp::*;
+        Some(match op {
+            "+" => Add,
+            "-" => Sub,
+            "*" => Mul,
+            "/" => Div,
+            "%" => Rem,
+            "^" => Pow,
+            "==" => Eq,
+            "!=" => Ne,
+            "<" => Lt,
+            "<=" => Le,
+            ">" => Gt,
+            ">=" => Ge,
+            "&" => BitAnd,
+            "|" => BitOr,
+            "+%" => WrappingAdd,
+            "-%" => WrappingSub,
+            "*%" => WrappingMul,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> &'static str {
+        use BinaryOp::*;
+        match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            Pow => "^",
+            Eq => "==",
+            Ne => "!=",
+            Lt => "<",
+            Le => "<=",
+            Gt => ">",
+            Ge => ">=",
+            BitAnd => "&",
+            BitOr => "|",
+            WrappingAdd => "+%",
+            WrappingSub => "-%",
+            WrappingMul => "*%",
+        }
+    }
+
+    /// Binding power of the operator. Higher binds tighter.
+    pub fn precedence(self) -> u8 {
+        use BinaryOp::*;
+        match self {
+            Eq | Ne | Lt | Le | Gt | Ge => 1,
+            BitOr => 2,
+            BitAnd => 3,
+            Add | Sub | WrappingAdd | WrappingSub => 4,
+            Mul | Div | Rem | WrappingMul => 5,
+            Pow => 7,
+        }
+    }
+
+    pub fn is_right_assoc(self) -> bool {
+        self == BinaryOp::Pow
+    }
+
+    /// Whether the operator can be used in a compound assignment, like `+=`.
+    pub fn is_compound(self) -> bool {
+        use BinaryOp::*;
+        matches!(self, Add | Sub | Mul | Div | Rem | Pow | BitAnd | BitOr)
+    }
+}
+
+/// Short-circuiting operators, which bind looser than comparisons. `&&` binds tighter than `||`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+impl LogicalOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            LogicalOp::And => "&&",
+            LogicalOp::Or => "||",
+        }
+    }
+}
+
+/// `{ a; b; c }`, where the value of the block is the trailing expression `c`. A block ending in
+/// a `;` has no `tail`.
+///
+/// Blocks are equal when their statements and tails are, wherever they are in the source.
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Arc<Expr>>,
+    /// The bytes of the source each statement is at, and then the tail, for a block that was
+    /// parsed. Blocks made otherwise can leave it empty.
+    pub spans: Vec<Range<usize>>,
+}
+
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        self.stmts == other.stmts && self.tail == other.tail
+    }
+}
+
+impl Block {
+    /// Where the `n`th statement is, or the tail, if `n` is the number of statements.
+    pub fn span(&self, n: usize) -> Option<Range<usize>> {
+        self.spans.get(n).cloned()
+    }
+}
+
+/// A statement. Loops carry a `label`, which is reserved for labeled `break` and `continue`, and
+/// always `None` for now.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Stmt {
+    Expr(Expr),
+    /// `name := value`
+    Let {
+        name: String,
+        value: Expr,
+    },
+    /// `place = value`, or `place op= value` for compound assignment like `a[i] += 1`.
+    Assign {
+        place: Expr,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+    /// `while cond { ... }`
+    While {
+        label: Option<String>,
+        cond: Expr,
+        body: Block,
+    },
+    /// `for binding in iter { ... }`
+    For {
+        label: Option<String>,
+        binding: String,
+        iter: Expr,
+        body: Block,
+    },
+    Break {
+        label: Option<String>,
+    },
+    Continue {
+        label: Option<String>,
+    },
+    /// `return value` or a bare `return`.
+    Return(Option<Expr>),
+}
+
+impl Stmt {
+    pub fn is_block_like(&self) -> bool {
+        match self {
+            Stmt::Expr(e) => e.is_block_like(),
+            Stmt::While { .. } | Stmt::For { .. } => true,
+            Stmt::Let { .. }
+            | Stmt::Assign { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Return(_) => false,
+        }
+    }
+}
+
+/// A whole chant file. Programs are equal when their items are, like [`Block`]s.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub items: Vec<Item>,
+    /// The bytes of the source each item is at, for a program that was parsed, or loaded, in
+    /// which case they're in the file of the program. Programs made otherwise can leave it empty.
+    pub spans: Vec<Range<usize>>,
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+/// An error about the code at `span`, a range of bytes of the source, so that it can say which
+/// line it's on. It shows as the error it's about.
+#[derive(Debug)]
+pub struct Located {
+    pub span: Range<usize>,
+    pub error: anyhow::Error,
+}
+
+impl Located {
+    /// `error`, said to be about the code at `span`, unless it already says where it is.
+    pub fn wrap(error: anyhow::Error, span: Option<Range<usize>>) -> anyhow::Error {
+        match span {
+            Some(span) if !error.is::<Located>() => anyhow::Error::msg(Located { span, error }),
+            _ => error,
+        }
+    }
+
+    /// Where `error` is about, if it says.
+    pub fn span(error: &anyhow::Error) -> Option<Range<usize>> {
+        error.downcast_ref::<Located>().map(|l| l.span.clone())
+    }
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+/// The line `offset` is on in `src`, counting from 1.
+pub fn line(src: &str, offset: usize) -> usize {
+    let before = src.as_bytes().get(..offset).unwrap_or(src.as_bytes());
+    before.iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// A top level declaration.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Item {
+    Fn(Function),
+    Struct(Struct),
+    Enum(Enum),
+    Trait(Trait),
+    Impl(Impl),
+    Const(Const),
+    /// `mod name { ... }`, or `mod name;` for a module in its own file, in which case `items` is
+    /// `None` until the file is loaded.
+    Mod {
+        name: String,
+        items: Option<Vec<Item>>,
+    },
+    /// `use path;`
+    Use(Path),
+}
+
+/// A path to an item, like `geometry.area`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Path {
+    pub segments: Vec<String>,
+}
+
+/// `fn name<T>(a: Int, b: T) -> Real { ... }`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Function {
+    pub sig: Signature,
+    pub body: Block,
+}
+
+/// `fn name<T>(a: Int, b: T) -> Real`, the part of a function that comes before the body.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Signature {
+    pub name: FnName,
+    /// Whether this is a `const fn`, which can be called in a const context.
+    pub is_const: bool,
+    /// Whether this is a `co fn`, a coroutine that can `yield` values to its caller.
+    pub is_co: bool,
+    pub generics: Vec<GenericParam>,
+    pub params: Vec<Param>,
+    pub ret: Option<TypeExpr>,
+}
+
+/// The name of a function. Functions named after an operator, like `fn +(a: Self, b: Self)`,
+/// overload that operator for their parameter types, when declared in an `impl` of the trait for
+/// that operator.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum FnName {
+    Ident(String),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+}
+
+/// `T` or `T: Bound + ...`, in the generic parameters of an item, or a lifetime parameter like
+/// `'a`, which has no bounds.
+#[derive(PartialEq, Clone, Debug)]
+pub struct GenericParam {
+    pub name: String,
+    pub lifetime: bool,
+    pub bounds: Vec<TypeExpr>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Param {
+    pub name: String,
+    pub ty: TypeExpr,
+}
+
+/// `struct Name<T> { field: Type, ... }`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Struct {
+    pub name: String,
+    pub generics: Vec<GenericParam>,
+    pub fields: Vec<Field>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    pub ty: TypeExpr,
+}
+
+/// `enum Name<T> { Variant(Type), ... }`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Enum {
+    pub name: String,
+    pub generics: Vec<GenericParam>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Fields<TypeExpr>,
+}
+
+/// `trait Name<T> { fn f(a: Self) -> Self ... }`
+#[derive(PartialEq, Clone, Debug)]
+pub struct Trait {
+    pub name: String,
+    pub generics: Vec<GenericParam>,
+    pub fns: Vec<TraitFn>,
+}
+
+/// A function in a trait, which may have a `default` body.
+#[derive(PartialEq, Clone, Debug)]
+pub struct TraitFn {
+    pub sig: Signature,
+    pub default: Option<Block>,
+}
+
+/// `impl<T> Trait for Type { ... }`, or `impl Type { ... }` for inherent methods.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Impl {
+    pub generics: Vec<GenericParam>,
+    pub trait_: Option<TypeExpr>,
+    pub ty: TypeExpr,
+    pub fns: Vec<Function>,
+}
+
+/// `const NAME: Type = value;`
+///
+/// The `value` of a constant is a const context, meaning that it must be evaluated at compile
+/// time, and so can only call `const fn`s.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Const {
+    pub name: String,
+    pub ty: TypeExpr,
+    pub value: Expr,
+}
+
+/// A type, as written in the source.
+#[derive(PartialEq, Clone, Debug)]
+pub enum TypeExpr {
+    Named(String),
+    /// `(A, B, ...)`, where `()` is the unit type.
+    Tuple(Vec<TypeExpr>),
+    /// `Name<A, B, ...>`
+    Generic {
+        name: String,
+        args: Vec<TypeExpr>,
+    },
+    /// `fn(A, B) -> C`
+    Fn {
+        params: Vec<TypeExpr>,
+        ret: Option<Box<TypeExpr>>,
+    },
+    /// `&T`, `&mut T` or `&'a T`, where `lifetime` is the name without the `'`.
+    Ref {
+        lifetime: Option<String>,
+        mutable: bool,
+        inner: Box<TypeExpr>,
+    },
+}
@@ -0,0 +1,871 @@
+//! JSON for the AST, in the shape documented in [`crate::json`].
+//!
+//! Operators are written as they are in the source, like `"+"` and `"&&"`. A program has the
+//! [`VERSION`] of the shape, and its items have their `"span"`, like tokens, or `null` if it
+//! isn't known. Blocks have the `"spans"` of their statements and then their tail, see
+//! [`Block::spans`]. Other nodes have no span.
+
+use super::*;
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::json::{node, object, span, span_from_json, FromJson, Json, ToJson, VERSION};
+use anyhow::*;
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        match self {
+            Expr::Integer(n) => node("Integer", vec![("value", Json::Integer(*n))]),
+            Expr::Real(x) => node("Real", vec![("value", Json::from(*x))]),
+            Expr::Imaginary(x) => node("Imaginary", vec![("value", Json::from(*x))]),
+            Expr::FReal(x) => node("FReal", vec![("value", Json::from(*x))]),
+            Expr::String(s) => node("String", vec![("value", s.to_json())]),
+            Expr::Interpolate { parts } => node("Interpolate", vec![("parts", parts.to_json())]),
+            Expr::Symbol(s) => node("Symbol", vec![("name", s.to_json())]),
+            Expr::Unary(op, e) => node(
+                "Unary",
+                vec![("op", Json::from(op.symbol())), ("value", e.to_json())],
+            ),
+            Expr::Binary(a, op, b) => node(
+                "Binary",
+                vec![
+                    ("op", Json::from(op.symbol())),
+                    ("lhs", a.to_json()),
+                    ("rhs", b.to_json()),
+                ],
+            ),
+            Expr::Logical(a, op, b) => node(
+                "Logical",
+                vec![
+                    ("op", Json::from(op.symbol())),
+                    ("lhs", a.to_json()),
+                    ("rhs", b.to_json()),
+                ],
+            ),
+            Expr::Call(f, args) => node(
+                "Call",
+                vec![("callee", f.to_json()), ("args", args.to_json())],
+            ),
+            Expr::Block(b) => node("Block", vec![("block", b.to_json())]),
+            Expr::Region { lifetime, body } => node(
+                "Region",
+                vec![("lifetime", lifetime.to_json()), ("body", body.to_json())],
+            ),
+            Expr::Task(b) => node("Task", vec![("block", b.to_json())]),
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => node(
+                "If",
+                vec![
+                    ("cond", cond.to_json()),
+                    ("then", then.to_json()),
+                    ("otherwise", otherwise.to_json()),
+                ],
+            ),
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => node(
+                "Range",
+                vec![
+                    ("start", start.to_json()),
+                    ("end", end.to_json()),
+                    ("inclusive", Json::from(*inclusive)),
+                ],
+            ),
+            Expr::Struct { name, fields } => node(
+                "Struct",
+                vec![("name", name.to_json()), ("fields", named(fields))],
+            ),
+            Expr::Variant { ty, name, args } => node(
+                "Variant",
+                vec![
+                    ("ty", ty.to_json()),
+                    ("name", name.to_json()),
+                    ("args", args.to_json()),
+                ],
+            ),
+            Expr::Field(e, field) => node(
+                "Field",
+                vec![("value", e.to_json()), ("field", field.to_json())],
+            ),
+            Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => node(
+                "MethodCall",
+                vec![
+                    ("receiver", receiver.to_json()),
+                    ("method", method.to_json()),
+                    ("args", args.to_json()),
+                ],
+            ),
+            Expr::Tuple(items) => node("Tuple", vec![("items", items.to_json())]),
+            Expr::TupleIndex(e, index) => node(
+                "TupleIndex",
+                vec![
+                    ("value", e.to_json()),
+                    ("index", Json::Integer(*index as i64)),
+                ],
+            ),
+            Expr::Array(items) => node("Array", vec![("items", items.to_json())]),
+            Expr::Repeat { value, count } => node(
+                "Repeat",
+                vec![("value", value.to_json()), ("count", count.to_json())],
+            ),
+            Expr::Index(e, index) => node(
+                "Index",
+                vec![("value", e.to_json()), ("index", index.to_json())],
+            ),
+            Expr::Ascribe(e, ty) => node(
+                "Ascribe",
+                vec![("value", e.to_json()), ("ty", ty.to_json())],
+            ),
+            Expr::Lambda {
+                params,
+                body,
+                captures,
+            } => node(
+                "Lambda",
+                vec![
+                    ("params", params.to_json()),
+                    ("body", body.to_json()),
+                    ("captures", captures.to_json()),
+                ],
+            ),
+            Expr::Match { scrutinee, arms } => node(
+                "Match",
+                vec![("scrutinee", scrutinee.to_json()), ("arms", arms.to_json())],
+            ),
+            Expr::Yield(value) => node("Yield", vec![("value", value.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Expr {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Integer" => Expr::Integer(json.field("value")?),
+            "Real" => Expr::Real(json.field("value")?),
+            "Imaginary" => Expr::Imaginary(json.field("value")?),
+            "FReal" => Expr::FReal(json.field("value")?),
+            "String" => Expr::String(json.field("value")?),
+            "Interpolate" => Expr::Interpolate {
+                parts: json.field("parts")?,
+            },
+            "Symbol" => Expr::Symbol(json.field("name")?),
+            "Unary" => Expr::Unary(unary_op(json.get("op")?)?, json.field("value")?),
+            "Binary" => Expr::Binary(
+                json.field("lhs")?,
+                binary_op(json.get("op")?)?,
+                json.field("rhs")?,
+            ),
+            "Logical" => Expr::Logical(
+                json.field("lhs")?,
+                logical_op(json.get("op")?)?,
+                json.field("rhs")?,
+            ),
+            "Call" => Expr::Call(json.field("callee")?, json.field("args")?),
+            "Block" => Expr::Block(json.field("block")?),
+            "Region" => Expr::Region {
+                lifetime: json.field("lifetime")?,
+                body: json.field("body")?,
+            },
+            "Task" => Expr::Task(json.field("block")?),
+            "If" => Expr::If {
+                cond: json.field("cond")?,
+                then: json.field("then")?,
+                otherwise: json.field("otherwise")?,
+            },
+            "Range" => Expr::Range {
+                start: json.field("start")?,
+                end: json.field("end")?,
+                inclusive: json.field("inclusive")?,
+            },
+            "Struct" => Expr::Struct {
+                name: json.field("name")?,
+                fields: named_from_json(json.get("fields")?)?,
+            },
+            "Variant" => Expr::Variant {
+                ty: json.field("ty")?,
+                name: json.field("name")?,
+                args: json.field("args")?,
+            },
+            "Field" => Expr::Field(json.field("value")?, json.field("field")?),
+            "MethodCall" => Expr::MethodCall {
+                receiver: json.field("receiver")?,
+                method: json.field("method")?,
+                args: json.field("args")?,
+            },
+            "Tuple" => Expr::Tuple(json.field("items")?),
+            "TupleIndex" => Expr::TupleIndex(json.field("value")?, json.field("index")?),
+            "Array" => Expr::Array(json.field("items")?),
+            "Repeat" => Expr::Repeat {
+                value: json.field("value")?,
+                count: json.field("count")?,
+            },
+            "Index" => Expr::Index(json.field("value")?, json.field("index")?),
+            "Ascribe" => Expr::Ascribe(json.field("value")?, json.field("ty")?),
+            "Lambda" => Expr::Lambda {
+                params: json.field("params")?,
+                body: json.field("body")?,
+                captures: json.field("captures")?,
+            },
+            "Match" => Expr::Match {
+                scrutinee: json.field("scrutinee")?,
+                arms: json.field("arms")?,
+            },
+            "Yield" => Expr::Yield(json.field("value")?),
+            kind => bail!("unknown kind of expression {kind:?}"),
+        })
+    }
+}
+
+fn unary_op(json: &Json) -> Result<UnaryOp> {
+    match json.as_str()? {
+        "-" => Ok(UnaryOp::Neg),
+        "!" => Ok(UnaryOp::Not),
+        op => bail!("unknown unary operator {op:?}"),
+    }
+}
+
+fn binary_op(json: &Json) -> Result<BinaryOp> {
+    let op = json.as_str()?;
+    BinaryOp::from_symbol(op).ok_or_else(|| anyhow!("unknown binary operator {op:?}"))
+}
+
+fn logical_op(json: &Json) -> Result<LogicalOp> {
+    match json.as_str()? {
+        "&&" => Ok(LogicalOp::And),
+        "||" => Ok(LogicalOp::Or),
+        op => bail!("unknown logical operator {op:?}"),
+    }
+}
+
+/// `name: value` pairs, as an object.
+fn named<T: ToJson>(fields: &[(String, T)]) -> Json {
+    Json::Object(
+        fields
+            .iter()
+            .map(|(name, t)| (name.clone(), t.to_json()))
+            .collect(),
+    )
+}
+
+/// Reads `name: value` pairs written by [`named`].
+fn named_from_json<T: FromJson>(json: &Json) -> Result<Vec<(String, T)>> {
+    let Json::Object(fields) = json else {
+        bail!("expected an object of fields, got {json}")
+    };
+    let fields = fields.iter().map(|(name, t)| {
+        let t = T::from_json(t).with_context(|| format!("in field {name:?}"))?;
+        Ok((name.clone(), t))
+    });
+    fields.collect()
+}
+
+impl ToJson for StrPart {
+    fn to_json(&self) -> Json {
+        match self {
+            StrPart::Text(s) => node("Text", vec![("value", s.to_json())]),
+            StrPart::Expr(e) => node("Expr", vec![("value", e.to_json())]),
+        }
+    }
+}
+
+impl FromJson for StrPart {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Text" => StrPart::Text(json.field("value")?),
+            "Expr" => StrPart::Expr(json.field("value")?),
+            kind => bail!("unknown kind of string part {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Arm {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("pattern", self.pattern.to_json()),
+            ("body", self.body.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Arm {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Arm {
+            pattern: json.field("pattern")?,
+            body: json.field("body")?,
+        })
+    }
+}
+
+impl ToJson for Pattern {
+    fn to_json(&self) -> Json {
+        match self {
+            Pattern::Wildcard => node("Wildcard", vec![]),
+            Pattern::Binding(name) => node("Binding", vec![("name", name.to_json())]),
+            Pattern::Literal(e) => node("Literal", vec![("value", e.to_json())]),
+            Pattern::Tuple(items) => node("Tuple", vec![("items", items.to_json())]),
+            Pattern::Or(alternatives) => node("Or", vec![("alternatives", alternatives.to_json())]),
+            Pattern::Variant { ty, name, args } => node(
+                "Variant",
+                vec![
+                    ("ty", ty.to_json()),
+                    ("name", name.to_json()),
+                    ("args", args.to_json()),
+                ],
+            ),
+        }
+    }
+}
+
+impl FromJson for Pattern {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Wildcard" => Pattern::Wildcard,
+            "Binding" => Pattern::Binding(json.field("name")?),
+            "Literal" => Pattern::Literal(json.field("value")?),
+            "Tuple" => Pattern::Tuple(json.field("items")?),
+            "Or" => Pattern::Or(json.field("alternatives")?),
+            "Variant" => Pattern::Variant {
+                ty: json.field("ty")?,
+                name: json.field("name")?,
+                args: json.field("args")?,
+            },
+            kind => bail!("unknown kind of pattern {kind:?}"),
+        })
+    }
+}
+
+impl<T: ToJson> ToJson for Fields<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Fields::Unit => node("Unit", vec![]),
+            Fields::Tuple(items) => node("Tuple", vec![("items", items.to_json())]),
+            Fields::Named(fields) => node("Named", vec![("fields", named(fields))]),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Fields<T> {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Unit" => Fields::Unit,
+            "Tuple" => Fields::Tuple(json.field("items")?),
+            "Named" => Fields::Named(named_from_json(json.get("fields")?)?),
+            kind => bail!("unknown kind of fields {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Block {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("stmts", self.stmts.to_json()),
+            ("tail", self.tail.to_json()),
+            ("spans", Json::Array(self.spans.iter().map(span).collect())),
+        ])
+    }
+}
+
+impl FromJson for Block {
+    fn from_json(json: &Json) -> Result<Self> {
+        let spans = json.get("spans")?.as_array()?;
+        Ok(Block {
+            stmts: json.field("stmts")?,
+            tail: json.field("tail")?,
+            spans: spans.iter().map(span_from_json).collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl ToJson for Stmt {
+    fn to_json(&self) -> Json {
+        match self {
+            Stmt::Expr(e) => node("Expr", vec![("value", e.to_json())]),
+            Stmt::Let { name, value } => node(
+                "Let",
+                vec![("name", name.to_json()), ("value", value.to_json())],
+            ),
+            Stmt::Assign { place, op, value } => node(
+                "Assign",
+                vec![
+                    ("place", place.to_json()),
+                    ("op", op.map_or(Json::Null, |op| Json::from(op.symbol()))),
+                    ("value", value.to_json()),
+                ],
+            ),
+            Stmt::While { label, cond, body } => node(
+                "While",
+                vec![
+                    ("label", label.to_json()),
+                    ("cond", cond.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Stmt::For {
+                label,
+                binding,
+                iter,
+                body,
+            } => node(
+                "For",
+                vec![
+                    ("label", label.to_json()),
+                    ("binding", binding.to_json()),
+                    ("iter", iter.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Stmt::Break { label } => node("Break", vec![("label", label.to_json())]),
+            Stmt::Continue { label } => node("Continue", vec![("label", label.to_json())]),
+            Stmt::Return(value) => node("Return", vec![("value", value.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Stmt {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Expr" => Stmt::Expr(json.field("value")?),
+            "Let" => Stmt::Let {
+                name: json.field("name")?,
+                value: json.field("value")?,
+            },
+            "Assign" => Stmt::Assign {
+                place: json.field("place")?,
+                op: match json.get("op")? {
+                    Json::Null => None,
+                    op => Some(binary_op(op)?),
+                },
+                value: json.field("value")?,
+            },
+            "While" => Stmt::While {
+                label: json.field("label")?,
+                cond: json.field("cond")?,
+                body: json.field("body")?,
+            },
+            "For" => Stmt::For {
+                label: json.field("label")?,
+                binding: json.field("binding")?,
+                iter: json.field("iter")?,
+                body: json.field("body")?,
+            },
+            "Break" => Stmt::Break {
+                label: json.field("label")?,
+            },
+            "Continue" => Stmt::Continue {
+                label: json.field("label")?,
+            },
+            "Return" => Stmt::Return(json.field("value")?),
+            kind => bail!("unknown kind of statement {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Program {
+    fn to_json(&self) -> Json {
+        let items = self.items.iter().enumerate().map(|(n, item)| {
+            let Json::Object(mut fields) = item.to_json() else {
+                unreachable!()
+            };
+            let at = self.spans.get(n).map_or(Json::Null, span);
+            fields.push(("span".to_string(), at));
+            Json::Object(fields)
+        });
+        object(vec![
+            ("version", Json::Integer(VERSION)),
+            ("items", Json::Array(items.collect())),
+        ])
+    }
+}
+
+/// Reads a program of the current [`VERSION`]. Its spans are only kept if every item has one.
+impl FromJson for Program {
+    fn from_json(json: &Json) -> Result<Self> {
+        let version = json.get("version")?.as_i64()?;
+        ensure!(
+            version == VERSION,
+            "the JSON is of version {version} of the AST, and only version {VERSION} can be read"
+        );
+        let items = json.get("items")?.as_array()?;
+        let spans = items.iter().map(|item| match item.get("span")? {
+            Json::Null => Ok(None),
+            span => span_from_json(span).map(Some),
+        });
+        let spans: Option<Vec<_>> = spans.collect::<Result<_>>()?;
+        Ok(Program {
+            items: json.field("items")?,
+            spans: spans.unwrap_or_default(),
+        })
+    }
+}
+
+impl ToJson for Item {
+    fn to_json(&self) -> Json {
+        match self {
+            Item::Fn(f) => node("Fn", vec![("fn", f.to_json())]),
+            Item::Struct(s) => node(
+                "Struct",
+                vec![
+                    ("name", s.name.to_json()),
+                    ("generics", s.generics.to_json()),
+                    ("fields", s.fields.to_json()),
+                ],
+            ),
+            Item::Enum(e) => node(
+                "Enum",
+                vec![
+                    ("name", e.name.to_json()),
+                    ("generics", e.generics.to_json()),
+                    ("variants", e.variants.to_json()),
+                ],
+            ),
+            Item::Trait(t) => node(
+                "Trait",
+                vec![
+                    ("name", t.name.to_json()),
+                    ("generics", t.generics.to_json()),
+                    ("fns", t.fns.to_json()),
+                ],
+            ),
+            Item::Impl(imp) => node(
+                "Impl",
+                vec![
+                    ("generics", imp.generics.to_json()),
+                    ("trait", imp.trait_.to_json()),
+                    ("ty", imp.ty.to_json()),
+                    ("fns", imp.fns.to_json()),
+                ],
+            ),
+            Item::Const(c) => node(
+                "Const",
+                vec![
+                    ("name", c.name.to_json()),
+                    ("ty", c.ty.to_json()),
+                    ("value", c.value.to_json()),
+                ],
+            ),
+            Item::Mod { name, items } => node(
+                "Mod",
+                vec![("name", name.to_json()), ("items", items.to_json())],
+            ),
+            Item::Use(path) => node("Use", vec![("path", path.segments.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Item {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Fn" => Item::Fn(json.field("fn")?),
+            "Struct" => Item::Struct(Struct {
+                name: json.field("name")?,
+                generics: json.field("generics")?,
+                fields: json.field("fields")?,
+            }),
+            "Enum" => Item::Enum(Enum {
+                name: json.field("name")?,
+                generics: json.field("generics")?,
+                variants: json.field("variants")?,
+            }),
+            "Trait" => Item::Trait(Trait {
+                name: json.field("name")?,
+                generics: json.field("generics")?,
+                fns: json.field("fns")?,
+            }),
+            "Impl" => Item::Impl(Impl {
+                generics: json.field("generics")?,
+                trait_: json.field("trait")?,
+                ty: json.field("ty")?,
+                fns: json.field("fns")?,
+            }),
+            "Const" => Item::Const(Const {
+                name: json.field("name")?,
+                ty: json.field("ty")?,
+                value: json.field("value")?,
+            }),
+            "Mod" => Item::Mod {
+                name: json.field("name")?,
+                items: json.field("items")?,
+            },
+            "Use" => Item::Use(Path {
+                segments: json.field("path")?,
+            }),
+            kind => bail!("unknown kind of item {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Function {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("sig", self.sig.to_json()),
+            ("body", self.body.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Function {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Function {
+            sig: json.field("sig")?,
+            body: json.field("body")?,
+        })
+    }
+}
+
+impl ToJson for Signature {
+    fn to_json(&self) -> Json {
+        let name = match &self.name {
+            FnName::Ident(name) => node("Ident", vec![("name", name.to_json())]),
+            FnName::Binary(op) => node("Binary", vec![("op", Json::from(op.symbol()))]),
+            FnName::Unary(op) => node("Unary", vec![("op", Json::from(op.symbol()))]),
+        };
+        object(vec![
+            ("name", name),
+            ("is_const", Json::from(self.is_const)),
+            ("is_co", Json::from(self.is_co)),
+            ("generics", self.generics.to_json()),
+            ("params", self.params.to_json()),
+            ("ret", self.ret.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Signature {
+    fn from_json(json: &Json) -> Result<Self> {
+        let name = json.get("name")?;
+        let name = match name.kind()? {
+            "Ident" => FnName::Ident(name.field("name")?),
+            "Binary" => FnName::Binary(binary_op(name.get("op")?)?),
+            "Unary" => FnName::Unary(unary_op(name.get("op")?)?),
+            kind => bail!("unknown kind of function name {kind:?}"),
+        };
+        Ok(Signature {
+            name,
+            is_const: json.field("is_const")?,
+            is_co: json.field("is_co")?,
+            generics: json.field("generics")?,
+            params: json.field("params")?,
+            ret: json.field("ret")?,
+        })
+    }
+}
+
+impl ToJson for GenericParam {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("name", self.name.to_json()),
+            ("lifetime", Json::from(self.lifetime)),
+            ("bounds", self.bounds.to_json()),
+        ])
+    }
+}
+
+impl FromJson for GenericParam {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(GenericParam {
+            name: json.field("name")?,
+            lifetime: json.field("lifetime")?,
+            bounds: json.field("bounds")?,
+        })
+    }
+}
+
+impl ToJson for Param {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Param {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Param {
+            name: json.field("name")?,
+            ty: json.field("ty")?,
+        })
+    }
+}
+
+impl ToJson for Field {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Field {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Field {
+            name: json.field("name")?,
+            ty: json.field("ty")?,
+        })
+    }
+}
+
+impl ToJson for Variant {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("name", self.name.to_json()),
+            ("fields", self.fields.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Variant {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Variant {
+            name: json.field("name")?,
+            fields: json.field("fields")?,
+        })
+    }
+}
+
+impl ToJson for TraitFn {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("sig", self.sig.to_json()),
+            ("default", self.default.to_json()),
+        ])
+    }
+}
+
+impl FromJson for TraitFn {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(TraitFn {
+            sig: json.field("sig")?,
+            default: json.field("default")?,
+        })
+    }
+}
+
+impl ToJson for TypeExpr {
+    fn to_json(&self) -> Json {
+        match self {
+            TypeExpr::Named(name) => node("Named", vec![("name", name.to_json())]),
+            TypeExpr::Tuple(items) => node("Tuple", vec![("items", items.to_json())]),
+            TypeExpr::Generic { name, args } => node(
+                "Generic",
+                vec![("name", name.to_json()), ("args", args.to_json())],
+            ),
+            TypeExpr::Fn { params, ret } => node(
+                "Fn",
+                vec![("params", params.to_json()), ("ret", ret.to_json())],
+            ),
+            TypeExpr::Ref {
+                lifetime,
+                mutable,
+                inner,
+            } => node(
+                "Ref",
+                vec![
+                    ("lifetime", lifetime.to_json()),
+                    ("mutable", Json::from(*mutable)),
+                    ("inner", inner.to_json()),
+                ],
+            ),
+        }
+    }
+}
+
+impl FromJson for TypeExpr {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Named" => TypeExpr::Named(json.field("name")?),
+            "Tuple" => TypeExpr::Tuple(json.field("items")?),
+            "Generic" => TypeExpr::Generic {
+                name: json.field("name")?,
+                args: json.field("args")?,
+            },
+            "Fn" => TypeExpr::Fn {
+                params: json.field("params")?,
+                ret: json.field("ret")?,
+            },
+            "Ref" => TypeExpr::Ref {
+                lifetime: json.field("lifetime")?,
+                mutable: json.field("mutable")?,
+                inner: json.field("inner")?,
+            },
+            kind => bail!("unknown kind of type {kind:?}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::grammar;
+    use crate::json::{FromJson, Json, ToJson};
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    /// `program` as JSON text, read back.
+    fn read_back(program: &Program) -> Result<Program> {
+        Program::from_json(&Json::parse(&program.to_json().to_string())?)
+    }
+
+    #[test]
+    fn expressions() -> Result<()> {
+        let (e, _) = grammar::Expression.parse("x + 1")?;
+        assert_eq!(
+            e.to_json().to_string(),
+            r#"{"kind":"Binary","op":"+","lhs":{"kind":"Symbol","name":"x"},"rhs":{"kind":"Integer","value":1}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn items() -> Result<()> {
+        let (program, _) = grammar::Program.parse("const fn id(x: &'a T) -> T { x }")?;
+        assert_eq!(
+            program.to_json().to_string(),
+            concat!(
+                r#"{"version":1,"items":[{"kind":"Fn","fn":{"sig":{"name":{"kind":"Ident","#,
+                r#""name":"id"},"is_const":true,"is_co":false,"generics":[],"params":"#,
+                r#"[{"name":"x","ty":{"kind":"Ref","lifetime":"a","mutable":false,"inner":"#,
+                r#"{"kind":"Named","name":"T"}}}],"ret":{"kind":"Named","name":"T"}},"body":"#,
+                r#"{"stmts":[],"tail":{"kind":"Symbol","name":"x"},"spans":[[29,30]]}},"#,
+                r#""span":[0,32]}]}"#
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips() -> Result<()> {
+        for src in [
+            include_str!("../../tests/cases/arithmetic.chant"),
+            include_str!("../../tests/cases/items.chant"),
+            include_str!("../../tests/cases/strings.chant"),
+        ] {
+            let (program, _) = grammar::Program.parse(src)?;
+            // Equality leaves out spans, but their debug output has them.
+            assert_eq!(
+                format!("{:?}", read_back(&program)?),
+                format!("{program:?}")
+            );
+        }
+        for seed in 0..500 {
+            let program = crate::fuzz::arbitrary::Gen::new(seed, 4).program();
+            assert_eq!(read_back(&program)?, program);
+        }
+        let old = r#"{"version":0,"items":[]}"#;
+        let err = Program::from_json(&Json::parse(old)?).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the JSON is of version 0 of the AST, and only version 1 can be read"
+        );
+        Ok(())
+    }
+}
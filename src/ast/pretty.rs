@@ -0,0 +1,839 @@
+//! Renders the AST back into canonical chant source.
+//!
+//! Parentheses are only added where they are needed to parse back into the same tree, so
+//! `(a + b) * c` keeps its parentheses, and `a + (b * c)` loses them.
+
+use super::*;
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+
+/// Binding power of expressions, mirroring the grammar. An expression is parenthesized when it
+/// binds looser than its surroundings require.
+const LOWEST: u8 = 0;
+const RANGE: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+/// Added to `BinaryOp::precedence` to get the binding power of a binary expression.
+const BINARY: u8 = 3;
+const UNARY: u8 = BINARY + 6;
+const POSTFIX: u8 = 11;
+const PRIMARY: u8 = 12;
+
+/// Pretty printer, indenting blocks with `indent` spaces.
+pub struct Printer {
+    indent: String,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Printer::new(4)
+    }
+}
+
+impl Printer {
+    pub fn new(indent: usize) -> Self {
+        Printer {
+            indent: " ".repeat(indent),
+        }
+    }
+
+    pub fn expr(&self, e: &Expr) -> String {
+        let mut w = self.writer();
+        w.expr(e, LOWEST);
+        w.out
+    }
+
+    pub fn item(&self, item: &Item) -> String {
+        let mut w = self.writer();
+        w.item(item);
+        w.out
+    }
+
+    /// A whole file, with a blank line between items.
+    pub fn program(&self, program: &Program) -> String {
+        let mut w = self.writer();
+        for (n, item) in program.items.iter().enumerate() {
+            if n > 0 {
+                w.out.push('\n');
+                w.newline();
+            }
+            w.item(item);
+        }
+        w.out.push('\n');
+        w.out
+    }
+
+    fn writer(&self) -> Writer<'_> {
+        Writer {
+            indent: &self.indent,
+            depth: 0,
+            out: String::new(),
+        }
+    }
+}
+
+struct Writer<'a> {
+    indent: &'a str,
+    depth: usize,
+    out: String,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.depth {
+            self.out.push_str(self.indent);
+        }
+    }
+
+    /// `items`, separated by `, `.
+    fn comma_separated<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        for (n, item) in items.iter().enumerate() {
+            if n > 0 {
+                self.push(", ");
+            }
+            f(self, item);
+        }
+    }
+
+    /// `(a, b)`, where a single item is written `(a,)` so that it is a tuple and not grouped.
+    fn tuple<T>(&mut self, items: &[T], f: impl FnMut(&mut Self, &T)) {
+        self.push("(");
+        self.comma_separated(items, f);
+        if items.len() == 1 {
+            self.push(",");
+        }
+        self.push(")");
+    }
+
+    /// `{` followed by each of `items` on its own line, and a closing `}`, or `{}` if there are
+    /// no items.
+    fn lines<T>(&mut self, items: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        if items.is_empty() {
+            self.push("{}");
+            return;
+        }
+        self.push("{");
+        self.depth += 1;
+        for item in items {
+            self.newline();
+            f(self, item);
+        }
+        self.depth -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    /// An expression, parenthesized if it binds looser than `min`.
+    fn expr(&mut self, e: &Expr, min: u8) {
+        if binding_power(e) < min {
+            self.push("(");
+            self.expr(e, LOWEST);
+            self.push(")");
+            return;
+        }
+        match e {
+            Expr::Integer(n) => self.push(&n.to_string()),
+            Expr::Real(x) => self.push(&real(*x)),
+            Expr::Imaginary(x) => self.push(&format!("{}i", real(*x))),
+            Expr::FReal(x) => self.push(&format!("{}f", real(*x))),
+            Expr::String(s) => {
+                self.push("\"");
+                self.push(&escape(s));
+                self.push("\"");
+            }
+            Expr::Interpolate { parts } => {
+                self.push("\"");
+                for part in parts {
+                    match part {
+                        StrPart::Text(s) => self.push(&escape(s)),
+                        StrPart::Expr(e) => {
+                            self.push("{");
+                            self.expr(e, LOWEST);
+                            self.push("}");
+                        }
+                    }
+                }
+                self.push("\"");
+            }
+            Expr::Symbol(s) => self.push(s),
+            Expr::Unary(op, e) => {
+                self.push(op.symbol());
+                self.expr(e, UNARY + 1);
+            }
+            Expr::Binary(a, op, b) => {
+                let p = BINARY + op.precedence();
+                let (left, right) = if op.is_right_assoc() {
+                    (p + 1, p)
+                } else {
+                    (p, p + 1)
+                };
+                self.expr(a, left);
+                self.push(&format!(" {} ", op.symbol()));
+                self.expr(b, right);
+            }
+            Expr::Logical(a, op, b) => {
+                let (left, right) = match op {
+                    LogicalOp::Or => (OR, AND),
+                    LogicalOp::And => (AND, BINARY + 1),
+                };
+                self.expr(a, left);
+                self.push(&format!(" {} ", op.symbol()));
+                self.expr(b, right);
+            }
+            Expr::Call(f, args) => {
+                // Calling a field is `(a.f)(x)`, since `a.f(x)` is a method call, and calling a
+                // variant is `(E::A)(x)`, since `E::A(x)` is a variant with a field.
+                let min = match **f {
+                    Expr::Field(..)
+                    | Expr::Variant {
+                        args: Fields::Unit, ..
+                    } => PRIMARY + 1,
+                    _ => POSTFIX,
+                };
+                self.expr(f, min);
+                self.push("(");
+                self.comma_separated(args, |w, a| w.expr(a, LOWEST));
+                self.push(")");
+            }
+            Expr::Block(b) => self.block(b),
+            Expr::Region { lifetime, body } => {
+                self.push("region ");
+                if let Some(l) = lifetime {
+                    self.push(&format!("'{l} "));
+                }
+                self.block(body);
+            }
+            Expr::Task(b) => {
+                self.push("task ");
+                self.block(b);
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.push("if ");
+                self.condition(cond);
+                self.push(" ");
+                self.block(then);
+                if let Some(e) = otherwise {
+                    self.push(" else ");
+                    self.expr(e, LOWEST);
+                }
+            }
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                if let Some(e) = start {
+                    self.expr(e, OR);
+                }
+                self.push(if *inclusive { "..." } else { ".." });
+                if let Some(e) = end {
+                    self.expr(e, OR);
+                }
+            }
+            Expr::Struct { name, fields } => {
+                self.push(name);
+                self.push(" { ");
+                self.comma_separated(fields, |w, (name, e)| {
+                    w.push(name);
+                    w.push(": ");
+                    w.expr(e, LOWEST);
+                });
+                self.push(" }");
+            }
+            Expr::Variant { ty, name, args } => {
+                self.push(&format!("{ty}::{name}"));
+                self.fields(args, |w, e| w.expr(e, LOWEST));
+            }
+            Expr::Field(e, field) => {
+                self.expr(e, POSTFIX);
+                self.push(".");
+                self.push(field);
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                self.expr(receiver, POSTFIX);
+                self.push(".");
+                self.push(method);
+                self.push("(");
+                self.comma_separated(args, |w, a| w.expr(a, LOWEST));
+                self.push(")");
+            }
+            Expr::Tuple(items) => self.tuple(items, |w, e| w.expr(e, LOWEST)),
+            Expr::TupleIndex(e, index) => {
+                // `(1).0` is a tuple index, since `1.0` is a number.
+                let min = if let Expr::Integer(_) = **e {
+                    PRIMARY + 1
+                } else {
+                    POSTFIX
+                };
+                self.expr(e, min);
+                self.push(&format!(".{index}"));
+            }
+            Expr::Array(items) => {
+                self.push("[");
+                self.comma_separated(items, |w, e| w.expr(e, LOWEST));
+                self.push("]");
+            }
+            Expr::Repeat { value, count } => {
+                self.push("[");
+                self.expr(value, LOWEST);
+                self.push("; ");
+                self.expr(count, LOWEST);
+                self.push("]");
+            }
+            Expr::Index(e, index) => {
+                self.expr(e, POSTFIX);
+                self.push("[");
+                self.expr(index, LOWEST);
+                self.push("]");
+            }
+            Expr::Ascribe(e, ty) => {
+                self.expr(e, RANGE);
+                self.push(": ");
+                self.ty(ty);
+            }
+            Expr::Lambda { params, body, .. } => {
+                self.push("|");
+                self.push(&params.join(", "));
+                self.push("| ");
+                self.expr(body, LOWEST);
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.push("match ");
+                self.condition(scrutinee);
+                self.push(" ");
+                self.lines(arms, |w, arm| {
+                    w.pattern(&arm.pattern);
+                    w.push(" => ");
+                    w.expr(&arm.body, LOWEST);
+                    w.push(",");
+                });
+            }
+            Expr::Yield(value) => {
+                self.push("yield");
+                if let Some(e) = value {
+                    self.push(" ");
+                    self.expr(e, LOWEST);
+                }
+            }
+        }
+    }
+
+    /// An expression followed by a block, where struct literals need parentheses.
+    fn condition(&mut self, e: &Expr) {
+        if has_bare_struct(e) || ends_open(e) {
+            self.push("(");
+            self.expr(e, LOWEST);
+            self.push(")");
+        } else {
+            self.expr(e, LOWEST);
+        }
+    }
+
+    fn block(&mut self, b: &Block) {
+        if b.stmts.is_empty() && b.tail.is_none() {
+            self.push("{}");
+            return;
+        }
+        self.push("{");
+        self.depth += 1;
+        for (n, stmt) in b.stmts.iter().enumerate() {
+            self.newline();
+            self.stmt(stmt);
+            // A block-like expression at the end of a block would be its tail without the `;`,
+            // and one followed by an operator would be its left operand.
+            let continued = match b.stmts.get(n + 1) {
+                Some(Stmt::Expr(e)) => starts_with_operator(e),
+                Some(_) => false,
+                None => b.tail.as_ref().is_none_or(|e| starts_with_operator(e)),
+            };
+            if !stmt.is_block_like() || (continued && matches!(stmt, Stmt::Expr(_))) {
+                self.push(";");
+            }
+        }
+        if let Some(e) = &b.tail {
+            self.newline();
+            self.expr(e, LOWEST);
+        }
+        self.depth -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) => self.expr(e, LOWEST),
+            Stmt::Let { name, value } => {
+                self.push(name);
+                self.push(" := ");
+                self.expr(value, LOWEST);
+            }
+            Stmt::Assign { place, op, value } => {
+                self.expr(place, LOWEST);
+                self.push(" ");
+                if let Some(op) = op {
+                    self.push(op.symbol());
+                }
+                self.push("= ");
+                self.expr(value, LOWEST);
+            }
+            Stmt::While { cond, body, .. } => {
+                self.push("while ");
+                self.condition(cond);
+                self.push(" ");
+                self.block(body);
+            }
+            Stmt::For {
+                binding,
+                iter,
+                body,
+                ..
+            } => {
+                self.push(&format!("for {binding} in "));
+                self.condition(iter);
+                self.push(" ");
+                self.block(body);
+            }
+            Stmt::Break { .. } => self.push("break"),
+            Stmt::Continue { .. } => self.push("continue"),
+            Stmt::Return(value) => {
+                self.push("return");
+                if let Some(e) = value {
+                    self.push(" ");
+                    self.expr(e, LOWEST);
+                }
+            }
+        }
+    }
+
+    fn pattern(&mut self, p: &Pattern) {
+        match p {
+            Pattern::Wildcard => self.push("_"),
+            Pattern::Binding(name) => self.push(name),
+            Pattern::Literal(e) => self.expr(e, LOWEST),
+            Pattern::Tuple(items) => self.tuple(items, |w, p| w.pattern(p)),
+            Pattern::Or(alternatives) => {
+                for (n, p) in alternatives.iter().enumerate() {
+                    if n > 0 {
+                        self.push(" | ");
+                    }
+                    self.pattern(p);
+                }
+            }
+            Pattern::Variant { ty, name, args } => {
+                self.push(&format!("{ty}::{name}"));
+                self.fields(args, |w, p| w.pattern(p));
+            }
+        }
+    }
+
+    fn fields<T>(&mut self, fields: &Fields<T>, mut f: impl FnMut(&mut Self, &T)) {
+        match fields {
+            Fields::Unit => {}
+            Fields::Tuple(items) => {
+                self.push("(");
+                self.comma_separated(items, f);
+                self.push(")");
+            }
+            Fields::Named(items) => {
+                self.push(" { ");
+                self.comma_separated(items, |w, (name, t)| {
+                    w.push(name);
+                    w.push(": ");
+                    f(w, t);
+                });
+                self.push(" }");
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Fn(f) => self.function(f),
+            Item::Struct(s) => {
+                self.push("struct ");
+                self.push(&s.name);
+                self.generics(&s.generics);
+                self.push(" ");
+                self.lines(&s.fields, |w, field| {
+                    w.push(&field.name);
+                    w.push(": ");
+                    w.ty(&field.ty);
+                    w.push(",");
+                });
+            }
+            Item::Enum(e) => {
+                self.push("enum ");
+                self.push(&e.name);
+                self.generics(&e.generics);
+                self.push(" ");
+                self.lines(&e.variants, |w, v| {
+                    w.push(&v.name);
+                    w.fields(&v.fields, |w, ty| w.ty(ty));
+                    w.push(",");
+                });
+            }
+            Item::Trait(t) => {
+                self.push("trait ");
+                self.push(&t.name);
+                self.generics(&t.generics);
+                self.push(" ");
+                self.lines(&t.fns, |w, f| {
+                    w.signature(&f.sig);
+                    match &f.default {
+                        Some(body) => {
+                            w.push(" ");
+                            w.block(body);
+                        }
+                        None => w.push(";"),
+                    }
+                });
+            }
+            Item::Impl(imp) => {
+                self.push("impl");
+                self.generics(&imp.generics);
+                self.push(" ");
+                if let Some(t) = &imp.trait_ {
+                    self.ty(t);
+                    self.push(" for ");
+                }
+                self.ty(&imp.ty);
+                self.push(" ");
+                self.lines(&imp.fns, |w, f| w.function(f));
+            }
+            Item::Const(c) => {
+                self.push(&format!("const {}: ", c.name));
+                self.ty(&c.ty);
+                self.push(" = ");
+                self.expr(&c.value, LOWEST);
+                self.push(";");
+            }
+            Item::Mod { name, items } => {
+                self.push("mod ");
+                self.push(name);
+                match items {
+                    Some(items) => {
+                        self.push(" ");
+                        self.lines(items, |w, item| w.item(item));
+                    }
+                    None => self.push(";"),
+                }
+            }
+            Item::Use(path) => {
+                self.push("use ");
+                self.push(&path.segments.join("."));
+                self.push(";");
+            }
+        }
+    }
+
+    fn function(&mut self, f: &Function) {
+        self.signature(&f.sig);
+        self.push(" ");
+        self.block(&f.body);
+    }
+
+    fn signature(&mut self, sig: &Signature) {
+        if sig.is_const {
+            self.push("const ");
+        }
+        if sig.is_co {
+            self.push("co ");
+        }
+        self.push("fn ");
+        self.push(match &sig.name {
+            FnName::Ident(name) => name,
+            FnName::Binary(op) => op.symbol(),
+            FnName::Unary(op) => op.symbol(),
+        });
+        self.generics(&sig.generics);
+        self.push("(");
+        self.comma_separated(&sig.params, |w, p| {
+            w.push(&p.name);
+            w.push(": ");
+            w.ty(&p.ty);
+        });
+        self.push(")");
+        if let Some(ty) = &sig.ret {
+            self.push(" -> ");
+            self.ty(ty);
+        }
+    }
+
+    fn generics(&mut self, generics: &[GenericParam]) {
+        if generics.is_empty() {
+            return;
+        }
+        self.push("<");
+        self.comma_separated(generics, |w, g| {
+            if g.lifetime {
+                w.push("'");
+            }
+            w.push(&g.name);
+            for (n, bound) in g.bounds.iter().enumerate() {
+                w.push(if n == 0 { ": " } else { " + " });
+                w.ty(bound);
+            }
+        });
+        self.push(">");
+    }
+
+    fn ty(&mut self, ty: &TypeExpr) {
+        match ty {
+            TypeExpr::Named(name) => self.push(name),
+            TypeExpr::Tuple(items) => self.tuple(items, |w, ty| w.ty(ty)),
+            TypeExpr::Generic { name, args } => {
+                self.push(name);
+                self.push("<");
+                self.comma_separated(args, |w, ty| w.ty(ty));
+                self.push(">");
+            }
+            TypeExpr::Fn { params, ret } => {
+                self.push("fn(");
+                self.comma_separated(params, |w, ty| w.ty(ty));
+                self.push(")");
+                if let Some(ty) = ret {
+                    self.push(" -> ");
+                    self.ty(ty);
+                }
+            }
+            TypeExpr::Ref {
+                lifetime,
+                mutable,
+                inner,
+            } => {
+                self.push("&");
+                if let Some(l) = lifetime {
+                    self.push(&format!("'{l} "));
+                }
+                if *mutable {
+                    self.push("mut ");
+                }
+                self.ty(inner);
+            }
+        }
+    }
+}
+
+fn binding_power(e: &Expr) -> u8 {
+    match e {
+        Expr::Ascribe(..) | Expr::Lambda { .. } | Expr::Yield(_) => LOWEST,
+        // These aren't followed by postfix operators when parsed, so they are parenthesized
+        // whenever they are operands.
+        Expr::Block(_)
+        | Expr::If { .. }
+        | Expr::Match { .. }
+        | Expr::Region { .. }
+        | Expr::Task(_) => LOWEST,
+        Expr::Range { .. } => RANGE,
+        Expr::Logical(_, LogicalOp::Or, _) => OR,
+        Expr::Logical(_, LogicalOp::And, _) => AND,
+        Expr::Binary(_, op, _) => BINARY + op.precedence(),
+        Expr::Unary(..) => UNARY,
+        Expr::Call(..)
+        | Expr::Field(..)
+        | Expr::MethodCall { .. }
+        | Expr::TupleIndex(..)
+        | Expr::Index(..) => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+/// Whether `e` contains a struct literal that isn't inside parentheses or brackets, which would
+/// be mistaken for a block in a condition.
+fn has_bare_struct(e: &Expr) -> bool {
+    match e {
+        Expr::Struct { .. } => true,
+        Expr::Variant { args, .. } => matches!(args, Fields::Named(_)),
+        Expr::Unary(_, e)
+        | Expr::Field(e, _)
+        | Expr::TupleIndex(e, _)
+        | Expr::Index(e, _)
+        | Expr::Call(e, _)
+        | Expr::MethodCall { receiver: e, .. }
+        | Expr::Ascribe(e, _) => has_bare_struct(e),
+        Expr::Binary(a, _, b) | Expr::Logical(a, _, b) => has_bare_struct(a) || has_bare_struct(b),
+        Expr::Range { start, end, .. } => start.iter().chain(end).any(|e| has_bare_struct(e)),
+        _ => false,
+    }
+}
+
+/// Whether `e` starts with an operator that is also a binary operator, like the `-` of `-a` or
+/// the `|` of a lambda.
+fn starts_with_operator(e: &Expr) -> bool {
+    match e {
+        Expr::Unary(UnaryOp::Neg, _) | Expr::Range { start: None, .. } | Expr::Lambda { .. } => {
+            true
+        }
+        Expr::Binary(e, ..)
+        | Expr::Logical(e, ..)
+        | Expr::Call(e, _)
+        | Expr::Field(e, _)
+        | Expr::MethodCall { receiver: e, .. }
+        | Expr::TupleIndex(e, _)
+        | Expr::Index(e, _)
+        | Expr::Ascribe(e, _)
+        | Expr::Range { start: Some(e), .. } => starts_with_operator(e),
+        _ => false,
+    }
+}
+
+/// Whether `e` ends in an expression that would take the block after a condition as part of it.
+/// Ranges without an end take it as their end, and `yield` values and lambda bodies are parsed
+/// with struct literals allowed, which go on to the block.
+fn ends_open(e: &Expr) -> bool {
+    matches!(
+        e,
+        Expr::Yield(_) | Expr::Lambda { .. } | Expr::Range { end: None, .. }
+    )
+}
+
+/// A real number, with a `.` even when it's integral, like `2.0` or `100000000000000000000.0`, so
+/// that it isn't parsed as an integer, which a large one wouldn't even fit in.
+fn real(x: f64) -> String {
+    let mut s = x.to_string();
+    if !s.contains('.') {
+        s.push_str(".0");
+    }
+    s
+}
+
+/// Escapes `s` for use in a string literal.
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '{' => out.push_str("\\{"),
+            '}' => out.push_str("\\}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::pretty::*;
+    use crate::grammar;
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    fn reparse(src: &str) -> Result<()> {
+        let (program, _) = grammar::Program.parse(src)?;
+        let printed = Printer::default().program(&program);
+        let (again, _) = grammar::Program.parse(&printed)?;
+        assert_eq!(program, again, "{printed}");
+        assert_eq!(printed, Printer::default().program(&again));
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        reparse(
+            r#"
+            use geometry.area;
+            mod shapes { struct Point<T> { x: T, y: T } }
+            mod io;
+            enum Shape { Circle(Real), Rect { w: Real, h: Real }, Empty }
+            const G: Real = 9.81;
+            trait Add<T> { fn +(a: Self, b: T) -> Self; fn zero() -> Self { 0 } }
+            impl<T: Numeric + Ord> Add<T> for Vec<T> { const fn +(a: Self, b: T) -> Self { a } }
+            fn first<'a, T>(xs: &'a mut Vec<T>, f: fn(T) -> (T,)) -> &'a T { xs[0] }
+            co fn gen() { yield 1; yield }
+            const co fn fold() { while (|| x) { (task { x }).0 } for i in (0..) {} }
+            fn main() {
+                x := (a + b) * c ^ d ^ e + 1.5 - 2.0 * 4i + 0.5i + 1.5f;
+                y := -(-x) - -x;
+                p.x += a || b && !c;
+                t.0 = [1, 2, 3][0];
+                z := [0; 3];
+                s := "a\"\n{x + 1} \{";
+                for i in 0..n |> sum { continue }
+                while (P { x: 1 }).x == 1 { break }
+                if a { 1 } else if b { x } else { y }
+                f := |a, b| a + b;
+                match s {
+                    Shape::Circle(r) | Shape::Empty => r,
+                    Shape::Rect { w: _, h: (1, -2) } => { h }
+                    _ => "str",
+                };
+                v := (if a { 1 } else { 2 }) + (a: Real);
+                r := (..b, a...b, (a..b).len(), |x| x);
+                region 'r { a := b: &'r Int; region { a } }
+                t := task { fetch(a); fetch(b) };
+                if a { 1 };
+                -x;
+                return 1..
+            }
+            "#,
+        )
+    }
+
+    #[test]
+    fn reals() -> Result<()> {
+        let printer = Printer::default();
+        for (e, printed) in [
+            (Expr::Real(1e20), "100000000000000000000.0"),
+            (Expr::Imaginary(2.), "2.0i"),
+            (Expr::FReal(1e20), "100000000000000000000.0f"),
+            (Expr::Real(0.1), "0.1"),
+        ] {
+            assert_eq!(printer.expr(&e), printed);
+            let (again, _) = grammar::Expression.parse(printed)?;
+            assert_eq!(again, Some(e));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn indentation() -> Result<()> {
+        let (program, _) = grammar::Program
+            .parse("fn f(a: Int) -> Int { if a { b := 1; b } else { 0 } } struct Unit {}")?;
+        assert_eq!(
+            Printer::new(2).program(&program),
+            "fn f(a: Int) -> Int {\n  if a {\n    b := 1;\n    b\n  } else {\n    0\n  }\n}\n\
+             \n\
+             struct Unit {}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parentheses() -> Result<()> {
+        let printer = Printer::default();
+        for (src, printed) in [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a ^ b) ^ c", "(a ^ b) ^ c"),
+            ("-(a ^ b)", "-a ^ b"),
+            ("(-a) ^ b", "(-a) ^ b"),
+            ("(|x| x)(1)", "(|x| x)(1)"),
+            ("(a || b) && c", "(a || b) && c"),
+            ("(a.f)(x)", "(a.f)(x)"),
+            ("(E::A)(x)", "(E::A)(x)"),
+            ("(1).0", "(1).0"),
+        ] {
+            let (e, _) = grammar::Expression.parse(src)?;
+            assert_eq!(printer.expr(&e.unwrap()), printed);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! Rewriting the AST into a new one, for passes like desugaring and const folding that run one
+//! after the other.
+//!
+//! The children of expressions are behind [`Arc`]s, so a rewrite shares the subtrees it doesn't
+//! change with the tree it was given, rather than copying them. The other nodes, like statements
+//! and items, are copied along with the nodes next to them that change, but only down to the
+//! first `Arc`s. So a pass that changes little takes little memory, however many trees are kept.
+//!
+//! [`VisitMut`](super::visit::VisitMut) passes change a tree in place instead, unsharing the
+//! nodes they visit if they're shared, with [`Arc::make_mut`].
+
+use super::*;
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+
+/// A rewrite of some kinds of expressions, applied bottom up by [`program`] and [`expr`].
+pub trait Rewrite {
+    /// The replacement for `e`, whose children have been rewritten already, or `None` to keep it.
+    fn rewrite_expr(&mut self, e: &Expr) -> Option<Expr>;
+}
+
+/// Returns `None`, for unchanged, if none of the rewritten children are `Some`.
+macro_rules! unchanged {
+    ($($new:ident),*) => {
+        if $($new.is_none())&&* {
+            return None;
+        }
+    };
+}
+
+/// The rewritten `new`, or the `old` it would have replaced.
+fn keep<T: Clone>(new: Option<T>, old: &T) -> T {
+    new.unwrap_or_else(|| old.clone())
+}
+
+/// Rewrites the items of `old` with `f`, or returns `None` if none of them changed.
+fn each<T: Clone>(old: &[T], mut f: impl FnMut(&T) -> Option<T>) -> Option<Vec<T>> {
+    let (n, first) = old.iter().enumerate().find_map(|(n, t)| Some((n, f(t)?)))?;
+    let mut new = old[..n].to_vec();
+    new.push(first);
+    new.extend(old[n + 1..].iter().map(|t| keep(f(t), t)));
+    Some(new)
+}
+
+fn fields<T: Clone>(old: &Fields<T>, mut f: impl FnMut(&T) -> Option<T>) -> Option<Fields<T>> {
+    match old {
+        Fields::Unit => None,
+        Fields::Tuple(items) => each(items, f).map(Fields::Tuple),
+        Fields::Named(items) => {
+            each(items, |(name, t)| Some((name.clone(), f(t)?))).map(Fields::Named)
+        }
+    }
+}
+
+/// Rewrites every expression of `program`.
+pub fn program<R: Rewrite + ?Sized>(r: &mut R, program: &Program) -> Program {
+    Program {
+        items: keep(each(&program.items, |i| item(r, i)), &program.items),
+        spans: program.spans.clone(),
+    }
+}
+
+pub fn item<R: Rewrite + ?Sized>(r: &mut R, item: &Item) -> Option<Item> {
+    Some(match item {
+        Item::Fn(f) => Item::Fn(function(r, f)?),
+        Item::Trait(t) => Item::Trait(Trait {
+            name: t.name.clone(),
+            generics: t.generics.clone(),
+            fns: each(&t.fns, |f| {
+                Some(TraitFn {
+                    sig: f.sig.clone(),
+                    default: Some(block(r, f.default.as_ref()?)?),
+                })
+            })?,
+        }),
+        Item::Impl(imp) => Item::Impl(Impl {
+            generics: imp.generics.clone(),
+            trait_: imp.trait_.clone(),
+            ty: imp.ty.clone(),
+            fns: each(&imp.fns, |f| function(r, f))?,
+        }),
+        Item::Const(c) => Item::Const(Const {
+            name: c.name.clone(),
+            ty: c.ty.clone(),
+            value: expr(r, &c.value)?,
+        }),
+        Item::Mod {
+            name,
+            items: Some(items),
+        } => Item::Mod {
+            name: name.clone(),
+            items: Some(each(items, |i| self::item(r, i))?),
+        },
+        Item::Struct(_) | Item::Enum(_) | Item::Mod { items: None, .. } | Item::Use(_) => {
+            return None
+        }
+    })
+}
+
+pub fn function<R: Rewrite + ?Sized>(r: &mut R, f: &Function) -> Option<Function> {
+    Some(Function {
+        sig: f.sig.clone(),
+        body: block(r, &f.body)?,
+    })
+}
+
+pub fn block<R: Rewrite + ?Sized>(r: &mut R, block: &Block) -> Option<Block> {
+    let stmts = each(&block.stmts, |s| stmt(r, s));
+    let tail = option(r, &block.tail);
+    unchanged!(stmts, tail);
+    Some(Block {
+        stmts: keep(stmts, &block.stmts),
+        tail: keep(tail, &block.tail),
+        spans: block.spans.clone(),
+    })
+}
+
+pub fn stmt<R: Rewrite + ?Sized>(r: &mut R, stmt: &Stmt) -> Option<Stmt> {
+    Some(match stmt {
+        Stmt::Expr(e) => Stmt::Expr(expr(r, e)?),
+        Stmt::Let { name, value } => Stmt::Let {
+            name: name.clone(),
+            value: expr(r, value)?,
+        },
+        Stmt::Assign { place, op, value } => {
+            let (new_place, new_value) = (expr(r, place), expr(r, value));
+            unchanged!(new_place, new_value);
+            Stmt::Assign {
+                place: keep(new_place, place),
+                op: *op,
+                value: keep(new_value, value),
+            }
+        }
+        Stmt::While { label, cond, body } => {
+            let (new_cond, new_body) = (expr(r, cond), self::block(r, body));
+            unchanged!(new_cond, new_body);
+            Stmt::While {
+                label: label.clone(),
+                cond: keep(new_cond, cond),
+                body: keep(new_body, body),
+            }
+        }
+        Stmt::For {
+            label,
+            binding,
+            iter,
+            body,
+        } => {
+            let (new_iter, new_body) = (expr(r, iter), self::block(r, body));
+            unchanged!(new_iter, new_body);
+            Stmt::For {
+                label: label.clone(),
+                binding: binding.clone(),
+                iter: keep(new_iter, iter),
+                body: keep(new_body, body),
+            }
+        }
+        Stmt::Return(Some(e)) => Stmt::Return(Some(expr(r, e)?)),
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return(None) => return None,
+    })
+}
+
+/// Rewrites `e`, sharing it if nothing in it changed.
+pub fn shared<R: Rewrite + ?Sized>(r: &mut R, e: &Arc<Expr>) -> Option<Arc<Expr>> {
+    expr(r, e).map(Arc::new)
+}
+
+fn option<R: Rewrite + ?Sized>(r: &mut R, e: &Option<Arc<Expr>>) -> Option<Option<Arc<Expr>>> {
+    shared(r, e.as_ref()?).map(Some)
+}
+
+/// Rewrites the children of `e`, and then `e`.
+pub fn expr<R: Rewrite + ?Sized>(r: &mut R, e: &Expr) -> Option<Expr> {
+    let children = walk_expr(r, e);
+    r.rewrite_expr(children.as_ref().unwrap_or(e)).or(children)
+}
+
+/// `e` with its children rewritten, or `None` if none of them changed.
+fn walk_expr<R: Rewrite + ?Sized>(r: &mut R, e: &Expr) -> Option<Expr> {
+    Some(match e {
+        Expr::Integer(_)
+        | Expr::Real(_)
+        | Expr::Imaginary(_)
+        | Expr::FReal(_)
+        | Expr::String(_)
+        | Expr::Symbol(_)
+        | Expr::Yield(None) => return None,
+        Expr::Interpolate { parts } => Expr::Interpolate {
+            parts: each(parts, |part| match part {
+                StrPart::Text(_) => None,
+                StrPart::Expr(e) => expr(r, e).map(StrPart::Expr),
+            })?,
+        },
+        Expr::Unary(op, a) => Expr::Unary(*op, shared(r, a)?),
+        Expr::Binary(a, op, b) => {
+            let (new_a, new_b) = (shared(r, a), shared(r, b));
+            unchanged!(new_a, new_b);
+            Expr::Binary(keep(new_a, a), *op, keep(new_b, b))
+        }
+        Expr::Logical(a, op, b) => {
+            let (new_a, new_b) = (shared(r, a), shared(r, b));
+            unchanged!(new_a, new_b);
+            Expr::Logical(keep(new_a, a), *op, keep(new_b, b))
+        }
+        Expr::Call(f, args) => {
+            let (new_f, new_args) = (shared(r, f), each(args, |a| expr(r, a)));
+            unchanged!(new_f, new_args);
+            Expr::Call(keep(new_f, f), keep(new_args, args))
+        }
+        Expr::Block(b) => Expr::Block(block(r, b)?),
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            let new_cond = shared(r, cond);
+            let new_then = block(r, then);
+            let new_otherwise = option(r, otherwise);
+            unchanged!(new_cond, new_then, new_otherwise);
+            Expr::If {
+                cond: keep(new_cond, cond),
+                then: keep(new_then, then),
+                otherwise: keep(new_otherwise, otherwise),
+            }
+        }
+        Expr::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let (new_start, new_end) = (option(r, start), option(r, end));
+            unchanged!(new_start, new_end);
+            Expr::Range {
+                start: keep(new_start, start),
+                end: keep(new_end, end),
+                inclusive: *inclusive,
+            }
+        }
+        Expr::Struct { name, fields } => Expr::Struct {
+            name: name.clone(),
+            fields: each(fields, |(name, e)| Some((name.clone(), expr(r, e)?)))?,
+        },
+        Expr::Variant { ty, name, args } => Expr::Variant {
+            ty: ty.clone(),
+            name: name.clone(),
+            args: self::fields(args, |e| expr(r, e))?,
+        },
+        Expr::Field(a, name) => Expr::Field(shared(r, a)?, name.clone()),
+        Expr::MethodCall {
+            receiver,
+            method,
+            args,
+        } => {
+            let (new_receiver, new_args) = (shared(r, receiver), each(args, |a| expr(r, a)));
+            unchanged!(new_receiver, new_args);
+            Expr::MethodCall {
+                receiver: keep(new_receiver, receiver),
+                method: method.clone(),
+                args: keep(new_args, args),
+            }
+        }
+        Expr::Tuple(items) => Expr::Tuple(each(items, |e| expr(r, e))?),
+        Expr::TupleIndex(a, n) => Expr::TupleIndex(shared(r, a)?, *n),
+        Expr::Array(items) => Expr::Array(each(items, |e| expr(r, e))?),
+        Expr::Repeat { value, count } => {
+            let (new_value, new_count) = (shared(r, value), shared(r, count));
+            unchanged!(new_value, new_count);
+            Expr::Repeat {
+                value: keep(new_value, value),
+                count: keep(new_count, count),
+            }
+        }
+        Expr::Index(a, b) => {
+            let (new_a, new_b) = (shared(r, a), shared(r, b));
+            unchanged!(new_a, new_b);
+            Expr::Index(keep(new_a, a), keep(new_b, b))
+        }
+        Expr::Ascribe(a, ty) => Expr::Ascribe(shared(r, a)?, ty.clone()),
+        Expr::Lambda {
+            params,
+            body,
+            captures,
+        } => Expr::Lambda {
+            params: params.clone(),
+            body: shared(r, body)?,
+            captures: captures.clone(),
+        },
+        Expr::Match { scrutinee, arms } => {
+            let new_scrutinee = shared(r, scrutinee);
+            let new_arms = each(arms, |arm| {
+                let (new_pattern, new_body) = (pattern(r, &arm.pattern), expr(r, &arm.body));
+                unchanged!(new_pattern, new_body);
+                Some(Arm {
+                    pattern: keep(new_pattern, &arm.pattern),
+                    body: keep(new_body, &arm.body),
+                })
+            });
+            unchanged!(new_scrutinee, new_arms);
+            Expr::Match {
+                scrutinee: keep(new_scrutinee, scrutinee),
+                arms: keep(new_arms, arms),
+            }
+        }
+        Expr::Yield(Some(a)) => Expr::Yield(Some(shared(r, a)?)),
+        Expr::Region { lifetime, body } => Expr::Region {
+            lifetime: lifetime.clone(),
+            body: block(r, body)?,
+        },
+        Expr::Task(b) => Expr::Task(block(r, b)?),
+    })
+}
+
+pub fn pattern<R: Rewrite + ?Sized>(r: &mut R, p: &Pattern) -> Option<Pattern> {
+    Some(match p {
+        Pattern::Wildcard | Pattern::Binding(_) => return None,
+        Pattern::Literal(e) => Pattern::Literal(expr(r, e)?),
+        Pattern::Tuple(items) => Pattern::Tuple(each(items, |p| pattern(r, p))?),
+        Pattern::Or(items) => Pattern::Or(each(items, |p| pattern(r, p))?),
+        Pattern::Variant { ty, name, args } => Pattern::Variant {
+            ty: ty.clone(),
+            name: name.clone(),
+            args: fields(args, |p| pattern(r, p))?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::rewrite::*;
+    use crate::grammar;
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    /// Folds additions of two numbers.
+    struct FoldAdd;
+
+    impl Rewrite for FoldAdd {
+        fn rewrite_expr(&mut self, e: &Expr) -> Option<Expr> {
+            match e {
+                Expr::Binary(a, BinaryOp::Add, b) => match (&**a, &**b) {
+                    (Expr::Integer(a), Expr::Integer(b)) => Some(Expr::Integer(a + b)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+    }
+
+    fn tail(program: &Program, n: usize) -> &Arc<Expr> {
+        let Item::Fn(f) = &program.items[n] else {
+            panic!("item {n} isn't a function")
+        };
+        f.body.tail.as_ref().unwrap()
+    }
+
+    #[test]
+    fn rewrite() -> Result<()> {
+        let src = "fn f() { g(x * (1 + 2)) } fn g() { [h(x), 3 * (4 + y)] }";
+        let (before, _) = grammar::Program.parse(src)?;
+        let after = program(&mut FoldAdd, &before);
+        let (expected, _) =
+            grammar::Program.parse("fn f() { g(x * 3) } fn g() { [h(x), 3 * (4 + y)] }")?;
+        assert_eq!(after, expected);
+        // `g` has nothing to fold, so it's shared rather than copied.
+        assert!(Arc::ptr_eq(tail(&before, 1), tail(&after, 1)));
+        assert!(!Arc::ptr_eq(tail(&before, 0), tail(&after, 0)));
+        // The tree it was given is unchanged.
+        assert_eq!(before, grammar::Program.parse(src)?.0);
+        Ok(())
+    }
+
+    #[test]
+    fn shares_unchanged_children() -> Result<()> {
+        let (e, _) = grammar::Expression.parse("(1 + 2) * (a + b)")?;
+        let Some(Expr::Binary(_, _, b)) = expr(&mut FoldAdd, e.as_ref().unwrap()) else {
+            panic!("the product wasn't rewritten")
+        };
+        let Some(Expr::Binary(_, _, original)) = e else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(&b, &original));
+        Ok(())
+    }
+}
@@ -0,0 +1,596 @@
+//! Traversal of the AST, for passes that only care about some kinds of nodes.
+//!
+//! Each `visit_*` method walks the children of the node by default, using the `walk_*` function
+//! of the same name. A pass overrides the methods for the nodes it cares about, and calls the
+//! `walk_*` function itself if it still wants to visit their children. [`VisitMut`] unshares the
+//! subexpressions it visits, if they're shared with other trees.
+
+use super::*;
+
+/// Visits the AST by reference.
+pub trait Visit {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        walk_function(self, f)
+    }
+
+    fn visit_signature(&mut self, sig: &Signature) {
+        walk_signature(self, sig)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        walk_expr(self, e)
+    }
+
+    fn visit_pattern(&mut self, p: &Pattern) {
+        walk_pattern(self, p)
+    }
+
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        walk_type(self, ty)
+    }
+}
+
+pub fn walk_program<V: Visit + ?Sized>(v: &mut V, program: &Program) {
+    for item in &program.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: Visit + ?Sized>(v: &mut V, item: &Item) {
+    match item {
+        Item::Fn(f) => v.visit_function(f),
+        Item::Struct(s) => {
+            walk_generics(v, &s.generics);
+            for field in &s.fields {
+                v.visit_type(&field.ty);
+            }
+        }
+        Item::Enum(e) => {
+            walk_generics(v, &e.generics);
+            for variant in &e.variants {
+                walk_fields(&variant.fields, |ty| v.visit_type(ty));
+            }
+        }
+        Item::Trait(t) => {
+            walk_generics(v, &t.generics);
+            for f in &t.fns {
+                v.visit_signature(&f.sig);
+                if let Some(body) = &f.default {
+                    v.visit_block(body);
+                }
+            }
+        }
+        Item::Impl(imp) => {
+            walk_generics(v, &imp.generics);
+            if let Some(t) = &imp.trait_ {
+                v.visit_type(t);
+            }
+            v.visit_type(&imp.ty);
+            for f in &imp.fns {
+                v.visit_function(f);
+            }
+        }
+        Item::Const(c) => {
+            v.visit_type(&c.ty);
+            v.visit_expr(&c.value);
+        }
+        Item::Mod { items, .. } => {
+            for item in items.iter().flatten() {
+                v.visit_item(item);
+            }
+        }
+        Item::Use(_) => {}
+    }
+}
+
+pub fn walk_function<V: Visit + ?Sized>(v: &mut V, f: &Function) {
+    v.visit_signature(&f.sig);
+    v.visit_block(&f.body);
+}
+
+pub fn walk_signature<V: Visit + ?Sized>(v: &mut V, sig: &Signature) {
+    walk_generics(v, &sig.generics);
+    for p in &sig.params {
+        v.visit_type(&p.ty);
+    }
+    if let Some(ty) = &sig.ret {
+        v.visit_type(ty);
+    }
+}
+
+fn walk_generics<V: Visit + ?Sized>(v: &mut V, generics: &[GenericParam]) {
+    for g in generics {
+        for bound in &g.bounds {
+            v.visit_type(bound);
+        }
+    }
+}
+
+fn walk_fields<T>(fields: &Fields<T>, mut f: impl FnMut(&T)) {
+    match fields {
+        Fields::Unit => {}
+        Fields::Tuple(items) => items.iter().for_each(f),
+        Fields::Named(items) => items.iter().for_each(|(_, t)| f(t)),
+    }
+}
+
+pub fn walk_block<V: Visit + ?Sized>(v: &mut V, block: &Block) {
+    for stmt in &block.stmts {
+        v.visit_stmt(stmt);
+    }
+    if let Some(e) = &block.tail {
+        v.visit_expr(e);
+    }
+}
+
+pub fn walk_stmt<V: Visit + ?Sized>(v: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expr(e) | Stmt::Let { value: e, .. } | Stmt::Return(Some(e)) => v.visit_expr(e),
+        Stmt::Assign { place, value, .. } => {
+            v.visit_expr(place);
+            v.visit_expr(value);
+        }
+        Stmt::While { cond, body, .. } => {
+            v.visit_expr(cond);
+            v.visit_block(body);
+        }
+        Stmt::For { iter, body, .. } => {
+            v.visit_expr(iter);
+            v.visit_block(body);
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return(None) => {}
+    }
+}
+
+pub fn walk_expr<V: Visit + ?Sized>(v: &mut V, e: &Expr) {
+    match e {
+        Expr::Integer(_)
+        | Expr::Real(_)
+        | Expr::Imaginary(_)
+        | Expr::FReal(_)
+        | Expr::String(_)
+        | Expr::Symbol(_)
+        | Expr::Yield(None) => {}
+        Expr::Interpolate { parts } => {
+            for part in parts {
+                if let StrPart::Expr(e) = part {
+                    v.visit_expr(e);
+                }
+            }
+        }
+        Expr::Unary(_, e)
+        | Expr::Field(e, _)
+        | Expr::TupleIndex(e, _)
+        | Expr::Lambda { body: e, .. }
+        | Expr::Yield(Some(e)) => v.visit_expr(e),
+        Expr::Binary(a, _, b)
+        | Expr::Logical(a, _, b)
+        | Expr::Index(a, b)
+        | Expr::Repeat { value: a, count: b } => {
+            v.visit_expr(a);
+            v.visit_expr(b);
+        }
+        Expr::Call(f, args) => {
+            v.visit_expr(f);
+            for a in args {
+                v.visit_expr(a);
+            }
+        }
+        Expr::MethodCall { receiver, args, .. } => {
+            v.visit_expr(receiver);
+            for a in args {
+                v.visit_expr(a);
+            }
+        }
+        Expr::Block(b) | Expr::Region { body: b, .. } | Expr::Task(b) => v.visit_block(b),
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            v.visit_expr(cond);
+            v.visit_block(then);
+            if let Some(e) = otherwise {
+                v.visit_expr(e);
+            }
+        }
+        Expr::Range { start, end, .. } => {
+            for e in start.iter().chain(end) {
+                v.visit_expr(e);
+            }
+        }
+        Expr::Struct { fields, .. } => {
+            for (_, e) in fields {
+                v.visit_expr(e);
+            }
+        }
+        Expr::Variant { args, .. } => walk_fields(args, |e| v.visit_expr(e)),
+        Expr::Tuple(items) | Expr::Array(items) => {
+            for e in items {
+                v.visit_expr(e);
+            }
+        }
+        Expr::Ascribe(e, ty) => {
+            v.visit_expr(e);
+            v.visit_type(ty);
+        }
+        Expr::Match { scrutinee, arms } => {
+            v.visit_expr(scrutinee);
+            for arm in arms {
+                v.visit_pattern(&arm.pattern);
+                v.visit_expr(&arm.body);
+            }
+        }
+    }
+}
+
+pub fn walk_pattern<V: Visit + ?Sized>(v: &mut V, p: &Pattern) {
+    match p {
+        Pattern::Wildcard | Pattern::Binding(_) => {}
+        Pattern::Literal(e) => v.visit_expr(e),
+        Pattern::Tuple(items) | Pattern::Or(items) => {
+            for p in items {
+                v.visit_pattern(p);
+            }
+        }
+        Pattern::Variant { args, .. } => walk_fields(args, |p| v.visit_pattern(p)),
+    }
+}
+
+pub fn walk_type<V: Visit + ?Sized>(v: &mut V, ty: &TypeExpr) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Tuple(items) | TypeExpr::Generic { args: items, .. } => {
+            for ty in items {
+                v.visit_type(ty);
+            }
+        }
+        TypeExpr::Fn { params, ret } => {
+            for ty in params {
+                v.visit_type(ty);
+            }
+            if let Some(ty) = ret {
+                v.visit_type(ty);
+            }
+        }
+        TypeExpr::Ref { inner, .. } => v.visit_type(inner),
+    }
+}
+
+/// Visits the AST by mutable reference, for passes that rewrite it in place.
+pub trait VisitMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item)
+    }
+
+    fn visit_function_mut(&mut self, f: &mut Function) {
+        walk_function_mut(self, f)
+    }
+
+    fn visit_signature_mut(&mut self, sig: &mut Signature) {
+        walk_signature_mut(self, sig)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expr_mut(&mut self, e: &mut Expr) {
+        walk_expr_mut(self, e)
+    }
+
+    fn visit_pattern_mut(&mut self, p: &mut Pattern) {
+        walk_pattern_mut(self, p)
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut TypeExpr) {
+        walk_type_mut(self, ty)
+    }
+}
+
+pub fn walk_program_mut<V: VisitMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        v.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitMut + ?Sized>(v: &mut V, item: &mut Item) {
+    match item {
+        Item::Fn(f) => v.visit_function_mut(f),
+        Item::Struct(s) => {
+            walk_generics_mut(v, &mut s.generics);
+            for field in &mut s.fields {
+                v.visit_type_mut(&mut field.ty);
+            }
+        }
+        Item::Enum(e) => {
+            walk_generics_mut(v, &mut e.generics);
+            for variant in &mut e.variants {
+                walk_fields_mut(&mut variant.fields, |ty| v.visit_type_mut(ty));
+            }
+        }
+        Item::Trait(t) => {
+            walk_generics_mut(v, &mut t.generics);
+            for f in &mut t.fns {
+                v.visit_signature_mut(&mut f.sig);
+                if let Some(body) = &mut f.default {
+                    v.visit_block_mut(body);
+                }
+            }
+        }
+        Item::Impl(imp) => {
+            walk_generics_mut(v, &mut imp.generics);
+            if let Some(t) = &mut imp.trait_ {
+                v.visit_type_mut(t);
+            }
+            v.visit_type_mut(&mut imp.ty);
+            for f in &mut imp.fns {
+                v.visit_function_mut(f);
+            }
+        }
+        Item::Const(c) => {
+            v.visit_type_mut(&mut c.ty);
+            v.visit_expr_mut(&mut c.value);
+        }
+        Item::Mod { items, .. } => {
+            for item in items.iter_mut().flatten() {
+                v.visit_item_mut(item);
+            }
+        }
+        Item::Use(_) => {}
+    }
+}
+
+pub fn walk_function_mut<V: VisitMut + ?Sized>(v: &mut V, f: &mut Function) {
+    v.visit_signature_mut(&mut f.sig);
+    v.visit_block_mut(&mut f.body);
+}
+
+pub fn walk_signature_mut<V: VisitMut + ?Sized>(v: &mut V, sig: &mut Signature) {
+    walk_generics_mut(v, &mut sig.generics);
+    for p in &mut sig.params {
+        v.visit_type_mut(&mut p.ty);
+    }
+    if let Some(ty) = &mut sig.ret {
+        v.visit_type_mut(ty);
+    }
+}
+
+fn walk_generics_mut<V: VisitMut + ?Sized>(v: &mut V, generics: &mut [GenericParam]) {
+    for g in generics {
+        for bound in &mut g.bounds {
+            v.visit_type_mut(bound);
+        }
+    }
+}
+
+fn walk_fields_mut<T>(fields: &mut Fields<T>, mut f: impl FnMut(&mut T)) {
+    match fields {
+        Fields::Unit => {}
+        Fields::Tuple(items) => items.iter_mut().for_each(f),
+        Fields::Named(items) => items.iter_mut().for_each(|(_, t)| f(t)),
+    }
+}
+
+pub fn walk_block_mut<V: VisitMut + ?Sized>(v: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        v.visit_stmt_mut(stmt);
+    }
+    if let Some(e) = &mut block.tail {
+        v.visit_expr_mut(Arc::make_mut(e));
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expr(e) | Stmt::Let { value: e, .. } | Stmt::Return(Some(e)) => v.visit_expr_mut(e),
+        Stmt::Assign { place, value, .. } => {
+            v.visit_expr_mut(place);
+            v.visit_expr_mut(value);
+        }
+        Stmt::While { cond, body, .. } => {
+            v.visit_expr_mut(cond);
+            v.visit_block_mut(body);
+        }
+        Stmt::For { iter, body, .. } => {
+            v.visit_expr_mut(iter);
+            v.visit_block_mut(body);
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return(None) => {}
+    }
+}
+
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(v: &mut V, e: &mut Expr) {
+    match e {
+        Expr::Integer(_)
+        | Expr::Real(_)
+        | Expr::Imaginary(_)
+        | Expr::FReal(_)
+        | Expr::String(_)
+        | Expr::Symbol(_)
+        | Expr::Yield(None) => {}
+        Expr::Interpolate { parts } => {
+            for part in parts {
+                if let StrPart::Expr(e) = part {
+                    v.visit_expr_mut(e);
+                }
+            }
+        }
+        Expr::Unary(_, e)
+        | Expr::Field(e, _)
+        | Expr::TupleIndex(e, _)
+        | Expr::Lambda { body: e, .. }
+        | Expr::Yield(Some(e)) => v.visit_expr_mut(Arc::make_mut(e)),
+        Expr::Binary(a, _, b)
+        | Expr::Logical(a, _, b)
+        | Expr::Index(a, b)
+        | Expr::Repeat { value: a, count: b } => {
+            v.visit_expr_mut(Arc::make_mut(a));
+            v.visit_expr_mut(Arc::make_mut(b));
+        }
+        Expr::Call(f, args) => {
+            v.visit_expr_mut(Arc::make_mut(f));
+            for a in args {
+                v.visit_expr_mut(a);
+            }
+        }
+        Expr::MethodCall { receiver, args, .. } => {
+            v.visit_expr_mut(Arc::make_mut(receiver));
+            for a in args {
+                v.visit_expr_mut(a);
+            }
+        }
+        Expr::Block(b) | Expr::Region { body: b, .. } | Expr::Task(b) => v.visit_block_mut(b),
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            v.visit_expr_mut(Arc::make_mut(cond));
+            v.visit_block_mut(then);
+            if let Some(e) = otherwise {
+                v.visit_expr_mut(Arc::make_mut(e));
+            }
+        }
+        Expr::Range { start, end, .. } => {
+            for e in start.iter_mut().chain(end) {
+                v.visit_expr_mut(Arc::make_mut(e));
+            }
+        }
+        Expr::Struct { fields, .. } => {
+            for (_, e) in fields {
+                v.visit_expr_mut(e);
+            }
+        }
+        Expr::Variant { args, .. } => walk_fields_mut(args, |e| v.visit_expr_mut(e)),
+        Expr::Tuple(items) | Expr::Array(items) => {
+            for e in items {
+                v.visit_expr_mut(e);
+            }
+        }
+        Expr::Ascribe(e, ty) => {
+            v.visit_expr_mut(Arc::make_mut(e));
+            v.visit_type_mut(ty);
+        }
+        Expr::Match { scrutinee, arms } => {
+            v.visit_expr_mut(Arc::make_mut(scrutinee));
+            for arm in arms {
+                v.visit_pattern_mut(&mut arm.pattern);
+                v.visit_expr_mut(&mut arm.body);
+            }
+        }
+    }
+}
+
+pub fn walk_pattern_mut<V: VisitMut + ?Sized>(v: &mut V, p: &mut Pattern) {
+    match p {
+        Pattern::Wildcard | Pattern::Binding(_) => {}
+        Pattern::Literal(e) => v.visit_expr_mut(e),
+        Pattern::Tuple(items) | Pattern::Or(items) => {
+            for p in items {
+                v.visit_pattern_mut(p);
+            }
+        }
+        Pattern::Variant { args, .. } => walk_fields_mut(args, |p| v.visit_pattern_mut(p)),
+    }
+}
+
+pub fn walk_type_mut<V: VisitMut + ?Sized>(v: &mut V, ty: &mut TypeExpr) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Tuple(items) | TypeExpr::Generic { args: items, .. } => {
+            for ty in items {
+                v.visit_type_mut(ty);
+            }
+        }
+        TypeExpr::Fn { params, ret } => {
+            for ty in params {
+                v.visit_type_mut(ty);
+            }
+            if let Some(ty) = ret {
+                v.visit_type_mut(ty);
+            }
+        }
+        TypeExpr::Ref { inner, .. } => v.visit_type_mut(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::visit::*;
+    use crate::grammar;
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    /// Collects every symbol used.
+    struct Symbols(Vec<String>);
+
+    impl Visit for Symbols {
+        fn visit_expr(&mut self, e: &Expr) {
+            if let Expr::Symbol(s) = e {
+                self.0.push(s.clone());
+            }
+            walk_expr(self, e)
+        }
+    }
+
+    /// Folds additions of two numbers.
+    struct FoldAdd;
+
+    impl VisitMut for FoldAdd {
+        fn visit_expr_mut(&mut self, e: &mut Expr) {
+            walk_expr_mut(self, e);
+            if let Expr::Binary(a, BinaryOp::Add, b) = e {
+                if let (Expr::Integer(a), Expr::Integer(b)) = (&**a, &**b) {
+                    *e = Expr::Integer(a + b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn visit() -> Result<()> {
+        let src = "fn f(a: T) { x := a.b + g(c); match d { Some::X(e) => [f; n], _ => \"{h}\" } }";
+        let (program, _) = grammar::Program.parse(src)?;
+        let mut symbols = Symbols(vec![]);
+        symbols.visit_program(&program);
+        assert_eq!(symbols.0, ["a", "g", "c", "d", "f", "n", "h"]);
+        Ok(())
+    }
+
+    #[test]
+    fn visit_mut() -> Result<()> {
+        let (original, _) = grammar::Program.parse("const N: Real = 1 + 2 + x + (4 + 5);")?;
+        let mut program = original.clone();
+        FoldAdd.visit_program_mut(&mut program);
+        let (expected, _) = grammar::Program.parse("const N: Real = 3 + x + 9;")?;
+        assert_eq!(program, expected);
+        // The clone shared its subexpressions with the original, which are copied on write.
+        assert_ne!(original, expected);
+        Ok(())
+    }
+}
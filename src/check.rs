@@ -0,0 +1,371 @@
+//! Checks on the AST that don't need to know the types of expressions.
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::ast::visit::{self, Visit};
+use crate::ast::*;
+use anyhow::*;
+use core::ops::Range;
+
+/// Checks that every lifetime is declared, by a generic parameter or an enclosing `region`, and
+/// that references into a region don't escape it.
+///
+/// A reference escapes a region when the value of the region block, or a `return` from inside
+/// the region, has its type `&'r T`, or is assigned to a variable declared outside of the
+/// region. The type of a value is what it's ascribed, and the types of the variables, the
+/// tuples, arrays, structs and variants, and the blocks it's made of, but not the types calls
+/// return, which aren't known until types are inferred.
+///
+/// This check is all there is to regions. The runtime doesn't allocate the values of a region
+/// together, or free them all at once: the region only clears its variables at its end, so
+/// what only they refer to is freed there.
+///
+/// The errors are [`Located`] at the statement they're in, or the item, when it has a span.
+pub fn regions(program: &Program) -> Result<()> {
+    check_regions(&program.items, &program.spans)
+}
+
+/// Checks the regions of `items`, like [`regions`], for a part of a program.
+pub fn regions_in(items: &[Item]) -> Result<()> {
+    check_regions(items, &[])
+}
+
+fn check_regions(items: &[Item], spans: &[Range<usize>]) -> Result<()> {
+    let mut check = Regions::default();
+    for (n, item) in items.iter().enumerate() {
+        check.span = spans.get(n).cloned();
+        check.visit_item(item);
+    }
+    match check.error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// A variable, with the regions the references in its value point into.
+struct Local {
+    name: String,
+    /// How many regions were around its declaration.
+    depth: usize,
+    lifetimes: Vec<String>,
+}
+
+/// The lifetimes of the references in a type.
+#[derive(Default)]
+struct Lifetimes(Vec<String>);
+
+impl Visit for Lifetimes {
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        if let TypeExpr::Ref {
+            lifetime: Some(lifetime),
+            ..
+        } = ty
+        {
+            self.0.push(lifetime.clone());
+        }
+        visit::walk_type(self, ty)
+    }
+}
+
+#[derive(Default)]
+struct Regions {
+    /// Lifetimes declared by generic parameters.
+    generics: Vec<String>,
+    /// Lifetimes of the enclosing regions, innermost last.
+    regions: Vec<String>,
+    /// Lifetimes of the regions that the expression being visited is leaving.
+    escaping: Vec<String>,
+    /// Variables in scope, innermost last.
+    locals: Vec<Local>,
+    /// Where the innermost statement being visited is, if it's known.
+    span: Option<Range<usize>>,
+    error: Option<Error>,
+}
+
+impl Regions {
+    fn fail(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(Located::wrap(error, self.span.clone()));
+        }
+    }
+
+    /// Visits `f` with the lifetimes of `generics` in scope.
+    fn with_generics(&mut self, generics: &[GenericParam], f: impl FnOnce(&mut Self)) {
+        let n = self.generics.len();
+        let lifetimes = generics.iter().filter(|g| g.lifetime);
+        self.generics.extend(lifetimes.map(|g| g.name.clone()));
+        f(self);
+        self.generics.truncate(n);
+    }
+
+    /// Visits `e` as a value leaving the regions `escaping`.
+    fn leaving(&mut self, escaping: Vec<String>, e: &Expr) {
+        if let Some(lifetime) = self.lifetimes(e).iter().find(|l| escaping.contains(l)) {
+            self.fail(anyhow!("a reference into region '{lifetime} escapes it"));
+        }
+        let outer = core::mem::replace(&mut self.escaping, escaping);
+        self.visit_expr(e);
+        self.escaping = outer;
+    }
+
+    /// The lifetimes of the references in the type of `e`, as far as it's known.
+    fn lifetimes(&mut self, e: &Expr) -> Vec<String> {
+        let mut lifetimes = Vec::new();
+        match e {
+            Expr::Ascribe(e, ty) => {
+                let mut of_type = Lifetimes::default();
+                of_type.visit_type(ty);
+                lifetimes = of_type.0;
+                lifetimes.extend(self.lifetimes(e));
+            }
+            Expr::Symbol(name) => {
+                if let Some(local) = self.locals.iter().rev().find(|l| l.name == *name) {
+                    lifetimes = local.lifetimes.clone();
+                }
+            }
+            Expr::Tuple(items) | Expr::Array(items) => {
+                for item in items {
+                    lifetimes.extend(self.lifetimes(item));
+                }
+            }
+            Expr::Repeat { value, .. } => lifetimes = self.lifetimes(value),
+            Expr::Struct { fields, .. } => {
+                for (_, value) in fields {
+                    lifetimes.extend(self.lifetimes(value));
+                }
+            }
+            Expr::Variant { args, .. } => match args {
+                Fields::Unit => {}
+                Fields::Tuple(args) => {
+                    for arg in args {
+                        lifetimes.extend(self.lifetimes(arg));
+                    }
+                }
+                Fields::Named(args) => {
+                    for (_, arg) in args {
+                        lifetimes.extend(self.lifetimes(arg));
+                    }
+                }
+            },
+            Expr::Block(b) | Expr::Region { body: b, .. } => lifetimes = self.block_lifetimes(b),
+            Expr::If {
+                then, otherwise, ..
+            } => {
+                lifetimes = self.block_lifetimes(then);
+                if let Some(otherwise) = otherwise {
+                    lifetimes.extend(self.lifetimes(otherwise));
+                }
+            }
+            Expr::Match { arms, .. } => {
+                for arm in arms {
+                    lifetimes.extend(self.lifetimes(&arm.body));
+                }
+            }
+            _ => {}
+        }
+        lifetimes
+    }
+
+    /// Like [`Regions::lifetimes`], for the value of `block`, with its variables in scope.
+    fn block_lifetimes(&mut self, block: &Block) -> Vec<String> {
+        let n = self.locals.len();
+        for stmt in &block.stmts {
+            if let Stmt::Let { name, value } = stmt {
+                self.declare(name, value);
+            }
+        }
+        let lifetimes = match &block.tail {
+            Some(tail) => self.lifetimes(tail),
+            None => vec![],
+        };
+        self.locals.truncate(n);
+        lifetimes
+    }
+
+    /// Brings the variable `name` into scope, with the lifetimes of `value`.
+    fn declare(&mut self, name: &str, value: &Expr) {
+        let lifetimes = self.lifetimes(value);
+        self.locals.push(Local {
+            name: name.to_string(),
+            depth: self.regions.len(),
+            lifetimes,
+        });
+    }
+
+    /// Fails if `value` refers into a region that the variable of `place` was declared outside
+    /// of, and adds the lifetimes of `value` to the variable otherwise.
+    fn assign(&mut self, place: &Expr, value: &Expr) {
+        let mut root = place;
+        while let Expr::Field(e, _) | Expr::TupleIndex(e, _) | Expr::Index(e, _) = root {
+            root = e;
+        }
+        let Expr::Symbol(name) = root else {
+            return;
+        };
+        let lifetimes = self.lifetimes(value);
+        let Some(local) = self.locals.iter_mut().rev().find(|l| l.name == *name) else {
+            return;
+        };
+        let inner = &self.regions[local.depth..];
+        match lifetimes.iter().find(|l| inner.contains(l)) {
+            Some(lifetime) => {
+                let error = anyhow!("a reference into region '{lifetime} escapes it");
+                self.fail(error)
+            }
+            None => local.lifetimes.extend(lifetimes),
+        }
+    }
+}
+
+impl Visit for Regions {
+    fn visit_item(&mut self, item: &Item) {
+        let generics = match item {
+            Item::Struct(s) => &s.generics,
+            Item::Enum(e) => &e.generics,
+            Item::Trait(t) => &t.generics,
+            Item::Impl(imp) => &imp.generics,
+            _ => return visit::walk_item(self, item),
+        };
+        // The default body of a trait fn is visited apart from its signature.
+        let mut generics = generics.clone();
+        if let Item::Trait(t) = item {
+            generics.extend(t.fns.iter().flat_map(|f| f.sig.generics.clone()));
+        }
+        self.with_generics(&generics, |v| visit::walk_item(v, item))
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        // Regions don't extend into nested functions, which can't refer to their values.
+        let regions = core::mem::take(&mut self.regions);
+        let locals = core::mem::take(&mut self.locals);
+        self.with_generics(&f.sig.generics, |v| visit::walk_function(v, f));
+        self.regions = regions;
+        self.locals = locals;
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return(Some(e)) => self.leaving(self.regions.clone(), e),
+            Stmt::Let { name, value } => {
+                self.visit_expr(value);
+                self.declare(name, value);
+            }
+            Stmt::Assign { place, value, .. } => {
+                visit::walk_stmt(self, stmt);
+                self.assign(place, value);
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        let Expr::Region {
+            lifetime: Some(lifetime),
+            body,
+        } = e
+        else {
+            return visit::walk_expr(self, e);
+        };
+        if self.generics.contains(lifetime) || self.regions.contains(lifetime) {
+            self.fail(anyhow!("region '{lifetime} shadows another lifetime"));
+        }
+        // The value of the block leaves this region, along with any the region is leaving.
+        let mut leaving = self.escaping.clone();
+        leaving.push(lifetime.clone());
+        let outer = core::mem::replace(&mut self.escaping, leaving);
+        self.regions.push(lifetime.clone());
+        self.visit_block(body);
+        self.regions.pop();
+        self.escaping = outer;
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        // Only the value of a block leaves it.
+        let escaping = core::mem::take(&mut self.escaping);
+        let (n, span) = (self.locals.len(), self.span.clone());
+        for (m, stmt) in block.stmts.iter().enumerate() {
+            self.span = block.span(m).or_else(|| span.clone());
+            self.visit_stmt(stmt);
+        }
+        self.escaping = escaping;
+        if let Some(tail) = &block.tail {
+            self.span = block.span(block.stmts.len()).or_else(|| span.clone());
+            self.leaving(self.escaping.clone(), tail);
+        }
+        self.locals.truncate(n);
+        self.span = span;
+    }
+
+    fn visit_type(&mut self, ty: &TypeExpr) {
+        if let TypeExpr::Ref {
+            lifetime: Some(lifetime),
+            ..
+        } = ty
+        {
+            if self.escaping.contains(lifetime) {
+                self.fail(anyhow!("a reference into region '{lifetime} escapes it"));
+            } else if !self.generics.contains(lifetime)
+                && !self.regions.contains(lifetime)
+                && lifetime != "static"
+            {
+                self.fail(anyhow!("undeclared lifetime '{lifetime}"));
+            }
+        }
+        visit::walk_type(self, ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check::*;
+    use crate::grammar;
+    use crate::parser::Parser;
+
+    fn check(src: &str) -> Result<()> {
+        let (program, _) = grammar::Program.parse(src)?;
+        regions(&program)
+    }
+
+    #[test]
+    fn lifetimes() -> Result<()> {
+        check("fn f<'a>(x: &'a Int) -> &'static Int { region 'r { y := x: &'r Int; 1 } }")?;
+        check("struct S<'a> { x: &'a Int } trait T { fn f<'a>(x: &'a Int) { x: &'a Int } }")?;
+        check("fn f() { region 'r { region 's { x: &'r Int }; 1 } }")?;
+        check("fn f() { region 'r { region { y := x: &'r Int; 1 } } }")?;
+        let err = check("fn f(x: &'a Int) {}").unwrap_err();
+        assert_eq!(err.to_string(), "undeclared lifetime 'a");
+        let err = check("fn f() { region 'r {} x: &'r Int }").unwrap_err();
+        assert_eq!(err.to_string(), "undeclared lifetime 'r");
+        let err = check("fn f<'r>() { region 'r {} }").unwrap_err();
+        assert_eq!(err.to_string(), "region 'r shadows another lifetime");
+        Ok(())
+    }
+
+    #[test]
+    fn escaping() -> Result<()> {
+        let escapes = "a reference into region 'r escapes it";
+        let err = check("fn f() { region 'r { x := 1; x: &'r Int } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        let err = check("fn f() { region 'r { return x: &'r Int; } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        let err = check("fn f() { region 'r { region 's { return x: &'r Int; } } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        let err = check("fn f() { region 'r { { region 's { x: &'r Int } } } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        let err = check("fn main() { region 'r { y := 2: &'r Int; y } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        let err = check("fn main() { z := 0; region 'r { y := 2: &'r Int; z = y; }; z }");
+        assert_eq!(err.unwrap_err().to_string(), escapes);
+        let err = check("fn f() { region 'r { y := (1: &'r Int, 2); z := [y]; { w := z; w } } }");
+        assert_eq!(err.unwrap_err().to_string(), escapes);
+        let err =
+            check("fn f() { region 'r { y := 1: &'r Int; return if true { y } else { 1 }; } }");
+        assert_eq!(err.unwrap_err().to_string(), escapes);
+        let err = check("fn f() { z := [0]; region 'r { z[0] = 1: &'r Int; } }").unwrap_err();
+        assert_eq!(err.to_string(), escapes);
+        // Variables of the region can refer into it, and hold on to what's outside of it.
+        check("fn f() { z := 0; region 'r { y := 2: &'r Int; w := 0; w = y; z = 1; w; 1 } }")?;
+        check("fn f() { z := 1; region 'r { y := z; y } }")?;
+        Ok(())
+    }
+}
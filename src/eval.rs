@@ -0,0 +1,1567 @@
+//! Evaluation of chant programs.
+//!
+//! Programs are compiled to the stack machine of [`compile`], and run by a [`Machine`]. Calls
+//! push a frame on a stack of frames in the heap, so deeply recursive programs don't overflow
+//! the native stack, and instead fail with [`RuntimeError::StackOverflow`] once they're deeper
+//! than [`Limits::max_depth`]. A machine can also be stopped from outside, between ops, with
+//! the flags of [`interrupt`], and fails with [`RuntimeError::OutOfMemory`] once it's using more
+//! of the heap than [`Limits::max_heap`], as counted by [`heap`].
+//!
+//! # Memory
+//! Values are reference counted. Tuples, arrays, structs and variants are copied on write, and
+//! lambdas capture the values of variables rather than the variables themselves, so nothing
+//! those values refer to can refer back to them, and they're freed once their last reference is
+//! dropped. Channels and coroutines are shared instead, so they can be part of cycles, like a
+//! channel with its own sender in its queue, which the collector of [`gc`] frees at
+//! safepoints. `gc_stats()` is a `GcStats` struct of what it has done so far, with the bytes
+//! of the heap in use.
+//!
+//! Variables hold on to their values until the function they're in returns, except for the
+//! variables of a `region` block, which are dropped at the end of the block, so what only they
+//! refer to is freed there.
+//!
+//! `weak(v)` makes a [`Value::Weak`], which refers to `v` without keeping it alive, so that
+//! caches and observers can hold on to values without deciding when they're freed. Its
+//! `upgrade()` is `Option::Some(v)` until then, and `Option::None` after.
+//!
+//! # Coroutines
+//! Calling a `co fn` makes a [`Value::Coroutine`], which runs each time `c.resume()` is called,
+//! until it yields a value or returns. While it's suspended, its frames are kept in the
+//! coroutine, off the stack of the machine. `spawn(c)` runs a coroutine alongside the rest of
+//! the program instead, on a [`Scheduler`], which [`Machine::call`] runs until every coroutine
+//! is done. A `yield` then gives the others a turn, and `sleep(ms)` waits.
+//!
+//! Coroutines talk to each other over channels. `channel(n)` makes a channel holding at most `n`
+//! values, or any number with `channel()`, as a tuple of a [`Value::Sender`] and a
+//! [`Value::Receiver`]. `send(tx, v)` waits while the channel is full, and is `false` if the
+//! receiver is gone. `recv(rx)` waits while it's empty, and is `Option::Some` of the next value,
+//! or `Option::None` once every sender is gone. If every coroutine ends up waiting, the call fails
+//! with [`RuntimeError::Deadlock`].
+//!
+//! The coroutines spawned in a `task { ... }` block are its children, on a [`Group`]. The block
+//! waits for all of them, and evaluates to an array of what they returned, in the order they
+//! were spawned. If one of them fails, the others are cancelled, and the block fails too.
+
+pub mod compile;
+pub mod convert;
+pub mod gc;
+pub mod heap;
+pub mod host;
+pub mod interrupt;
+pub mod value;
+
+use crate::ast::{Fields, Program};
+use crate::num::natural::Natural;
+use crate::runtime::channel::{self, RecvError, SendError};
+use crate::runtime::task::{Child, Group, Poll};
+use crate::runtime::{self, Context, Scheduler, Suspend};
+use anyhow::*;
+use compile::{Capture, Code, Global, Module, Op, Pat, Shape, Step};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use value::{Closure, Coroutine, Struct, Value, Variant};
+
+/// Errors from running a program that embedders may want to handle apart from other errors, by
+/// downcasting them from the [`anyhow::Error`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub enum RuntimeError {
+    /// More calls were in progress than [`Limits::max_depth`].
+    StackOverflow { depth: usize },
+    /// The flag the machine was watching was set, by Ctrl-C or otherwise.
+    Interrupted,
+    /// The machine would be using more bytes of the heap than [`Limits::max_heap`].
+    OutOfMemory { limit: usize },
+    /// Every coroutine that isn't done is waiting on a channel or a `task` block, so none of them
+    /// ever can go on.
+    Deadlock,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::StackOverflow { depth } => {
+                write!(f, "stack overflow, from more than {depth} nested calls")
+            }
+            RuntimeError::Interrupted => write!(f, "interrupted"),
+            RuntimeError::OutOfMemory { limit } => {
+                write!(f, "out of memory, from using more than {limit} bytes")
+            }
+            RuntimeError::Deadlock => write!(f, "deadlock, with every coroutine waiting"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Limits on the resources a [`Machine`] can use, and on what it can observe. Limits are built
+/// from the default ones with the setters, like `Limits::default().max_depth(100)`, since more
+/// can be added.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[non_exhaustive]
+pub struct Limits {
+    /// The most calls that can be in progress at once.
+    pub max_depth: usize,
+    /// The most bytes of the heap the machine can use, if there's a limit. Only enforced when
+    /// [`heap::Counting`] is the global allocator.
+    pub max_heap: Option<usize>,
+    /// Whether runs are reproducible. Coroutines are scheduled by [`Scheduler::deterministic`],
+    /// so sleeping takes no real time, and reading the clock with `now()` fails.
+    pub deterministic: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 100_000,
+            max_heap: None,
+            deterministic: false,
+        }
+    }
+}
+
+impl Limits {
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_heap(mut self, max_heap: Option<usize>) -> Self {
+        self.max_heap = max_heap;
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+}
+
+/// A call in progress.
+pub(crate) struct Frame {
+    closure: Rc<Closure>,
+    /// The index of the next op.
+    pc: usize,
+    locals: Vec<Value>,
+    /// Where the values of this frame start, on the stack of the machine.
+    base: usize,
+}
+
+/// Runs the functions of a [`Module`].
+pub struct Machine<'m> {
+    module: &'m Module,
+    limits: Limits,
+    /// The values of functions and constants, which are `None` for constants that haven't been
+    /// evaluated yet.
+    globals: Vec<Option<Value>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    /// Stops the machine at the next safepoint once it's set, see [`Machine::watch`].
+    interrupt: Option<&'m AtomicBool>,
+    /// The bytes of the heap allocated while the machine was running, and not freed since.
+    heap: usize,
+    /// Frees the cycles of channels and coroutines.
+    gc: gc::Collector,
+    /// The coroutines being resumed, innermost last.
+    resumed: Vec<Resumed>,
+    /// The children of the `task` blocks being run, innermost last, with the number of frames
+    /// when each block started.
+    groups: Vec<(usize, Group<Value>)>,
+    /// The time by the clock of the scheduler, when the coroutine running now was resumed.
+    now: Duration,
+}
+
+/// How far a coroutine has got.
+pub(crate) enum CoState {
+    /// Not started, with the function and the arguments it was called with.
+    Start(Rc<Closure>, Vec<Value>),
+    /// Suspended, with its frames, whose bases are from the start of `stack`.
+    Suspended {
+        frames: Vec<Frame>,
+        stack: Vec<Value>,
+    },
+    Running,
+    Done,
+}
+
+impl CoState {
+    /// Takes the state of `co` to run it, leaving it running, or fails if it can't be resumed.
+    fn take(co: &Coroutine) -> Result<CoState> {
+        match co.state.replace(CoState::Running) {
+            CoState::Running => bail!("the coroutine `{}` is already running", co.name),
+            CoState::Done => {
+                *co.state.borrow_mut() = CoState::Done;
+                bail!(
+                    "the coroutine `{}` is done, so it can't be resumed",
+                    co.name
+                )
+            }
+            state => Ok(state),
+        }
+    }
+}
+
+/// A coroutine being resumed by `c.resume()`, whose frames are the ones from `depth` up.
+struct Resumed {
+    co: Rc<Coroutine>,
+    depth: usize,
+    /// The height of the stack when it was resumed, where its values start.
+    height: usize,
+}
+
+/// What running an op needs from the scheduler.
+enum Effect {
+    Suspend(Suspend),
+    /// Run the coroutine alongside the one that's running, from the state it was taken in.
+    Spawn(Rc<Coroutine>, CoState),
+}
+
+/// Compiles `program`, and returns the result of calling its `main` function, which is
+/// interrupted by Ctrl-C if [`interrupt::install`] has been called.
+pub fn run(program: &Program) -> Result<Value> {
+    run_with(program, Limits::default())
+}
+
+/// Like [`run`], with `limits` rather than the default ones.
+pub fn run_with(program: &Program, limits: Limits) -> Result<Value> {
+    let module = Module::compile(program)?;
+    let mut machine = Machine::new(&module, limits)?;
+    machine.watch(interrupt::sigint());
+    machine.call("main", vec![])
+}
+
+impl<'m> Machine<'m> {
+    /// A machine for `module`, which evaluates its constants, in the order they're declared.
+    pub fn new(module: &'m Module, limits: Limits) -> Result<Self> {
+        let globals = module
+            .globals
+            .iter()
+            .map(|g| match g {
+                Global::Fn(code) => Some(function(code.clone())),
+                Global::Const(_) => None,
+                Global::Host(v) => Some(v.clone()),
+            })
+            .collect();
+        let mut machine = Machine {
+            module,
+            limits,
+            globals,
+            stack: vec![],
+            frames: vec![],
+            interrupt: None,
+            heap: 0,
+            gc: gc::Collector::default(),
+            resumed: vec![],
+            groups: vec![],
+            now: Duration::ZERO,
+        };
+        for (n, global) in module.globals.iter().enumerate() {
+            if let Global::Const(code) = global {
+                let value = machine.run(function(code.clone()), vec![])?;
+                machine.globals[n] = Some(value);
+            }
+        }
+        Ok(machine)
+    }
+
+    /// Makes calls fail with [`RuntimeError::Interrupted`] once `flag` is set, at the next call or
+    /// loop iteration. The flag is cleared again when that happens, so the machine can be used
+    /// for other calls afterwards.
+    pub fn watch(&mut self, flag: &'m AtomicBool) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Fails if the flag the machine is watching has been set, or if it's using too much of the
+    /// heap, once the collector has had a chance to free some of it.
+    fn safepoint(&mut self) -> Result<()> {
+        if let Some(flag) = self.interrupt.filter(|f| f.load(Ordering::Relaxed)) {
+            flag.store(false, Ordering::Relaxed);
+            return Err(RuntimeError::Interrupted.into());
+        }
+        self.gc.safepoint();
+        self.reserve(0)
+    }
+
+    /// What the collector has done since the machine was made.
+    pub fn gc_stats(&self) -> gc::Stats {
+        self.gc.stats()
+    }
+
+    /// Fails if the machine would be using too much of the heap after allocating `bytes` more.
+    fn reserve(&self, bytes: usize) -> Result<()> {
+        match (self.limits.max_heap, heap::in_use()) {
+            (Some(limit), Some(n)) if n.saturating_add(bytes) > limit => {
+                Err(RuntimeError::OutOfMemory { limit }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Calls the function `name` with `args`, and runs until it returns, and the coroutines it
+    /// spawned are done.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let Some(&n) = self.module.names.get(name) else {
+            bail!("there's no function named `{name}`")
+        };
+        let f = self.globals[n].clone().unwrap();
+        let outer = heap::enter(self.heap);
+        let result = self.schedule(f, args);
+        self.heap = heap::leave(outer);
+        result
+    }
+
+    /// Calls `f` as the first coroutine on a [`Scheduler`], and runs it until every coroutine is
+    /// done, or one of them has failed.
+    fn schedule(&mut self, f: Value, args: Vec<Value>) -> Result<Value> {
+        self.call_value(f, args)?;
+        let root = Fiber::new(None, self.save());
+        let driver = Driver {
+            machine: RefCell::new(self),
+            error: RefCell::new(None),
+            result: RefCell::new(None),
+        };
+        let mut scheduler = match driver.machine.borrow().limits.deterministic {
+            true => Scheduler::deterministic(),
+            false => Scheduler::new(),
+        };
+        scheduler.spawn(driver.detached(root));
+        let progressed = scheduler.run_until_stuck(|| driver.error.borrow().is_some());
+        drop(scheduler);
+        match (driver.error.into_inner(), progressed) {
+            (Some(e), _) => Err(e),
+            (None, false) => Err(RuntimeError::Deadlock.into()),
+            (None, true) => Ok(driver.result.into_inner().expect("the call has returned")),
+        }
+    }
+
+    /// Calls `f`, and runs until it returns.
+    fn run(&mut self, f: Value, args: Vec<Value>) -> Result<Value> {
+        let outer = heap::enter(self.heap);
+        let result = self.run_counted(f, args);
+        self.heap = heap::leave(outer);
+        result
+    }
+
+    /// Like [`Machine::run`], once the heap the machine uses is being counted. This is how
+    /// constants are evaluated, which can't use the scheduler.
+    fn run_counted(&mut self, f: Value, args: Vec<Value>) -> Result<Value> {
+        let (depth, height) = (self.frames.len(), self.stack.len());
+        let result = self.call_value(f, args).and_then(|()| {
+            while self.frames.len() > depth {
+                if self.step()?.is_some() {
+                    bail!("constants can't spawn, sleep or yield to other coroutines")
+                }
+            }
+            Ok(self.stack.pop().unwrap())
+        });
+        result.map_err(|e| self.unwind(e, depth, height))
+    }
+
+    /// Drops the frames from `depth` up, and the stack from `height` up, after `e`, and adds
+    /// where it happened to it. Coroutines that were running in those frames are done.
+    fn unwind(&mut self, e: Error, depth: usize, height: usize) -> Error {
+        let name = self.frames.last().map(|f| f.closure.code.name.clone());
+        while self.resumed.last().is_some_and(|r| r.depth >= depth) {
+            let r = self.resumed.pop().unwrap();
+            *r.co.state.borrow_mut() = CoState::Done;
+        }
+        while self.groups.last().is_some_and(|(d, _)| *d > depth) {
+            self.groups.pop();
+        }
+        self.frames.truncate(depth);
+        self.stack.truncate(height);
+        match name {
+            Some(name) => e.context(format!("in `{name}`")),
+            None => e,
+        }
+    }
+
+    /// Pushes a frame for calling `f`, or pushes a coroutine if `f` is a `co fn`.
+    fn call_value(&mut self, f: Value, args: Vec<Value>) -> Result<()> {
+        let Value::Fn(closure) = f else {
+            bail!("can't call {}, which isn't a function", f.type_name())
+        };
+        let code = &closure.code;
+        if args.len() != code.arity {
+            bail!(
+                "`{}` takes {} arguments, but was given {}",
+                code.name,
+                code.arity,
+                args.len()
+            )
+        }
+        if code.is_co {
+            let co = Rc::new(Coroutine::new(closure, args));
+            self.gc.coroutine(&co);
+            self.stack.push(Value::Coroutine(co));
+            return Ok(());
+        }
+        self.enter(closure, args)
+    }
+
+    /// Pushes a frame running the code of `closure`, with `args` as its first locals.
+    fn enter(&mut self, closure: Rc<Closure>, args: Vec<Value>) -> Result<()> {
+        self.safepoint()?;
+        self.check_depth(1)?;
+        let mut locals = args;
+        locals.resize(closure.code.locals, Value::Unit);
+        self.frames.push(Frame {
+            closure,
+            pc: 0,
+            locals,
+            base: self.stack.len(),
+        });
+        Ok(())
+    }
+
+    /// Fails if pushing `n` more frames would be more than [`Limits::max_depth`].
+    fn check_depth(&self, n: usize) -> Result<()> {
+        if self.frames.len() + n > self.limits.max_depth {
+            return Err(RuntimeError::StackOverflow {
+                depth: self.limits.max_depth,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Resumes `co` on top of the frames of the machine, until it yields or returns.
+    fn resume(&mut self, co: Rc<Coroutine>) -> Result<()> {
+        let (depth, height) = (self.frames.len(), self.stack.len());
+        match CoState::take(&co)? {
+            CoState::Start(closure, args) => {
+                self.resumed.push(Resumed { co, depth, height });
+                self.enter(closure, args)?;
+            }
+            CoState::Suspended { frames, stack } => {
+                if let Err(e) = self.check_depth(frames.len()) {
+                    *co.state.borrow_mut() = CoState::Suspended { frames, stack };
+                    return Err(e);
+                }
+                self.stack.extend(stack);
+                let frames = frames.into_iter().map(|f| Frame {
+                    base: f.base + height,
+                    ..f
+                });
+                self.frames.extend(frames);
+                self.resumed.push(Resumed { co, depth, height });
+            }
+            CoState::Running | CoState::Done => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Takes the frames and the stack of the machine, leaving them empty.
+    fn save(&mut self) -> CoState {
+        CoState::Suspended {
+            frames: std::mem::take(&mut self.frames),
+            stack: std::mem::take(&mut self.stack),
+        }
+    }
+
+    /// The method overloading the operator `name` for `v`. Operators on structs and variants are
+    /// overloaded by methods named after them.
+    fn overload(&self, v: &Value, name: String) -> Option<Value> {
+        let (Value::Struct(_) | Value::Variant(_)) = v else {
+            return None;
+        };
+        let n = self
+            .module
+            .methods
+            .get(&(v.type_name().to_string(), name))?;
+        self.globals[*n].clone()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    /// Pops the top `n` values, in the order they were pushed.
+    fn pop_n(&mut self, n: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+
+    /// Runs the next op of the innermost frame, and returns what it needs from the scheduler.
+    fn step(&mut self) -> Result<Option<Effect>> {
+        let frame = self.frames.last_mut().unwrap();
+        let code = frame.closure.code.clone();
+        let op = &code.ops[frame.pc];
+        frame.pc += 1;
+        match op {
+            Op::Push(v) => self.stack.push(v.clone()),
+            Op::Pop => {
+                self.pop();
+            }
+            Op::Dup => self.stack.push(self.stack.last().unwrap().clone()),
+            Op::Truncate(n) => self.stack.truncate(frame.base + n),
+            Op::Load(slot) => {
+                let v = frame.locals[*slot].clone();
+                self.stack.push(v)
+            }
+            Op::Store(slot) => {
+                let v = self.stack.pop().unwrap();
+                self.frames.last_mut().unwrap().locals[*slot] = v;
+            }
+            Op::LoadCapture(n) => {
+                let v = frame.closure.captures[*n].clone();
+                self.stack.push(v)
+            }
+            Op::Global(n) => match &self.globals[*n] {
+                Some(v) => self.stack.push(v.clone()),
+                None => bail!("a constant was used before it was defined"),
+            },
+            Op::Unary(op) => {
+                let v = self.pop();
+                match self.overload(&v, compile::unary_name(*op)) {
+                    Some(f) => self.call_value(f, vec![v])?,
+                    None => self.stack.push(value::unary(*op, &v)?),
+                }
+            }
+            Op::Binary(op) => {
+                let b = self.pop();
+                let a = self.pop();
+                match self.overload(&a, op.symbol().to_string()) {
+                    Some(f) => self.call_value(f, vec![a, b])?,
+                    None => {
+                        let v = value::binary_with(*op, &a, &b, self.module.natural_sub)?;
+                        self.stack.push(v)
+                    }
+                }
+            }
+            Op::Jump(target) => {
+                // Jumps back are how loops repeat.
+                if *target < frame.pc {
+                    self.safepoint()?;
+                }
+                self.frames.last_mut().unwrap().pc = *target;
+            }
+            Op::JumpUnless(target) => {
+                if !self.stack.pop().unwrap().as_bool()? {
+                    self.frames.last_mut().unwrap().pc = *target;
+                }
+            }
+            Op::Call(n) => {
+                let args = self.pop_n(*n);
+                let f = self.pop();
+                self.call_value(f, args)?;
+            }
+            Op::MethodCall(method, n) => {
+                let args = self.pop_n(*n + 1);
+                if let Value::Host(module) = &args[0] {
+                    let v = module.call(method, &args[1..])?;
+                    self.stack.push(v);
+                    return Ok(None);
+                }
+                if let Value::Coroutine(co) = &args[0] {
+                    match (method.as_str(), args.len()) {
+                        ("resume", 1) => self.resume(co.clone())?,
+                        ("is_done", 1) => {
+                            let done = matches!(*co.state.borrow(), CoState::Done);
+                            self.stack.push(Value::Bool(done));
+                        }
+                        ("resume" | "is_done", n) => {
+                            bail!("`{method}` takes 0 arguments, but was given {}", n - 1)
+                        }
+                        _ => bail!("Coroutine has no method `{method}`"),
+                    }
+                    return Ok(None);
+                }
+                let key = (args[0].type_name().to_string(), method.clone());
+                let Some(&f) = self.module.methods.get(&key) else {
+                    let Some(v) = value::builtin_method(&args[0], method, &args[1..]) else {
+                        bail!("{} has no method `{method}`", args[0].type_name())
+                    };
+                    self.stack.push(v?);
+                    return Ok(None);
+                };
+                let f = self.globals[f].clone().unwrap();
+                self.call_value(f, args)?;
+            }
+            Op::Return => {
+                let v = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.stack.truncate(frame.base);
+                self.stack.push(v);
+                if self
+                    .resumed
+                    .last()
+                    .is_some_and(|r| r.depth == self.frames.len())
+                {
+                    let r = self.resumed.pop().unwrap();
+                    *r.co.state.borrow_mut() = CoState::Done;
+                }
+            }
+            Op::Tuple(n) => {
+                let items = self.pop_n(*n);
+                self.stack.push(Value::Tuple(Rc::new(items)));
+            }
+            Op::Array(n) => {
+                let items = self.pop_n(*n);
+                self.stack.push(Value::Array(Rc::new(items)));
+            }
+            Op::Repeat => {
+                let count = self.pop().as_index()?;
+                let v = self.pop();
+                // Checked up front, since the array could be far bigger than what's left.
+                self.reserve(count.saturating_mul(std::mem::size_of::<Value>()))?;
+                self.stack.push(Value::Array(Rc::new(vec![v; count])));
+            }
+            Op::Index => {
+                let index = self.pop();
+                let v = self.pop();
+                let item = index_of(&v, &index)?.clone();
+                self.stack.push(item);
+            }
+            Op::Field(name) => {
+                let v = self.pop();
+                let field = field_of(&v, name)?.clone();
+                self.stack.push(field);
+            }
+            Op::TupleIndex(n) => {
+                let v = self.pop();
+                let item = tuple_item(&v, *n)?.clone();
+                self.stack.push(item);
+            }
+            Op::Struct(name, fields) => {
+                let values = self.pop_n(fields.len());
+                let fields = fields.iter().cloned().zip(values).collect();
+                let name = name.clone();
+                self.stack
+                    .push(Value::Struct(Rc::new(Struct { name, fields })));
+            }
+            Op::Variant { ty, name, shape } => {
+                let fields = match shape {
+                    Shape::Unit => Fields::Unit,
+                    Shape::Tuple(n) => Fields::Tuple(self.pop_n(*n)),
+                    Shape::Named(names) => {
+                        let values = self.pop_n(names.len());
+                        Fields::Named(names.iter().cloned().zip(values).collect())
+                    }
+                };
+                let (ty, name) = (ty.clone(), name.clone());
+                self.stack
+                    .push(Value::Variant(Rc::new(Variant { ty, name, fields })));
+            }
+            Op::Range { inclusive } => {
+                let end = self.pop();
+                let start = self.pop();
+                self.stack.push(Value::Range {
+                    start: start.as_bound()?,
+                    end: end.as_bound()?,
+                    inclusive: *inclusive,
+                });
+            }
+            Op::Closure(code, captures) => {
+                let captures = captures
+                    .iter()
+                    .map(|c| match c {
+                        Capture::Local(slot) => frame.locals[*slot].clone(),
+                        Capture::Capture(n) => frame.closure.captures[*n].clone(),
+                    })
+                    .collect();
+                let code = code.clone();
+                self.stack
+                    .push(Value::Fn(Rc::new(Closure { code, captures })));
+            }
+            Op::Assign { slot, path, op } => {
+                let v = self.pop();
+                let indices = path.iter().filter(|s| **s == Step::Index).count();
+                let indices = self.pop_n(indices);
+                let frame = self.frames.last_mut().unwrap();
+                let place = place(&mut frame.locals[*slot], path, &indices)?;
+                *place = match op {
+                    Some(op) => value::binary_with(*op, place, &v, self.module.natural_sub)?,
+                    None => v,
+                };
+            }
+            Op::Interpolate(n) => {
+                let parts = self.pop_n(*n);
+                let s: String = parts.iter().map(|v| v.to_string()).collect();
+                self.stack.push(Value::String(s.into()));
+            }
+            Op::Next { iter, index, done } => {
+                let n = frame.locals[*index].as_index()?;
+                let item = match &frame.locals[*iter] {
+                    Value::Array(items) => items.get(n).cloned(),
+                    Value::Range {
+                        start,
+                        end,
+                        inclusive,
+                    } => {
+                        let i = start.checked_add(n as i64);
+                        let more = |i: i64| if *inclusive { i <= *end } else { i < *end };
+                        i.filter(|i| more(*i))
+                            .map(|i| Value::Integer(crate::num::integer::Integer::new(i)))
+                    }
+                    v => bail!("can't iterate over {}", v.type_name()),
+                };
+                match item {
+                    Some(item) => {
+                        let next = crate::num::integer::Integer::new(n as i64 + 1);
+                        frame.locals[*index] = Value::Integer(next);
+                        self.stack.push(item);
+                    }
+                    None => frame.pc = *done,
+                }
+            }
+            Op::Match(pat) => {
+                let v = self.stack.last().unwrap();
+                let matched = matches(pat, v, &mut frame.locals)?;
+                self.stack.push(Value::Bool(matched));
+            }
+            Op::Ascribe(ty) => {
+                let v = self.pop();
+                let v = match (ty, &v) {
+                    // Narrowing an integer to a natural number is explicit, with an ascription.
+                    (crate::num::NumType::Natural, Value::Integer(n)) => {
+                        Value::Natural(n.get().try_into()?)
+                    }
+                    _ => v.widen(*ty)?,
+                };
+                self.stack.push(v);
+            }
+            Op::Clear(slots) => frame.locals[slots.clone()].fill(Value::Unit),
+            Op::Yield => {
+                let v = self.pop();
+                match self.resumed.last() {
+                    Some(r) if r.depth + 1 == self.frames.len() => {
+                        let r = self.resumed.pop().unwrap();
+                        let frames = self.frames.split_off(r.depth).into_iter();
+                        let frames = frames.map(|f| Frame {
+                            base: f.base - r.height,
+                            ..f
+                        });
+                        let mut stack = self.stack.split_off(r.height);
+                        // What the `yield` evaluates to, once the coroutine is resumed.
+                        stack.push(Value::Unit);
+                        *r.co.state.borrow_mut() = CoState::Suspended {
+                            frames: frames.collect(),
+                            stack,
+                        };
+                        self.stack.push(v);
+                    }
+                    // A coroutine that wasn't resumed by hand was spawned, so it gives the
+                    // others a turn.
+                    _ => {
+                        self.stack.push(Value::Unit);
+                        return Ok(Some(Effect::Suspend(Suspend::Yield)));
+                    }
+                }
+            }
+            Op::Spawn => {
+                let co = match self.pop() {
+                    Value::Coroutine(co) => co,
+                    Value::Fn(closure) if closure.code.arity == 0 => {
+                        let co = Rc::new(Coroutine::new(closure, vec![]));
+                        self.gc.coroutine(&co);
+                        co
+                    }
+                    v => bail!(
+                        "can't spawn {}, which isn't a coroutine or a function without parameters",
+                        v.type_name()
+                    ),
+                };
+                let state = CoState::take(&co)?;
+                self.stack.push(Value::Unit);
+                return Ok(Some(Effect::Spawn(co, state)));
+            }
+            Op::Sleep => {
+                let ms = match self.pop() {
+                    Value::Natural(n) => n.get(),
+                    Value::Integer(n) => n.get().max(0) as u64,
+                    v => bail!("expected a number of milliseconds, found {}", v.type_name()),
+                };
+                self.stack.push(Value::Unit);
+                let sleep = Suspend::Sleep(Duration::from_millis(ms));
+                return Ok(Some(Effect::Suspend(sleep)));
+            }
+            Op::Now => {
+                if self.limits.deterministic {
+                    bail!("the clock can't be read in deterministic mode")
+                }
+                let ms = self.now.as_millis().min(i64::MAX as u128) as i64;
+                self.stack
+                    .push(Value::Integer(crate::num::integer::Integer::new(ms)));
+            }
+            Op::Weak => {
+                let v = self.pop();
+                self.stack.push(Value::Weak(value::Weak::new(&v)?));
+            }
+            Op::Channel { bounded } => {
+                let (tx, rx) = match bounded {
+                    true => channel::bounded(self.pop().as_index()?),
+                    false => channel::unbounded(),
+                };
+                self.gc.channel(&tx);
+                let ends = vec![Value::Sender(tx), Value::Receiver(Rc::new(rx))];
+                self.stack.push(Value::Tuple(Rc::new(ends)));
+            }
+            Op::Send => {
+                let v = self.pop();
+                let tx = match self.pop() {
+                    Value::Sender(tx) => tx,
+                    tx => bail!("can't send with {}, which isn't a sender", tx.type_name()),
+                };
+                match tx.send(v) {
+                    Result::Ok(()) => self.stack.push(Value::Bool(true)),
+                    Err(SendError::Closed(_)) => self.stack.push(Value::Bool(false)),
+                    Err(SendError::Full(v)) => {
+                        // Sends again once it's resumed.
+                        let ready = tx.ready();
+                        self.stack.extend([Value::Sender(tx), v]);
+                        self.frames.last_mut().unwrap().pc -= 1;
+                        return Ok(Some(Effect::Suspend(ready)));
+                    }
+                }
+            }
+            Op::Recv => {
+                let rx = match self.pop() {
+                    Value::Receiver(rx) => rx,
+                    rx => bail!(
+                        "can't receive with {}, which isn't a receiver",
+                        rx.type_name()
+                    ),
+                };
+                match rx.recv() {
+                    Result::Ok(v) => self.stack.push(value::option(Some(v))),
+                    Err(RecvError::Closed) => self.stack.push(value::option(None)),
+                    Err(RecvError::Empty) => {
+                        // Receives again once it's resumed.
+                        let ready = rx.ready();
+                        self.stack.push(Value::Receiver(rx));
+                        self.frames.last_mut().unwrap().pc -= 1;
+                        return Ok(Some(Effect::Suspend(ready)));
+                    }
+                }
+            }
+            Op::GcStats => {
+                let stats = self.gc.stats();
+                let natural = |n: u64| Value::Natural(Natural::new(n));
+                let fields = [
+                    ("collections", natural(stats.collections)),
+                    ("full_collections", natural(stats.full_collections)),
+                    ("freed", natural(stats.freed)),
+                    ("tracked", natural(self.gc.tracked() as u64)),
+                    ("heap", natural(heap::in_use().unwrap_or(0) as u64)),
+                ];
+                let fields = fields.map(|(name, v)| (name.to_string(), v)).into();
+                let name = "GcStats".to_string();
+                self.stack
+                    .push(Value::Struct(Rc::new(Struct { name, fields })));
+            }
+            Op::TaskStart => self.groups.push((self.frames.len(), Group::new())),
+            Op::TaskJoin => match self.groups.last().unwrap().1.join() {
+                Poll::Pending(suspend) => {
+                    // Joins again once it's resumed.
+                    self.frames.last_mut().unwrap().pc -= 1;
+                    return Ok(Some(Effect::Suspend(suspend)));
+                }
+                Poll::Ready(results) => {
+                    self.groups.pop();
+                    self.stack.push(Value::Array(Rc::new(results?)));
+                }
+            },
+            Op::Fail(message) => bail!("{message}"),
+        }
+        Ok(None)
+    }
+}
+
+/// A coroutine the [`Driver`] runs, with its frames while it's not running.
+struct Fiber {
+    /// The coroutine that was spawned, which is `None` for the call the scheduler started with.
+    co: Option<Rc<Coroutine>>,
+    state: CoState,
+    /// The coroutines it was resuming by hand when it was suspended.
+    resumed: Vec<Resumed>,
+    /// The `task` blocks it was in when it was suspended.
+    groups: Vec<(usize, Group<Value>)>,
+}
+
+impl Fiber {
+    fn new(co: Option<Rc<Coroutine>>, state: CoState) -> Self {
+        Fiber {
+            co,
+            state,
+            resumed: vec![],
+            groups: vec![],
+        }
+    }
+}
+
+impl Drop for Fiber {
+    fn drop(&mut self) {
+        // Fibers that aren't done are dropped when they're cancelled, or another one has failed.
+        if let Some(co) = &self.co {
+            *co.state.borrow_mut() = CoState::Done;
+        }
+    }
+}
+
+/// Runs coroutines on a machine, for a [`Scheduler`]. Only one of them is on the machine at a
+/// time, and the frames of the others are kept in their [`Fiber`]s.
+struct Driver<'d, 'm> {
+    machine: RefCell<&'d mut Machine<'m>>,
+    /// The first error, which stops the scheduler.
+    error: RefCell<Option<Error>>,
+    /// What the call the scheduler started with returned.
+    result: RefCell<Option<Value>>,
+}
+
+impl<'d, 'm> Driver<'d, 'm> {
+    /// A coroutine for the scheduler, running `fiber`.
+    fn detached<'s>(&'s self, mut fiber: Fiber) -> impl runtime::Coroutine<'s> + use<'s, 'd, 'm> {
+        move |cx: &mut Context<'s>| match self.resume(&mut fiber, cx) {
+            Poll::Pending(suspend) => suspend,
+            Poll::Ready(Result::Ok(v)) => {
+                if fiber.co.is_none() {
+                    *self.result.borrow_mut() = Some(v);
+                }
+                Suspend::Done
+            }
+            Poll::Ready(Err(e)) => {
+                self.error.borrow_mut().get_or_insert(e);
+                Suspend::Done
+            }
+        }
+    }
+
+    /// A child task of a `task` block, running `fiber`.
+    fn child<'s>(&'s self, mut fiber: Fiber) -> impl Child<'s, Value> + use<'s, 'd, 'm> {
+        move |cx: &mut Context<'s>| self.resume(&mut fiber, cx)
+    }
+
+    /// Runs `fiber` on the machine until it suspends or returns.
+    fn resume<'s>(&'s self, fiber: &mut Fiber, cx: &mut Context<'s>) -> Poll<Value> {
+        let mut machine = self.machine.borrow_mut();
+        machine.now = cx.now();
+        machine.resumed = std::mem::take(&mut fiber.resumed);
+        machine.groups = std::mem::take(&mut fiber.groups);
+        let started = match std::mem::replace(&mut fiber.state, CoState::Running) {
+            CoState::Start(closure, args) => machine.enter(closure, args),
+            CoState::Suspended { frames, stack } => {
+                machine.frames = frames;
+                machine.stack = stack;
+                Result::Ok(())
+            }
+            CoState::Running | CoState::Done => unreachable!(),
+        };
+        let suspended = started.and_then(|()| {
+            while !machine.frames.is_empty() {
+                match machine.step()? {
+                    None => {}
+                    Some(Effect::Spawn(co, state)) => {
+                        let fiber = Fiber::new(Some(co), state);
+                        match machine.groups.last() {
+                            Some((_, group)) => group.spawn(cx, self.child(fiber)),
+                            None => {
+                                cx.spawn(self.detached(fiber));
+                            }
+                        }
+                    }
+                    Some(Effect::Suspend(suspend)) => return Ok(Some(suspend)),
+                }
+            }
+            Ok(None)
+        });
+        let result = match suspended {
+            Result::Ok(Some(suspend)) => {
+                fiber.state = machine.save();
+                fiber.resumed = std::mem::take(&mut machine.resumed);
+                fiber.groups = std::mem::take(&mut machine.groups);
+                return Poll::Pending(suspend);
+            }
+            Result::Ok(None) => Ok(machine.stack.pop().unwrap()),
+            Err(e) => Err(machine.unwind(e, 0, 0)),
+        };
+        fiber.state = CoState::Done;
+        if let Some(co) = &fiber.co {
+            *co.state.borrow_mut() = CoState::Done;
+        }
+        Poll::Ready(result)
+    }
+}
+
+/// A function without captures.
+fn function(code: Rc<Code>) -> Value {
+    Value::Fn(Rc::new(Closure {
+        code,
+        captures: vec![],
+    }))
+}
+
+fn index_of<'v>(v: &'v Value, index: &Value) -> Result<&'v Value> {
+    let Value::Array(items) = v else {
+        bail!("can't index into {}", v.type_name())
+    };
+    let n = index.as_index()?;
+    items
+        .get(n)
+        .ok_or_else(|| anyhow!("index {n} is out of bounds of an array of {}", items.len()))
+}
+
+fn field_of<'v>(v: &'v Value, name: &str) -> Result<&'v Value> {
+    let fields = match v {
+        Value::Struct(s) => &s.fields,
+        Value::Variant(v) => match &v.fields {
+            Fields::Named(fields) => fields,
+            _ => bail!("{}::{} has no field `{name}`", v.ty, v.name),
+        },
+        _ => bail!("{} has no field `{name}`", v.type_name()),
+    };
+    match fields.iter().find(|(n, _)| n == name) {
+        Some((_, v)) => Ok(v),
+        None => bail!("{} has no field `{name}`", v.type_name()),
+    }
+}
+
+fn tuple_item(v: &Value, n: usize) -> Result<&Value> {
+    let items = match v {
+        Value::Tuple(items) => &items[..],
+        Value::Variant(v) => match &v.fields {
+            Fields::Tuple(items) => &items[..],
+            _ => bail!("{}::{} has no field {n}", v.ty, v.name),
+        },
+        _ => bail!("{} has no field {n}", v.type_name()),
+    };
+    items
+        .get(n)
+        .ok_or_else(|| anyhow!("{} has no field {n}", v.type_name()))
+}
+
+/// The place reached by following `path` from `root`, which copies any values on the way that
+/// are shared with other values.
+fn place<'v>(root: &'v mut Value, path: &[Step], indices: &[Value]) -> Result<&'v mut Value> {
+    let mut place = root;
+    let mut indices = indices.iter();
+    for step in path {
+        let type_name = place.type_name().to_string();
+        place = match (step, place) {
+            (Step::Index, Value::Array(items)) => {
+                let n = indices.next().unwrap().as_index()?;
+                let len = items.len();
+                Rc::make_mut(items)
+                    .get_mut(n)
+                    .ok_or_else(|| anyhow!("index {n} is out of bounds of an array of {len}"))?
+            }
+            (Step::Field(name), Value::Struct(s)) => {
+                let field = Rc::make_mut(s).fields.iter_mut().find(|(n, _)| n == name);
+                match field {
+                    Some((_, v)) => v,
+                    None => bail!("{type_name} has no field `{name}`"),
+                }
+            }
+            (Step::TupleIndex(n), Value::Tuple(items)) => match Rc::make_mut(items).get_mut(*n) {
+                Some(v) => v,
+                None => bail!("{type_name} has no field {n}"),
+            },
+            _ => bail!("can't assign to a part of {type_name}"),
+        };
+    }
+    Ok(place)
+}
+
+/// Whether `v` matches `pat`, binding its variables in `locals` if it does.
+fn matches(pat: &Pat, v: &Value, locals: &mut [Value]) -> Result<bool> {
+    Ok(match pat {
+        Pat::Wildcard => true,
+        Pat::Binding(slot) => {
+            locals[*slot] = v.clone();
+            true
+        }
+        Pat::Literal(lit) => {
+            v.num_type().is_some() == lit.num_type().is_some()
+                && value::binary(crate::ast::BinaryOp::Eq, v, lit)?.as_bool()?
+        }
+        Pat::Tuple(pats) => match v {
+            Value::Tuple(items) if items.len() == pats.len() => {
+                for (p, item) in pats.iter().zip(items.iter()) {
+                    if !matches(p, item, locals)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            _ => false,
+        },
+        Pat::Or(pats) => {
+            for p in pats {
+                if matches(p, v, locals)? {
+                    return Ok(true);
+                }
+            }
+            false
+        }
+        Pat::Variant { ty, name, args } => {
+            let Value::Variant(variant) = v else {
+                return Ok(false);
+            };
+            if variant.ty != *ty || variant.name != *name {
+                return Ok(false);
+            }
+            match (args, &variant.fields) {
+                (Fields::Unit, Fields::Unit) => true,
+                (Fields::Tuple(pats), Fields::Tuple(items)) if pats.len() == items.len() => {
+                    for (p, item) in pats.iter().zip(items) {
+                        if !matches(p, item, locals)? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                (Fields::Named(pats), Fields::Named(fields)) => {
+                    for (n, p) in pats {
+                        let Some((_, item)) = fields.iter().find(|(f, _)| f == n) else {
+                            bail!("{ty}::{name} has no field `{n}`")
+                        };
+                        if !matches(p, item, locals)? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                _ => false,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::host::HostModule;
+    use crate::eval::*;
+    use crate::grammar;
+    use crate::num::integer::Integer;
+    use crate::num::real::Real;
+    use crate::parser::Parser;
+
+    fn eval(src: &str) -> Result<Value> {
+        let (program, n) = grammar::Program.parse(src)?;
+        assert_eq!(n, src.len(), "didn't parse all of {src:?}");
+        run(&program)
+    }
+
+    fn int(n: i64) -> Value {
+        Value::Integer(Integer::new(n))
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        assert_eq!(eval("fn main() { 1 + 2 * 3 }")?, int(7));
+        assert_eq!(eval("const N: Integer = 3; fn main() { -N ^ 2 }")?, int(-9));
+        assert_eq!(eval("fn main() { x := 1; x += 2; x }")?, int(3));
+        assert_eq!(
+            eval(r#"fn main() { a := 2; "{a} squared is {a * a}" }"#)?,
+            Value::String("2 squared is 4".into())
+        );
+        assert!(eval("fn main() { 1 / 0 }").is_err());
+        assert_eq!(
+            eval("const MAX: Integer = 9223372036854775807; fn main() { (MAX +% 1, -2 *% MAX) }")?,
+            Value::Tuple(Rc::new(vec![int(i64::MIN), int(2)]))
+        );
+        assert!(eval("fn main() { 9223372036854775807 + 1 }").is_err());
+        assert_eq!(
+            eval("fn main() { z := (1 + 1i) ^ 2; (z, z.abs(), (3 - 4i).conj(), (-2).abs()) }")?
+                .to_string(),
+            "(0 + 2i, 2, 3 + 4i, 2)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn control_flow() -> Result<()> {
+        let src = "fn main() {
+            sum := 0;
+            for i in 0..10 {
+                if i == 5 { continue }
+                if i == 8 { break }
+                sum += i;
+            }
+            i := 0;
+            while true { i += 1; if i > 3 || sum == 0 { break } }
+            (sum, i, f(3) && false)
+        }
+        fn f(n: Integer) -> Bool { n > 2 }";
+        assert_eq!(
+            eval(src)?,
+            Value::Tuple(Rc::new(vec![int(23), int(4), Value::Bool(false)]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recursion() -> Result<()> {
+        let fib =
+            "fn fib(n: Integer) -> Integer { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }";
+        assert_eq!(eval(&format!("{fib} fn main() {{ fib(15) }}"))?, int(610));
+        // Much deeper than the native stack would allow, with a frame per call.
+        let count = "fn count(n: Integer) -> Integer { if n == 0 { 0 } else { 1 + count(n - 1) } }";
+        assert_eq!(
+            eval(&format!("{count} fn main() {{ count(50000) }}"))?,
+            int(50000)
+        );
+        let err = eval(&format!("{count} fn main() {{ count(1000000) }}")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RuntimeError>(),
+            Some(&RuntimeError::StackOverflow { depth: 100_000 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn data() -> Result<()> {
+        let src = "
+            enum Shape { Circle(Integer), Rect { w: Integer, h: Integer }, Empty }
+            struct P { x: Integer, y: Integer }
+            impl P { fn sum(p: Self) -> Integer { p.x + p.y } }
+            impl Add for P { fn +(a: Self, b: Self) -> Self { P { x: a.x + b.x, y: a.y + b.y } } }
+            fn area(s: Shape) -> Integer {
+                match s {
+                    Shape::Circle(r) => 3 * r * r,
+                    Shape::Rect { w: w, h: h } => w * h,
+                    Shape::Empty => 0,
+                }
+            }
+            fn main() {
+                a := [1, 2, 3];
+                b := a;
+                b[0] = 10;
+                p := P { x: 1, y: 2 } + P { x: 1, y: 1 };
+                p.x *= 10;
+                shapes := [Shape::Circle(1), Shape::Rect { w: 2, h: 3 }, Shape::Empty];
+                total := 0;
+                for s in shapes { total += area(s) }
+                (a[0] + b[0], p.sum(), total, [0; 2])
+            }";
+        let expected = vec![
+            int(11),
+            int(23),
+            int(9),
+            Value::Array(Rc::new(vec![int(0), int(0)])),
+        ];
+        assert_eq!(eval(src)?, Value::Tuple(Rc::new(expected)));
+        Ok(())
+    }
+
+    #[test]
+    fn unary_overloads() -> Result<()> {
+        let src = "
+            struct V { x: Integer }
+            impl V {
+                fn -(a: Self) -> Self { V { x: -a.x } }
+                fn -(a: Self, b: Self) -> Self { V { x: a.x - b.x } }
+            }
+            fn main() { v := V { x: 5 }; ((-v).x, (v - V { x: 2 }).x) }";
+        assert_eq!(eval(src)?, Value::Tuple(Rc::new(vec![int(-5), int(3)])));
+        Ok(())
+    }
+
+    #[test]
+    fn modules() -> Result<()> {
+        let src = "
+            mod geometry {
+                const PI: Real = 3.0;
+                fn area(r: Real) -> Real { PI * square(r) }
+                fn square(x: Real) -> Real { x * x }
+                mod shapes { fn unit() -> Real { area(1.0) } }
+            }
+            use geometry.shapes;
+            use geometry.area;
+            fn main() { (geometry.area(2.0), shapes.unit(), area(1.0) == geometry.PI) }";
+        let expected = vec![
+            Value::Real(Real::new(12.0)),
+            Value::Real(Real::new(3.0)),
+            Value::Bool(true),
+        ];
+        assert_eq!(eval(src)?, Value::Tuple(Rc::new(expected)));
+        let err = |src: &str| format!("{:#}", eval(src).unwrap_err());
+        assert_eq!(
+            err("mod m { fn f() {} } fn main() { f() }"),
+            "`f` isn't defined"
+        );
+        assert_eq!(err("use m.g; mod m {}"), "`m.g` isn't defined");
+        assert_eq!(
+            err("mod m; fn main() {}"),
+            "module `m` is in a file of its own, which hasn't been loaded"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lambdas() -> Result<()> {
+        let src = "fn main() {
+            n := 10;
+            add := |a| |b| a + b + n;
+            twice := |f, x| f(f(x));
+            twice(add(1), 0)
+        }";
+        assert_eq!(eval(src)?, int(22));
+        Ok(())
+    }
+
+    #[test]
+    fn coroutines() -> Result<()> {
+        let src = r#"
+            co fn count(n: Integer) { i := 0; while i < n { yield i; i += 1 } "done" }
+            fn main() {
+                c := count(2);
+                d := c;
+                (c.resume(), d.resume(), c.is_done(), c.resume(), d.is_done(), c)
+            }"#;
+        assert_eq!(
+            eval(src)?.to_string(),
+            "(0, 1, false, done, true, <coroutine count>)"
+        );
+        let err = |src: &str| format!("{:#}", eval(src).unwrap_err());
+        assert_eq!(
+            err("fn main() { yield 1 }"),
+            "`yield` can only be used in a `co fn`"
+        );
+        assert_eq!(
+            err("co fn f() {} fn main() { c := f(); c.resume(); c.resume() }"),
+            "in `main`: the coroutine `f` is done, so it can't be resumed"
+        );
+        assert_eq!(
+            err("co fn f() { 1 / 0 } fn main() { c := f(); c.resume() }"),
+            "in `f`: `1 / 0` divides by zero"
+        );
+        assert_eq!(
+            err("const C: Integer = sleep(1); fn main() { C }"),
+            "in `C`: constants can't spawn, sleep or yield to other coroutines"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn weak_references() -> Result<()> {
+        let src = "fn main() {
+            kept := [1, 2];
+            w := weak(kept);
+            lost := region 'r { dropped := [3]; weak(dropped) };
+            (w.upgrade(), lost.upgrade(), w == weak(kept), w == lost)
+        }";
+        assert_eq!(
+            eval(src)?.to_string(),
+            "(Option::Some([1, 2]), Option::None, true, false)"
+        );
+        assert_eq!(
+            format!("{:#}", eval("fn main() { weak(1) }").unwrap_err()),
+            "in `main`: can't make a weak reference to Integer, which isn't shared"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scheduled() -> Result<()> {
+        let src = r#"
+            co fn tick(name: String, n: Integer, ms: Integer) {
+                for i in 0..n { log.push("{name}{i}"); sleep(ms) }
+            }
+            co fn idle() { log.push("a"); yield; log.push("b") }
+            fn main() { spawn(tick("x", 3, 20)); spawn(tick("y", 2, 30)); spawn(idle); "main" }"#;
+        let log = Rc::new(std::cell::RefCell::new(vec![]));
+        let host = log.clone();
+        let host = HostModule::new("log").function("push", move |s: String| {
+            host.borrow_mut().push(s);
+        });
+        let (program, _) = grammar::Program.parse(src)?;
+        let module = Module::compile_with(&program, vec![host])?;
+        let value = Machine::new(&module, Limits::default())?.call("main", vec![])?;
+        assert_eq!(value, Value::String("main".into()));
+        assert_eq!(*log.borrow(), ["x0", "y0", "a", "b", "x1", "y1", "x2"]);
+        let err = format!(
+            "{:#}",
+            eval("co fn f() { 1 / 0 } fn main() { spawn(f()); 1 }").unwrap_err()
+        );
+        assert_eq!(err, "in `f`: `1 / 0` divides by zero");
+        Ok(())
+    }
+
+    #[test]
+    fn channels() -> Result<()> {
+        let src = r#"
+            co fn produce(tx: Sender, n: Integer) {
+                for i in 0..n { send(tx, i * i); log.push("sent {i}") }
+            }
+            fn main() {
+                rx := region 'r { c := channel(0); spawn(produce(c.0, 3)); c.1 };
+                sum := 0;
+                while true {
+                    match recv(rx) {
+                        Option::Some(n) => { log.push("got {n}"); sum += n },
+                        Option::None => { break },
+                    }
+                }
+                sum
+            }"#;
+        let log = Rc::new(std::cell::RefCell::new(vec![]));
+        let host = log.clone();
+        let host = HostModule::new("log").function("push", move |s: String| {
+            host.borrow_mut().push(s);
+        });
+        let (program, _) = grammar::Program.parse(src)?;
+        let module = Module::compile_with(&program, vec![host])?;
+        let value = Machine::new(&module, Limits::default())?.call("main", vec![])?;
+        assert_eq!(value, int(5));
+        // Each send waits for the value before it to be received.
+        assert_eq!(
+            *log.borrow(),
+            ["sent 0", "got 0", "sent 1", "got 1", "sent 2", "got 4"]
+        );
+        let src = "fn main() { tx := region 'r { c := channel(); c.0 }; send(tx, 1) }";
+        assert_eq!(eval(src)?, Value::Bool(false));
+        let err = eval("fn main() { c := channel(); recv(c.1) }").unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&RuntimeError::Deadlock));
+        assert_eq!(
+            format!("{:#}", eval("fn main() { send(1, 2) }").unwrap_err()),
+            "in `main`: can't send with Integer, which isn't a sender"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn collects_cycles() -> Result<()> {
+        let src = "
+            co fn hold(rx: Receiver) { me := recv(rx); yield; }
+            fn main() {
+                for i in 0..100 { c := channel(); send(c.0, c); }
+                for i in 0..50 { c := channel(); h := hold(c.1); send(c.0, h); h.resume(); }
+                s := gc_stats();
+                (s.collections, s.full_collections, s.freed, s.tracked)
+            }";
+        // The channel and the coroutine of the last iteration before each collection are still
+        // in use, and the objects made since the last one haven't been collected yet.
+        assert_eq!(eval(src)?.to_string(), "(3, 0, 186, 14)");
+        Ok(())
+    }
+
+    #[test]
+    fn deterministic() -> Result<()> {
+        let limits = Limits::default().deterministic(true);
+        let run = |src: &str| -> Result<Value> {
+            let (program, _) = grammar::Program.parse(src)?;
+            run_with(&program, limits.clone())
+        };
+        // An hour of sleeping takes no time on the virtual clock.
+        let start = std::time::Instant::now();
+        let src = "
+            co fn nap(n: Integer, ms: Integer) { sleep(ms); n }
+            fn main() { task { spawn(nap(1, 3600000)); spawn(nap(2, 60000)) } }";
+        assert_eq!(run(src)?.to_string(), "[1, 2]");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(
+            format!("{:#}", run("fn main() { now() }").unwrap_err()),
+            "in `main`: the clock can't be read in deterministic mode"
+        );
+        assert!(eval("fn main() { now() }").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn tasks() -> Result<()> {
+        let src = "
+            co fn square(n: Integer, ms: Integer) { sleep(ms); n * n }
+            fn main() {
+                results := task { spawn(square(2, 20)); spawn(square(3, 0)); 1 };
+                nested := task { spawn(|| task { spawn(square(4, 0)) }) };
+                (results, nested, task {})
+            }";
+        assert_eq!(eval(src)?.to_string(), "([4, 9], [[16]], [])");
+        let start = std::time::Instant::now();
+        let src = "
+            co fn slow() { sleep(10000); 1 }
+            co fn fail() { yield; 1 / 0 }
+            fn main() { task { spawn(slow()); spawn(fail()) } }";
+        let err = format!("{:#}", eval(src).unwrap_err());
+        assert_eq!(err, "in `main`: in `fail`: `1 / 0` divides by zero");
+        // The child that was asleep was cancelled rather than waited for.
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        let err = |src: &str| format!("{:#}", eval(src).unwrap_err());
+        assert_eq!(
+            err("co fn f() { task { yield; } } fn main() {}"),
+            "`yield` can't be used in a `task` block"
+        );
+        assert_eq!(
+            err("fn main() { while true { task { break; } } }"),
+            "`break` and `continue` can't leave a `task` block"
+        );
+        assert_eq!(
+            err("fn main() { task { return 1; } }"),
+            "`return` can't be used in a `task` block"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interrupts() -> Result<()> {
+        let (program, _) = grammar::Program.parse("fn main() { while true {} } fn one() { 1 }")?;
+        let module = Module::compile(&program)?;
+        let flag = AtomicBool::new(false);
+        let mut machine = Machine::new(&module, Limits::default())?;
+        machine.watch(&flag);
+        let err = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                flag.store(true, Ordering::Relaxed);
+            });
+            machine.call("main", vec![]).unwrap_err()
+        });
+        assert_eq!(
+            err.downcast_ref::<RuntimeError>(),
+            Some(&RuntimeError::Interrupted)
+        );
+        assert_eq!(machine.call("one", vec![])?, int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_memory() -> Result<()> {
+        let src = r#"
+            fn double() { s := "chant"; while true { s = s + s } }
+            fn big() { [0; 1000000000] }
+            fn small() { [0; 1000] }
+        "#;
+        let (program, _) = grammar::Program.parse(src)?;
+        let module = Module::compile(&program)?;
+        let limits = Limits::default().max_heap(Some(1 << 20));
+        let mut machine = Machine::new(&module, limits)?;
+        for f in ["double", "big"] {
+            let err = machine.call(f, vec![]).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<RuntimeError>(),
+                Some(&RuntimeError::OutOfMemory { limit: 1 << 20 })
+            );
+        }
+        // What the failed calls allocated was freed with them.
+        machine.call("small", vec![])?;
+        // Either array fits, but not both, so the first has to be freed at the end of its region.
+        let (program, _) = grammar::Program.parse(
+            "fn main() { region 'a { a := [0; 20000]; }; region 'b { b := [0; 20000]; b[0] } }",
+        )?;
+        let module = Module::compile(&program)?;
+        let limits = Limits::default().max_heap(Some(20000 * std::mem::size_of::<Value>() * 3 / 2));
+        assert_eq!(Machine::new(&module, limits)?.call("main", vec![])?, int(0));
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src: &str| format!("{:#}", eval(src).unwrap_err());
+        assert_eq!(err("fn main() { x }"), "`x` isn't defined");
+        assert_eq!(
+            err("fn main() { f(1) } fn f() {}"),
+            "in `main`: `f` takes 0 arguments, but was given 1"
+        );
+        assert_eq!(
+            err("fn main() { match 1 { 2 => 3 } }"),
+            "in `main`: no arm of the match matched"
+        );
+        assert_eq!(
+            err("fn main() { 1 + true }"),
+            "in `main`: can't apply `+` to Integer and Bool"
+        );
+    }
+}
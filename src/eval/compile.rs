@@ -0,0 +1,1143 @@
+//! Lowers the AST of a program to the instructions run by [`crate::eval::Machine`].
+//!
+//! Every function is a flat list of [`Op`]s for a stack machine. Expressions push exactly one
+//! value, and statements leave the stack as they found it. Local variables live in numbered
+//! slots of the frame of the function, and lambdas capture the values of the variables they use
+//! when they're created.
+//!
+//! The items of modules are named by their path, like `geometry.area`, and names are looked up
+//! in the module the code is in before the modules around it.
+
+use crate::ast::*;
+use crate::eval::host::HostModule;
+use crate::eval::value::{self, Value};
+use crate::num::complex::Complex;
+use crate::num::freal::FReal;
+use crate::num::integer::Integer;
+use crate::num::natural::SubPolicy;
+use crate::num::real::Real;
+use crate::num::NumType;
+use anyhow::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A compiled function.
+#[derive(PartialEq, Debug)]
+pub struct Code {
+    pub name: String,
+    pub arity: usize,
+    /// The number of slots for local variables, including the parameters in the first slots.
+    pub locals: usize,
+    pub ops: Vec<Op>,
+    /// Whether it's a `co fn`, which makes a coroutine when it's called, rather than running.
+    pub is_co: bool,
+}
+
+/// An instruction. Jumps are to the index of an op in the same function.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Op {
+    Push(Value),
+    Pop,
+    Dup,
+    /// Drops everything above the first `n` values of the frame, for leaving a loop from the
+    /// middle of an expression.
+    Truncate(usize),
+    Load(usize),
+    Store(usize),
+    LoadCapture(usize),
+    /// Loads a function or constant of the [`Module`].
+    Global(usize),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    Jump(usize),
+    /// Pops a `Bool`, and jumps if it's false.
+    JumpUnless(usize),
+    /// Calls the function below `n` arguments.
+    Call(usize),
+    /// Calls the method on the value below `n` arguments, with the value as first argument.
+    MethodCall(String, usize),
+    Return,
+    Tuple(usize),
+    Array(usize),
+    /// `[value; count]`
+    Repeat,
+    Index,
+    Field(String),
+    TupleIndex(usize),
+    /// Builds a struct from the values of `fields`, in order.
+    Struct(String, Vec<String>),
+    Variant {
+        ty: String,
+        name: String,
+        shape: Shape,
+    },
+    Range {
+        inclusive: bool,
+    },
+    Closure(Rc<Code>, Vec<Capture>),
+    /// Pops a value, and the indices of the `Index` steps of `path`, and assigns it to the place
+    /// reached by following `path` from the local `slot`, combining them with `op` if it's a
+    /// compound assignment.
+    Assign {
+        slot: usize,
+        path: Vec<Step>,
+        op: Option<BinaryOp>,
+    },
+    /// Concatenates `n` values into a string.
+    Interpolate(usize),
+    /// Pushes the next item of the array or range in the local `iter`, counting in the local
+    /// `index`, or jumps to `done` after the last one.
+    Next {
+        iter: usize,
+        index: usize,
+        done: usize,
+    },
+    /// Pushes whether the value on top of the stack matches the pattern, binding its variables
+    /// if it does.
+    Match(Rc<Pat>),
+    /// Converts the value on top of the stack to a numeric type.
+    Ascribe(NumType),
+    /// Sets the locals in the range to `()`, dropping their values, at the end of a region.
+    Clear(std::ops::Range<usize>),
+    /// Pops a value, and suspends the coroutine, which gives the value to what resumed it.
+    Yield,
+    /// Pops a coroutine, or a function without parameters, and runs it alongside the rest.
+    Spawn,
+    /// Pops a number of milliseconds, and suspends the coroutine until they've passed.
+    Sleep,
+    /// Pushes the milliseconds since the program started, by the clock of the scheduler.
+    Now,
+    /// Replaces the value on top of the stack with a weak reference to it.
+    Weak,
+    /// Pushes a tuple of the sender and the receiver of a new channel, which pops its capacity
+    /// when it's bounded.
+    Channel {
+        bounded: bool,
+    },
+    /// Pops a value and a sender, sends the value, suspending the coroutine while the channel
+    /// is full, and pushes whether the receiver was still there to take it.
+    Send,
+    /// Pushes a `GcStats` struct, of what the collector has done, and the bytes of the heap in use.
+    GcStats,
+    /// Pops a receiver, and pushes `Option::Some` of the next value it receives, suspending the
+    /// coroutine while the channel is empty, or `Option::None` once every sender is gone.
+    Recv,
+    /// Starts a `task` block, which the coroutines spawned until its `TaskJoin` are children of.
+    TaskStart,
+    /// Waits for the children of the innermost `task` block, and pushes an array of their results.
+    TaskJoin,
+    Fail(String),
+}
+
+/// The fields of a variant being constructed.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Shape {
+    Unit,
+    Tuple(usize),
+    Named(Vec<String>),
+}
+
+/// Where a lambda gets a captured value from, in the function that creates it.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Capture {
+    Local(usize),
+    Capture(usize),
+}
+
+/// A step from a variable to the place being assigned, like `.x` in `p.x = 1`.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Step {
+    Field(String),
+    TupleIndex(usize),
+    Index,
+}
+
+/// A compiled pattern, which binds variables to local slots.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Pat {
+    Wildcard,
+    Binding(usize),
+    Literal(Value),
+    Tuple(Vec<Pat>),
+    Or(Vec<Pat>),
+    Variant {
+        ty: String,
+        name: String,
+        args: Fields<Pat>,
+    },
+}
+
+/// The functions and constants of a compiled program.
+#[derive(Debug, Default)]
+pub struct Module {
+    /// Functions and constants, by name.
+    pub(crate) names: HashMap<String, usize>,
+    pub(crate) globals: Vec<Global>,
+    /// Methods from `impl` blocks, by type name and method name.
+    pub(crate) methods: HashMap<(String, String), usize>,
+    /// What subtracting a natural number from a smaller one results in, in this program.
+    pub(crate) natural_sub: SubPolicy,
+}
+
+#[derive(Debug)]
+pub(crate) enum Global {
+    Fn(Rc<Code>),
+    /// A constant, which is evaluated by calling its code when the program starts.
+    Const(Rc<Code>),
+    /// A host module.
+    Host(Value),
+}
+
+impl Module {
+    pub fn compile(program: &Program) -> Result<Module> {
+        Self::compile_with(program, vec![])
+    }
+
+    /// Compiles `program`, which can use the modules of `hosts` like constants.
+    pub fn compile_with(program: &Program, hosts: Vec<HostModule>) -> Result<Module> {
+        let mut module = Module::default();
+        for host in hosts {
+            let n = module.globals.len();
+            module.declare(host.name().to_string())?;
+            module.globals[n] = Global::Host(Value::from(host));
+        }
+        let items = flatten(&program.items, &program.spans, "", None)?;
+        let mut uses = vec![];
+        for (module_path, item, span) in &items {
+            let at = |e| Located::wrap(e, span.clone());
+            match item {
+                Item::Fn(f) => {
+                    let FnName::Ident(name) = &f.sig.name else {
+                        let error =
+                            anyhow!("only functions in an impl can be named after operators");
+                        return Err(at(error));
+                    };
+                    module.declare(qualify(module_path, name)).map_err(at)?;
+                }
+                Item::Const(c) => {
+                    module.declare(qualify(module_path, &c.name)).map_err(at)?;
+                }
+                Item::Impl(imp) => {
+                    let ty = match &imp.ty {
+                        TypeExpr::Named(name) | TypeExpr::Generic { name, .. } => name,
+                        _ => return Err(at(anyhow!("can't implement methods for this type"))),
+                    };
+                    for f in &imp.fns {
+                        let key = (ty.clone(), f.sig.name_string());
+                        if module.methods.contains_key(&key) {
+                            return Err(at(anyhow!("`{}` is defined twice for {ty}", key.1)));
+                        }
+                        module.methods.insert(key, usize::MAX);
+                    }
+                }
+                Item::Use(path) => uses.push((module_path, path, span)),
+                Item::Struct(_) | Item::Enum(_) | Item::Trait(_) | Item::Mod { .. } => {}
+            }
+        }
+        for (module_path, path, span) in uses {
+            let at = |e| Located::wrap(e, span.clone());
+            module.import(module_path, &path.segments).map_err(at)?;
+        }
+        // Every function can refer to every other, so names are declared before any bodies are
+        // compiled.
+        let mut methods = vec![];
+        for (module_path, item, span) in &items {
+            let at = |e| Located::wrap(e, span.clone());
+            match item {
+                Item::Fn(f) => {
+                    let code = Compiler::new(&module, module_path)
+                        .function(f)
+                        .map_err(at)?;
+                    let n = module.names[&qualify(module_path, &f.sig.name_string())];
+                    module.globals[n] = Global::Fn(code);
+                }
+                Item::Const(c) => {
+                    let mut compiler = Compiler::new(&module, module_path);
+                    compiler.fns.push(FnState::new(&c.name, 0));
+                    compiler.expr(&c.value).map_err(at)?;
+                    compiler.emit(Op::Return);
+                    let code = compiler.finish();
+                    let n = module.names[&qualify(module_path, &c.name)];
+                    module.globals[n] = Global::Const(code);
+                }
+                Item::Impl(imp) => {
+                    let (TypeExpr::Named(ty) | TypeExpr::Generic { name: ty, .. }) = &imp.ty else {
+                        unreachable!()
+                    };
+                    for f in &imp.fns {
+                        let code = Compiler::new(&module, module_path)
+                            .function(f)
+                            .map_err(at)?;
+                        methods.push(((ty.clone(), f.sig.name_string()), code));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (key, code) in methods {
+            let n = module.globals.len();
+            module.globals.push(Global::Fn(code));
+            module.methods.insert(key, n);
+        }
+        Ok(module)
+    }
+
+    /// Reserves a global for `name`, which is filled in once it's compiled.
+    fn declare(&mut self, name: String) -> Result<()> {
+        if self.names.contains_key(&name) {
+            bail!("`{name}` is defined twice")
+        }
+        self.names.insert(name, self.globals.len());
+        self.globals.push(Global::Fn(Rc::new(Code::empty(""))));
+        Ok(())
+    }
+
+    /// Names the item at `path` by its last segment in the module `module_path`, for `use path;`.
+    /// Using a module names each of the items in it, and in the modules in it.
+    fn import(&mut self, module_path: &str, path: &[String]) -> Result<()> {
+        let target = path.join(".");
+        let alias = qualify(module_path, path.last().unwrap());
+        let prefix = format!("{target}.");
+        let mut imported: Vec<_> = self
+            .names
+            .iter()
+            .filter_map(|(name, n)| Some((format!("{alias}.{}", name.strip_prefix(&prefix)?), *n)))
+            .collect();
+        if let Some(n) = self.names.get(&target) {
+            imported.push((alias, *n));
+        }
+        if imported.is_empty() {
+            bail!("`{target}` isn't defined")
+        }
+        for (name, n) in imported {
+            if self.names.contains_key(&name) {
+                bail!("`{name}` is defined twice")
+            }
+            self.names.insert(name, n);
+        }
+        Ok(())
+    }
+}
+
+/// An item, with the path of the module it's in and where it is.
+type Flat<'p> = (String, &'p Item, Option<Range<usize>>);
+
+/// The items of `items` and of the modules in them, with the path of the module each is in,
+/// like `geometry.shapes`, or `""` at the top. Items are where `spans` says, or where the module
+/// they're in is, `span`.
+fn flatten<'p>(
+    items: &'p [Item],
+    spans: &[Range<usize>],
+    module_path: &str,
+    span: Option<Range<usize>>,
+) -> Result<Vec<Flat<'p>>> {
+    let mut flat = vec![];
+    for (n, item) in items.iter().enumerate() {
+        let span = spans.get(n).cloned().or_else(|| span.clone());
+        if let Item::Mod { name, items } = item {
+            let Some(items) = items else {
+                let error =
+                    anyhow!("module `{name}` is in a file of its own, which hasn't been loaded");
+                return Err(Located::wrap(error, span));
+            };
+            flat.extend(flatten(
+                items,
+                &[],
+                &qualify(module_path, name),
+                span.clone(),
+            )?);
+        }
+        flat.push((module_path.to_string(), item, span));
+    }
+    Ok(flat)
+}
+
+/// The name of `name` in the module `module_path`.
+fn qualify(module_path: &str, name: &str) -> String {
+    match module_path {
+        "" => name.to_string(),
+        _ => format!("{module_path}.{name}"),
+    }
+}
+
+impl Code {
+    fn empty(name: &str) -> Self {
+        Code {
+            name: name.to_string(),
+            arity: 0,
+            locals: 0,
+            ops: vec![],
+            is_co: false,
+        }
+    }
+}
+
+impl Signature {
+    /// The name of the function, or its operator. A unary `-` is named `unary -`, so it doesn't
+    /// clash with a binary `-` in the same impl.
+    fn name_string(&self) -> String {
+        match &self.name {
+            FnName::Ident(name) => name.clone(),
+            FnName::Binary(op) => op.symbol().to_string(),
+            FnName::Unary(op) => unary_name(*op),
+        }
+    }
+}
+
+/// The name of the method that overloads the unary operator `op`.
+pub(crate) fn unary_name(op: UnaryOp) -> String {
+    format!("unary {}", op.symbol())
+}
+
+/// A loop being compiled, which `break` and `continue` jump out of.
+struct Loop {
+    /// The height of the stack in the loop body.
+    height: usize,
+    start: usize,
+    /// Jumps to patch with the end of the loop.
+    breaks: Vec<usize>,
+    /// The number of `task` blocks the loop is in.
+    tasks: usize,
+}
+
+/// A function being compiled.
+struct FnState {
+    code: Code,
+    /// Variables in scope, innermost scope last.
+    scopes: Vec<Vec<(String, usize)>>,
+    /// Variables captured from the functions this is nested in, if it's a lambda.
+    captures: Vec<(String, Capture)>,
+    /// The number of values on the stack of the frame, where the code is up to.
+    height: usize,
+    loops: Vec<Loop>,
+    /// The number of `task` blocks the code is in, which it can't jump out of.
+    tasks: usize,
+}
+
+impl FnState {
+    fn new(name: &str, arity: usize) -> Self {
+        FnState {
+            code: Code {
+                arity,
+                ..Code::empty(name)
+            },
+            scopes: vec![vec![]],
+            captures: vec![],
+            height: 0,
+            loops: vec![],
+            tasks: 0,
+        }
+    }
+}
+
+struct Compiler<'m> {
+    module: &'m Module,
+    /// The path of the module the code is in, which names are looked up in first.
+    module_path: &'m str,
+    /// The function being compiled, after the functions that contain it.
+    fns: Vec<FnState>,
+}
+
+/// Where a variable was found.
+enum Var {
+    Local(usize),
+    Capture(usize),
+}
+
+impl<'m> Compiler<'m> {
+    fn new(module: &'m Module, module_path: &'m str) -> Self {
+        Compiler {
+            module,
+            module_path,
+            fns: vec![],
+        }
+    }
+
+    fn function(mut self, f: &Function) -> Result<Rc<Code>> {
+        let name = f.sig.name_string();
+        let params: Vec<_> = f.sig.params.iter().map(|p| p.name.as_str()).collect();
+        self.fns.push(FnState::new(&name, params.len()));
+        self.state().code.is_co = f.sig.is_co;
+        for p in params {
+            self.bind(p);
+        }
+        self.block(&f.body)?;
+        self.emit(Op::Return);
+        Ok(self.finish())
+    }
+
+    fn finish(&mut self) -> Rc<Code> {
+        Rc::new(self.fns.pop().unwrap().code)
+    }
+
+    fn state(&mut self) -> &mut FnState {
+        self.fns.last_mut().unwrap()
+    }
+
+    /// Adds `op`, keeping track of the height of the stack.
+    fn emit(&mut self, op: Op) -> usize {
+        let state = self.state();
+        let effect: isize = match &op {
+            Op::Push(_)
+            | Op::Dup
+            | Op::Load(_)
+            | Op::LoadCapture(_)
+            | Op::Global(_)
+            | Op::Closure(..)
+            | Op::Next { .. }
+            | Op::Match(_)
+            | Op::Now
+            | Op::Channel { bounded: false }
+            | Op::GcStats
+            | Op::TaskJoin => 1,
+            Op::Pop | Op::Store(_) | Op::Binary(_) | Op::JumpUnless(_) => -1,
+            Op::Return | Op::Repeat | Op::Index | Op::Range { .. } | Op::Send => -1,
+            Op::Call(n) | Op::MethodCall(_, n) => -(*n as isize),
+            Op::Tuple(n) | Op::Array(n) | Op::Interpolate(n) => 1 - *n as isize,
+            Op::Struct(_, fields) => 1 - fields.len() as isize,
+            Op::Variant { shape, .. } => match shape {
+                Shape::Unit => 1,
+                Shape::Tuple(n) => 1 - *n as isize,
+                Shape::Named(fields) => 1 - fields.len() as isize,
+            },
+            Op::Assign { path, .. } => {
+                -1 - path.iter().filter(|s| **s == Step::Index).count() as isize
+            }
+            Op::Truncate(n) => *n as isize - state.height as isize,
+            Op::Unary(_)
+            | Op::Jump(_)
+            | Op::Field(_)
+            | Op::TupleIndex(_)
+            | Op::Ascribe(_)
+            | Op::Clear(_)
+            | Op::Yield
+            | Op::Spawn
+            | Op::Sleep
+            | Op::Weak
+            | Op::Channel { bounded: true }
+            | Op::Recv
+            | Op::TaskStart
+            | Op::Fail(_) => 0,
+        };
+        state.height = (state.height as isize + effect) as usize;
+        state.code.ops.push(op);
+        state.code.ops.len() - 1
+    }
+
+    /// The index of the next op.
+    fn here(&mut self) -> usize {
+        self.state().code.ops.len()
+    }
+
+    /// Points the jump at `at` to the next op.
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.state().code.ops[at] {
+            Op::Jump(t) | Op::JumpUnless(t) | Op::Next { done: t, .. } => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Declares a variable in the innermost scope, in a new slot.
+    fn bind(&mut self, name: &str) -> usize {
+        let state = self.state();
+        let slot = state.code.locals;
+        state.code.locals += 1;
+        state
+            .scopes
+            .last_mut()
+            .unwrap()
+            .push((name.to_string(), slot));
+        slot
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.state().scopes.push(vec![]);
+        let t = f(self);
+        self.state().scopes.pop();
+        t
+    }
+
+    /// Looks up a variable in the function at `depth` and the functions it's nested in,
+    /// capturing it from them if needed.
+    fn lookup(&mut self, name: &str, depth: usize) -> Option<Var> {
+        let state = &self.fns[depth];
+        let local = state.scopes.iter().rev().flat_map(|s| s.iter().rev());
+        if let Some((_, slot)) = local.clone().find(|(n, _)| n == name) {
+            return Some(Var::Local(*slot));
+        }
+        if let Some(n) = state.captures.iter().position(|(n, _)| n == name) {
+            return Some(Var::Capture(n));
+        }
+        let from = match self.lookup(name, depth.checked_sub(1)?)? {
+            Var::Local(slot) => Capture::Local(slot),
+            Var::Capture(n) => Capture::Capture(n),
+        };
+        let captures = &mut self.fns[depth].captures;
+        captures.push((name.to_string(), from));
+        Some(Var::Capture(captures.len() - 1))
+    }
+
+    /// Compiles `block`, with errors [`Located`] at the statement they're in.
+    fn block(&mut self, block: &Block) -> Result<()> {
+        let at = |n| move |e| Located::wrap(e, block.span(n));
+        self.scoped(|c| {
+            for (n, stmt) in block.stmts.iter().enumerate() {
+                c.stmt(stmt).map_err(at(n))?;
+            }
+            match &block.tail {
+                Some(tail) => c.expr(tail).map_err(at(block.stmts.len())),
+                None => {
+                    c.emit(Op::Push(Value::Unit));
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Expr(e) => {
+                self.expr(e)?;
+                self.emit(Op::Pop);
+            }
+            Stmt::Let { name, value } => {
+                self.expr(value)?;
+                let slot = self.bind(name);
+                self.emit(Op::Store(slot));
+            }
+            Stmt::Assign { place, op, value } => self.assign(place, *op, value)?,
+            Stmt::While { cond, body, .. } => {
+                let start = self.here();
+                self.expr(cond)?;
+                let exit = self.emit(Op::JumpUnless(0));
+                let height = self.state().height;
+                self.looped(start, height, |c| c.block(body))?;
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.end_loop();
+            }
+            Stmt::For {
+                binding,
+                iter,
+                body,
+                ..
+            } => self.scoped(|c| {
+                c.expr(iter)?;
+                let iter = c.bind("");
+                c.emit(Op::Store(iter));
+                let index = c.bind("");
+                c.emit(Op::Push(Value::Integer(Integer::new(0))));
+                c.emit(Op::Store(index));
+                let (start, height) = (c.here(), c.state().height);
+                let next = c.emit(Op::Next {
+                    iter,
+                    index,
+                    done: 0,
+                });
+                c.looped(start, height, |c| {
+                    c.scoped(|c| {
+                        let slot = c.bind(binding);
+                        c.emit(Op::Store(slot));
+                        c.block(body)
+                    })
+                })?;
+                c.emit(Op::Pop);
+                c.emit(Op::Jump(start));
+                c.patch(next);
+                c.end_loop();
+                Ok(())
+            })?,
+            Stmt::Break { .. } | Stmt::Continue { .. } => {
+                let (height, tasks) = (self.state().height, self.state().tasks);
+                let Some(l) = self.state().loops.last() else {
+                    bail!("`break` and `continue` can only be used in a loop")
+                };
+                if l.tasks < tasks {
+                    bail!("`break` and `continue` can't leave a `task` block")
+                }
+                let (loop_height, start) = (l.height, l.start);
+                if height > loop_height {
+                    self.emit(Op::Truncate(loop_height));
+                }
+                if matches!(stmt, Stmt::Break { .. }) {
+                    let at = self.emit(Op::Jump(0));
+                    self.state().loops.last_mut().unwrap().breaks.push(at);
+                } else {
+                    self.emit(Op::Jump(start));
+                }
+                self.state().height = height;
+            }
+            Stmt::Return(value) => {
+                if self.state().tasks > 0 {
+                    bail!("`return` can't be used in a `task` block")
+                }
+                match value {
+                    Some(e) => self.expr(e)?,
+                    None => {
+                        self.emit(Op::Push(Value::Unit));
+                    }
+                };
+                self.emit(Op::Return);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the body of a loop starting at `start`, which is left with `height` values on
+    /// the stack.
+    fn looped(
+        &mut self,
+        start: usize,
+        height: usize,
+        body: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let tasks = self.state().tasks;
+        self.state().loops.push(Loop {
+            height,
+            start,
+            breaks: vec![],
+            tasks,
+        });
+        body(self)
+    }
+
+    fn end_loop(&mut self) {
+        let l = self.state().loops.pop().unwrap();
+        for at in l.breaks {
+            self.patch(at);
+        }
+    }
+
+    fn assign(&mut self, place: &Expr, op: Option<BinaryOp>, value: &Expr) -> Result<()> {
+        let mut path = vec![];
+        let mut indices = vec![];
+        let mut e = place;
+        let root = loop {
+            match e {
+                Expr::Symbol(name) => break name,
+                Expr::Field(inner, name) => {
+                    path.push(Step::Field(name.clone()));
+                    e = inner;
+                }
+                Expr::TupleIndex(inner, n) => {
+                    path.push(Step::TupleIndex(*n));
+                    e = inner;
+                }
+                Expr::Index(inner, index) => {
+                    path.push(Step::Index);
+                    indices.push(index);
+                    e = inner;
+                }
+                _ => bail!("expected a place to assign to"),
+            }
+        };
+        path.reverse();
+        let depth = self.fns.len() - 1;
+        let slot = match self.lookup(root, depth) {
+            Some(Var::Local(slot)) => slot,
+            Some(Var::Capture(_)) => bail!("can't assign to `{root}`, which is captured"),
+            None => bail!("can't assign to `{root}`, which isn't a variable"),
+        };
+        for index in indices.into_iter().rev() {
+            self.expr(index)?;
+        }
+        self.expr(value)?;
+        self.emit(Op::Assign { slot, path, op });
+        Ok(())
+    }
+
+    fn exprs(&mut self, es: &[Expr]) -> Result<usize> {
+        for e in es {
+            self.expr(e)?;
+        }
+        Ok(es.len())
+    }
+
+    fn expr(&mut self, e: &Expr) -> Result<()> {
+        match e {
+            Expr::Integer(_)
+            | Expr::Real(_)
+            | Expr::Imaginary(_)
+            | Expr::FReal(_)
+            | Expr::String(_) => {
+                self.emit(Op::Push(literal(e)?));
+            }
+            Expr::Interpolate { parts } => {
+                for part in parts {
+                    match part {
+                        StrPart::Text(s) => {
+                            self.emit(Op::Push(Value::String(s.as_str().into())));
+                        }
+                        StrPart::Expr(e) => self.expr(e)?,
+                    }
+                }
+                self.emit(Op::Interpolate(parts.len()));
+            }
+            Expr::Symbol(name) => self.symbol(name)?,
+            Expr::Unary(op, e) => {
+                self.expr(e)?;
+                self.emit(Op::Unary(*op));
+            }
+            Expr::Binary(a, op, b) => {
+                self.expr(a)?;
+                self.expr(b)?;
+                self.emit(Op::Binary(*op));
+            }
+            Expr::Logical(a, op, b) => {
+                self.expr(a)?;
+                self.emit(Op::Dup);
+                let decided = match op {
+                    LogicalOp::And => self.emit(Op::JumpUnless(0)),
+                    LogicalOp::Or => {
+                        let rhs = self.emit(Op::JumpUnless(0));
+                        let decided = self.emit(Op::Jump(0));
+                        self.patch(rhs);
+                        decided
+                    }
+                };
+                self.emit(Op::Pop);
+                self.expr(b)?;
+                self.patch(decided);
+            }
+            Expr::Call(f, args) => {
+                if let Some(op) = self.builtin(f, args.len())? {
+                    self.exprs(args)?;
+                    self.emit(op);
+                    return Ok(());
+                }
+                self.expr(f)?;
+                let n = self.exprs(args)?;
+                self.emit(Op::Call(n));
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                if let Some(f) = self.item_in(receiver, method) {
+                    self.emit(Op::Global(f));
+                    let n = self.exprs(args)?;
+                    self.emit(Op::Call(n));
+                    return Ok(());
+                }
+                self.expr(receiver)?;
+                let n = self.exprs(args)?;
+                self.emit(Op::MethodCall(method.clone(), n));
+            }
+            Expr::Block(b) => self.block(b)?,
+            Expr::Region { body, .. } => {
+                // The variables of a region are the ones in the slots it adds, and nothing
+                // outside can refer to their values, as checked by `check::regions`.
+                let start = self.state().code.locals;
+                self.block(body)?;
+                let end = self.state().code.locals;
+                if start < end {
+                    self.emit(Op::Clear(start..end));
+                }
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.expr(cond)?;
+                let skip = self.emit(Op::JumpUnless(0));
+                self.block(then)?;
+                let end = self.emit(Op::Jump(0));
+                self.state().height -= 1;
+                self.patch(skip);
+                match otherwise {
+                    Some(e) => self.expr(e)?,
+                    None => {
+                        self.emit(Op::Push(Value::Unit));
+                    }
+                }
+                self.patch(end);
+            }
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let (Some(start), Some(end)) = (start, end) else {
+                    bail!("ranges without a start or an end can't be evaluated yet")
+                };
+                self.expr(start)?;
+                self.expr(end)?;
+                self.emit(Op::Range {
+                    inclusive: *inclusive,
+                });
+            }
+            Expr::Struct { name, fields } => {
+                for (_, e) in fields {
+                    self.expr(e)?;
+                }
+                let names = fields.iter().map(|(n, _)| n.clone()).collect();
+                self.emit(Op::Struct(name.clone(), names));
+            }
+            Expr::Variant { ty, name, args } => {
+                let shape = match args {
+                    Fields::Unit => Shape::Unit,
+                    Fields::Tuple(args) => Shape::Tuple(self.exprs(args)?),
+                    Fields::Named(fields) => {
+                        for (_, e) in fields {
+                            self.expr(e)?;
+                        }
+                        Shape::Named(fields.iter().map(|(n, _)| n.clone()).collect())
+                    }
+                };
+                self.emit(Op::Variant {
+                    ty: ty.clone(),
+                    name: name.clone(),
+                    shape,
+                });
+            }
+            Expr::Field(e, name) => {
+                if let Some(n) = self.item_in(e, name) {
+                    self.emit(Op::Global(n));
+                    return Ok(());
+                }
+                self.expr(e)?;
+                self.emit(Op::Field(name.clone()));
+            }
+            Expr::Tuple(items) => {
+                let n = self.exprs(items)?;
+                self.emit(Op::Tuple(n));
+            }
+            Expr::TupleIndex(e, n) => {
+                self.expr(e)?;
+                self.emit(Op::TupleIndex(*n));
+            }
+            Expr::Array(items) => {
+                let n = self.exprs(items)?;
+                self.emit(Op::Array(n));
+            }
+            Expr::Repeat { value, count } => {
+                self.expr(value)?;
+                self.expr(count)?;
+                self.emit(Op::Repeat);
+            }
+            Expr::Index(e, index) => {
+                self.expr(e)?;
+                self.expr(index)?;
+                self.emit(Op::Index);
+            }
+            Expr::Ascribe(e, ty) => {
+                self.expr(e)?;
+                if let TypeExpr::Named(name) = ty {
+                    if let Some(ty) = NumType::from_name(name) {
+                        self.emit(Op::Ascribe(ty));
+                    }
+                }
+            }
+            Expr::Lambda { params, body, .. } => {
+                self.fns.push(FnState::new("lambda", params.len()));
+                for p in params {
+                    self.bind(p);
+                }
+                self.expr(body)?;
+                self.emit(Op::Return);
+                let captures = self.state().captures.iter().map(|(_, c)| *c).collect();
+                let code = self.finish();
+                self.emit(Op::Closure(code, captures));
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.expr(scrutinee)?;
+                let mut ends = vec![];
+                for arm in arms {
+                    self.scoped(|c| {
+                        let mut bindings = HashMap::new();
+                        let pat = c.pattern(&arm.pattern, &mut bindings)?;
+                        c.emit(Op::Match(Rc::new(pat)));
+                        let next = c.emit(Op::JumpUnless(0));
+                        c.emit(Op::Pop);
+                        c.expr(&arm.body)?;
+                        // The next arm starts with only the scrutinee on the stack, like this
+                        // one ends with only its value.
+                        ends.push(c.emit(Op::Jump(0)));
+                        c.patch(next);
+                        Ok(())
+                    })?;
+                }
+                self.emit(Op::Fail("no arm of the match matched".to_string()));
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+            Expr::Yield(value) => {
+                if !self.state().code.is_co {
+                    bail!("`yield` can only be used in a `co fn`")
+                }
+                // A coroutine resumed by hand leaves its frames when it yields, but the children
+                // of its `task` blocks can't be left behind.
+                if self.state().tasks > 0 {
+                    bail!("`yield` can't be used in a `task` block")
+                }
+                match value {
+                    Some(e) => self.expr(e)?,
+                    None => {
+                        self.emit(Op::Push(Value::Unit));
+                    }
+                }
+                self.emit(Op::Yield);
+            }
+            Expr::Task(body) => {
+                self.emit(Op::TaskStart);
+                self.state().tasks += 1;
+                let compiled = self.block(body);
+                self.state().tasks -= 1;
+                compiled?;
+                self.emit(Op::Pop);
+                self.emit(Op::TaskJoin);
+            }
+        }
+        Ok(())
+    }
+
+    fn symbol(&mut self, name: &str) -> Result<()> {
+        let depth = self.fns.len() - 1;
+        let op = match self.lookup(name, depth) {
+            Some(Var::Local(slot)) => Op::Load(slot),
+            Some(Var::Capture(n)) => Op::LoadCapture(n),
+            None => match (self.global(name), name) {
+                (Some(n), _) => Op::Global(n),
+                (None, "true") => Op::Push(Value::Bool(true)),
+                (None, "false") => Op::Push(Value::Bool(false)),
+                (None, _) => bail!("`{name}` isn't defined"),
+            },
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    /// The op calling `f` compiles to, if `f` is one of the built-in functions `spawn`, `sleep`,
+    /// `now`, `weak`, `channel`, `send`, `recv` and `gc_stats`, and nothing else of that name is
+    /// defined.
+    fn builtin(&mut self, f: &Expr, arity: usize) -> Result<Option<Op>> {
+        let Expr::Symbol(name) = f else {
+            return Ok(None);
+        };
+        let (op, params) = match name.as_str() {
+            "spawn" => (Op::Spawn, 1),
+            "sleep" => (Op::Sleep, 1),
+            "now" => (Op::Now, 0),
+            "weak" => (Op::Weak, 1),
+            "channel" if arity == 0 => (Op::Channel { bounded: false }, 0),
+            "channel" => (Op::Channel { bounded: true }, 1),
+            "send" => (Op::Send, 2),
+            "recv" => (Op::Recv, 1),
+            "gc_stats" => (Op::GcStats, 0),
+            _ => return Ok(None),
+        };
+        let depth = self.fns.len() - 1;
+        if self.lookup(name, depth).is_some() || self.global(name).is_some() {
+            return Ok(None);
+        }
+        if arity != params {
+            bail!("`{name}` takes {params} arguments, but was given {arity}")
+        }
+        Ok(Some(op))
+    }
+
+    /// The global `path` names in the module the code is in, which is an item of that module or of
+    /// the modules it's in.
+    fn global(&self, path: &str) -> Option<usize> {
+        let mut module_path = self.module_path;
+        loop {
+            if let Some(n) = self.module.names.get(&qualify(module_path, path)) {
+                return Some(*n);
+            }
+            if module_path.is_empty() {
+                return None;
+            }
+            module_path = module_path.rsplit_once('.').map_or("", |(outer, _)| outer);
+        }
+    }
+
+    /// The global named by `e` followed by `.name`, if `e` is a path like `geometry.shapes`
+    /// rather than a variable.
+    fn item_in(&mut self, e: &Expr, name: &str) -> Option<usize> {
+        let mut segments = vec![name];
+        let mut e = e;
+        let root = loop {
+            match e {
+                Expr::Symbol(root) => break root,
+                Expr::Field(inner, name) => {
+                    segments.push(name);
+                    e = inner;
+                }
+                _ => return None,
+            }
+        };
+        let depth = self.fns.len() - 1;
+        if self.lookup(root, depth).is_some() {
+            return None;
+        }
+        segments.push(root);
+        segments.reverse();
+        self.global(&segments.join("."))
+    }
+
+    /// Compiles a pattern, giving each variable it binds a slot in `bindings`. The alternatives
+    /// of an `|` pattern share the slots of their variables.
+    fn pattern(&mut self, p: &Pattern, bindings: &mut HashMap<String, usize>) -> Result<Pat> {
+        Ok(match p {
+            Pattern::Wildcard => Pat::Wildcard,
+            Pattern::Binding(name) => {
+                let slot = match bindings.get(name) {
+                    Some(slot) => *slot,
+                    None => {
+                        let slot = self.bind(name);
+                        bindings.insert(name.clone(), slot);
+                        slot
+                    }
+                };
+                Pat::Binding(slot)
+            }
+            Pattern::Literal(e) => Pat::Literal(literal(e)?),
+            Pattern::Tuple(ps) => Pat::Tuple(
+                ps.iter()
+                    .map(|p| self.pattern(p, bindings))
+                    .collect::<Result<_>>()?,
+            ),
+            Pattern::Or(ps) => Pat::Or(
+                ps.iter()
+                    .map(|p| self.pattern(p, bindings))
+                    .collect::<Result<_>>()?,
+            ),
+            Pattern::Variant { ty, name, args } => Pat::Variant {
+                ty: ty.clone(),
+                name: name.clone(),
+                args: match args {
+                    Fields::Unit => Fields::Unit,
+                    Fields::Tuple(ps) => Fields::Tuple(
+                        ps.iter()
+                            .map(|p| self.pattern(p, bindings))
+                            .collect::<Result<_>>()?,
+                    ),
+                    Fields::Named(ps) => Fields::Named(
+                        ps.iter()
+                            .map(|(n, p)| Ok((n.clone(), self.pattern(p, bindings)?)))
+                            .collect::<Result<_>>()?,
+                    ),
+                },
+            },
+        })
+    }
+}
+
+/// The value of a literal, like `1`, `-1` or `"one"`.
+fn literal(e: &Expr) -> Result<Value> {
+    Ok(match e {
+        Expr::Integer(n) => Value::Integer(Integer::new(*n)),
+        Expr::Real(x) => Value::Real(Real::new(*x)),
+        Expr::Imaginary(x) => Value::Complex(Complex::imaginary(Real::new(*x))),
+        Expr::FReal(x) => Value::FReal(FReal::new(*x)),
+        Expr::String(s) => Value::String(s.as_str().into()),
+        Expr::Unary(op, e) => value::unary(*op, &literal(e)?)?,
+        _ => bail!("expected a literal"),
+    })
+}
@@ -0,0 +1,447 @@
+//! Conversions between Rust data and chant [`Value`]s, for passing arguments into programs and
+//! getting results out of them.
+//!
+//! Numbers convert to the chant number type closest to them: the unsigned integers to `Natural`,
+//! the signed ones to `Integer`, and floats to `Real`. Tuples convert to tuples and vectors to
+//! arrays. Chant has no maps, so maps convert to arrays of key-value tuples. Data of your own can
+//! implement the traits with the impls of its fields, and [`Value::structure`] or
+//! [`FromChant::fields`] for structs.
+
+use crate::eval::value::{Struct, Value};
+use crate::num::complex::Complex;
+use crate::num::freal::FReal;
+use crate::num::integer::Integer;
+use crate::num::natural::Natural;
+use crate::num::real::Real;
+use anyhow::*;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::rc::Rc;
+
+/// Conversion into a chant value.
+pub trait ToChant {
+    fn to_chant(&self) -> Value;
+}
+
+/// Conversion from a chant value, which fails if the value has the wrong type.
+pub trait FromChant: Sized {
+    fn from_chant(v: &Value) -> Result<Self>;
+
+    /// The fields of the struct `v`, which has to be named `name`, for implementing
+    /// [`FromChant::from_chant`] on structs.
+    fn fields<'v>(v: &'v Value, name: &str) -> Result<Fields<'v>> {
+        match v {
+            Value::Struct(s) if s.name == name => Ok(Fields(s)),
+            _ => bail!("expected {name}, found {}", v.type_name()),
+        }
+    }
+}
+
+/// The fields of a struct value, see [`FromChant::fields`].
+pub struct Fields<'v>(&'v Struct);
+
+impl Fields<'_> {
+    /// Converts the field `name`.
+    pub fn get<T: FromChant>(&self, name: &str) -> Result<T> {
+        let Some((_, v)) = self.0.fields.iter().find(|(n, _)| n == name) else {
+            bail!("{} has no field `{name}`", self.0.name)
+        };
+        T::from_chant(v).with_context(|| format!("in field `{name}` of {}", self.0.name))
+    }
+}
+
+impl Value {
+    /// A struct named `name`, with `fields`, for implementing [`ToChant::to_chant`] on structs.
+    pub fn structure(name: &str, fields: Vec<(&str, Value)>) -> Value {
+        let fields = fields.into_iter().map(|(n, v)| (n.to_string(), v));
+        Value::Struct(Rc::new(Struct {
+            name: name.to_string(),
+            fields: fields.collect(),
+        }))
+    }
+}
+
+fn expected<T>(ty: &str, v: &Value) -> Result<T> {
+    bail!("expected {ty}, found {}", v.type_name())
+}
+
+impl ToChant for Value {
+    fn to_chant(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromChant for Value {
+    fn from_chant(v: &Value) -> Result<Self> {
+        Ok(v.clone())
+    }
+}
+
+impl ToChant for () {
+    fn to_chant(&self) -> Value {
+        Value::Unit
+    }
+}
+
+impl FromChant for () {
+    fn from_chant(v: &Value) -> Result<Self> {
+        match v {
+            Value::Unit => Ok(()),
+            _ => expected("()", v),
+        }
+    }
+}
+
+impl ToChant for bool {
+    fn to_chant(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromChant for bool {
+    fn from_chant(v: &Value) -> Result<Self> {
+        v.as_bool()
+    }
+}
+
+macro_rules! natural {
+    ($($t:ty),*) => {$(
+        impl ToChant for $t {
+            fn to_chant(&self) -> Value {
+                Value::Natural(Natural::new(*self as u64))
+            }
+        }
+
+        impl FromChant for $t {
+            fn from_chant(v: &Value) -> Result<Self> {
+                let n = match v {
+                    Value::Natural(n) => n.get() as i128,
+                    Value::Integer(n) => n.get() as i128,
+                    _ => return expected("Natural", v),
+                };
+                <$t>::try_from(n).map_err(|_| anyhow!("{n} doesn't fit in {}", stringify!($t)))
+            }
+        }
+    )*};
+}
+
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl ToChant for $t {
+            fn to_chant(&self) -> Value {
+                Value::Integer(Integer::new(*self as i64))
+            }
+        }
+
+        impl FromChant for $t {
+            fn from_chant(v: &Value) -> Result<Self> {
+                let n = match v {
+                    Value::Natural(n) => n.get() as i128,
+                    Value::Integer(n) => n.get() as i128,
+                    _ => return expected("Integer", v),
+                };
+                <$t>::try_from(n).map_err(|_| anyhow!("{n} doesn't fit in {}", stringify!($t)))
+            }
+        }
+    )*};
+}
+
+natural!(u8, u16, u32, u64, usize);
+integer!(i8, i16, i32, i64, isize);
+
+impl ToChant for f64 {
+    fn to_chant(&self) -> Value {
+        Value::Real(Real::new(*self))
+    }
+}
+
+impl FromChant for f64 {
+    fn from_chant(v: &Value) -> Result<Self> {
+        match v {
+            Value::Natural(n) => Ok(n.get() as f64),
+            Value::Integer(n) => Ok(n.get() as f64),
+            Value::Real(x) => Ok(x.get()),
+            Value::FReal(x) => Ok(x.get()),
+            _ => expected("Real", v),
+        }
+    }
+}
+
+impl ToChant for f32 {
+    fn to_chant(&self) -> Value {
+        (*self as f64).to_chant()
+    }
+}
+
+impl FromChant for f32 {
+    fn from_chant(v: &Value) -> Result<Self> {
+        Ok(f64::from_chant(v)? as f32)
+    }
+}
+
+impl ToChant for Natural {
+    fn to_chant(&self) -> Value {
+        Value::Natural(*self)
+    }
+}
+
+impl FromChant for Natural {
+    fn from_chant(v: &Value) -> Result<Self> {
+        u64::from_chant(v).map(Natural::new)
+    }
+}
+
+impl ToChant for Integer {
+    fn to_chant(&self) -> Value {
+        Value::Integer(*self)
+    }
+}
+
+impl FromChant for Integer {
+    fn from_chant(v: &Value) -> Result<Self> {
+        i64::from_chant(v).map(Integer::new)
+    }
+}
+
+impl ToChant for Real {
+    fn to_chant(&self) -> Value {
+        Value::Real(*self)
+    }
+}
+
+impl FromChant for Real {
+    fn from_chant(v: &Value) -> Result<Self> {
+        f64::from_chant(v).map(Real::new)
+    }
+}
+
+impl ToChant for Complex {
+    fn to_chant(&self) -> Value {
+        Value::Complex(*self)
+    }
+}
+
+impl FromChant for Complex {
+    fn from_chant(v: &Value) -> Result<Self> {
+        match v {
+            Value::Complex(c) => Ok(*c),
+            _ => Ok(Complex::from(Real::new(f64::from_chant(v)?))),
+        }
+    }
+}
+
+impl ToChant for FReal {
+    fn to_chant(&self) -> Value {
+        Value::FReal(*self)
+    }
+}
+
+impl FromChant for FReal {
+    fn from_chant(v: &Value) -> Result<Self> {
+        f64::from_chant(v).map(FReal::new)
+    }
+}
+
+impl ToChant for str {
+    fn to_chant(&self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl ToChant for String {
+    fn to_chant(&self) -> Value {
+        self.as_str().to_chant()
+    }
+}
+
+impl FromChant for String {
+    fn from_chant(v: &Value) -> Result<Self> {
+        match v {
+            Value::String(s) => Ok(s.to_string()),
+            _ => expected("String", v),
+        }
+    }
+}
+
+impl<T: ToChant + ?Sized> ToChant for &T {
+    fn to_chant(&self) -> Value {
+        (**self).to_chant()
+    }
+}
+
+impl<T: ToChant> ToChant for [T] {
+    fn to_chant(&self) -> Value {
+        Value::Array(Rc::new(self.iter().map(ToChant::to_chant).collect()))
+    }
+}
+
+impl<T: ToChant, const N: usize> ToChant for [T; N] {
+    fn to_chant(&self) -> Value {
+        self.as_slice().to_chant()
+    }
+}
+
+impl<T: ToChant> ToChant for Vec<T> {
+    fn to_chant(&self) -> Value {
+        self.as_slice().to_chant()
+    }
+}
+
+impl<T: FromChant> FromChant for Vec<T> {
+    fn from_chant(v: &Value) -> Result<Self> {
+        let Value::Array(items) = v else {
+            return expected("Array", v);
+        };
+        let items = items.iter().enumerate();
+        items
+            .map(|(n, item)| T::from_chant(item).with_context(|| format!("in item {n}")))
+            .collect()
+    }
+}
+
+impl<K: ToChant + Ord, V: ToChant> ToChant for HashMap<K, V> {
+    /// The entries are sorted by key, like the ones of a [`BTreeMap`], so the value doesn't
+    /// depend on the order the map happens to iterate in.
+    fn to_chant(&self) -> Value {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.to_chant()
+    }
+}
+
+impl<K: FromChant + Eq + Hash, V: FromChant> FromChant for HashMap<K, V> {
+    fn from_chant(v: &Value) -> Result<Self> {
+        Ok(Vec::<(K, V)>::from_chant(v)?.into_iter().collect())
+    }
+}
+
+impl<K: ToChant, V: ToChant> ToChant for BTreeMap<K, V> {
+    fn to_chant(&self) -> Value {
+        let entries = self.iter().map(|entry| entry.to_chant());
+        Value::Array(Rc::new(entries.collect()))
+    }
+}
+
+impl<K: FromChant + Ord, V: FromChant> FromChant for BTreeMap<K, V> {
+    fn from_chant(v: &Value) -> Result<Self> {
+        Ok(Vec::<(K, V)>::from_chant(v)?.into_iter().collect())
+    }
+}
+
+macro_rules! tuple {
+    ($n:literal, $($t:ident $i:tt),*) => {
+        impl<$($t: ToChant),*> ToChant for ($($t,)*) {
+            fn to_chant(&self) -> Value {
+                Value::Tuple(Rc::new(vec![$(self.$i.to_chant()),*]))
+            }
+        }
+
+        impl<$($t: FromChant),*> FromChant for ($($t,)*) {
+            fn from_chant(v: &Value) -> Result<Self> {
+                match v {
+                    Value::Tuple(items) if items.len() == $n => Ok(($(
+                        $t::from_chant(&items[$i])
+                            .with_context(|| format!("in item {} of the tuple", $i))?,
+                    )*)),
+                    _ => bail!("expected a tuple of {} items, found {}", $n, v.type_name()),
+                }
+            }
+        }
+    };
+}
+
+tuple!(1, A 0);
+tuple!(2, A 0, B 1);
+tuple!(3, A 0, B 1, C 2);
+tuple!(4, A 0, B 1, C 2, D 3);
+tuple!(5, A 0, B 1, C 2, D 3, E 4);
+tuple!(6, A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::convert::*;
+
+    #[derive(PartialEq, Debug)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    impl ToChant for Point {
+        fn to_chant(&self) -> Value {
+            Value::structure(
+                "Point",
+                vec![("x", self.x.to_chant()), ("y", self.y.to_chant())],
+            )
+        }
+    }
+
+    impl FromChant for Point {
+        fn from_chant(v: &Value) -> Result<Self> {
+            let fields = Self::fields(v, "Point")?;
+            Ok(Point {
+                x: fields.get("x")?,
+                y: fields.get("y")?,
+            })
+        }
+    }
+
+    /// Converts `x` to a value and back.
+    fn round_trip<T: ToChant + FromChant>(x: &T) -> Result<T> {
+        T::from_chant(&x.to_chant())
+    }
+
+    #[test]
+    fn round_trips() -> Result<()> {
+        assert_eq!(
+            round_trip(&(1u8, -2i64, 0.5, true, ()))?,
+            (1, -2, 0.5, true, ())
+        );
+        assert_eq!(
+            round_trip(&vec!["a".to_string(), "b".to_string()])?,
+            ["a", "b"]
+        );
+        let map = HashMap::from([(1, vec![2.5]), (2, vec![])]);
+        assert_eq!(round_trip(&map)?, map);
+        let map: HashMap<_, _> = (0..100).map(|n| (n, -n)).collect();
+        let sorted: BTreeMap<_, _> = map.clone().into_iter().collect();
+        assert_eq!(map.to_chant(), sorted.to_chant());
+        let points = BTreeMap::from([("p".to_string(), Point { x: 1, y: -1 })]);
+        assert_eq!(round_trip(&points)?, points);
+        let numbers = (
+            Natural::new(3),
+            Integer::new(-3),
+            Real::new(0.5),
+            FReal::new(1.5),
+        );
+        assert_eq!(round_trip(&numbers)?, numbers);
+        assert_eq!(Real::from_chant(&2.to_chant())?, Real::new(2.0));
+        assert_eq!((2, "two", [1.5]).to_chant().to_string(), "(2, two, [1.5])");
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let err = |r: Result<()>| format!("{:#}", r.unwrap_err());
+        assert_eq!(
+            err(u8::from_chant(&300.to_chant()).map(drop)),
+            "300 doesn't fit in u8"
+        );
+        assert_eq!(
+            err(Natural::from_chant(&(-1).to_chant()).map(drop)),
+            "-1 doesn't fit in u64"
+        );
+        assert_eq!(
+            err(Vec::<bool>::from_chant(&vec![1].to_chant()).map(drop)),
+            "in item 0: expected a Bool, found Integer"
+        );
+        assert_eq!(
+            err(Point::from_chant(&Value::structure("Point", vec![])).map(drop)),
+            "Point has no field `x`"
+        );
+        assert_eq!(
+            err(<(i8, i8)>::from_chant(&Value::Unit).map(drop)),
+            "expected a tuple of 2 items, found ()"
+        );
+    }
+}
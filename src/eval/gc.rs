@@ -0,0 +1,280 @@
+//! A collector for the cycles that channels and coroutines can be part of.
+//!
+//! Values are reference counted, which frees everything but cycles. Channels and coroutines are
+//! the only values that are shared rather than copied, and that can be given values once they've
+//! been made: a channel the values sent on it, and a coroutine what it receives. So a cycle has
+//! to go through one of them, and they're the objects the collector tracks. The
+//! [`Machine`](super::Machine) runs it at its safepoints, at calls and at the ends of loop
+//! iterations.
+//!
+//! A collection is a mark-and-sweep over the objects it's collecting. The roots are the ones
+//! referred to from outside of the others, which are found by counting the references each of
+//! them gets from the values the others hold, and comparing that with how many it has in all.
+//! Everything reachable from the roots is marked, and the objects that aren't are emptied: the
+//! queue of a channel, and the frames of a coroutine, which frees them. A value that's held by
+//! an object and also referred to from elsewhere counts as being referred to from outside,
+//! since the collector can't tell from where, so cycles through values like that aren't
+//! collected.
+//!
+//! Objects are young until they've survived a collection. Most collections are of the young
+//! ones, which count references from the old ones as from outside, once [`YOUNG`] objects have
+//! been made since the last one. Every [`FULL`]th collection is of all of them.
+
+use crate::ast::Fields;
+use crate::eval::value::{Closure, Coroutine, Value};
+use crate::eval::CoState;
+use crate::runtime::channel::{Sender, Tracked};
+use std::collections::HashMap;
+use std::rc::{self, Rc};
+
+/// How many objects are made between collections.
+pub const YOUNG: usize = 64;
+
+/// How many collections there are to each one of all the objects.
+pub const FULL: u64 = 8;
+
+/// What the collector has done, which programs get with `gc_stats()`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Collections, including the ones of all the objects.
+    pub collections: u64,
+    /// Collections of all the objects.
+    pub full_collections: u64,
+    /// Channels and coroutines freed by collections.
+    pub freed: u64,
+}
+
+/// A channel or a coroutine, which the collector doesn't keep alive.
+enum Object {
+    Channel(Tracked<Value>),
+    Coroutine(rc::Weak<Coroutine>),
+}
+
+impl Object {
+    fn id(&self) -> usize {
+        match self {
+            Object::Channel(c) => c.id(),
+            Object::Coroutine(co) => co.as_ptr() as *const () as usize,
+        }
+    }
+
+    /// How many references there are to the object, or 0 once it's been freed.
+    fn handles(&self) -> usize {
+        match self {
+            Object::Channel(c) => c.handles(),
+            Object::Coroutine(co) => co.strong_count(),
+        }
+    }
+
+    /// Calls `f` with the ids of the objects the values the object holds refer to. If `owned`,
+    /// that's only the references they're alone in holding.
+    fn refs(&self, owned: bool, f: &mut impl FnMut(usize)) {
+        match self {
+            Object::Channel(c) => c.for_each(|v| refs(v, owned, f)),
+            Object::Coroutine(co) => {
+                let Some(co) = co.upgrade() else {
+                    return;
+                };
+                // One that's running is on the machine, so its values are outside of every object.
+                match &*co.state.borrow() {
+                    CoState::Start(closure, args) => {
+                        captured(closure, owned, f);
+                        args.iter().for_each(|v| refs(v, owned, f));
+                    }
+                    CoState::Suspended { frames, stack } => {
+                        for frame in frames {
+                            captured(&frame.closure, owned, f);
+                            frame.locals.iter().for_each(|v| refs(v, owned, f));
+                        }
+                        stack.iter().for_each(|v| refs(v, owned, f));
+                    }
+                    CoState::Running | CoState::Done => {}
+                };
+            }
+        }
+    }
+
+    /// Takes out what the object holds, the values in the queue of a channel, and the state of a
+    /// coroutine.
+    fn empty(&self, values: &mut Vec<Value>, states: &mut Vec<CoState>) {
+        match self {
+            Object::Channel(c) => values.extend(c.drain()),
+            Object::Coroutine(co) => {
+                if let Some(co) = co.upgrade() {
+                    states.push(co.state.replace(CoState::Done));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Collector {
+    young: Vec<Object>,
+    old: Vec<Object>,
+    stats: Stats,
+}
+
+impl Collector {
+    /// Tracks the channel `tx` sends on.
+    pub(crate) fn channel(&mut self, tx: &Sender<Value>) {
+        self.young.push(Object::Channel(tx.track()));
+    }
+
+    pub(crate) fn coroutine(&mut self, co: &Rc<Coroutine>) {
+        self.young.push(Object::Coroutine(Rc::downgrade(co)));
+    }
+
+    /// Collects, if enough objects have been made since the last collection.
+    pub(crate) fn safepoint(&mut self) {
+        if self.young.len() >= YOUNG {
+            self.collect(self.stats.collections % FULL == FULL - 1);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// How many of the objects being tracked haven't been freed.
+    pub(crate) fn tracked(&self) -> usize {
+        let objects = self.young.iter().chain(&self.old);
+        objects.filter(|o| o.handles() > 0).count()
+    }
+
+    /// Frees the young objects that are only reachable from each other, or all the objects like
+    /// that if `full`. The ones that are left are old.
+    pub(crate) fn collect(&mut self, full: bool) {
+        let mut objects = std::mem::take(&mut self.young);
+        if full {
+            objects.append(&mut self.old);
+        }
+        objects.retain(|o| o.handles() > 0);
+        let index: HashMap<usize, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(n, o)| (o.id(), n))
+            .collect();
+        let mut internal = vec![0; objects.len()];
+        for o in &objects {
+            o.refs(true, &mut |id| {
+                if let Some(&n) = index.get(&id) {
+                    internal[n] += 1;
+                }
+            });
+        }
+        let mut marked: Vec<bool> = objects
+            .iter()
+            .zip(&internal)
+            .map(|(o, n)| o.handles() > *n)
+            .collect();
+        let mut roots: Vec<usize> = (0..objects.len()).filter(|n| marked[*n]).collect();
+        while let Some(n) = roots.pop() {
+            objects[n].refs(false, &mut |id| {
+                if let Some(&m) = index.get(&id) {
+                    if !std::mem::replace(&mut marked[m], true) {
+                        roots.push(m);
+                    }
+                }
+            });
+        }
+        let (mut values, mut states, mut freed) = (vec![], vec![], 0);
+        for (o, marked) in objects.into_iter().zip(marked) {
+            match marked {
+                true => self.old.push(o),
+                false => {
+                    o.empty(&mut values, &mut states);
+                    freed += 1;
+                }
+            }
+        }
+        self.stats.collections += 1;
+        self.stats.full_collections += full as u64;
+        self.stats.freed += freed;
+        // Dropped once every object is empty, since dropping a sender updates its channel.
+        drop((values, states));
+    }
+}
+
+/// Calls `f` with the id of each channel and coroutine `v` refers to. If `owned`, that's only
+/// the ones `v` is alone in referring to, so that each of them is one of their handles.
+fn refs(v: &Value, owned: bool, f: &mut impl FnMut(usize)) {
+    let reached = |count: usize| !owned || count == 1;
+    match v {
+        Value::Sender(tx) => f(tx.id()),
+        Value::Receiver(rx) if reached(Rc::strong_count(rx)) => f(rx.id()),
+        Value::Coroutine(co) => f(Rc::as_ptr(co) as *const () as usize),
+        Value::Tuple(items) | Value::Array(items) if reached(Rc::strong_count(items)) => {
+            items.iter().for_each(|v| refs(v, owned, f))
+        }
+        Value::Struct(s) if reached(Rc::strong_count(s)) => {
+            s.fields.iter().for_each(|(_, v)| refs(v, owned, f))
+        }
+        Value::Variant(variant) if reached(Rc::strong_count(variant)) => match &variant.fields {
+            Fields::Unit => {}
+            Fields::Tuple(items) => items.iter().for_each(|v| refs(v, owned, f)),
+            Fields::Named(fields) => fields.iter().for_each(|(_, v)| refs(v, owned, f)),
+        },
+        Value::Fn(closure) => captured(closure, owned, f),
+        _ => {}
+    }
+}
+
+/// Like [`refs`], for what `closure` captured.
+fn captured(closure: &Rc<Closure>, owned: bool, f: &mut impl FnMut(usize)) {
+    if !owned || Rc::strong_count(closure) == 1 {
+        closure.captures.iter().for_each(|v| refs(v, owned, f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::gc::*;
+    use crate::runtime::channel;
+
+    #[test]
+    fn cycles() {
+        let mut gc = Collector::default();
+        let (tx, rx) = channel::unbounded();
+        gc.channel(&tx);
+        assert_eq!(tx.send(Value::Sender(tx.clone())), Ok(()));
+        let (kept_tx, kept_rx) = channel::unbounded();
+        gc.channel(&kept_tx);
+        assert_eq!(kept_tx.send(Value::Sender(kept_tx.clone())), Ok(()));
+        drop((tx, rx, kept_tx));
+        assert_eq!(gc.tracked(), 2);
+        gc.collect(false);
+        assert_eq!(gc.tracked(), 1);
+        assert_eq!(
+            gc.stats(),
+            Stats {
+                collections: 1,
+                full_collections: 0,
+                freed: 1,
+            }
+        );
+        // Old objects are only collected by a full collection.
+        drop(kept_rx);
+        gc.collect(false);
+        assert_eq!(gc.tracked(), 1);
+        gc.collect(true);
+        assert_eq!(gc.tracked(), 0);
+        assert_eq!(gc.stats().freed, 2);
+    }
+
+    #[test]
+    fn shared_values() {
+        let mut gc = Collector::default();
+        let (tx, rx) = channel::unbounded();
+        gc.channel(&tx);
+        let shared = Value::Tuple(Rc::new(vec![Value::Sender(tx.clone())]));
+        assert_eq!(tx.send(shared.clone()), Ok(()));
+        drop((tx, rx));
+        // The tuple might be referred to from another object, so the cycle through it is kept.
+        gc.collect(true);
+        assert_eq!(gc.tracked(), 1);
+        drop(shared);
+        gc.collect(true);
+        assert_eq!(gc.tracked(), 0);
+    }
+}
@@ -0,0 +1,84 @@
+//! Accounting for the memory that machines use.
+//!
+//! [`Counting`] is a global allocator that counts the bytes allocated and freed on a thread while
+//! a [`Machine`](super::Machine) is running on it. That's how the machine knows how much of the
+//! heap it's using, so it can fail with [`RuntimeError::OutOfMemory`](super::RuntimeError) once
+//! it's using more than [`Limits::max_heap`](super::Limits::max_heap), instead of the process
+//! running out. Hosts have to install it for that to work, with
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: heap::Counting = heap::Counting;
+//! ```
+//!
+//! Without it nothing is counted, and machines never run out.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Allocates with [`System`], counting the bytes of the machine running on the thread.
+pub struct Counting;
+
+thread_local! {
+    /// The bytes used by the machine running on this thread, if any.
+    static IN_USE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Updates the bytes in use with `f`, if a machine is running.
+fn count(f: impl FnOnce(usize) -> usize) {
+    // The thread local is gone while the thread is exiting, when nothing is running anyway.
+    let _ = IN_USE.try_with(|in_use| {
+        if let Some(n) = in_use.get() {
+            in_use.set(Some(f(n)));
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            count(|n| n + layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            count(|n| n + layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        // Memory allocated before the machine started can be freed while it's running.
+        count(|n| n.saturating_sub(layout.size()));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            count(|n| n.saturating_sub(layout.size()) + new_size);
+        }
+        new
+    }
+}
+
+/// Starts counting the bytes used on this thread from `in_use`, and returns what was being
+/// counted before, for [`leave`].
+pub(super) fn enter(in_use: usize) -> Option<usize> {
+    IN_USE.with(|n| n.replace(Some(in_use)))
+}
+
+/// Stops counting, going back to counting `outer`, and returns the bytes that were in use.
+pub(super) fn leave(outer: Option<usize>) -> usize {
+    IN_USE.with(|n| n.replace(outer)).unwrap_or(0)
+}
+
+/// The bytes used by the machine running on this thread, which are only counted if [`Counting`]
+/// is the global allocator.
+pub fn in_use() -> Option<usize> {
+    IN_USE.with(Cell::get)
+}
@@ -0,0 +1,246 @@
+//! Host modules, which make Rust functions callable from chant programs.
+//!
+//! A [`HostModule`] is a value, like `math`, whose functions are called like methods, as in
+//! `math.sqrt(2.0)`. Arguments and results are converted with [`FromChant`] and [`ToChant`], and
+//! the errors of the Rust functions are errors of the call. [`host_module!`](crate::host_module)
+//! makes a module from the methods of a value.
+
+use crate::eval::convert::{FromChant, ToChant};
+use crate::eval::value::Value;
+use anyhow::*;
+use std::fmt;
+use std::rc::Rc;
+
+/// A module of Rust functions, made with [`HostModule::function`], which programs compiled with
+/// [`Module::compile_with`](crate::eval::compile::Module::compile_with) can call.
+pub struct HostModule {
+    name: String,
+    fns: Vec<HostFn>,
+}
+
+/// A [`HostFunction`], with the types of its arguments erased.
+type Call = Box<dyn Fn(&[Value]) -> Result<Value>>;
+
+struct HostFn {
+    name: String,
+    arity: usize,
+    call: Call,
+}
+
+impl HostModule {
+    pub fn new(name: &str) -> Self {
+        HostModule {
+            name: name.to_string(),
+            fns: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds `f` as the function `name`.
+    pub fn function<Args>(mut self, name: &str, f: impl HostFunction<Args> + 'static) -> Self {
+        self.fns.push(HostFn {
+            name: name.to_string(),
+            arity: f.arity(),
+            call: Box::new(move |args| f.call(args)),
+        });
+        self
+    }
+
+    /// Calls the function `name` with `args`.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value> {
+        let Some(f) = self.fns.iter().rev().find(|f| f.name == name) else {
+            bail!("{} has no function `{name}`", self.name)
+        };
+        if args.len() != f.arity {
+            bail!(
+                "`{}.{name}` takes {} arguments, but was given {}",
+                self.name,
+                f.arity,
+                args.len()
+            )
+        }
+        (f.call)(args).with_context(|| format!("in `{}.{name}`", self.name))
+    }
+}
+
+impl PartialEq for HostModule {
+    /// Modules are only equal to themselves, since functions can't be compared.
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for HostModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.fns.iter().map(|f| &f.name).collect();
+        f.debug_struct("HostModule")
+            .field("name", &self.name)
+            .field("fns", &names)
+            .finish()
+    }
+}
+
+/// A Rust function that can be called from chant, with arguments of the types `Args`, which
+/// is implemented for closures of up to 6 arguments.
+pub trait HostFunction<Args> {
+    fn arity(&self) -> usize;
+    fn call(&self, args: &[Value]) -> Result<Value>;
+}
+
+/// The result of a Rust function called from chant, which can either be converted to a value,
+/// or be a [`Result`] of one.
+pub trait HostResult {
+    fn into_value(self) -> Result<Value>;
+}
+
+impl<T: ToChant> HostResult for T {
+    fn into_value(self) -> Result<Value> {
+        Ok(self.to_chant())
+    }
+}
+
+impl<T: ToChant> HostResult for Result<T> {
+    fn into_value(self) -> Result<Value> {
+        self.map(|v| v.to_chant())
+    }
+}
+
+macro_rules! host_function {
+    ($n:literal $(, $t:ident $i:tt)*) => {
+        impl<Func, R, $($t),*> HostFunction<($($t,)*)> for Func
+        where
+            Func: Fn($($t),*) -> R,
+            R: HostResult,
+            $($t: FromChant,)*
+        {
+            fn arity(&self) -> usize {
+                $n
+            }
+
+            #[allow(unused_variables)]
+            fn call(&self, args: &[Value]) -> Result<Value> {
+                self($(
+                    $t::from_chant(&args[$i])
+                        .with_context(|| format!("in argument {}", $i + 1))?,
+                )*)
+                .into_value()
+            }
+        }
+    };
+}
+
+host_function!(0);
+host_function!(1, A 0);
+host_function!(2, A 0, B 1);
+host_function!(3, A 0, B 1, C 2);
+host_function!(4, A 0, B 1, C 2, D 3);
+host_function!(5, A 0, B 1, C 2, D 3, E 4);
+host_function!(6, A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// A [`HostModule`] named `$name`, with the methods of `$state` listed in the braces, which take
+/// `&self` or `&mut self`. The module owns the state, which lives as long as the module does.
+///
+/// ```ignore
+/// let module = host_module!("counter", Counter::default(), {
+///     fn add(n: i64);
+///     fn total();
+/// });
+/// ```
+#[macro_export]
+macro_rules! host_module {
+    ($name:expr, $state:expr, { $(fn $f:ident($($arg:ident: $t:ty),* $(,)?);)* }) => {{
+        let state = ::std::rc::Rc::new(::std::cell::RefCell::new($state));
+        let module = $crate::eval::host::HostModule::new($name);
+        $(
+            let s = state.clone();
+            let module = module.function(stringify!($f), move |$($arg: $t),*| {
+                s.borrow_mut().$f($($arg),*)
+            });
+        )*
+        module
+    }};
+}
+
+impl From<HostModule> for Value {
+    fn from(module: HostModule) -> Self {
+        Value::Host(Rc::new(module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::compile::Module;
+    use crate::eval::host::*;
+    use crate::eval::{Limits, Machine};
+    use crate::grammar;
+    use crate::parser::Parser;
+
+    #[derive(Default)]
+    struct Counter {
+        total: i64,
+    }
+
+    impl Counter {
+        fn add(&mut self, n: i64) -> Result<i64> {
+            ensure!(n >= 0, "can't add {n}, which is negative");
+            self.total += n;
+            Ok(self.total)
+        }
+
+        fn total(&self) -> i64 {
+            self.total
+        }
+    }
+
+    fn run(src: &str, hosts: Vec<HostModule>) -> Result<Value> {
+        let (program, _) = grammar::Program.parse(src)?;
+        let module = Module::compile_with(&program, hosts)?;
+        Machine::new(&module, Limits::default())?.call("main", vec![])
+    }
+
+    #[test]
+    fn functions() -> Result<()> {
+        let math = HostModule::new("math")
+            .function("sqrt", f64::sqrt)
+            .function("max", |a: i64, b: i64| a.max(b))
+            .function("words", |s: String| {
+                s.split(' ').map(str::to_string).collect::<Vec<_>>()
+            });
+        let src = r#"fn main() { (math.sqrt(4), math.max(-1, 2), math.words("a b")) }"#;
+        assert_eq!(run(src, vec![math])?.to_string(), "(2, 2, [a, b])");
+        Ok(())
+    }
+
+    #[test]
+    fn methods() -> Result<()> {
+        let counter = || {
+            host_module!("counter", Counter::default(), {
+                fn add(n: i64);
+                fn total();
+            })
+        };
+        let src = "fn main() { counter.add(2); counter.add(3); counter.total() }";
+        assert_eq!(run(src, vec![counter()])?.to_string(), "5");
+        let err = |src: &str| format!("{:#}", run(src, vec![counter()]).unwrap_err());
+        assert_eq!(
+            err("fn main() { counter.add(-1) }"),
+            "in `main`: in `counter.add`: can't add -1, which is negative"
+        );
+        assert_eq!(
+            err(r#"fn main() { counter.add("one") }"#),
+            "in `main`: in `counter.add`: in argument 1: expected Integer, found String"
+        );
+        assert_eq!(
+            err("fn main() { counter.total(1) }"),
+            "in `main`: `counter.total` takes 0 arguments, but was given 1"
+        );
+        assert_eq!(
+            err("fn main() { counter.reset() }"),
+            "in `main`: counter has no function `reset`"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! Interrupting a running program with Ctrl-C.
+//!
+//! The signal handler only sets a flag, which a [`Machine`](super::Machine) watching it checks at
+//! safepoints: calls, and jumps back to the start of a loop. So the program stops between ops,
+//! with [`RuntimeError::Interrupted`](super::RuntimeError::Interrupted), rather than the process
+//! being killed part way through, and whatever was running the program can carry on.
+
+use anyhow::*;
+use std::sync::atomic::AtomicBool;
+
+static SIGINT: AtomicBool = AtomicBool::new(false);
+
+/// The flag set by Ctrl-C, once [`install`] has been called.
+pub fn sigint() -> &'static AtomicBool {
+    &SIGINT
+}
+
+/// Handles SIGINT by setting [`sigint`], instead of killing the process. A second Ctrl-C before
+/// the program has reached a safepoint still kills it, in case it's stuck.
+///
+/// Does nothing on platforms other than unix.
+pub fn install() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::ffi::c_int;
+        use std::sync::atomic::Ordering;
+
+        extern "C" {
+            fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+            fn _exit(status: c_int) -> !;
+        }
+
+        const SIGINT_NUMBER: c_int = 2;
+        const SIG_ERR: usize = usize::MAX;
+
+        extern "C" fn handler(signum: c_int) {
+            // Only async-signal-safe functions can be called here, which rules out most of std.
+            if SIGINT.swap(true, Ordering::SeqCst) {
+                unsafe { _exit(128 + signum) }
+            }
+        }
+
+        if unsafe { signal(SIGINT_NUMBER, handler) } == SIG_ERR {
+            bail!("couldn't install a handler for Ctrl-C")
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,647 @@
+//! Values of running chant programs, and the operators on them.
+
+use crate::ast::{BinaryOp, Fields, UnaryOp};
+use crate::eval::compile::Code;
+use crate::eval::host::HostModule;
+use crate::eval::CoState;
+use crate::num::complex::Complex;
+use crate::num::freal::FReal;
+use crate::num::integer::{Integer, Overflow};
+use crate::num::natural::{Natural, SubPolicy};
+use crate::num::real::Real;
+use crate::num::NumType;
+use crate::runtime::channel::{Receiver, Sender};
+use anyhow::*;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::{self, Rc};
+
+/// A value. Tuples, arrays, structs and variants are shared until they are modified, like in
+/// `a[0] = 1`, so values behave as if they were copied every time they're used.
+///
+/// What a value refers to lives as long as it does, except for a [`Value::Weak`], made by
+/// `weak(v)`, which observes `v` without keeping it alive: `w.upgrade()` is `Option::Some(v)`
+/// while something else holds on to `v`, and `Option::None` once it's been freed (see the
+/// [memory section] of `eval`). Modifying a shared value copies it, so a weak reference to it
+/// keeps observing the value from before, and not the copy.
+///
+/// [memory section]: mod@crate::eval#memory
+#[derive(PartialEq, Clone, Debug)]
+#[non_exhaustive]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Natural(Natural),
+    Integer(Integer),
+    Real(Real),
+    Complex(Complex),
+    FReal(FReal),
+    String(Rc<str>),
+    Tuple(Rc<Vec<Value>>),
+    Array(Rc<Vec<Value>>),
+    /// `start..end`, or `start...end` when `inclusive`.
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+    Struct(Rc<Struct>),
+    Variant(Rc<Variant>),
+    Fn(Rc<Closure>),
+    /// A module of Rust functions.
+    Host(Rc<HostModule>),
+    /// A call of a `co fn`, which runs each time it's resumed, until it yields or returns.
+    Coroutine(Rc<Coroutine>),
+    Weak(Weak),
+    /// The sending half of a channel. A channel is closed once all of them are gone.
+    Sender(Sender<Value>),
+    /// The receiving half of a channel, which is shared by the copies of it.
+    Receiver(Rc<Receiver<Value>>),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Variant {
+    pub ty: String,
+    pub name: String,
+    pub fields: Fields<Value>,
+}
+
+/// A function, with the values it captured from its surrounding scope, if it's a lambda.
+#[derive(PartialEq, Debug)]
+pub struct Closure {
+    pub(crate) code: Rc<Code>,
+    pub(crate) captures: Vec<Value>,
+}
+
+/// A coroutine, which is shared rather than copied, since it changes as it runs. It's only equal
+/// to itself.
+pub struct Coroutine {
+    pub(crate) name: String,
+    pub(crate) state: RefCell<CoState>,
+}
+
+impl Coroutine {
+    /// A coroutine that calls `closure` with `args` when it's first resumed.
+    pub(crate) fn new(closure: Rc<Closure>, args: Vec<Value>) -> Self {
+        Coroutine {
+            name: closure.code.name.clone(),
+            state: RefCell::new(CoState::Start(closure, args)),
+        }
+    }
+}
+
+impl PartialEq for Coroutine {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Coroutine")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reference to one of the values that are shared, which doesn't keep it alive. It's only
+/// equal to the references to the same value. Senders can't be referred to weakly, since every
+/// sender keeps its channel open.
+#[derive(Clone, Debug)]
+pub enum Weak {
+    String(rc::Weak<str>),
+    Tuple(rc::Weak<Vec<Value>>),
+    Array(rc::Weak<Vec<Value>>),
+    Struct(rc::Weak<Struct>),
+    Variant(rc::Weak<Variant>),
+    Fn(rc::Weak<Closure>),
+    Coroutine(rc::Weak<Coroutine>),
+    Receiver(rc::Weak<Receiver<Value>>),
+}
+
+impl Weak {
+    /// A weak reference to `v`, or an error if `v` isn't shared, like a number.
+    pub fn new(v: &Value) -> Result<Self> {
+        Ok(match v {
+            Value::String(s) => Weak::String(Rc::downgrade(s)),
+            Value::Tuple(items) => Weak::Tuple(Rc::downgrade(items)),
+            Value::Array(items) => Weak::Array(Rc::downgrade(items)),
+            Value::Struct(s) => Weak::Struct(Rc::downgrade(s)),
+            Value::Variant(v) => Weak::Variant(Rc::downgrade(v)),
+            Value::Fn(closure) => Weak::Fn(Rc::downgrade(closure)),
+            Value::Coroutine(co) => Weak::Coroutine(Rc::downgrade(co)),
+            Value::Receiver(rx) => Weak::Receiver(Rc::downgrade(rx)),
+            _ => bail!(
+                "can't make a weak reference to {}, which isn't shared",
+                v.type_name()
+            ),
+        })
+    }
+
+    /// The value, if it hasn't been freed.
+    pub fn upgrade(&self) -> Option<Value> {
+        Some(match self {
+            Weak::String(s) => Value::String(s.upgrade()?),
+            Weak::Tuple(items) => Value::Tuple(items.upgrade()?),
+            Weak::Array(items) => Value::Array(items.upgrade()?),
+            Weak::Struct(s) => Value::Struct(s.upgrade()?),
+            Weak::Variant(v) => Value::Variant(v.upgrade()?),
+            Weak::Fn(closure) => Value::Fn(closure.upgrade()?),
+            Weak::Coroutine(co) => Value::Coroutine(co.upgrade()?),
+            Weak::Receiver(rx) => Value::Receiver(rx.upgrade()?),
+        })
+    }
+}
+
+impl PartialEq for Weak {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Weak::String(a), Weak::String(b)) => a.ptr_eq(b),
+            (Weak::Tuple(a), Weak::Tuple(b)) | (Weak::Array(a), Weak::Array(b)) => a.ptr_eq(b),
+            (Weak::Struct(a), Weak::Struct(b)) => a.ptr_eq(b),
+            (Weak::Variant(a), Weak::Variant(b)) => a.ptr_eq(b),
+            (Weak::Fn(a), Weak::Fn(b)) => a.ptr_eq(b),
+            (Weak::Coroutine(a), Weak::Coroutine(b)) => a.ptr_eq(b),
+            (Weak::Receiver(a), Weak::Receiver(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
+}
+
+/// `Option::Some(v)`, or `Option::None`, as a variant.
+pub fn option(v: Option<Value>) -> Value {
+    let (name, fields) = match v {
+        Some(v) => ("Some", Fields::Tuple(vec![v])),
+        None => ("None", Fields::Unit),
+    };
+    Value::Variant(Rc::new(Variant {
+        ty: "Option".to_string(),
+        name: name.to_string(),
+        fields,
+    }))
+}
+
+impl Value {
+    /// The name of the type of the value, for error messages and method lookup.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Unit => "()",
+            Value::Bool(_) => "Bool",
+            Value::Natural(_) => "Natural",
+            Value::Integer(_) => "Integer",
+            Value::Real(_) => "Real",
+            Value::Complex(_) => "Complex",
+            Value::FReal(_) => "FReal",
+            Value::String(_) => "String",
+            Value::Tuple(_) => "Tuple",
+            Value::Array(_) => "Array",
+            Value::Range { .. } => "Range",
+            Value::Struct(s) => &s.name,
+            Value::Variant(v) => &v.ty,
+            Value::Fn(_) => "Fn",
+            Value::Host(m) => m.name(),
+            Value::Coroutine(_) => "Coroutine",
+            Value::Weak(_) => "Weak",
+            Value::Sender(_) => "Sender",
+            Value::Receiver(_) => "Receiver",
+        }
+    }
+
+    pub fn num_type(&self) -> Option<NumType> {
+        Some(match self {
+            Value::Natural(_) => NumType::Natural,
+            Value::Integer(_) => NumType::Integer,
+            Value::Real(_) => NumType::Real,
+            Value::Complex(_) => NumType::Complex,
+            Value::FReal(_) => NumType::FReal,
+            _ => return None,
+        })
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => bail!("expected a Bool, found {}", self.type_name()),
+        }
+    }
+
+    /// The value as an index, or a count like in `[x; n]`.
+    pub fn as_index(&self) -> Result<usize> {
+        match self {
+            Value::Natural(n) => {
+                usize::try_from(n.get()).map_err(|_| anyhow!("{n} is too large to be an index"))
+            }
+            Value::Integer(n) => {
+                usize::try_from(n.get()).map_err(|_| anyhow!("{n} is negative, so not an index"))
+            }
+            _ => bail!("expected an index, found {}", self.type_name()),
+        }
+    }
+
+    /// The value as the start or end of a range, which are Integers, so a Natural has to fit
+    /// in one.
+    pub fn as_bound(&self) -> Result<i64> {
+        match self {
+            Value::Integer(n) => Ok(n.get()),
+            Value::Natural(n) => Ok(Integer::try_from(*n)?.get()),
+            _ => bail!("ranges of {} can't be evaluated yet", self.type_name()),
+        }
+    }
+
+    /// Converts a number to `to`, which it has to widen to.
+    pub fn widen(&self, to: NumType) -> Result<Value> {
+        let from = self.num_type();
+        if !from.is_some_and(|from| from.widens_to(to)) {
+            bail!("expected {}, found {}", to.name(), self.type_name())
+        }
+        Ok(match (self, to) {
+            (_, NumType::Natural) | (Value::Integer(_), NumType::Integer) => self.clone(),
+            (Value::Natural(n), NumType::Integer) => Value::Integer(Integer::try_from(*n)?),
+            (_, NumType::Real) => Value::Real(self.real()),
+            (Value::Complex(c), NumType::Complex) => Value::Complex(*c),
+            (_, NumType::Complex) => Value::Complex(Complex::from(self.real())),
+            (_, NumType::FReal) => self.clone(),
+            _ => unreachable!(),
+        })
+    }
+
+    /// A number, other than a complex or fast float, as a real.
+    fn real(&self) -> Real {
+        match self {
+            Value::Natural(n) => Real::from(*n),
+            Value::Integer(n) => Real::from(*n),
+            Value::Real(x) => *x,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Calls the method `name` that `v` has built in, with `args`, or returns `None` if it has no
+/// such method. Numbers have `abs`, and complex numbers `conj`, `re`, `im` and `arg` too, which
+/// the other numbers have as if they were complex, except that `conj` of a real is itself. Weak
+/// references have `upgrade`.
+pub fn builtin_method(v: &Value, name: &str, args: &[Value]) -> Option<Result<Value>> {
+    let method = match (v, name) {
+        (Value::Natural(_), "abs") => Ok(v.clone()),
+        (Value::Integer(n), "abs") if n.get() < 0 => {
+            n.try_neg(Overflow::Checked).map(Value::Integer)
+        }
+        (Value::Integer(_), "abs") => Ok(v.clone()),
+        (Value::Real(x), "abs") => Ok(Value::Real(x.abs())),
+        (Value::FReal(x), "abs") => Ok(Value::FReal(FReal::new(x.get().abs()))),
+        (Value::Complex(c), "abs") => Ok(Value::Real(c.abs())),
+        (Value::Complex(c), "conj") => Ok(Value::Complex(c.conj())),
+        (Value::Complex(c), "re") => Ok(Value::Real(c.re())),
+        (Value::Complex(c), "im") => Ok(Value::Real(c.im())),
+        (Value::Complex(c), "arg") => Ok(Value::Real(c.arg())),
+        (Value::Weak(w), "upgrade") => Ok(option(w.upgrade())),
+        (Value::Natural(_) | Value::Integer(_) | Value::Real(_), _) => {
+            let c = Complex::from(v.real());
+            match name {
+                "conj" => Ok(v.clone()),
+                "re" => Ok(Value::Real(c.re())),
+                "im" => Ok(Value::Real(c.im())),
+                "arg" => Ok(Value::Real(c.arg())),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if !args.is_empty() {
+        return Some(Err(anyhow!(
+            "`{name}` takes 0 arguments, but was given {}",
+            args.len()
+        )));
+    }
+    Some(method)
+}
+
+pub fn unary(op: UnaryOp, v: &Value) -> Result<Value> {
+    Ok(match (op, v) {
+        (UnaryOp::Not, Value::Bool(b)) => Value::Bool(!b),
+        (UnaryOp::Neg, Value::Natural(n)) => {
+            let n = Integer::try_from(*n)?;
+            Value::Integer(n.try_neg(Overflow::Checked)?)
+        }
+        (UnaryOp::Neg, Value::Integer(n)) => Value::Integer(n.try_neg(Overflow::Checked)?),
+        (UnaryOp::Neg, Value::Real(x)) => Value::Real(-*x),
+        (UnaryOp::Neg, Value::Complex(c)) => Value::Complex(-*c),
+        (UnaryOp::Neg, Value::FReal(x)) => Value::FReal(-*x),
+        _ => bail!("can't apply `{}` to {}", op.symbol(), v.type_name()),
+    })
+}
+
+/// `a op b`, where subtracting a natural number from a smaller one is an error.
+pub fn binary(op: BinaryOp, a: &Value, b: &Value) -> Result<Value> {
+    binary_with(op, a, b, SubPolicy::Error)
+}
+
+/// `a op b`, where `sub` says what subtracting a natural number from a smaller one results in.
+pub fn binary_with(op: BinaryOp, a: &Value, b: &Value, sub: SubPolicy) -> Result<Value> {
+    use BinaryOp::*;
+    match op {
+        Eq => return Ok(Value::Bool(equal(a, b)?)),
+        Ne => return Ok(Value::Bool(!equal(a, b)?)),
+        Lt | Le | Gt | Ge => {
+            let ordering = compare(a, b)?;
+            return Ok(Value::Bool(match op {
+                Lt => ordering == Ordering::Less,
+                Le => ordering != Ordering::Greater,
+                Gt => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }));
+        }
+        _ => {}
+    }
+    let mismatch = || {
+        anyhow!(
+            "can't apply `{}` to {} and {}",
+            op.symbol(),
+            a.type_name(),
+            b.type_name()
+        )
+    };
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => match op {
+            BitAnd => return Ok(Value::Bool(x & y)),
+            BitOr => return Ok(Value::Bool(x | y)),
+            _ => return Err(mismatch()),
+        },
+        (Value::String(x), Value::String(y)) if op == Add => {
+            return Ok(Value::String(format!("{x}{y}").into()))
+        }
+        _ => {}
+    }
+    let ty = match (a.num_type(), b.num_type()) {
+        (Some(x), Some(y)) => x.join(y).ok_or_else(mismatch)?,
+        _ => return Err(mismatch()),
+    };
+    Ok(match (a.widen(ty)?, b.widen(ty)?) {
+        (Value::Natural(x), Value::Natural(y)) => Value::Natural(match op {
+            Add => x.try_add(y)?,
+            Sub => x.try_sub(y, sub)?,
+            Mul => x.try_mul(y)?,
+            Div => x.try_div(y)?,
+            Rem => x.try_rem(y)?,
+            Pow => x.try_pow(y)?,
+            _ => return Err(mismatch()),
+        }),
+        (Value::Integer(x), Value::Integer(y)) => {
+            let overflow = match op {
+                WrappingAdd | WrappingSub | WrappingMul => Overflow::Wrapping,
+                _ => Overflow::Checked,
+            };
+            Value::Integer(match op {
+                Add | WrappingAdd => x.try_add(y, overflow)?,
+                Sub | WrappingSub => x.try_sub(y, overflow)?,
+                Mul | WrappingMul => x.try_mul(y, overflow)?,
+                Div => x.try_div(y, overflow)?,
+                Rem => x.try_rem(y, overflow)?,
+                Pow => x.try_pow(Natural::try_from(y.get())?, overflow)?,
+                _ => return Err(mismatch()),
+            })
+        }
+        (Value::Real(x), Value::Real(y)) => Value::Real(match op {
+            Add => x + y,
+            Sub => x - y,
+            Mul => x * y,
+            Div => x / y,
+            Rem => x % y,
+            Pow => x.pow(y),
+            _ => return Err(mismatch()),
+        }),
+        (Value::Complex(x), Value::Complex(y)) => Value::Complex(match op {
+            Add => x + y,
+            Sub => x - y,
+            Mul => x * y,
+            Div => x / y,
+            Pow => x.pow(y),
+            _ => return Err(mismatch()),
+        }),
+        (Value::FReal(x), Value::FReal(y)) => Value::FReal(match op {
+            Add => x + y,
+            Sub => x - y,
+            Mul => x * y,
+            Div => x / y,
+            Pow => x.pow(y),
+            _ => return Err(mismatch()),
+        }),
+        _ => unreachable!(),
+    })
+}
+
+/// `a == b`, where numbers are compared after widening them to the same type.
+fn equal(a: &Value, b: &Value) -> Result<bool> {
+    match (a.num_type(), b.num_type()) {
+        (Some(x), Some(y)) => {
+            let ty = x
+                .join(y)
+                .ok_or_else(|| anyhow!("can't compare {} and {}", a.type_name(), b.type_name()))?;
+            Ok(a.widen(ty)? == b.widen(ty)?)
+        }
+        _ => Ok(a == b),
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Result<Ordering> {
+    let incomparable = || anyhow!("can't compare {} and {}", a.type_name(), b.type_name());
+    if let (Value::String(x), Value::String(y)) = (a, b) {
+        return Ok(x.cmp(y));
+    }
+    let ty = match (a.num_type(), b.num_type()) {
+        (Some(x), Some(y)) => x.join(y).ok_or_else(incomparable)?,
+        _ => return Err(incomparable()),
+    };
+    let ordering = match (a.widen(ty)?, b.widen(ty)?) {
+        (Value::Natural(x), Value::Natural(y)) => Some(x.cmp(&y)),
+        (Value::Integer(x), Value::Integer(y)) => Some(x.cmp(&y)),
+        (Value::Real(x), Value::Real(y)) => x.partial_cmp(&y),
+        (Value::FReal(x), Value::FReal(y)) => x.partial_cmp(&y),
+        _ => return Err(incomparable()),
+    };
+    ordering.ok_or_else(|| anyhow!("can't compare NaN"))
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, items: &[Value]| {
+            for (n, item) in items.iter().enumerate() {
+                if n > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{item}")?;
+            }
+            fmt::Result::Ok(())
+        };
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Natural(n) => write!(f, "{n}"),
+            Value::Integer(n) => write!(f, "{n}"),
+            Value::Real(x) => write!(f, "{x}"),
+            Value::Complex(c) => write!(f, "{c}"),
+            Value::FReal(x) => write!(f, "{x}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                list(f, items)?;
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                list(f, items)?;
+                write!(f, "]")
+            }
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(f, "{start}{}{end}", if *inclusive { "..." } else { ".." }),
+            Value::Struct(s) => {
+                write!(f, "{} {{", s.name)?;
+                for (n, (name, value)) in s.fields.iter().enumerate() {
+                    write!(f, "{} {name}: {value}", if n > 0 { "," } else { "" })?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant(v) => {
+                write!(f, "{}::{}", v.ty, v.name)?;
+                match &v.fields {
+                    Fields::Unit => fmt::Result::Ok(()),
+                    Fields::Tuple(items) => {
+                        write!(f, "(")?;
+                        list(f, items)?;
+                        write!(f, ")")
+                    }
+                    Fields::Named(fields) => {
+                        write!(f, " {{")?;
+                        for (n, (name, value)) in fields.iter().enumerate() {
+                            write!(f, "{} {name}: {value}", if n > 0 { "," } else { "" })?;
+                        }
+                        write!(f, " }}")
+                    }
+                }
+            }
+            Value::Fn(closure) => write!(f, "<fn {}>", closure.code.name),
+            Value::Host(m) => write!(f, "<module {}>", m.name()),
+            Value::Coroutine(co) => write!(f, "<coroutine {}>", co.name),
+            Value::Weak(_) => write!(f, "<weak>"),
+            Value::Sender(_) => write!(f, "<sender>"),
+            Value::Receiver(_) => write!(f, "<receiver>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::value::*;
+
+    fn int(n: i64) -> Value {
+        Value::Integer(Integer::new(n))
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        assert_eq!(binary(BinaryOp::Add, &int(1), &int(2))?, int(3));
+        assert_eq!(
+            binary(BinaryOp::Mul, &int(2), &Value::Real(Real::new(0.5)))?,
+            Value::Real(Real::new(1.))
+        );
+        assert_eq!(binary(BinaryOp::Pow, &int(2), &int(10))?, int(1024));
+        assert_eq!(
+            binary(BinaryOp::Lt, &int(1), &Value::Real(Real::new(1.5)))?,
+            Value::Bool(true)
+        );
+        assert_eq!(
+            binary(BinaryOp::Eq, &int(1), &Value::Real(Real::new(1.)))?,
+            Value::Bool(true)
+        );
+        assert!(binary(BinaryOp::Add, &int(i64::MAX), &int(1)).is_err());
+        assert_eq!(
+            binary(BinaryOp::WrappingAdd, &int(i64::MAX), &int(1))?,
+            int(i64::MIN)
+        );
+        assert!(binary(BinaryOp::WrappingMul, &int(2), &Value::Real(Real::new(1.))).is_err());
+        assert!(binary(BinaryOp::Div, &int(1), &int(0)).is_err());
+        let (two, three) = (
+            Value::Natural(Natural::new(2)),
+            Value::Natural(Natural::new(3)),
+        );
+        assert!(binary(BinaryOp::Sub, &two, &three).is_err());
+        assert_eq!(
+            binary_with(BinaryOp::Sub, &two, &three, SubPolicy::Saturate)?,
+            Value::Natural(Natural::ZERO)
+        );
+        assert!(binary(BinaryOp::Add, &Value::FReal(FReal::new(1.)), &int(1)).is_err());
+        assert_eq!(unary(UnaryOp::Neg, &int(1))?, int(-1));
+        Ok(())
+    }
+
+    #[test]
+    fn bounds() -> Result<()> {
+        let huge = Value::Natural(Natural::new(u64::MAX));
+        assert_eq!(Value::Natural(Natural::new(3)).as_bound()?, 3);
+        assert_eq!(
+            huge.as_bound().unwrap_err().to_string(),
+            "18446744073709551615 is too large for an Integer"
+        );
+        assert!(int(-1).as_index().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn builtin_methods() -> Result<()> {
+        let z = Value::Complex(Complex::new(Real::new(3.), Real::new(-4.)));
+        let method = |v: &Value, name| builtin_method(v, name, &[]).unwrap();
+        assert_eq!(method(&z, "abs")?, Value::Real(Real::new(5.)));
+        assert_eq!(method(&z, "conj")?.to_string(), "3 + 4i");
+        assert_eq!(method(&z, "im")?, Value::Real(Real::new(-4.)));
+        assert_eq!(method(&int(-2), "abs")?, int(2));
+        assert_eq!(method(&int(-2), "conj")?, int(-2));
+        assert_eq!(method(&int(2), "im")?, Value::Real(Real::new(0.)));
+        assert!(method(&int(i64::MIN), "abs").is_err());
+        assert!(builtin_method(&z, "abs", &[int(1)]).unwrap().is_err());
+        assert!(builtin_method(&z, "len", &[]).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn weak() -> Result<()> {
+        let a = Value::Array(Rc::new(vec![int(1)]));
+        let w = Value::Weak(Weak::new(&a)?);
+        let upgrade = |w: &Value| builtin_method(w, "upgrade", &[]).unwrap();
+        assert_eq!(upgrade(&w)?, option(Some(a.clone())));
+        assert_eq!(w, Value::Weak(Weak::new(&a.clone())?));
+        assert_ne!(
+            w,
+            Value::Weak(Weak::new(&Value::Array(Rc::new(vec![int(1)])))?)
+        );
+        drop(a);
+        assert_eq!(upgrade(&w)?, option(None));
+        assert_eq!(w.to_string(), "<weak>");
+        assert!(Weak::new(&int(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn display() {
+        let tuple = Value::Tuple(Rc::new(vec![int(1), Value::String("a".into())]));
+        assert_eq!(tuple.to_string(), "(1, a)");
+        assert_eq!(Value::Tuple(Rc::new(vec![int(1)])).to_string(), "(1,)");
+        let s = Struct {
+            name: "P".to_string(),
+            fields: vec![("x".to_string(), int(1)), ("y".to_string(), int(2))],
+        };
+        assert_eq!(Value::Struct(Rc::new(s)).to_string(), "P { x: 1, y: 2 }");
+    }
+}
@@ -0,0 +1,182 @@
+//! Invariants of the lexer and the parser, checked on arbitrary input by the fuzz targets in
+//! `fuzz/`, and by `cargo run --release --example fuzz` where libFuzzer isn't available.
+//!
+//! Each check panics when an invariant doesn't hold. Errors are fine, since most input isn't
+//! chant, but the lexer and parser have to return them rather than panic, and what they do
+//! return has to make sense for the input.
+//!
+//! [`round_trip`] goes the other way, from a syntax tree to source, with random trees from
+//! [`arbitrary`], and [`differential`] runs random trees on the [`Machine`], comparing what it
+//! does with what the evaluator of [`reference`](mod@reference) does. A tree they fail on is
+//! shrunk, by [`shrinking`], before it's reported.
+//!
+//! The module is hidden from the docs, since it's only for fuzzing, and can change in any
+//! release.
+
+pub mod arbitrary;
+pub mod reference;
+
+use crate::ast::pretty::Printer;
+use crate::ast::{Expr, Item, Program};
+use crate::eval::compile::Module;
+use crate::eval::{Limits, Machine};
+use crate::grammar;
+use crate::parser::{whitespace, Parser};
+use crate::tokenizer::{lex, Lexer};
+use arbitrary::Tree;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Checks that lexing `data` doesn't panic, and that the tokens and the whitespace between them
+/// tile the input, in order, with every token lexing again to itself from the compact tokens.
+pub fn tokenize(data: &[u8]) {
+    let Result::Ok(src) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut end = 0;
+    let mut tokens = vec![];
+    for spanned in Lexer::new(src) {
+        let Result::Ok(spanned) = spanned else {
+            return;
+        };
+        let span = spanned.span.clone();
+        assert!(span.start < span.end, "empty token at {span:?} of {src:?}");
+        assert!(
+            src.get(span.clone()).is_some(),
+            "{span:?} isn't within {src:?}"
+        );
+        assert_eq!(
+            end + whitespace(&src[end..span.start]),
+            span.start,
+            "{src:?} has more than whitespace between {end} and {span:?}"
+        );
+        end = span.end;
+        tokens.push(spanned);
+    }
+    assert_eq!(
+        end + whitespace(&src[end..]),
+        src.len(),
+        "{src:?} has more than whitespace after the last token"
+    );
+    let compact = lex(src).expect("lex fails where the lexer doesn't");
+    assert_eq!(compact.iter().collect::<Vec<_>>(), tokens, "of {src:?}");
+}
+
+/// Checks that parsing `data` as an expression and as a program doesn't panic, and consumes at
+/// most the whole input, ending on a character.
+pub fn parse(data: &[u8]) {
+    let Result::Ok(src) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Result::Ok((_, n)) = grammar::Expression.parse(src) {
+        assert!(src.is_char_boundary(n), "expression ends at {n} of {src:?}");
+    }
+    if let Result::Ok((_, n)) = grammar::Program.parse(src) {
+        assert_eq!(n, src.len(), "program ends at {n} of {src:?}");
+    }
+}
+
+/// Checks that `program` prints as source that parses back into `program`, and that lexing
+/// the source gives tokens that tile it.
+pub fn round_trip(program: &Program) {
+    let src = Printer::default().program(program);
+    match grammar::Program.parse(&src) {
+        Result::Ok((parsed, _)) => assert_eq!(&parsed, program, "printed as {src:?}"),
+        Err(e) => panic!("printed as {src:?}, which fails to parse: {e:#}"),
+    }
+    tokenize(src.as_bytes());
+}
+
+/// The variables [`differential`] defines, one for each name the trees of [`arbitrary`] use.
+const VARIABLES: &str = "a := 3; b := 2.5; x := true; len := [1, 2]; fetch := \"f\"; iffy := 1i;";
+
+/// Checks that the [`Machine`] evaluates `e` like [`reference::eval`] does, in a function with
+/// a variable for each name the trees of [`arbitrary`] use, if `e` is in the subset the reference
+/// handles and compiles. They either have to give the same value, or both fail.
+pub fn differential(e: &Expr) {
+    let src = format!("fn main() {{ {VARIABLES} {} }}", Printer::default().expr(e));
+    let program = match grammar::Program.parse(&src) {
+        Result::Ok((program, _)) => program,
+        Err(e) => panic!("{src:?} fails to parse: {e:#}"),
+    };
+    let Some(Item::Fn(main)) = program.items.first() else {
+        panic!("{src:?} isn't a function")
+    };
+    if !reference::supports(&main.body) {
+        return;
+    }
+    // Compiling checks what the reference doesn't, like the lifetimes of regions.
+    let Result::Ok(module) = Module::compile(&program) else {
+        return;
+    };
+    let ran = Machine::new(&module, Limits::default()).and_then(|mut m| m.call("main", vec![]));
+    match (reference::eval(&main.body), ran) {
+        // Compared by how they're debugged, so that NaN is the same as itself.
+        (Result::Ok(expected), Result::Ok(v)) => {
+            assert_eq!(format!("{v:?}"), format!("{expected:?}"), "of {src:?}")
+        }
+        (Err(_), Err(_)) => {}
+        (Result::Ok(expected), Err(e)) => panic!("{src:?} fails with {e:#}, not {expected}"),
+        (Err(e), Result::Ok(v)) => panic!("{src:?} is {v}, not an error like {e:#}"),
+    }
+}
+
+/// Runs `check` on `tree`, and if it panics, shrinks the tree with [`arbitrary::shrink`] to one
+/// that `check` still panics on, and panics with that.
+pub fn shrinking<T: Tree>(tree: &T, check: impl Fn(&T)) {
+    let fails = |t: &T| panic::catch_unwind(AssertUnwindSafe(|| check(t))).is_err();
+    if fails(tree) {
+        let smaller = arbitrary::shrink(tree, fails);
+        panic!("fails on {:?}, from {:?}", smaller.print(), tree.print())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzz::*;
+
+    #[test]
+    fn checks() {
+        for src in [
+            "fn f(x: &'a Real) { t.0.1 + 4i * 1.5f - \"{x}\" }",
+            "x := |a|-a; \"unterminated",
+            "a # b",
+            "\u{a0}é(",
+            "",
+        ] {
+            tokenize(src.as_bytes());
+            parse(src.as_bytes());
+        }
+        tokenize(&[0xff, b'a']);
+    }
+
+    #[test]
+    fn round_trips() {
+        for seed in 0..2000 {
+            shrinking(&arbitrary::Gen::new(seed, 4).program(), round_trip);
+        }
+    }
+
+    #[test]
+    fn shrinks() {
+        let src = "fn main() { a := 1; b := [len, 2] + 3; x } const N: Int = 1;";
+        let (program, _) = grammar::Program.parse(src).unwrap();
+        let smaller = arbitrary::shrink(&program, |p| p.print().contains("len"));
+        assert_eq!(smaller.print(), "fn main() {\n    b := len;\n}\n");
+    }
+
+    #[test]
+    fn differentials() {
+        for src in [
+            "if a > 2 { (b * 2, iffy.im()) } else { x }",
+            "{ a := a + 1; a -= 1; [a, a ^ 2][1] } : Real",
+            "x && \"{len[0]}\" || 1",
+            "len[2]",
+            "fetch.abs()",
+        ] {
+            differential(&grammar::Expression.parse(src).unwrap().0.unwrap());
+        }
+        for seed in 0..2000 {
+            shrinking(&arbitrary::Gen::new(seed, 4).expr(), differential);
+        }
+    }
+}
@@ -0,0 +1,621 @@
+//! Random syntax trees, for checking that printing and parsing agree on every kind of node.
+//!
+//! The trees are the ones the parser produces, so they only use names that aren't keywords,
+//! never have negative literals or lambda captures, and have every `else` followed by a block or
+//! an `if`. Anything else can be printed, and has to parse back into the same tree.
+//!
+//! Literals are mostly small, but some are large, or have as many digits as an `i64` or an `f64`
+//! can, since those are where the lexer has gone wrong before.
+//!
+//! A tree that fails a check can be made smaller with [`shrink`]. The trees aren't generated
+//! with a property testing crate like proptest, since the fuzz targets and the fuzz example have
+//! to generate the same trees from a seed without one, and chant only depends on anyhow, so
+//! shrinking is done here, on the syntax tree, by replacing nodes with their children.
+
+use crate::ast::pretty::Printer;
+use crate::ast::visit::{self, Visit, VisitMut};
+use crate::ast::*;
+use std::sync::Arc;
+
+const NAMES: &[&str] = &["a", "b", "x", "len", "fetch", "iffy"];
+const TYPES: &[&str] = &["Int", "Real", "T", "Point", "Self"];
+const LIFETIMES: &[&str] = &["a", "r"];
+const TEXT: &[&str] = &["", "a", " ", "\"", "\\", "\n", "\t", "{", "}", "é"];
+const BINARY: &[BinaryOp] = &[
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::Pow,
+    BinaryOp::Eq,
+    BinaryOp::Ne,
+    BinaryOp::Lt,
+    BinaryOp::Le,
+    BinaryOp::Gt,
+    BinaryOp::Ge,
+    BinaryOp::BitAnd,
+    BinaryOp::BitOr,
+    BinaryOp::WrappingAdd,
+    BinaryOp::WrappingSub,
+    BinaryOp::WrappingMul,
+];
+
+/// A generator of random trees, which are the same for the same seed.
+pub struct Gen {
+    /// Xorshift state, which is never 0.
+    state: u64,
+    /// How many more levels of nesting the tree can have.
+    depth: usize,
+}
+
+impl Gen {
+    /// Generates trees nested at most `depth` levels deep.
+    pub fn new(seed: u64, depth: usize) -> Self {
+        Gen {
+            state: seed | 1,
+            depth,
+        }
+    }
+
+    fn bits(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.bits() % n as u64) as usize
+    }
+
+    fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())].clone()
+    }
+
+    fn name(&mut self) -> String {
+        self.pick(NAMES).to_string()
+    }
+
+    fn type_name(&mut self) -> String {
+        self.pick(TYPES).to_string()
+    }
+
+    /// Up to `max` things made by `f`, one level deeper, or none at the deepest level.
+    fn some<T>(&mut self, max: usize, mut f: impl FnMut(&mut Self) -> T) -> Vec<T> {
+        if self.depth == 0 {
+            return vec![];
+        }
+        let n = self.below(max + 1);
+        self.nested(|g| (0..n).map(|_| f(g)).collect())
+    }
+
+    /// What `f` makes one level deeper, where the deepest level only has leaves.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let depth = self.depth;
+        self.depth = depth.saturating_sub(1);
+        let t = f(self);
+        self.depth = depth;
+        t
+    }
+
+    fn boxed(&mut self) -> Arc<Expr> {
+        Arc::new(self.expr())
+    }
+
+    pub fn program(&mut self) -> Program {
+        Program {
+            items: self.some(4, Gen::item),
+            spans: vec![],
+        }
+    }
+
+    pub fn item(&mut self) -> Item {
+        match self.below(8) {
+            0 => Item::Struct(Struct {
+                name: self.type_name(),
+                generics: self.generics(),
+                fields: self.some(3, |g| Field {
+                    name: g.name(),
+                    ty: g.ty(),
+                }),
+            }),
+            1 => Item::Enum(Enum {
+                name: self.type_name(),
+                generics: self.generics(),
+                variants: self.some(3, |g| Variant {
+                    name: g.type_name(),
+                    fields: g.fields(Gen::ty),
+                }),
+            }),
+            2 => Item::Trait(Trait {
+                name: self.type_name(),
+                generics: self.generics(),
+                // Functions in traits can't be `const` or `co`.
+                fns: self.some(2, |g| TraitFn {
+                    sig: Signature {
+                        is_const: false,
+                        is_co: false,
+                        ..g.signature()
+                    },
+                    default: g.one_in(2).then(|| g.block()),
+                }),
+            }),
+            3 => Item::Impl(Impl {
+                generics: self.generics(),
+                trait_: self.one_in(2).then(|| self.ty()),
+                ty: self.ty(),
+                fns: self.some(2, Gen::function),
+            }),
+            4 => Item::Const(Const {
+                name: self.type_name(),
+                ty: self.ty(),
+                value: self.expr(),
+            }),
+            5 => Item::Mod {
+                name: self.name(),
+                items: self.one_in(3).then(|| self.some(2, Gen::item)),
+            },
+            6 => Item::Use(Path {
+                segments: (0..self.below(3) + 1).map(|_| self.name()).collect(),
+            }),
+            _ => Item::Fn(self.function()),
+        }
+    }
+
+    fn function(&mut self) -> Function {
+        Function {
+            sig: self.signature(),
+            body: self.block(),
+        }
+    }
+
+    fn signature(&mut self) -> Signature {
+        let param = |g: &mut Self| Param {
+            name: g.name(),
+            ty: g.ty(),
+        };
+        // Operators are told apart by how many parameters they take.
+        let (name, params) = match self.below(4) {
+            0 => (
+                FnName::Binary(self.pick(BINARY)),
+                vec![param(self), param(self)],
+            ),
+            1 => (
+                FnName::Unary(self.pick(&[UnaryOp::Neg, UnaryOp::Not])),
+                vec![param(self)],
+            ),
+            _ => (FnName::Ident(self.name()), self.some(3, param)),
+        };
+        Signature {
+            name,
+            is_const: self.one_in(4),
+            is_co: self.one_in(4),
+            generics: self.generics(),
+            params,
+            ret: self.one_in(2).then(|| self.ty()),
+        }
+    }
+
+    fn generics(&mut self) -> Vec<GenericParam> {
+        if !self.one_in(3) {
+            return vec![];
+        }
+        self.some(2, |g| {
+            if g.one_in(3) {
+                GenericParam {
+                    name: g.pick(LIFETIMES).to_string(),
+                    lifetime: true,
+                    bounds: vec![],
+                }
+            } else {
+                GenericParam {
+                    name: g.type_name(),
+                    lifetime: false,
+                    bounds: g.some(2, Gen::ty),
+                }
+            }
+        })
+    }
+
+    pub fn ty(&mut self) -> TypeExpr {
+        if self.depth == 0 {
+            return TypeExpr::Named(self.type_name());
+        }
+        match self.below(6) {
+            0 => TypeExpr::Tuple(self.some(3, Gen::ty)),
+            1 => TypeExpr::Generic {
+                name: self.type_name(),
+                args: self.nested(|g| vec![g.ty()]),
+            },
+            2 => TypeExpr::Fn {
+                params: self.some(2, Gen::ty),
+                ret: self.one_in(2).then(|| Box::new(self.nested(Gen::ty))),
+            },
+            3 => TypeExpr::Ref {
+                lifetime: self.one_in(2).then(|| self.pick(LIFETIMES).to_string()),
+                mutable: self.one_in(2),
+                inner: Box::new(self.nested(Gen::ty)),
+            },
+            _ => TypeExpr::Named(self.type_name()),
+        }
+    }
+
+    fn fields<T>(&mut self, mut f: impl FnMut(&mut Self) -> T) -> Fields<T> {
+        match self.below(3) {
+            0 => Fields::Unit,
+            // Variants with no fields are written without the parentheses or braces.
+            1 => match self.some(2, &mut f) {
+                items if items.is_empty() => Fields::Unit,
+                items => Fields::Tuple(items),
+            },
+            _ => match self.some(2, |g| (g.name(), f(g))) {
+                items if items.is_empty() => Fields::Unit,
+                items => Fields::Named(items),
+            },
+        }
+    }
+
+    pub fn block(&mut self) -> Block {
+        Block {
+            stmts: self.some(3, Gen::stmt),
+            tail: self.one_in(2).then(|| self.nested(Gen::boxed)),
+            spans: vec![],
+        }
+    }
+
+    pub fn stmt(&mut self) -> Stmt {
+        match self.below(9) {
+            0 => Stmt::Let {
+                name: self.name(),
+                value: self.expr(),
+            },
+            1 => Stmt::Assign {
+                place: self.place(),
+                op: self
+                    .one_in(2)
+                    .then(|| self.pick(BINARY))
+                    .filter(|op| op.is_compound()),
+                value: self.expr(),
+            },
+            2 => Stmt::While {
+                label: None,
+                cond: self.expr(),
+                body: self.block(),
+            },
+            3 => Stmt::For {
+                label: None,
+                binding: self.name(),
+                iter: self.expr(),
+                body: self.block(),
+            },
+            4 => Stmt::Break { label: None },
+            5 => Stmt::Continue { label: None },
+            6 => Stmt::Return(self.one_in(2).then(|| self.expr())),
+            _ => Stmt::Expr(self.expr()),
+        }
+    }
+
+    /// A place to assign to, like `a.x[i]`.
+    fn place(&mut self) -> Expr {
+        let mut place = Expr::Symbol(self.name());
+        for _ in 0..self.below(3) {
+            place = match self.below(3) {
+                0 => Expr::Field(Arc::new(place), self.name()),
+                1 => Expr::TupleIndex(Arc::new(place), self.below(3)),
+                _ => Expr::Index(Arc::new(place), self.nested(Gen::boxed)),
+            };
+        }
+        place
+    }
+
+    fn literal(&mut self) -> Expr {
+        match self.below(5) {
+            0 => Expr::Real(self.real()),
+            1 => Expr::Imaginary(self.real()),
+            2 => Expr::FReal(self.real()),
+            3 => Expr::String(self.pick(TEXT).to_string()),
+            _ => Expr::Integer(self.integer()),
+        }
+    }
+
+    /// A real that isn't negative, since negative literals are negated ones.
+    fn real(&mut self) -> f64 {
+        match self.below(8) {
+            // Any bits, so all 17 significant digits, from `1e-308` to `1e308`.
+            0 => match f64::from_bits(self.bits() >> 1) {
+                x if x.is_finite() => x,
+                _ => f64::MAX,
+            },
+            // Integral, and too large for an `i64`, like `100000000000000000000.0`.
+            1 => 10f64.powi(self.below(290) as i32 + 19),
+            _ => self.below(40) as f64 / 4.0,
+        }
+    }
+
+    fn integer(&mut self) -> i64 {
+        match self.below(8) {
+            0 => (self.bits() >> 1) as i64,
+            1 => i64::MAX - self.below(10) as i64,
+            _ => self.below(1000) as i64,
+        }
+    }
+
+    pub fn expr(&mut self) -> Expr {
+        if self.depth == 0 || self.one_in(4) {
+            return match self.below(3) {
+                0 => self.literal(),
+                _ => Expr::Symbol(self.name()),
+            };
+        }
+        self.nested(Gen::compound)
+    }
+
+    fn compound(&mut self) -> Expr {
+        match self.below(24) {
+            0 => Expr::Unary(self.pick(&[UnaryOp::Neg, UnaryOp::Not]), self.boxed()),
+            1 | 2 => Expr::Binary(self.boxed(), self.pick(BINARY), self.boxed()),
+            3 => Expr::Logical(
+                self.boxed(),
+                self.pick(&[LogicalOp::And, LogicalOp::Or]),
+                self.boxed(),
+            ),
+            4 => Expr::Call(self.boxed(), self.some(2, Gen::expr)),
+            5 => Expr::Block(self.block()),
+            6 => Expr::If {
+                cond: self.boxed(),
+                then: self.block(),
+                otherwise: match self.below(3) {
+                    0 => None,
+                    1 => Some(Arc::new(Expr::Block(self.block()))),
+                    _ => Some(Arc::new(self.nested(|g| Expr::If {
+                        cond: g.boxed(),
+                        then: g.block(),
+                        otherwise: None,
+                    }))),
+                },
+            },
+            7 => {
+                let inclusive = self.one_in(3);
+                Expr::Range {
+                    start: self.one_in(2).then(|| self.boxed()),
+                    end: (inclusive || self.one_in(2)).then(|| self.boxed()),
+                    inclusive,
+                }
+            }
+            8 => Expr::Struct {
+                name: self.type_name(),
+                fields: self.some(2, |g| (g.name(), g.expr())),
+            },
+            9 => Expr::Variant {
+                ty: self.type_name(),
+                name: self.type_name(),
+                args: self.fields(Gen::expr),
+            },
+            10 => Expr::Field(self.boxed(), self.name()),
+            11 => Expr::MethodCall {
+                receiver: self.boxed(),
+                method: self.name(),
+                args: self.some(2, Gen::expr),
+            },
+            12 => Expr::Tuple(self.some(3, Gen::expr)),
+            13 => Expr::TupleIndex(self.boxed(), self.below(3)),
+            14 => Expr::Array(self.some(3, Gen::expr)),
+            15 => Expr::Repeat {
+                value: self.boxed(),
+                count: self.boxed(),
+            },
+            16 => Expr::Index(self.boxed(), self.boxed()),
+            17 => Expr::Ascribe(self.boxed(), self.ty()),
+            18 => Expr::Lambda {
+                params: self.some(2, Gen::name),
+                body: self.boxed(),
+                captures: vec![],
+            },
+            19 => Expr::Match {
+                scrutinee: self.boxed(),
+                arms: self.some(3, |g| Arm {
+                    pattern: g.pattern(),
+                    body: g.expr(),
+                }),
+            },
+            20 => Expr::Yield(self.one_in(2).then(|| self.boxed())),
+            21 => Expr::Region {
+                lifetime: self.one_in(2).then(|| self.pick(LIFETIMES).to_string()),
+                body: self.block(),
+            },
+            22 => Expr::Task(self.block()),
+            _ => self.interpolate(),
+        }
+    }
+
+    /// An interpolated string, which has at least one expression, and no empty or adjacent text.
+    fn interpolate(&mut self) -> Expr {
+        let mut parts = vec![];
+        for _ in 0..self.below(2) + 1 {
+            if self.one_in(2) {
+                parts.push(StrPart::Text(self.pick(&TEXT[1..]).to_string()));
+            }
+            parts.push(StrPart::Expr(self.expr()));
+        }
+        if self.one_in(2) {
+            parts.push(StrPart::Text(self.pick(&TEXT[1..]).to_string()));
+        }
+        Expr::Interpolate { parts }
+    }
+
+    pub fn pattern(&mut self) -> Pattern {
+        if self.depth == 0 {
+            return Pattern::Wildcard;
+        }
+        match self.below(6) {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Binding(self.name()),
+            2 => Pattern::Literal(self.literal()),
+            3 => Pattern::Tuple(self.some(3, Gen::pattern)),
+            // Alternatives of alternatives would be flattened, so they're left out.
+            4 => Pattern::Or(
+                (0..2)
+                    .map(|_| match self.nested(Gen::pattern) {
+                        Pattern::Or(_) => Pattern::Wildcard,
+                        p => p,
+                    })
+                    .collect(),
+            ),
+            _ => Pattern::Variant {
+                ty: self.type_name(),
+                name: self.type_name(),
+                args: self.fields(Gen::pattern),
+            },
+        }
+    }
+}
+
+/// A tree that [`shrink`] can make smaller.
+pub trait Tree: Clone {
+    fn walk(&mut self, v: &mut impl VisitMut);
+
+    /// The tree as source.
+    fn print(&self) -> String;
+}
+
+impl Tree for Program {
+    fn walk(&mut self, v: &mut impl VisitMut) {
+        v.visit_program_mut(self)
+    }
+
+    fn print(&self) -> String {
+        Printer::default().program(self)
+    }
+}
+
+impl Tree for Expr {
+    fn walk(&mut self, v: &mut impl VisitMut) {
+        v.visit_expr_mut(self)
+    }
+
+    fn print(&self) -> String {
+        Printer::default().expr(self)
+    }
+}
+
+/// A smaller tree than `tree` that still `fails`, which `tree` has to. Items and statements are
+/// left out and expressions are replaced with their subexpressions, one at a time, for as long
+/// as the result still fails, so no single removal or replacement of the result fails too.
+pub fn shrink<T: Tree>(tree: &T, mut fails: impl FnMut(&T) -> bool) -> T {
+    let mut tree = tree.clone();
+    'smaller: loop {
+        let mut count = Nth::new(usize::MAX, Edit::Find);
+        tree.clone().walk(&mut count);
+        let mut edits = vec![];
+        for n in 0..count.blocks {
+            let mut found = Nth::new(n, Edit::Find);
+            tree.clone().walk(&mut found);
+            edits.extend((0..found.stmts).map(|k| (n, Edit::Remove(k))));
+        }
+        for n in 0..count.exprs {
+            let mut found = Nth::new(n, Edit::Find);
+            tree.clone().walk(&mut found);
+            edits.extend(found.children.into_iter().map(|e| (n, Edit::Replace(e))));
+        }
+        for (n, edit) in edits {
+            let mut smaller = tree.clone();
+            smaller.walk(&mut Nth::new(n, edit));
+            if fails(&smaller) {
+                tree = smaller;
+                continue 'smaller;
+            }
+        }
+        return tree;
+    }
+}
+
+enum Edit {
+    /// Finds the `n`th block and expression.
+    Find,
+    /// Leaves out a statement of the `n`th block, or its tail if it's the one after the last, or
+    /// an item if the program is the `n`th block.
+    Remove(usize),
+    /// Replaces the `n`th expression.
+    Replace(Expr),
+}
+
+/// Finds or edits the `n`th block or expression of a tree, counting in the order they're
+/// visited, where a program counts as a block of items.
+struct Nth {
+    n: usize,
+    edit: Edit,
+    /// How many blocks and expressions have been visited.
+    blocks: usize,
+    exprs: usize,
+    /// How many statements, with the tail, or items the `n`th block has.
+    stmts: usize,
+    /// The expressions directly below the `n`th expression, including the ones in its blocks.
+    children: Vec<Expr>,
+}
+
+impl Nth {
+    fn new(n: usize, edit: Edit) -> Self {
+        Nth {
+            n,
+            edit,
+            blocks: 0,
+            exprs: 0,
+            stmts: 0,
+            children: vec![],
+        }
+    }
+}
+
+impl VisitMut for Nth {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        if self.blocks == self.n {
+            match self.edit {
+                Edit::Find => self.stmts = program.items.len(),
+                Edit::Remove(k) => drop(program.items.remove(k)),
+                Edit::Replace(_) => {}
+            }
+        }
+        self.blocks += 1;
+        visit::walk_program_mut(self, program)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        if self.blocks == self.n {
+            match self.edit {
+                Edit::Find => self.stmts = block.stmts.len() + block.tail.is_some() as usize,
+                Edit::Remove(k) if k < block.stmts.len() => drop(block.stmts.remove(k)),
+                Edit::Remove(_) => block.tail = None,
+                Edit::Replace(_) => {}
+            }
+        }
+        self.blocks += 1;
+        visit::walk_block_mut(self, block)
+    }
+
+    fn visit_expr_mut(&mut self, e: &mut Expr) {
+        if self.exprs == self.n {
+            match &mut self.edit {
+                Edit::Find => visit::walk_expr(&mut Children(&mut self.children), e),
+                Edit::Replace(child) => *e = std::mem::replace(child, Expr::Integer(0)),
+                Edit::Remove(_) => {}
+            }
+        }
+        self.exprs += 1;
+        visit::walk_expr_mut(self, e)
+    }
+}
+
+/// Collects the expressions it visits, without visiting theirs.
+struct Children<'a>(&'a mut Vec<Expr>);
+
+impl Visit for Children<'_> {
+    fn visit_expr(&mut self, e: &Expr) {
+        self.0.push(e.clone())
+    }
+}
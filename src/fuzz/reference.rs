@@ -0,0 +1,245 @@
+//! A tree-walking evaluator for a subset of chant, to compare the [`Machine`] against.
+//!
+//! The subset is code without functions, loops or coroutines: literals, variables, operators,
+//! blocks, `if`, tuples, arrays, ranges, ascriptions and the methods numbers have built in. It
+//! evaluates the syntax tree directly, sharing only the operators on values with the machine, so
+//! that it's a second opinion on everything the compiler does with the tree.
+//!
+//! [`Machine`]: crate::eval::Machine
+
+use crate::ast::visit::{self, Visit};
+use crate::ast::*;
+use crate::eval::value::{self, Value};
+use crate::num::complex::Complex;
+use crate::num::freal::FReal;
+use crate::num::integer::Integer;
+use crate::num::natural::SubPolicy;
+use crate::num::real::Real;
+use crate::num::NumType;
+use anyhow::*;
+use std::rc::Rc;
+
+/// Whether everything in a tree is in the subset.
+struct Subset(bool);
+
+impl Visit for Subset {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(_) | Stmt::Let { .. } => visit::walk_stmt(self, stmt),
+            Stmt::Assign {
+                place: Expr::Symbol(_),
+                value,
+                ..
+            } => self.visit_expr(value),
+            _ => self.0 = false,
+        }
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        match e {
+            // Repeats are left out, since their size isn't bounded by the size of the tree.
+            Expr::Call(..)
+            | Expr::Lambda { .. }
+            | Expr::Match { .. }
+            | Expr::Yield(_)
+            | Expr::Struct { .. }
+            | Expr::Variant { .. }
+            | Expr::Repeat { .. } => self.0 = false,
+            Expr::Range { start, end, .. } if start.is_none() || end.is_none() => self.0 = false,
+            _ => visit::walk_expr(self, e),
+        }
+    }
+}
+
+/// Whether `block` is in the subset the evaluator handles.
+pub fn supports(block: &Block) -> bool {
+    let mut subset = Subset(true);
+    subset.visit_block(block);
+    subset.0
+}
+
+/// Evaluates `block`, which has to be in the subset, like the body of a function without
+/// parameters.
+pub fn eval(block: &Block) -> Result<Value> {
+    assert!(supports(block), "the block isn't in the subset");
+    Evaluator { scopes: vec![] }.block(block)
+}
+
+struct Evaluator {
+    /// Variables in scope, innermost scope last.
+    scopes: Vec<Vec<(String, Value)>>,
+}
+
+impl Evaluator {
+    fn var(&mut self, name: &str) -> Option<&mut Value> {
+        let mut vars = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|s| s.iter_mut().rev());
+        vars.find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    fn block(&mut self, block: &Block) -> Result<Value> {
+        self.scopes.push(vec![]);
+        let value = self.block_in_scope(block);
+        self.scopes.pop();
+        value
+    }
+
+    fn block_in_scope(&mut self, block: &Block) -> Result<Value> {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Expr(e) => {
+                    self.expr(e)?;
+                }
+                Stmt::Let { name, value } => {
+                    let value = self.expr(value)?;
+                    self.scopes.last_mut().unwrap().push((name.clone(), value));
+                }
+                Stmt::Assign {
+                    place: Expr::Symbol(name),
+                    op,
+                    value,
+                } => {
+                    let value = self.expr(value)?;
+                    let Some(var) = self.var(name) else {
+                        bail!("can't assign to `{name}`, which isn't a variable")
+                    };
+                    *var = match op {
+                        Some(op) => value::binary_with(*op, var, &value, SubPolicy::Error)?,
+                        None => value,
+                    };
+                }
+                _ => unreachable!("not in the subset"),
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn exprs(&mut self, es: &[Expr]) -> Result<Vec<Value>> {
+        es.iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn expr(&mut self, e: &Expr) -> Result<Value> {
+        Ok(match e {
+            Expr::Integer(n) => Value::Integer(Integer::new(*n)),
+            Expr::Real(x) => Value::Real(Real::new(*x)),
+            Expr::Imaginary(x) => Value::Complex(Complex::imaginary(Real::new(*x))),
+            Expr::FReal(x) => Value::FReal(FReal::new(*x)),
+            Expr::String(s) => Value::String(s.as_str().into()),
+            Expr::Interpolate { parts } => {
+                let mut s = String::new();
+                for part in parts {
+                    match part {
+                        StrPart::Text(text) => s.push_str(text),
+                        StrPart::Expr(e) => s.push_str(&self.expr(e)?.to_string()),
+                    }
+                }
+                Value::String(s.into())
+            }
+            Expr::Symbol(name) => match (self.var(name), name.as_str()) {
+                (Some(v), _) => v.clone(),
+                (None, "true") => Value::Bool(true),
+                (None, "false") => Value::Bool(false),
+                (None, _) => bail!("`{name}` isn't defined"),
+            },
+            Expr::Unary(op, e) => value::unary(*op, &self.expr(e)?)?,
+            Expr::Binary(a, op, b) => {
+                let (a, b) = (self.expr(a)?, self.expr(b)?);
+                value::binary(*op, &a, &b)?
+            }
+            // The left operand decides, unless it's `true` for `&&` or `false` for `||`, and
+            // then the right one is the value, whatever it is.
+            Expr::Logical(a, op, b) => {
+                let a = self.expr(a)?;
+                match (op, a.as_bool()?) {
+                    (LogicalOp::And, false) | (LogicalOp::Or, true) => a,
+                    _ => self.expr(b)?,
+                }
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                let receiver = self.expr(receiver)?;
+                let args = self.exprs(args)?;
+                match value::builtin_method(&receiver, method, &args) {
+                    Some(v) => v?,
+                    None => bail!("{} has no method `{method}`", receiver.type_name()),
+                }
+            }
+            Expr::Block(b) | Expr::Region { body: b, .. } => self.block(b)?,
+            // Nothing can be spawned in the subset, so a task block has no children.
+            Expr::Task(b) => {
+                self.block(b)?;
+                Value::Array(Rc::new(vec![]))
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => match (self.expr(cond)?.as_bool()?, otherwise) {
+                (true, _) => self.block(then)?,
+                (false, Some(e)) => self.expr(e)?,
+                (false, None) => Value::Unit,
+            },
+            Expr::Range {
+                start: Some(start),
+                end: Some(end),
+                inclusive,
+            } => {
+                let (start, end) = (self.expr(start)?, self.expr(end)?);
+                Value::Range {
+                    start: start.as_bound()?,
+                    end: end.as_bound()?,
+                    inclusive: *inclusive,
+                }
+            }
+            // There are no structs or variants in the subset, so nothing has fields.
+            Expr::Field(e, name) => {
+                let v = self.expr(e)?;
+                bail!("{} has no field `{name}`", v.type_name())
+            }
+            Expr::Tuple(items) => Value::Tuple(Rc::new(self.exprs(items)?)),
+            Expr::TupleIndex(e, n) => match self.expr(e)? {
+                Value::Tuple(items) => match items.get(*n) {
+                    Some(item) => item.clone(),
+                    None => bail!("Tuple has no field {n}"),
+                },
+                v => bail!("{} has no field {n}", v.type_name()),
+            },
+            Expr::Array(items) => Value::Array(Rc::new(self.exprs(items)?)),
+            Expr::Index(e, index) => {
+                let (v, index) = (self.expr(e)?, self.expr(index)?);
+                let Value::Array(items) = v else {
+                    bail!("can't index into {}", v.type_name())
+                };
+                let n = index.as_index()?;
+                match items.get(n) {
+                    Some(item) => item.clone(),
+                    None => bail!("index {n} is out of bounds of an array of {}", items.len()),
+                }
+            }
+            Expr::Ascribe(e, ty) => {
+                let v = self.expr(e)?;
+                let ty = match ty {
+                    TypeExpr::Named(name) => NumType::from_name(name),
+                    _ => None,
+                };
+                match (ty, &v) {
+                    (None, _) => v,
+                    (Some(NumType::Natural), Value::Integer(n)) => {
+                        Value::Natural(n.get().try_into()?)
+                    }
+                    (Some(ty), _) => v.widen(ty)?,
+                }
+            }
+            _ => unreachable!("not in the subset"),
+        })
+    }
+}
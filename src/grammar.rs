@@ -0,0 +1,2642 @@
+//! Grammar for the chant programming language, built from the parsers in [`crate::parser`].
+//!
+//! The parsers in this module return `None` (consuming nothing) when they are not applicable to
+//! the input, and an error when the input is applicable but malformed.
+
+pub mod incremental;
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::ast::visit::{self, VisitMut};
+use crate::ast::{self, BinaryOp, Expr, Located, LogicalOp, Stmt, TypeExpr, UnaryOp};
+use crate::parser::*;
+use alloc::sync::Arc;
+use anyhow::*;
+use core::ops::Range;
+
+/// Words that can't be used as symbols.
+const KEYWORDS: &[&str] = &[
+    "if", "else", "while", "for", "in", "break", "continue", "return", "match", "fn", "struct",
+    "enum", "mod", "use", "trait", "impl", "const", "mut", "co", "yield", "region", "task",
+];
+
+/// Binding power of prefix operators. Tighter than `*`, but not as tight as `^`, so `-2^2` is
+/// `-(2^2)`.
+const UNARY_PRECEDENCE: u8 = 6;
+
+/// Consumes the separator `c`, if it is next in the input.
+fn separator(i: &str, c: char) -> Result<Option<usize>> {
+    let (t, n) = token(Separator, i)?;
+    Ok((t == Token::Separator(c)).then_some(n))
+}
+
+/// Consumes the operator `op`, if it is next in the input.
+fn operator(i: &str, op: &str) -> Result<Option<usize>> {
+    let (t, n) = token(Operator, i)?;
+    Ok(matches!(t, Token::Operator(t) if t == op).then_some(n))
+}
+
+/// Consumes the keyword `kw`, if it is next in the input.
+fn keyword(i: &str, kw: &str) -> Result<Option<usize>> {
+    let (t, n) = token(Symbol, i)?;
+    Ok(matches!(t, Token::Symbol(t) if t == kw).then_some(n))
+}
+
+/// Consumes a lifetime, like `'a`, if it is next in the input.
+fn lifetime(i: &str) -> Result<Option<(String, usize)>> {
+    match token(Lifetime, i)? {
+        (Token::Lifetime(name), n) => Ok(Some((name, n))),
+        _ => Ok(None),
+    }
+}
+
+/// A short excerpt of the input, for error messages.
+fn near(i: &str) -> &str {
+    let i = i.trim_start();
+    &i[..i.char_indices().nth(16).map_or(i.len(), |(n, _)| n)]
+}
+
+/// The span of what's [`near`] the start of the input `i`. Parsers only see the rest of the
+/// source, so it counts back from the end of it, until [`Program`] turns it into offsets from
+/// the start.
+fn at(i: &str) -> Range<usize> {
+    let start = i.trim_start().len();
+    start..start - near(i).len()
+}
+
+/// A syntax error saying `message`, near the start of the input `i`.
+fn syntax_error(i: &str, message: String) -> Error {
+    Located::wrap(anyhow!("{message} near {:?}", near(i)), Some(at(i)))
+}
+
+/// Lexes a token after whitespace with `p`, with errors, like a literal that doesn't end, at
+/// the token.
+fn token<P: Parser>(p: P, i: &str) -> Result<(P::Token, usize)> {
+    p.after_whitespace()
+        .parse(i)
+        .map_err(|e| Located::wrap(e, Some(at(i))))
+}
+
+/// Like `bail!`, for a syntax error at the input `$i`.
+macro_rules! fail {
+    ($i:expr, $($message:tt)*) => {
+        return Err(syntax_error($i, format!($($message)*)))
+    };
+}
+
+/// Turns a result of `None` into an error, saying that `what` was expected at `i`.
+fn expect<T>((t, n): (Option<T>, usize), what: &str, i: &str) -> Result<(T, usize)> {
+    match t {
+        Some(t) => Ok((t, n)),
+        None => fail!(i, "expected {what}"),
+    }
+}
+
+/// Parses comma separated items, up to and including the `close` separator.
+fn list<T>(
+    i: &str,
+    item: impl Parser<Token = Option<T>>,
+    close: char,
+    what: &str,
+) -> Result<(Vec<T>, usize)> {
+    let mut items = vec![];
+    let mut rem = 0;
+    loop {
+        if let Some(n) = separator(&i[rem..], close)? {
+            return Ok((items, rem + n));
+        }
+        let (t, n) = expect(item.parse(&i[rem..])?, what, &i[rem..])?;
+        items.push(t);
+        rem += n;
+        if let Some(n) = separator(&i[rem..], ',')? {
+            rem += n;
+        } else if let Some(n) = separator(&i[rem..], close)? {
+            return Ok((items, rem + n));
+        } else {
+            fail!(&i[rem..], "expected `,` or `{close}`")
+        }
+    }
+}
+
+/// Parser for a symbol that isn't a keyword.
+pub struct Ident;
+
+impl Parser for Ident {
+    type Token = Option<String>;
+
+    fn parse(&self, i: &str) -> Result<(Option<String>, usize)> {
+        match token(Symbol, i)? {
+            (Token::Symbol(s), n) if !KEYWORDS.contains(&s.as_str()) => Ok((Some(s), n)),
+            _ => Ok((None, 0)),
+        }
+    }
+}
+
+/// Parser for any expression.
+pub struct Expression;
+
+impl Parser for Expression {
+    type Token = Option<Expr>;
+
+    fn parse(&self, i: &str) -> Result<(Option<Expr>, usize)> {
+        expression(i, true)
+    }
+}
+
+/// Parser for an expression followed by a block, like the condition of an `if`. Struct literals
+/// are only allowed inside parentheses here, so that `if x { .. }` isn't parsed as `if (x { .. })`.
+pub struct Condition;
+
+impl Parser for Condition {
+    type Token = Option<Expr>;
+
+    fn parse(&self, i: &str) -> Result<(Option<Expr>, usize)> {
+        expression(i, false)
+    }
+}
+
+/// An expression, where `structs` says whether struct literals are allowed.
+fn expression(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    if let Some(n) = keyword(i, "yield")? {
+        let (value, m) = expression(&i[n..], structs)?;
+        return Ok((Some(Expr::Yield(value.map(Arc::new))), n + m));
+    }
+    let (Some(e), rem) = pipeline(i, structs)? else {
+        return Ok((None, 0));
+    };
+    let Some(n) = operator(&i[rem..], ":")? else {
+        return Ok((Some(e), rem));
+    };
+    let (ty, m) = expect(Type.parse(&i[rem + n..])?, "type after `:`", &i[rem + n..])?;
+    Ok((Some(Expr::Ascribe(Arc::new(e), ty)), rem + n + m))
+}
+
+/// `data |> f |> g`, which is sugar for `g(f(data))`. It binds looser than any other binary
+/// operator, so `0..n |> sum` is `sum(0..n)`.
+fn pipeline(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let (Some(mut e), mut rem) = range(i, structs)? else {
+        return Ok((None, 0));
+    };
+    while let Some(n) = operator(&i[rem..], "|>")? {
+        rem += n;
+        let (f, n) = expect(range(&i[rem..], structs)?, "function after `|>`", &i[rem..])?;
+        e = Expr::Call(Arc::new(f), vec![e]);
+        rem += n;
+    }
+    Ok((Some(e), rem))
+}
+
+fn range(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let (start, mut rem) = logical(i, LogicalOp::Or, structs)?;
+    let Some((inclusive, n)) = range_op(&i[rem..])? else {
+        return Ok((start, rem));
+    };
+    rem += n;
+    let (end, n) = logical(&i[rem..], LogicalOp::Or, structs)?;
+    if inclusive && end.is_none() {
+        fail!(&i[rem..], "expected end of inclusive range")
+    }
+    let range = Expr::Range {
+        start: start.map(Arc::new),
+        end: end.map(Arc::new),
+        inclusive,
+    };
+    Ok((Some(range), rem + n))
+}
+
+/// Consumes `..` or `...`, returning whether the range is inclusive.
+fn range_op(i: &str) -> Result<Option<(bool, usize)>> {
+    let dot = Token::Separator('.');
+    let ((a, b), n) = Separator.after_whitespace().then(Separator).parse(i)?;
+    if a != dot || b != dot {
+        return Ok(None);
+    }
+    Ok(Some(match Separator.parse(&i[n..])? {
+        (c, m) if c == dot => (true, n + m),
+        _ => (false, n),
+    }))
+}
+
+/// `a || b`, or `a && b` when `op` is `And`, with operands binding tighter than `op`.
+fn logical(i: &str, op: LogicalOp, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let (symbol, operand): (_, fn(&str, bool) -> Result<_>) = match op {
+        LogicalOp::Or => ("||", |i, structs| logical(i, LogicalOp::And, structs)),
+        LogicalOp::And => ("&&", |i, structs| binary(i, 0, structs)),
+    };
+    let (Some(mut lhs), mut rem) = operand(i, structs)? else {
+        return Ok((None, 0));
+    };
+    while let Some(n) = operator(&i[rem..], symbol)? {
+        rem += n;
+        let (rhs, n) = expect(operand(&i[rem..], structs)?, "expression", &i[rem..])?;
+        lhs = Expr::Logical(Arc::new(lhs), op, Arc::new(rhs));
+        rem += n;
+    }
+    Ok((Some(lhs), rem))
+}
+
+/// Precedence climbing over binary operators that bind at least as tight as `min`.
+fn binary(i: &str, min: u8, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let (Some(mut lhs), mut rem) = unary(i, structs)? else {
+        return Ok((None, 0));
+    };
+    loop {
+        let (Token::Operator(op), n) = token(Operator, &i[rem..])? else {
+            break;
+        };
+        let Some(op) = BinaryOp::from_symbol(&op) else {
+            break;
+        };
+        if op.precedence() < min {
+            break;
+        }
+        let next = if op.is_right_assoc() {
+            op.precedence()
+        } else {
+            op.precedence() + 1
+        };
+        let i_rhs = &i[rem + n..];
+        let (rhs, m) = expect(binary(i_rhs, next, structs)?, "expression", i_rhs)?;
+        lhs = Expr::Binary(Arc::new(lhs), op, Arc::new(rhs));
+        rem += n + m;
+    }
+    Ok((Some(lhs), rem))
+}
+
+fn unary(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let op = if let Some(n) = operator(i, "-")? {
+        (UnaryOp::Neg, n)
+    } else if let Some(n) = operator(i, "!")? {
+        (UnaryOp::Not, n)
+    } else {
+        return postfix(i, structs);
+    };
+    let (e, m) = expect(
+        binary(&i[op.1..], UNARY_PRECEDENCE + 1, structs)?,
+        "expression",
+        &i[op.1..],
+    )?;
+    Ok((Some(Expr::Unary(op.0, Arc::new(e))), op.1 + m))
+}
+
+fn postfix(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    let (Some(mut e), mut rem) = primary(i, structs)? else {
+        return Ok((None, 0));
+    };
+    // `if c { .. } (x)` is two statements, not a call, but `(if c { .. })(x)` is a call.
+    if e.is_block_like() && separator(i, '(')?.is_none() {
+        return Ok((Some(e), rem));
+    }
+    loop {
+        if let Some(n) = separator(&i[rem..], '(')? {
+            let (args, m) = list(&i[rem + n..], Expression, ')', "argument")?;
+            e = Expr::Call(Arc::new(e), args);
+            rem += n + m;
+        } else if let Some(n) = separator(&i[rem..], '[')? {
+            let (index, m) = expect(Expression.parse(&i[rem + n..])?, "index", &i[rem + n..])?;
+            let Some(k) = separator(&i[rem + n + m..], ']')? else {
+                fail!(&i[rem + n + m..], "expected `]`")
+            };
+            e = Expr::Index(Arc::new(e), Arc::new(index));
+            rem += n + m + k;
+        } else if range_op(&i[rem..])?.is_some() {
+            break;
+        } else if let Some(n) = separator(&i[rem..], '.')? {
+            if let (Token::Integer(index), m) = token(NaturalNumber, &i[rem + n..])? {
+                e = Expr::TupleIndex(Arc::new(e), index as usize);
+                rem += n + m;
+                continue;
+            }
+            let (field, m) = expect(
+                Ident.parse(&i[rem + n..])?,
+                "field or method name after `.`",
+                &i[rem + n..],
+            )?;
+            rem += n + m;
+            if let Some(n) = separator(&i[rem..], '(')? {
+                let (args, m) = list(&i[rem + n..], Expression, ')', "argument")?;
+                e = Expr::MethodCall {
+                    receiver: Arc::new(e),
+                    method: field,
+                    args,
+                };
+                rem += n + m;
+            } else {
+                e = Expr::Field(Arc::new(e), field);
+            }
+        } else {
+            break;
+        }
+    }
+    Ok((Some(e), rem))
+}
+
+fn primary(i: &str, structs: bool) -> Result<(Option<Expr>, usize)> {
+    if let Some(n) = keyword(i, "if")? {
+        let (e, m) = if_else(&i[n..])?;
+        return Ok((Some(e), n + m));
+    }
+    if let Some(n) = keyword(i, "match")? {
+        let (e, m) = match_arms(&i[n..])?;
+        return Ok((Some(e), n + m));
+    }
+    if let Some(mut rem) = keyword(i, "region")? {
+        let lifetime = lifetime(&i[rem..])?.map(|(name, n)| {
+            rem += n;
+            name
+        });
+        let (body, n) = expect(Block.parse(&i[rem..])?, "region body", &i[rem..])?;
+        return Ok((Some(Expr::Region { lifetime, body }), rem + n));
+    }
+    if let Some(n) = keyword(i, "task")? {
+        let (body, m) = expect(Block.parse(&i[n..])?, "task body", &i[n..])?;
+        return Ok((Some(Expr::Task(body)), n + m));
+    }
+    if let (Some(block), n) = Block.parse(i)? {
+        return Ok((Some(Expr::Block(block)), n));
+    }
+    if let (Some(p), n) = parenthesized(i, Expression, "expression")? {
+        return Ok((Some(p.or_tuple(Expr::Tuple)), n));
+    }
+    if let Some(n) = operator(i, "||")? {
+        let (body, m) = expect(Expression.parse(&i[n..])?, "lambda body", &i[n..])?;
+        return Ok((Some(lambda(vec![], body)), n + m));
+    }
+    if let Some(mut rem) = operator(i, "|")? {
+        let mut params = vec![];
+        loop {
+            if let Some(n) = operator(&i[rem..], "|")? {
+                rem += n;
+                break;
+            }
+            let (param, n) = expect(Ident.parse(&i[rem..])?, "lambda parameter", &i[rem..])?;
+            params.push(param);
+            rem += n;
+            if let Some(n) = separator(&i[rem..], ',')? {
+                rem += n;
+            } else if operator(&i[rem..], "|")?.is_none() {
+                fail!(&i[rem..], "expected `,` or `|`")
+            }
+        }
+        let (body, n) = expect(Expression.parse(&i[rem..])?, "lambda body", &i[rem..])?;
+        return Ok((Some(lambda(params, body)), rem + n));
+    }
+    if let Some(n) = separator(i, '[')? {
+        let (e, m) = array(&i[n..])?;
+        return Ok((Some(e), n + m));
+    }
+    match token(Float, i)? {
+        (Token::Integer(x), n) => return Ok((Some(Expr::Integer(x)), n)),
+        (Token::Real(x), n) => return Ok((Some(Expr::Real(x)), n)),
+        (Token::Imaginary(x), n) => return Ok((Some(Expr::Imaginary(x)), n)),
+        (Token::FReal(x), n) => return Ok((Some(Expr::FReal(x)), n)),
+        _ => {}
+    }
+    match token(StringLiteral, i)? {
+        (Token::String(s), n) => return Ok((Some(Expr::String(s)), n)),
+        (Token::Interpolated(segments), n) => {
+            let start = i.len() - whitespace(i);
+            return Ok((Some(interpolate(segments, start..i.len() - n)?), n));
+        }
+        _ => {}
+    }
+    let (Some(s), n) = Ident.parse(i)? else {
+        return Ok((None, 0));
+    };
+    if let Some(m) = operator(&i[n..], "::")? {
+        let (name, k) = expect(
+            Ident.parse(&i[n + m..])?,
+            "variant name after `::`",
+            &i[n + m..],
+        )?;
+        let rem = n + m + k;
+        let (args, k) = fields(&i[rem..], Expression, Named(Expression), structs)?;
+        return Ok((Some(Expr::Variant { ty: s, name, args }), rem + k));
+    }
+    if structs {
+        if let Some(m) = separator(&i[n..], '{')? {
+            let (fields, k) = list(&i[n + m..], Named(Expression), '}', "field")?;
+            return Ok((Some(Expr::Struct { name: s, fields }), n + m + k));
+        }
+    }
+    Ok((Some(Expr::Symbol(s)), n))
+}
+
+/// Parses the expressions embedded in an interpolated string, which is at `span`. The code in
+/// the string was unescaped, so it's all said to be where the string is.
+fn interpolate(segments: Vec<Segment>, span: Range<usize>) -> Result<Expr> {
+    let mut parts = vec![];
+    for segment in segments {
+        parts.push(match segment {
+            Segment::Text(s) => ast::StrPart::Text(s),
+            Segment::Code(src) => {
+                let mut e = code(&src).map_err(|mut e| {
+                    if let Some(l) = e.downcast_mut::<Located>() {
+                        l.span = span.clone();
+                    }
+                    e
+                })?;
+                Respan(|s: &mut Range<usize>| *s = span.clone()).visit_expr_mut(&mut e);
+                ast::StrPart::Expr(e)
+            }
+        });
+    }
+    Ok(Expr::Interpolate { parts })
+}
+
+/// The expression in a `{}` of an interpolated string.
+fn code(src: &str) -> Result<Expr> {
+    let (e, n) = expect(Expression.parse(src)?, "expression in `{}`", src)?;
+    let n = n + whitespace(&src[n..]);
+    if n != src.len() {
+        fail!(&src[n..], "expected `}}` in string")
+    }
+    Ok(e)
+}
+
+/// Changes the span of every statement and tail in a tree with a function.
+struct Respan<F>(F);
+
+impl<F: FnMut(&mut Range<usize>)> VisitMut for Respan<F> {
+    fn visit_block_mut(&mut self, block: &mut ast::Block) {
+        block.spans.iter_mut().for_each(&mut self.0);
+        visit::walk_block_mut(self, block);
+    }
+}
+
+/// Turns a span that counts back from the end of `len` bytes of source, like the parsers make,
+/// into one of offsets from the start.
+fn from_start(len: usize) -> impl Fn(&mut Range<usize>) {
+    move |span| *span = len - span.start..len - span.end
+}
+
+/// Turns the span of `error`, if it has one, into offsets from the start of `len` bytes of
+/// source, like [`from_start`].
+fn error_from_start(mut error: Error, len: usize) -> Error {
+    if let Some(l) = error.downcast_mut::<Located>() {
+        from_start(len)(&mut l.span);
+    }
+    error
+}
+
+/// A lambda, whose captures are left for name resolution to fill in.
+fn lambda(params: Vec<String>, body: Expr) -> Expr {
+    Expr::Lambda {
+        params,
+        body: Arc::new(body),
+        captures: vec![],
+    }
+}
+
+/// The rest of an array literal, after the `[`.
+fn array(i: &str) -> Result<(Expr, usize)> {
+    let (Some(first), mut rem) = Expression.parse(i)? else {
+        let (items, n) = list(i, Expression, ']', "array element")?;
+        return Ok((Expr::Array(items), n));
+    };
+    if let Some(n) = separator(&i[rem..], ';')? {
+        rem += n;
+        let (count, n) = expect(Expression.parse(&i[rem..])?, "array length", &i[rem..])?;
+        rem += n;
+        let Some(n) = separator(&i[rem..], ']')? else {
+            fail!(&i[rem..], "expected `]`")
+        };
+        let value = Arc::new(first);
+        let count = Arc::new(count);
+        return Ok((Expr::Repeat { value, count }, rem + n));
+    }
+    let (mut items, n) = if let Some(n) = separator(&i[rem..], ',')? {
+        let (items, m) = list(&i[rem + n..], Expression, ']', "array element")?;
+        (items, n + m)
+    } else if let Some(n) = separator(&i[rem..], ']')? {
+        (vec![], n)
+    } else {
+        fail!(&i[rem..], "expected `,`, `;` or `]`")
+    };
+    items.insert(0, first);
+    Ok((Expr::Array(items), rem + n))
+}
+
+/// Something in parentheses, which is either grouped, `(a)`, or a tuple, like `()`, `(a,)` and
+/// `(a, b)`.
+enum Paren<T> {
+    Group(T),
+    Tuple(Vec<T>),
+}
+
+impl<T> Paren<T> {
+    fn or_tuple(self, tuple: impl FnOnce(Vec<T>) -> T) -> T {
+        match self {
+            Paren::Group(t) => t,
+            Paren::Tuple(items) => tuple(items),
+        }
+    }
+}
+
+fn parenthesized<T>(
+    i: &str,
+    item: impl Parser<Token = Option<T>>,
+    what: &str,
+) -> Result<(Option<Paren<T>>, usize)> {
+    let Some(mut rem) = separator(i, '(')? else {
+        return Ok((None, 0));
+    };
+    if let Some(n) = separator(&i[rem..], ')')? {
+        return Ok((Some(Paren::Tuple(vec![])), rem + n));
+    }
+    let (first, n) = expect(item.parse(&i[rem..])?, what, &i[rem..])?;
+    rem += n;
+    if let Some(n) = separator(&i[rem..], ')')? {
+        return Ok((Some(Paren::Group(first)), rem + n));
+    }
+    let Some(n) = separator(&i[rem..], ',')? else {
+        fail!(&i[rem..], "expected `,` or `)`")
+    };
+    rem += n;
+    let (mut rest, n) = list(&i[rem..], item, ')', what)?;
+    rest.insert(0, first);
+    Ok((Some(Paren::Tuple(rest)), rem + n))
+}
+
+/// `name: T`, like the fields of a struct literal.
+struct Named<P>(P);
+
+impl<T, P: Parser<Token = Option<T>>> Parser for Named<P> {
+    type Token = Option<(String, T)>;
+
+    fn parse(&self, i: &str) -> Result<(Option<(String, T)>, usize)> {
+        let (Some(name), n) = Ident.parse(i)? else {
+            return Ok((None, 0));
+        };
+        let Some(m) = operator(&i[n..], ":")? else {
+            fail!(&i[n..], "expected `:` after field name")
+        };
+        let (value, k) = expect(self.0.parse(&i[n + m..])?, "field", &i[n + m..])?;
+        Ok((Some((name, value)), n + m + k))
+    }
+}
+
+/// The fields of an enum variant, which can be `(a, b)`, `{ x: a, y: b }` or nothing at all.
+/// Braces are only considered if `structs` is true.
+fn fields<T>(
+    i: &str,
+    tuple: impl Parser<Token = Option<T>>,
+    named: impl Parser<Token = Option<(String, T)>>,
+    structs: bool,
+) -> Result<(ast::Fields<T>, usize)> {
+    if let Some(n) = separator(i, '(')? {
+        let (items, m) = list(&i[n..], tuple, ')', "field")?;
+        return Ok((ast::Fields::Tuple(items), n + m));
+    }
+    if structs {
+        if let Some(n) = separator(i, '{')? {
+            let (items, m) = list(&i[n..], named, '}', "field")?;
+            return Ok((ast::Fields::Named(items), n + m));
+        }
+    }
+    Ok((ast::Fields::Unit, 0))
+}
+
+/// The rest of an `if` expression, after the `if` keyword.
+fn if_else(i: &str) -> Result<(Expr, usize)> {
+    let (cond, mut rem) = expect(Condition.parse(i)?, "condition after `if`", i)?;
+    let (then, n) = expect(
+        Block.parse(&i[rem..])?,
+        "block after `if` condition",
+        &i[rem..],
+    )?;
+    rem += n;
+
+    let mut otherwise = None;
+    if let Some(n) = keyword(&i[rem..], "else")? {
+        rem += n;
+        let (e, m) = if let Some(k) = keyword(&i[rem..], "if")? {
+            let (e, m) = if_else(&i[rem + k..])?;
+            (e, k + m)
+        } else {
+            let (b, m) = expect(
+                Block.parse(&i[rem..])?,
+                "block or `if` after `else`",
+                &i[rem..],
+            )?;
+            (Expr::Block(b), m)
+        };
+        otherwise = Some(Arc::new(e));
+        rem += m;
+    }
+
+    Ok((
+        Expr::If {
+            cond: Arc::new(cond),
+            then,
+            otherwise,
+        },
+        rem,
+    ))
+}
+
+/// The rest of a `match` expression, after the `match` keyword.
+fn match_arms(i: &str) -> Result<(Expr, usize)> {
+    let (scrutinee, mut rem) = expect(Condition.parse(i)?, "expression after `match`", i)?;
+    let Some(n) = separator(&i[rem..], '{')? else {
+        fail!(&i[rem..], "expected `{{` after `match` expression")
+    };
+    rem += n;
+    let mut arms = vec![];
+    loop {
+        if let Some(n) = separator(&i[rem..], '}')? {
+            rem += n;
+            break;
+        }
+        let (pattern, n) = expect(Pattern.parse(&i[rem..])?, "pattern or `}`", &i[rem..])?;
+        rem += n;
+        let Some(n) = operator(&i[rem..], "=>")? else {
+            fail!(&i[rem..], "expected `=>` after pattern")
+        };
+        rem += n;
+        let (body, n) = expect(Expression.parse(&i[rem..])?, "match arm", &i[rem..])?;
+        rem += n;
+        if let Some(n) = separator(&i[rem..], ',')? {
+            rem += n;
+        } else if !body.is_block_like() && separator(&i[rem..], '}')?.is_none() {
+            fail!(&i[rem..], "expected `,` or `}}` after match arm")
+        }
+        arms.push(ast::Arm { pattern, body });
+    }
+    let scrutinee = Arc::new(scrutinee);
+    Ok((Expr::Match { scrutinee, arms }, rem))
+}
+
+/// Parser for a pattern, as used in `match` arms.
+pub struct Pattern;
+
+impl Parser for Pattern {
+    type Token = Option<ast::Pattern>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Pattern>, usize)> {
+        let (Some(first), mut rem) = single_pattern(i)? else {
+            return Ok((None, 0));
+        };
+        let mut alternatives = vec![first];
+        while let Some(n) = operator(&i[rem..], "|")? {
+            let (p, m) = expect(
+                single_pattern(&i[rem + n..])?,
+                "pattern after `|`",
+                &i[rem + n..],
+            )?;
+            alternatives.push(p);
+            rem += n + m;
+        }
+        if alternatives.len() == 1 {
+            return Ok((alternatives.pop(), rem));
+        }
+        Ok((Some(ast::Pattern::Or(alternatives)), rem))
+    }
+}
+
+/// A pattern without top level `|`.
+fn single_pattern(i: &str) -> Result<(Option<ast::Pattern>, usize)> {
+    if let (Some(p), n) = parenthesized(i, Pattern, "pattern")? {
+        return Ok((Some(p.or_tuple(ast::Pattern::Tuple)), n));
+    }
+    if let (Token::String(s), n) = token(StringLiteral, i)? {
+        return Ok((Some(ast::Pattern::Literal(Expr::String(s))), n));
+    }
+    let (neg, n) = match operator(i, "-")? {
+        Some(n) => (true, n),
+        None => (false, 0),
+    };
+    let (lit, m) = match token(Float, &i[n..])? {
+        (Token::Integer(x), m) => (Some(Expr::Integer(x)), m),
+        (Token::Real(x), m) => (Some(Expr::Real(x)), m),
+        (Token::Imaginary(x), m) => (Some(Expr::Imaginary(x)), m),
+        (Token::FReal(x), m) => (Some(Expr::FReal(x)), m),
+        _ => (None, 0),
+    };
+    if let Some(mut lit) = lit {
+        if neg {
+            lit = Expr::Unary(UnaryOp::Neg, Arc::new(lit));
+        }
+        return Ok((Some(ast::Pattern::Literal(lit)), n + m));
+    }
+    if neg {
+        fail!(&i[n..], "expected number after `-` in pattern")
+    }
+    let (Some(name), n) = Ident.parse(i)? else {
+        return Ok((None, 0));
+    };
+    if let Some(m) = operator(&i[n..], "::")? {
+        let (variant, k) = expect(
+            Ident.parse(&i[n + m..])?,
+            "variant name after `::`",
+            &i[n + m..],
+        )?;
+        let rem = n + m + k;
+        let (args, k) = fields(&i[rem..], Pattern, Named(Pattern), true)?;
+        let p = ast::Pattern::Variant {
+            ty: name,
+            name: variant,
+            args,
+        };
+        return Ok((Some(p), rem + k));
+    }
+    if name == "_" {
+        return Ok((Some(ast::Pattern::Wildcard), n));
+    }
+    Ok((Some(ast::Pattern::Binding(name)), n))
+}
+
+/// Parser for a `{ ... }` block, with statements separated by `;`, and an optional trailing
+/// expression.
+pub struct Block;
+
+impl Parser for Block {
+    type Token = Option<ast::Block>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Block>, usize)> {
+        let Some(mut rem) = separator(i, '{')? else {
+            return Ok((None, 0));
+        };
+        let (mut stmts, mut tail, mut spans) = (vec![], None, vec![]);
+        loop {
+            if let Some(n) = separator(&i[rem..], '}')? {
+                let block = ast::Block { stmts, tail, spans };
+                return Ok((Some(block), rem + n));
+            }
+            let start = i.len() - rem - whitespace(&i[rem..]);
+            let (stmt, n) = expect(Statement.parse(&i[rem..])?, "statement or `}`", &i[rem..])?;
+            rem += n;
+            spans.push(start..i.len() - rem);
+            if let Some(n) = separator(&i[rem..], ';')? {
+                rem += n;
+            } else if separator(&i[rem..], '}')?.is_some() {
+                if let Stmt::Expr(e) = stmt {
+                    tail = Some(Arc::new(e));
+                    continue;
+                }
+            } else if !stmt.is_block_like() {
+                fail!(&i[rem..], "expected `;` or `}}`")
+            }
+            stmts.push(stmt);
+        }
+    }
+}
+
+/// Parser for a single statement, not including the trailing `;`.
+pub struct Statement;
+
+impl Parser for Statement {
+    type Token = Option<Stmt>;
+
+    fn parse(&self, i: &str) -> Result<(Option<Stmt>, usize)> {
+        if let Some(n) = keyword(i, "while")? {
+            let (cond, m) = expect(
+                Condition.parse(&i[n..])?,
+                "condition after `while`",
+                &i[n..],
+            )?;
+            let (body, k) = expect(
+                Block.parse(&i[n + m..])?,
+                "block after `while` condition",
+                &i[n + m..],
+            )?;
+            let label = None;
+            return Ok((Some(Stmt::While { label, cond, body }), n + m + k));
+        }
+        if let Some(n) = keyword(i, "for")? {
+            let (binding, m) = match token(Symbol, &i[n..])? {
+                (Token::Symbol(s), m) if !KEYWORDS.contains(&s.as_str()) => (s, m),
+                _ => fail!(&i[n..], "expected loop variable after `for`"),
+            };
+            let mut rem = n + m;
+            let Some(n) = keyword(&i[rem..], "in")? else {
+                fail!(&i[rem..], "expected `in` after loop variable")
+            };
+            rem += n;
+            let (iter, n) = expect(
+                Condition.parse(&i[rem..])?,
+                "expression after `in`",
+                &i[rem..],
+            )?;
+            rem += n;
+            let (body, n) = expect(
+                Block.parse(&i[rem..])?,
+                "block after `for` iterator",
+                &i[rem..],
+            )?;
+            let label = None;
+            let stmt = Stmt::For {
+                label,
+                binding,
+                iter,
+                body,
+            };
+            return Ok((Some(stmt), rem + n));
+        }
+        if let Some(n) = keyword(i, "break")? {
+            return Ok((Some(Stmt::Break { label: None }), n));
+        }
+        if let Some(n) = keyword(i, "continue")? {
+            return Ok((Some(Stmt::Continue { label: None }), n));
+        }
+        if let Some(n) = keyword(i, "return")? {
+            let (value, m) = Expression.parse(&i[n..])?;
+            return Ok((Some(Stmt::Return(value)), n + m));
+        }
+        if let (Some(name), n) = Ident.parse(i)? {
+            if let Some(m) = operator(&i[n..], ":=")? {
+                let (value, k) = expect(
+                    Expression.parse(&i[n + m..])?,
+                    "expression after `:=`",
+                    &i[n + m..],
+                )?;
+                return Ok((Some(Stmt::Let { name, value }), n + m + k));
+            }
+        }
+        let (Some(e), n) = Expression.parse(i)? else {
+            return Ok((None, 0));
+        };
+        let Some((op, m)) = assign_op(&i[n..])? else {
+            return Ok((Some(Stmt::Expr(e)), n));
+        };
+        if !e.is_place() {
+            fail!(i, "expected a place to assign to")
+        }
+        let rem = n + m;
+        let (value, k) = expect(
+            Expression.parse(&i[rem..])?,
+            "expression after assignment",
+            &i[rem..],
+        )?;
+        let stmt = Stmt::Assign {
+            place: e,
+            op,
+            value,
+        };
+        Ok((Some(stmt), rem + k))
+    }
+}
+
+/// Consumes `=` or a compound assignment operator like `+=`, returning the operator it is
+/// compounded with.
+fn assign_op(i: &str) -> Result<Option<(Option<BinaryOp>, usize)>> {
+    let (Token::Operator(op), n) = token(Operator, i)? else {
+        return Ok(None);
+    };
+    if op == "=" {
+        return Ok(Some((None, n)));
+    }
+    let Some(op) = op.strip_suffix('=').and_then(BinaryOp::from_symbol) else {
+        return Ok(None);
+    };
+    Ok(op.is_compound().then_some((Some(op), n)))
+}
+
+/// Parser for a whole chant file.
+pub struct Program;
+
+impl Parser for Program {
+    type Token = ast::Program;
+
+    fn parse(&self, i: &str) -> Result<(ast::Program, usize)> {
+        let mut program = ast::Program {
+            items: vec![],
+            spans: vec![],
+        };
+        let mut rem = 0;
+        let parse = |at| {
+            Item.parse(&i[at..])
+                .map_err(|e| error_from_start(e, i.len()))
+        };
+        while let (Some(item), n) = parse(rem)? {
+            let start = rem + whitespace(&i[rem..]);
+            program.items.push(item);
+            rem += n;
+            program.spans.push(start..rem);
+        }
+        rem += whitespace(&i[rem..]);
+        if rem != i.len() {
+            return Err(error_from_start(
+                syntax_error(&i[rem..], "expected item".into()),
+                i.len(),
+            ));
+        }
+        Respan(from_start(i.len())).visit_program_mut(&mut program);
+        Ok((program, rem))
+    }
+}
+
+/// Parser for a top level declaration.
+pub struct Item;
+
+impl Parser for Item {
+    type Token = Option<ast::Item>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Item>, usize)> {
+        if let Some(n) = keyword(i, "fn")? {
+            let (f, m) = function(&i[n..])?;
+            return Ok((Some(ast::Item::Fn(f)), n + m));
+        }
+        if let Some(n) = keyword(i, "co")? {
+            let Some(m) = keyword(&i[n..], "fn")? else {
+                fail!(&i[n..], "expected `fn` after `co`")
+            };
+            let (mut f, k) = function(&i[n + m..])?;
+            f.sig.is_co = true;
+            return Ok((Some(ast::Item::Fn(f)), n + m + k));
+        }
+        if let Some(n) = keyword(i, "const")? {
+            // `const co fn`, like in an `impl`.
+            let co = keyword(&i[n..], "co")?;
+            let c = co.unwrap_or(0);
+            if let Some(m) = keyword(&i[n + c..], "fn")? {
+                let (mut f, k) = function(&i[n + c + m..])?;
+                f.sig.is_const = true;
+                f.sig.is_co = co.is_some();
+                return Ok((Some(ast::Item::Fn(f)), n + c + m + k));
+            }
+            if co.is_some() {
+                fail!(&i[n + c..], "expected `fn` after `co`")
+            }
+            let (name, m) = expect(Ident.parse(&i[n..])?, "constant name", &i[n..])?;
+            let mut rem = n + m;
+            let Some(n) = operator(&i[rem..], ":")? else {
+                fail!(&i[rem..], "expected `:` after constant name")
+            };
+            rem += n;
+            let (ty, n) = expect(Type.parse(&i[rem..])?, "constant type", &i[rem..])?;
+            rem += n;
+            let Some(n) = operator(&i[rem..], "=")? else {
+                fail!(&i[rem..], "expected `=` after constant type")
+            };
+            rem += n;
+            let (value, n) = expect(Expression.parse(&i[rem..])?, "constant value", &i[rem..])?;
+            rem += n;
+            let Some(n) = separator(&i[rem..], ';')? else {
+                fail!(&i[rem..], "expected `;` after constant")
+            };
+            let c = ast::Const { name, ty, value };
+            return Ok((Some(ast::Item::Const(c)), rem + n));
+        }
+        if let Some(n) = keyword(i, "struct")? {
+            let (name, m) = expect(Ident.parse(&i[n..])?, "struct name", &i[n..])?;
+            let mut rem = n + m;
+            let (generics, n) = generics(&i[rem..])?;
+            rem += n;
+            let Some(n) = separator(&i[rem..], '{')? else {
+                fail!(&i[rem..], "expected `{{` after struct name")
+            };
+            rem += n;
+            let (fields, n) = list(&i[rem..], Field, '}', "field")?;
+            let s = ast::Struct {
+                name,
+                generics,
+                fields,
+            };
+            return Ok((Some(ast::Item::Struct(s)), rem + n));
+        }
+        if let Some(n) = keyword(i, "enum")? {
+            let (name, m) = expect(Ident.parse(&i[n..])?, "enum name", &i[n..])?;
+            let mut rem = n + m;
+            let (generics, n) = generics(&i[rem..])?;
+            rem += n;
+            let Some(n) = separator(&i[rem..], '{')? else {
+                fail!(&i[rem..], "expected `{{` after enum name")
+            };
+            rem += n;
+            let (variants, n) = list(&i[rem..], Variant, '}', "variant")?;
+            let e = ast::Enum {
+                name,
+                generics,
+                variants,
+            };
+            return Ok((Some(ast::Item::Enum(e)), rem + n));
+        }
+        if let Some(n) = keyword(i, "trait")? {
+            let (name, m) = expect(Ident.parse(&i[n..])?, "trait name", &i[n..])?;
+            let mut rem = n + m;
+            let (generics, n) = generics(&i[rem..])?;
+            rem += n;
+            let Some(n) = separator(&i[rem..], '{')? else {
+                fail!(&i[rem..], "expected `{{` after trait name")
+            };
+            rem += n;
+            let mut fns = vec![];
+            loop {
+                if let Some(n) = separator(&i[rem..], '}')? {
+                    rem += n;
+                    break;
+                }
+                let Some(n) = keyword(&i[rem..], "fn")? else {
+                    fail!(&i[rem..], "expected `fn` or `}}` in trait")
+                };
+                rem += n;
+                let (sig, n) = signature(&i[rem..])?;
+                rem += n;
+                let (default, n) = Block.parse(&i[rem..])?;
+                rem += n;
+                if let Some(n) = separator(&i[rem..], ';')? {
+                    rem += n;
+                }
+                fns.push(ast::TraitFn { sig, default });
+            }
+            let t = ast::Trait {
+                name,
+                generics,
+                fns,
+            };
+            return Ok((Some(ast::Item::Trait(t)), rem));
+        }
+        if let Some(n) = keyword(i, "impl")? {
+            let (generics, m) = generics(&i[n..])?;
+            let mut rem = n + m;
+            let (mut ty, n) = expect(Type.parse(&i[rem..])?, "type after `impl`", &i[rem..])?;
+            rem += n;
+            let mut trait_ = None;
+            if let Some(n) = keyword(&i[rem..], "for")? {
+                rem += n;
+                let (for_ty, n) = expect(Type.parse(&i[rem..])?, "type after `for`", &i[rem..])?;
+                trait_ = Some(core::mem::replace(&mut ty, for_ty));
+                rem += n;
+            }
+            let Some(n) = separator(&i[rem..], '{')? else {
+                fail!(&i[rem..], "expected `{{` after `impl` type")
+            };
+            rem += n;
+            let mut fns = vec![];
+            loop {
+                if let Some(n) = separator(&i[rem..], '}')? {
+                    rem += n;
+                    break;
+                }
+                let is_const = keyword(&i[rem..], "const")?;
+                rem += is_const.unwrap_or(0);
+                let is_co = keyword(&i[rem..], "co")?;
+                rem += is_co.unwrap_or(0);
+                let Some(n) = keyword(&i[rem..], "fn")? else {
+                    fail!(&i[rem..], "expected `fn` or `}}` in impl")
+                };
+                let (mut f, m) = function(&i[rem + n..])?;
+                f.sig.is_const = is_const.is_some();
+                f.sig.is_co = is_co.is_some();
+                fns.push(f);
+                rem += n + m;
+            }
+            let imp = ast::Impl {
+                generics,
+                trait_,
+                ty,
+                fns,
+            };
+            return Ok((Some(ast::Item::Impl(imp)), rem));
+        }
+        if let Some(n) = keyword(i, "mod")? {
+            let (name, m) = expect(Ident.parse(&i[n..])?, "module name", &i[n..])?;
+            let mut rem = n + m;
+            if let Some(n) = separator(&i[rem..], ';')? {
+                let items = None;
+                return Ok((Some(ast::Item::Mod { name, items }), rem + n));
+            }
+            let Some(n) = separator(&i[rem..], '{')? else {
+                fail!(&i[rem..], "expected `{{` or `;` after module name")
+            };
+            rem += n;
+            let (items, n) = items_in_braces(&i[rem..])?;
+            let items = Some(items);
+            return Ok((Some(ast::Item::Mod { name, items }), rem + n));
+        }
+        if let Some(n) = keyword(i, "use")? {
+            let (path, m) = expect(Path.parse(&i[n..])?, "path after `use`", &i[n..])?;
+            let Some(k) = separator(&i[n + m..], ';')? else {
+                fail!(&i[n + m..], "expected `;` after `use` path")
+            };
+            return Ok((Some(ast::Item::Use(path)), n + m + k));
+        }
+        Ok((None, 0))
+    }
+}
+
+/// Items up to and including a closing `}`.
+fn items_in_braces(i: &str) -> Result<(Vec<ast::Item>, usize)> {
+    let mut items = vec![];
+    let mut rem = 0;
+    loop {
+        if let Some(n) = separator(&i[rem..], '}')? {
+            return Ok((items, rem + n));
+        }
+        let (item, n) = expect(Item.parse(&i[rem..])?, "item or `}`", &i[rem..])?;
+        items.push(item);
+        rem += n;
+    }
+}
+
+/// Parser for a path to an item, like `geometry.area`.
+pub struct Path;
+
+impl Parser for Path {
+    type Token = Option<ast::Path>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Path>, usize)> {
+        let (Some(first), mut rem) = Ident.parse(i)? else {
+            return Ok((None, 0));
+        };
+        let mut segments = vec![first];
+        while let Some(n) = separator(&i[rem..], '.')? {
+            let (s, m) = expect(
+                Ident.parse(&i[rem + n..])?,
+                "path segment after `.`",
+                &i[rem + n..],
+            )?;
+            segments.push(s);
+            rem += n + m;
+        }
+        Ok((Some(ast::Path { segments }), rem))
+    }
+}
+
+/// The rest of a function, after the `fn` keyword.
+fn function(i: &str) -> Result<(ast::Function, usize)> {
+    let (sig, rem) = signature(i)?;
+    let (body, n) = expect(Block.parse(&i[rem..])?, "function body", &i[rem..])?;
+    Ok((ast::Function { sig, body }, rem + n))
+}
+
+/// The signature of a function, after the `fn` keyword.
+fn signature(i: &str) -> Result<(ast::Signature, usize)> {
+    // Operators are overloaded by functions named after them, like `fn +(a: Self, b: Self)`.
+    let (name, mut rem) = match (Ident.parse(i)?, token(Operator, i)?) {
+        ((Some(name), n), _) => (Token::Symbol(name), n),
+        (_, (op @ Token::Operator(_), n)) => (op, n),
+        _ => fail!(i, "expected function name or operator"),
+    };
+    let (generics, n) = generics(&i[rem..])?;
+    rem += n;
+    let Some(n) = separator(&i[rem..], '(')? else {
+        fail!(&i[rem..], "expected `(` after function name")
+    };
+    rem += n;
+    let (params, n) = list(&i[rem..], Param, ')', "parameter")?;
+    rem += n;
+    let mut ret = None;
+    if let Some(n) = operator(&i[rem..], "->")? {
+        let (ty, m) = expect(Type.parse(&i[rem + n..])?, "return type", &i[rem + n..])?;
+        ret = Some(ty);
+        rem += n + m;
+    }
+    let name = match (name, params.len()) {
+        (Token::Operator(op), 1) if op == "-" => ast::FnName::Unary(UnaryOp::Neg),
+        (Token::Operator(op), 1) if op == "!" => ast::FnName::Unary(UnaryOp::Not),
+        (Token::Operator(op), n) => match BinaryOp::from_symbol(&op) {
+            Some(op) if n == 2 => ast::FnName::Binary(op),
+            Some(_) => bail!("`{op}` takes 2 parameters, not {n}"),
+            None => bail!("`{op}` can't be overloaded"),
+        },
+        (Token::Symbol(name), _) => ast::FnName::Ident(name),
+        _ => unreachable!(),
+    };
+    let sig = ast::Signature {
+        name,
+        is_const: false,
+        is_co: false,
+        generics,
+        params,
+        ret,
+    };
+    Ok((sig, rem))
+}
+
+/// Generic parameters, like `<T, U: Numeric + Ord>`, if there are any.
+fn generics(i: &str) -> Result<(Vec<ast::GenericParam>, usize)> {
+    let Some(mut rem) = angle(i, '<') else {
+        return Ok((vec![], 0));
+    };
+    let mut params = vec![];
+    loop {
+        if let Some(n) = angle(&i[rem..], '>') {
+            return Ok((params, rem + n));
+        }
+        if let Some((name, n)) = lifetime(&i[rem..])? {
+            params.push(ast::GenericParam {
+                name,
+                lifetime: true,
+                bounds: vec![],
+            });
+            rem += n;
+            if let Some(n) = separator(&i[rem..], ',')? {
+                rem += n;
+            } else if angle(&i[rem..], '>').is_none() {
+                fail!(&i[rem..], "expected `,` or `>`")
+            }
+            continue;
+        }
+        let (name, n) = expect(
+            Ident.parse(&i[rem..])?,
+            "generic parameter or `>`",
+            &i[rem..],
+        )?;
+        rem += n;
+        let mut bounds = vec![];
+        if let Some(n) = operator(&i[rem..], ":")? {
+            rem += n;
+            loop {
+                let (bound, n) = expect(Type.parse(&i[rem..])?, "bound", &i[rem..])?;
+                bounds.push(bound);
+                rem += n;
+                let Some(n) = operator(&i[rem..], "+")? else {
+                    break;
+                };
+                rem += n;
+            }
+        }
+        params.push(ast::GenericParam {
+            name,
+            lifetime: false,
+            bounds,
+        });
+        if let Some(n) = separator(&i[rem..], ',')? {
+            rem += n;
+        } else if angle(&i[rem..], '>').is_none() {
+            fail!(&i[rem..], "expected `,` or `>`")
+        }
+    }
+}
+
+/// `name: Type`, where `what` is what the name is (parameter, field, ...).
+fn typed_name(i: &str, what: &str) -> Result<(Option<(String, TypeExpr)>, usize)> {
+    let (Some(name), n) = Ident.parse(i)? else {
+        return Ok((None, 0));
+    };
+    let Some(m) = operator(&i[n..], ":")? else {
+        fail!(&i[n..], "expected `:` after {what} name")
+    };
+    let (ty, k) = expect(
+        Type.parse(&i[n + m..])?,
+        &format!("{what} type"),
+        &i[n + m..],
+    )?;
+    Ok((Some((name, ty)), n + m + k))
+}
+
+struct Param;
+
+impl Parser for Param {
+    type Token = Option<ast::Param>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Param>, usize)> {
+        let (p, n) = typed_name(i, "parameter")?;
+        Ok((p.map(|(name, ty)| ast::Param { name, ty }), n))
+    }
+}
+
+/// A variant in an enum declaration, like `Circle(Real)`.
+struct Variant;
+
+impl Parser for Variant {
+    type Token = Option<ast::Variant>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Variant>, usize)> {
+        let (Some(name), n) = Ident.parse(i)? else {
+            return Ok((None, 0));
+        };
+        let (fields, m) = fields(&i[n..], Type, Named(Type), true)?;
+        Ok((Some(ast::Variant { name, fields }), n + m))
+    }
+}
+
+struct Field;
+
+impl Parser for Field {
+    type Token = Option<ast::Field>;
+
+    fn parse(&self, i: &str) -> Result<(Option<ast::Field>, usize)> {
+        let (f, n) = typed_name(i, "field")?;
+        Ok((f.map(|(name, ty)| ast::Field { name, ty }), n))
+    }
+}
+
+/// Parser for a type.
+pub struct Type;
+
+impl Parser for Type {
+    type Token = Option<TypeExpr>;
+
+    fn parse(&self, i: &str) -> Result<(Option<TypeExpr>, usize)> {
+        if let (Some(p), n) = parenthesized(i, Type, "type")? {
+            return Ok((Some(p.or_tuple(TypeExpr::Tuple)), n));
+        }
+        if let Some(mut rem) = angle(i, '&') {
+            let mut name = None;
+            if let Some((l, n)) = lifetime(&i[rem..])? {
+                name = Some(l);
+                rem += n;
+            }
+            let mutable = keyword(&i[rem..], "mut")?;
+            rem += mutable.unwrap_or(0);
+            let (inner, n) = expect(Type.parse(&i[rem..])?, "referenced type", &i[rem..])?;
+            let ty = TypeExpr::Ref {
+                lifetime: name,
+                mutable: mutable.is_some(),
+                inner: Box::new(inner),
+            };
+            return Ok((Some(ty), rem + n));
+        }
+        if let Some(n) = keyword(i, "fn")? {
+            let Some(m) = separator(&i[n..], '(')? else {
+                fail!(&i[n..], "expected `(` after `fn`")
+            };
+            let mut rem = n + m;
+            let (params, n) = list(&i[rem..], Type, ')', "parameter type")?;
+            rem += n;
+            let mut ret = None;
+            if let Some(n) = operator(&i[rem..], "->")? {
+                let (ty, m) = expect(Type.parse(&i[rem + n..])?, "return type", &i[rem + n..])?;
+                ret = Some(Box::new(ty));
+                rem += n + m;
+            }
+            return Ok((Some(TypeExpr::Fn { params, ret }), rem));
+        }
+        let (Some(name), mut rem) = Ident.parse(i)? else {
+            return Ok((None, 0));
+        };
+        let Some(n) = angle(&i[rem..], '<') else {
+            return Ok((Some(TypeExpr::Named(name)), rem));
+        };
+        rem += n;
+        let mut args = vec![];
+        loop {
+            if let Some(n) = angle(&i[rem..], '>') {
+                rem += n;
+                break;
+            }
+            let (arg, n) = expect(Type.parse(&i[rem..])?, "type argument or `>`", &i[rem..])?;
+            args.push(arg);
+            rem += n;
+            if let Some(n) = separator(&i[rem..], ',')? {
+                rem += n;
+            } else if angle(&i[rem..], '>').is_none() {
+                fail!(&i[rem..], "expected `,` or `>`")
+            }
+        }
+        Ok((Some(TypeExpr::Generic { name, args }), rem))
+    }
+}
+
+/// Consumes a single `<`, `>` or `&`, even if it is the start of a longer operator. This way
+/// `Vec<Vec<T>>` closes both argument lists, even though `>>` would be a single operator in an
+/// expression, and `&&T` is a reference to a reference.
+fn angle(i: &str, c: char) -> Option<usize> {
+    let n = whitespace(i);
+    i[n..].starts_with(c).then_some(n + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Fields;
+    use crate::grammar::*;
+
+    fn sym(s: &str) -> Expr {
+        Expr::Symbol(s.to_string())
+    }
+
+    fn bin(a: Expr, op: BinaryOp, b: Expr) -> Expr {
+        Expr::Binary(Arc::new(a), op, Arc::new(b))
+    }
+
+    fn block(e: Expr) -> ast::Block {
+        stmts(vec![], Some(e))
+    }
+
+    fn stmts(stmts: Vec<Stmt>, tail: Option<Expr>) -> ast::Block {
+        ast::Block {
+            stmts,
+            tail: tail.map(Arc::new),
+            spans: vec![],
+        }
+    }
+
+    #[test]
+    fn precedence() -> Result<()> {
+        assert_eq!(
+            Expression.parse("1 + 2 * -3 ^ 2 == x")?,
+            (
+                Some(bin(
+                    bin(
+                        Expr::Integer(1),
+                        BinaryOp::Add,
+                        bin(
+                            Expr::Integer(2),
+                            BinaryOp::Mul,
+                            Expr::Unary(
+                                UnaryOp::Neg,
+                                Arc::new(bin(Expr::Integer(3), BinaryOp::Pow, Expr::Integer(2)))
+                            )
+                        )
+                    ),
+                    BinaryOp::Eq,
+                    sym("x")
+                )),
+                19
+            )
+        );
+        assert_eq!(
+            Expression.parse("f(a, (b))")?.0,
+            Some(Expr::Call(Arc::new(sym("f")), vec![sym("a"), sym("b")]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn if_else() -> Result<()> {
+        let src = "if a < b { a } else if b < a { b } else { 0 }";
+        assert_eq!(
+            Expression.parse(src)?,
+            (
+                Some(Expr::If {
+                    cond: Arc::new(bin(sym("a"), BinaryOp::Lt, sym("b"))),
+                    then: block(sym("a")),
+                    otherwise: Some(Arc::new(Expr::If {
+                        cond: Arc::new(bin(sym("b"), BinaryOp::Lt, sym("a"))),
+                        then: block(sym("b")),
+                        otherwise: Some(Arc::new(Expr::Block(block(Expr::Integer(0))))),
+                    })),
+                }),
+                src.len()
+            )
+        );
+        assert_eq!(
+            Expression.parse("if x { y }")?.0,
+            Some(Expr::If {
+                cond: Arc::new(sym("x")),
+                then: block(sym("y")),
+                otherwise: None,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_statements() -> Result<()> {
+        assert_eq!(
+            Block.parse("{ if a { b } c; }")?.0,
+            Some(stmts(
+                vec![
+                    Stmt::Expr(Expr::If {
+                        cond: Arc::new(sym("a")),
+                        then: block(sym("b")),
+                        otherwise: None,
+                    }),
+                    Stmt::Expr(sym("c")),
+                ],
+                None
+            ))
+        );
+        assert!(Block.parse("{ a b }").is_err());
+        assert!(Block.parse("{ else }").is_err());
+        assert!(Expression.parse("if a b").is_err());
+        assert!(Expression.parse("if a { b } else c").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn while_loop() -> Result<()> {
+        assert_eq!(
+            Block
+                .parse("{ while i < n { if x { break } continue } }")?
+                .0,
+            Some(stmts(
+                vec![Stmt::While {
+                    label: None,
+                    cond: bin(sym("i"), BinaryOp::Lt, sym("n")),
+                    body: stmts(
+                        vec![
+                            Stmt::Expr(Expr::If {
+                                cond: Arc::new(sym("x")),
+                                then: stmts(vec![Stmt::Break { label: None }], None),
+                                otherwise: None,
+                            }),
+                            Stmt::Continue { label: None },
+                        ],
+                        None
+                    ),
+                }],
+                None
+            ))
+        );
+        assert!(Statement.parse("while { x }").is_err());
+        assert!(Statement.parse("while x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ranges() -> Result<()> {
+        let range = |start: Option<Expr>, end: Option<Expr>, inclusive| Expr::Range {
+            start: start.map(Arc::new),
+            end: end.map(Arc::new),
+            inclusive,
+        };
+        assert_eq!(
+            Expression.parse("0..n + 1")?.0,
+            Some(range(
+                Some(Expr::Integer(0)),
+                Some(bin(sym("n"), BinaryOp::Add, Expr::Integer(1))),
+                false
+            ))
+        );
+        assert_eq!(
+            Expression.parse("1.5...3")?,
+            (
+                Some(range(Some(Expr::Real(1.5)), Some(Expr::Integer(3)), true)),
+                7
+            )
+        );
+        assert_eq!(Expression.parse("..")?.0, Some(range(None, None, false)));
+        assert!(Expression.parse("a...").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn for_loop() -> Result<()> {
+        assert_eq!(
+            Statement.parse("for i in 0..10 { f(i) }")?.0,
+            Some(Stmt::For {
+                label: None,
+                binding: "i".to_string(),
+                iter: Expr::Range {
+                    start: Some(Arc::new(Expr::Integer(0))),
+                    end: Some(Arc::new(Expr::Integer(10))),
+                    inclusive: false,
+                },
+                body: block(Expr::Call(Arc::new(sym("f")), vec![sym("i")])),
+            })
+        );
+        assert!(Statement.parse("for in x { }").is_err());
+        assert!(Statement.parse("for i x { }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tail_expression() -> Result<()> {
+        assert_eq!(
+            Block.parse("{ x := 1; y := f(x); x + y }")?.0,
+            Some(stmts(
+                vec![
+                    Stmt::Let {
+                        name: "x".to_string(),
+                        value: Expr::Integer(1),
+                    },
+                    Stmt::Let {
+                        name: "y".to_string(),
+                        value: Expr::Call(Arc::new(sym("f")), vec![sym("x")]),
+                    },
+                ],
+                Some(bin(sym("x"), BinaryOp::Add, sym("y")))
+            ))
+        );
+        assert_eq!(
+            Block.parse("{ f(x); }")?.0,
+            Some(stmts(
+                vec![Stmt::Expr(Expr::Call(Arc::new(sym("f")), vec![sym("x")]))],
+                None
+            ))
+        );
+        assert_eq!(Block.parse("{}")?.0, Some(stmts(vec![], None)));
+        assert!(Statement.parse("x :=").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn functions() -> Result<()> {
+        let src = "
+            fn square(x: Real) -> Real { x * x }
+            fn main() { square(2); }
+        ";
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        assert_eq!(
+            Program.parse(src)?,
+            (
+                ast::Program {
+                    items: vec![
+                        ast::Item::Fn(ast::Function {
+                            sig: ast::Signature {
+                                name: ast::FnName::Ident("square".to_string()),
+                                is_const: false,
+                                is_co: false,
+                                generics: vec![],
+                                params: vec![ast::Param {
+                                    name: "x".to_string(),
+                                    ty: named("Real"),
+                                }],
+                                ret: Some(named("Real")),
+                            },
+                            body: block(bin(sym("x"), BinaryOp::Mul, sym("x"))),
+                        }),
+                        ast::Item::Fn(ast::Function {
+                            sig: ast::Signature {
+                                name: ast::FnName::Ident("main".to_string()),
+                                is_const: false,
+                                is_co: false,
+                                generics: vec![],
+                                params: vec![],
+                                ret: None,
+                            },
+                            body: stmts(
+                                vec![Stmt::Expr(Expr::Call(
+                                    Arc::new(sym("square")),
+                                    vec![Expr::Integer(2)]
+                                ))],
+                                None
+                            ),
+                        }),
+                    ],
+                    spans: vec![],
+                },
+                src.len()
+            )
+        );
+        assert_eq!(
+            Program.parse(" ")?.0,
+            ast::Program {
+                items: vec![],
+                spans: vec![],
+            }
+        );
+        assert!(Program.parse("fn f(a, b: Int) {}").is_err());
+        assert!(Program.parse("fn f() -> {}").is_err());
+        assert!(Program.parse("fn f() {} x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn return_statement() -> Result<()> {
+        assert_eq!(
+            Block.parse("{ if x { return; } return x + 1 }")?.0,
+            Some(stmts(
+                vec![
+                    Stmt::Expr(Expr::If {
+                        cond: Arc::new(sym("x")),
+                        then: stmts(vec![Stmt::Return(None)], None),
+                        otherwise: None,
+                    }),
+                    Stmt::Return(Some(bin(sym("x"), BinaryOp::Add, Expr::Integer(1)))),
+                ],
+                None
+            ))
+        );
+        assert!(Block.parse("{ return x y }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn match_expression() -> Result<()> {
+        let src = "match x {
+            (0, _) | (_, 0) => 0,
+            (-1, n) => { -n }
+            other => f(other),
+        }";
+        let lit = |x| ast::Pattern::Literal(Expr::Integer(x));
+        let neg = |e| Expr::Unary(UnaryOp::Neg, Arc::new(e));
+        let binding = |s: &str| ast::Pattern::Binding(s.to_string());
+        assert_eq!(
+            Expression.parse(src)?,
+            (
+                Some(Expr::Match {
+                    scrutinee: Arc::new(sym("x")),
+                    arms: vec![
+                        ast::Arm {
+                            pattern: ast::Pattern::Or(vec![
+                                ast::Pattern::Tuple(vec![lit(0), ast::Pattern::Wildcard]),
+                                ast::Pattern::Tuple(vec![ast::Pattern::Wildcard, lit(0)]),
+                            ]),
+                            body: Expr::Integer(0),
+                        },
+                        ast::Arm {
+                            pattern: ast::Pattern::Tuple(vec![
+                                ast::Pattern::Literal(neg(Expr::Integer(1))),
+                                binding("n"),
+                            ]),
+                            body: Expr::Block(block(neg(sym("n")))),
+                        },
+                        ast::Arm {
+                            pattern: binding("other"),
+                            body: Expr::Call(Arc::new(sym("f")), vec![sym("other")]),
+                        },
+                    ],
+                }),
+                src.len()
+            )
+        );
+        assert!(Expression.parse("match x { 1 => a 2 => b }").is_err());
+        assert!(Expression.parse("match x { 1 a }").is_err());
+        assert!(Expression.parse("match x { - => a }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn structs() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        assert_eq!(
+            Item.parse("struct Point { x: Real, y: Real, }")?.0,
+            Some(ast::Item::Struct(ast::Struct {
+                name: "Point".to_string(),
+                generics: vec![],
+                fields: vec![
+                    ast::Field {
+                        name: "x".to_string(),
+                        ty: named("Real"),
+                    },
+                    ast::Field {
+                        name: "y".to_string(),
+                        ty: named("Real"),
+                    },
+                ],
+            }))
+        );
+        let point = Expr::Struct {
+            name: "Point".to_string(),
+            fields: vec![
+                ("x".to_string(), Expr::Integer(1)),
+                ("y".to_string(), Expr::Integer(2)),
+            ],
+        };
+        assert_eq!(
+            Expression.parse("Point { x: 1, y: 2 }.x")?.0,
+            Some(Expr::Field(Arc::new(point.clone()), "x".to_string()))
+        );
+        assert_eq!(
+            Expression.parse("if p.x { (Point { x: 1, y: 2 }) }")?.0,
+            Some(Expr::If {
+                cond: Arc::new(Expr::Field(Arc::new(sym("p")), "x".to_string())),
+                then: block(point),
+                otherwise: None,
+            })
+        );
+        assert_eq!(
+            Expression.parse("a.b..c")?.0,
+            Some(Expr::Range {
+                start: Some(Arc::new(Expr::Field(Arc::new(sym("a")), "b".to_string()))),
+                end: Some(Arc::new(sym("c"))),
+                inclusive: false,
+            })
+        );
+        assert!(Item.parse("struct P { x }").is_err());
+        assert!(Expression.parse("P { x 1 }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn enums() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        assert_eq!(
+            Item.parse("enum Shape { Circle(Real), Rect { w: Real, h: Real }, Empty }")?
+                .0,
+            Some(ast::Item::Enum(ast::Enum {
+                name: "Shape".to_string(),
+                generics: vec![],
+                variants: vec![
+                    ast::Variant {
+                        name: "Circle".to_string(),
+                        fields: Fields::Tuple(vec![named("Real")]),
+                    },
+                    ast::Variant {
+                        name: "Rect".to_string(),
+                        fields: Fields::Named(vec![
+                            ("w".to_string(), named("Real")),
+                            ("h".to_string(), named("Real")),
+                        ]),
+                    },
+                    ast::Variant {
+                        name: "Empty".to_string(),
+                        fields: Fields::Unit,
+                    },
+                ],
+            }))
+        );
+        let variant = |name: &str, args| Expr::Variant {
+            ty: "Shape".to_string(),
+            name: name.to_string(),
+            args,
+        };
+        assert_eq!(
+            Expression.parse("Shape::Circle(1)")?.0,
+            Some(variant("Circle", Fields::Tuple(vec![Expr::Integer(1)])))
+        );
+        assert_eq!(
+            Expression.parse("Shape::Rect { w: 1, h: 2 }")?.0,
+            Some(variant(
+                "Rect",
+                Fields::Named(vec![
+                    ("w".to_string(), Expr::Integer(1)),
+                    ("h".to_string(), Expr::Integer(2)),
+                ])
+            ))
+        );
+        assert_eq!(
+            Expression
+                .parse("match s { Shape::Circle(r) => r, Shape::Empty => 0 }")?
+                .0,
+            Some(Expr::Match {
+                scrutinee: Arc::new(sym("s")),
+                arms: vec![
+                    ast::Arm {
+                        pattern: ast::Pattern::Variant {
+                            ty: "Shape".to_string(),
+                            name: "Circle".to_string(),
+                            args: Fields::Tuple(vec![ast::Pattern::Binding("r".to_string())]),
+                        },
+                        body: sym("r"),
+                    },
+                    ast::Arm {
+                        pattern: ast::Pattern::Variant {
+                            ty: "Shape".to_string(),
+                            name: "Empty".to_string(),
+                            args: Fields::Unit,
+                        },
+                        body: Expr::Integer(0),
+                    },
+                ],
+            })
+        );
+        assert!(Expression.parse("Shape::").is_err());
+        assert!(Item.parse("enum Shape { Circle(Real) Empty }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tuples() -> Result<()> {
+        let tuple = Expr::Tuple(vec![
+            Expr::Integer(1),
+            Expr::String("two".to_string()),
+            Expr::Real(3.),
+        ]);
+        assert_eq!(
+            Expression.parse(r#"(1, "two", 3.0)"#)?.0,
+            Some(tuple.clone())
+        );
+        assert_eq!(
+            Expression.parse(r#"(1, "two", 3.0,).1"#)?.0,
+            Some(Expr::TupleIndex(Arc::new(tuple), 1))
+        );
+        assert_eq!(
+            Expression.parse("t.0.1")?.0,
+            Some(Expr::TupleIndex(
+                Arc::new(Expr::TupleIndex(Arc::new(sym("t")), 0)),
+                1
+            ))
+        );
+        assert_eq!(Expression.parse("(x)")?.0, Some(sym("x")));
+        assert_eq!(
+            Expression.parse("(x,)")?.0,
+            Some(Expr::Tuple(vec![sym("x")]))
+        );
+        assert_eq!(Expression.parse("()")?.0, Some(Expr::Tuple(vec![])));
+        assert!(Expression.parse("(x y)").is_err());
+        assert!(Expression.parse("(,)").is_err());
+
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        assert_eq!(
+            Type.parse("((Int, Real), (Int,), (Int), ())")?.0,
+            Some(TypeExpr::Tuple(vec![
+                TypeExpr::Tuple(vec![named("Int"), named("Real")]),
+                TypeExpr::Tuple(vec![named("Int")]),
+                named("Int"),
+                TypeExpr::Tuple(vec![]),
+            ]))
+        );
+        assert_eq!(
+            Pattern.parse("((a), _)")?.0,
+            Some(ast::Pattern::Tuple(vec![
+                ast::Pattern::Binding("a".to_string()),
+                ast::Pattern::Wildcard,
+            ]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn arrays() -> Result<()> {
+        let nums = |xs: &[i64]| xs.iter().map(|x| Expr::Integer(*x)).collect::<Vec<_>>();
+        assert_eq!(
+            Expression.parse("[1, 2, 3,]")?.0,
+            Some(Expr::Array(nums(&[1, 2, 3])))
+        );
+        assert_eq!(Expression.parse("[]")?.0, Some(Expr::Array(vec![])));
+        assert_eq!(Expression.parse("[1]")?.0, Some(Expr::Array(nums(&[1]))));
+        assert_eq!(
+            Expression.parse("[0; 16]")?.0,
+            Some(Expr::Repeat {
+                value: Arc::new(Expr::Integer(0)),
+                count: Arc::new(Expr::Integer(16)),
+            })
+        );
+        assert_eq!(
+            Expression.parse("a[i + 1][0]")?.0,
+            Some(Expr::Index(
+                Arc::new(Expr::Index(
+                    Arc::new(sym("a")),
+                    Arc::new(bin(sym("i"), BinaryOp::Add, Expr::Integer(1)))
+                )),
+                Arc::new(Expr::Integer(0))
+            ))
+        );
+        assert!(Expression.parse("[1 2]").is_err());
+        assert!(Expression.parse("[0; 16").is_err());
+        assert!(Expression.parse("a[]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lambdas() -> Result<()> {
+        assert_eq!(
+            Expression.parse("map(xs, |x, y| x + y)")?.0,
+            Some(Expr::Call(
+                Arc::new(sym("map")),
+                vec![
+                    sym("xs"),
+                    Expr::Lambda {
+                        params: vec!["x".to_string(), "y".to_string()],
+                        body: Arc::new(bin(sym("x"), BinaryOp::Add, sym("y"))),
+                        captures: vec![],
+                    }
+                ]
+            ))
+        );
+        assert_eq!(
+            Expression.parse("|| 1")?.0,
+            Some(Expr::Lambda {
+                params: vec![],
+                body: Arc::new(Expr::Integer(1)),
+                captures: vec![],
+            })
+        );
+        // Operators are lexed one at a time, so `|-` is two of them.
+        assert_eq!(
+            Expression.parse("|x|-x")?.0,
+            Some(Expr::Lambda {
+                params: vec!["x".to_string()],
+                body: Arc::new(Expr::Unary(UnaryOp::Neg, Arc::new(sym("x")))),
+                captures: vec![],
+            })
+        );
+        assert!(Expression.parse("|x y| x").is_err());
+        assert!(Expression.parse("|x|").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn method_calls() -> Result<()> {
+        assert_eq!(
+            Expression.parse("v.norm().scale(2, x).len")?.0,
+            Some(Expr::Field(
+                Arc::new(Expr::MethodCall {
+                    receiver: Arc::new(Expr::MethodCall {
+                        receiver: Arc::new(sym("v")),
+                        method: "norm".to_string(),
+                        args: vec![],
+                    }),
+                    method: "scale".to_string(),
+                    args: vec![Expr::Integer(2), sym("x")],
+                }),
+                "len".to_string()
+            ))
+        );
+        assert_eq!(
+            Expression.parse("(v.f)(x)")?.0,
+            Some(Expr::Call(
+                Arc::new(Expr::Field(Arc::new(sym("v")), "f".to_string())),
+                vec![sym("x")]
+            ))
+        );
+        assert!(Expression.parse("v.f(x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn modules() -> Result<()> {
+        assert_eq!(
+            Program
+                .parse("mod shapes; mod geometry { use shapes.circle.area; fn f() {} }")?
+                .0,
+            ast::Program {
+                items: vec![
+                    ast::Item::Mod {
+                        name: "shapes".to_string(),
+                        items: None,
+                    },
+                    ast::Item::Mod {
+                        name: "geometry".to_string(),
+                        items: Some(vec![
+                            ast::Item::Use(ast::Path {
+                                segments: vec![
+                                    "shapes".to_string(),
+                                    "circle".to_string(),
+                                    "area".to_string(),
+                                ],
+                            }),
+                            ast::Item::Fn(ast::Function {
+                                sig: ast::Signature {
+                                    name: ast::FnName::Ident("f".to_string()),
+                                    is_const: false,
+                                    is_co: false,
+                                    generics: vec![],
+                                    params: vec![],
+                                    ret: None,
+                                },
+                                body: stmts(vec![], None),
+                            }),
+                        ]),
+                    },
+                ],
+                spans: vec![],
+            }
+        );
+        assert!(Program.parse("use a.b").is_err());
+        assert!(Program.parse("use a.;").is_err());
+        assert!(Program.parse("mod a").is_err());
+        assert!(Program.parse("mod a { fn f() {}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ascription() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        assert_eq!(
+            Statement.parse("x := 1 + 2 : Natural")?.0,
+            Some(Stmt::Let {
+                name: "x".to_string(),
+                value: Expr::Ascribe(
+                    Arc::new(bin(Expr::Integer(1), BinaryOp::Add, Expr::Integer(2))),
+                    named("Natural")
+                ),
+            })
+        );
+        assert_eq!(
+            Type.parse("fn(Matrix<Complex>, (Int, Real)) -> Vec<Vec<Real>>")?,
+            (
+                Some(TypeExpr::Fn {
+                    params: vec![
+                        TypeExpr::Generic {
+                            name: "Matrix".to_string(),
+                            args: vec![named("Complex")],
+                        },
+                        TypeExpr::Tuple(vec![named("Int"), named("Real")]),
+                    ],
+                    ret: Some(Box::new(TypeExpr::Generic {
+                        name: "Vec".to_string(),
+                        args: vec![TypeExpr::Generic {
+                            name: "Vec".to_string(),
+                            args: vec![named("Real")],
+                        }],
+                    })),
+                }),
+                50
+            )
+        );
+        assert_eq!(
+            Type.parse("fn()")?.0,
+            Some(TypeExpr::Fn {
+                params: vec![],
+                ret: None,
+            })
+        );
+        assert!(Expression.parse("x :").is_err());
+        assert!(Type.parse("Vec<Real").is_err());
+        assert!(Type.parse("fn -> Real").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn generic_params() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        let generic = |name: &str, args| TypeExpr::Generic {
+            name: name.to_string(),
+            args,
+        };
+        let src = "fn map<T, U: Numeric + Ord>(xs: Vec<T>, f: fn(T) -> U) -> Vec<Vec<U>> { f(xs) }";
+        let Some(ast::Item::Fn(f)) = Item.parse(src)?.0 else {
+            panic!("expected a function")
+        };
+        assert_eq!(
+            f.sig.generics,
+            vec![
+                ast::GenericParam {
+                    name: "T".to_string(),
+                    lifetime: false,
+                    bounds: vec![],
+                },
+                ast::GenericParam {
+                    name: "U".to_string(),
+                    lifetime: false,
+                    bounds: vec![named("Numeric"), named("Ord")],
+                },
+            ]
+        );
+        assert_eq!(
+            f.sig.ret,
+            Some(generic("Vec", vec![generic("Vec", vec![named("U")])]))
+        );
+        assert_eq!(
+            Item.parse("struct Vec<T> { data: Array<T>, len: Natural }")?
+                .0,
+            Some(ast::Item::Struct(ast::Struct {
+                name: "Vec".to_string(),
+                generics: vec![ast::GenericParam {
+                    name: "T".to_string(),
+                    lifetime: false,
+                    bounds: vec![],
+                }],
+                fields: vec![
+                    ast::Field {
+                        name: "data".to_string(),
+                        ty: generic("Array", vec![named("T")]),
+                    },
+                    ast::Field {
+                        name: "len".to_string(),
+                        ty: named("Natural"),
+                    },
+                ],
+            }))
+        );
+        // In expressions, `<` and `>` are still comparisons.
+        assert_eq!(
+            Expression.parse("a < b > c")?.0,
+            Some(bin(
+                bin(sym("a"), BinaryOp::Lt, sym("b")),
+                BinaryOp::Gt,
+                sym("c")
+            ))
+        );
+        assert!(Item.parse("fn f<T U>() {}").is_err());
+        assert!(Item.parse("fn f<T:>() {}").is_err());
+        assert!(Item.parse("enum E<T { }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn traits() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        let param = |name: &str| ast::Param {
+            name: name.to_string(),
+            ty: named("Self"),
+        };
+        let src = "trait Numeric {
+            fn add(a: Self, b: Self) -> Self
+            fn zero() -> Self;
+            fn double(a: Self) -> Self { add(a, a) }
+        }";
+        assert_eq!(
+            Item.parse(src)?,
+            (
+                Some(ast::Item::Trait(ast::Trait {
+                    name: "Numeric".to_string(),
+                    generics: vec![],
+                    fns: vec![
+                        ast::TraitFn {
+                            sig: ast::Signature {
+                                name: ast::FnName::Ident("add".to_string()),
+                                is_const: false,
+                                is_co: false,
+                                generics: vec![],
+                                params: vec![param("a"), param("b")],
+                                ret: Some(named("Self")),
+                            },
+                            default: None,
+                        },
+                        ast::TraitFn {
+                            sig: ast::Signature {
+                                name: ast::FnName::Ident("zero".to_string()),
+                                is_const: false,
+                                is_co: false,
+                                generics: vec![],
+                                params: vec![],
+                                ret: Some(named("Self")),
+                            },
+                            default: None,
+                        },
+                        ast::TraitFn {
+                            sig: ast::Signature {
+                                name: ast::FnName::Ident("double".to_string()),
+                                is_const: false,
+                                is_co: false,
+                                generics: vec![],
+                                params: vec![param("a")],
+                                ret: Some(named("Self")),
+                            },
+                            default: Some(block(Expr::Call(
+                                Arc::new(sym("add")),
+                                vec![sym("a"), sym("a")]
+                            ))),
+                        },
+                    ],
+                })),
+                src.len()
+            )
+        );
+        assert!(Item.parse("trait T { x }").is_err());
+        assert!(Item.parse("trait T { fn f() ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn impls() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        let src = "impl<T: Numeric> Add for Matrix<T> {
+            fn add(a: Self, b: Self) -> Self { a }
+        }";
+        assert_eq!(
+            Item.parse(src)?,
+            (
+                Some(ast::Item::Impl(ast::Impl {
+                    generics: vec![ast::GenericParam {
+                        name: "T".to_string(),
+                        lifetime: false,
+                        bounds: vec![named("Numeric")],
+                    }],
+                    trait_: Some(named("Add")),
+                    ty: TypeExpr::Generic {
+                        name: "Matrix".to_string(),
+                        args: vec![named("T")],
+                    },
+                    fns: vec![ast::Function {
+                        sig: ast::Signature {
+                            name: ast::FnName::Ident("add".to_string()),
+                            is_const: false,
+                            is_co: false,
+                            generics: vec![],
+                            params: vec![
+                                ast::Param {
+                                    name: "a".to_string(),
+                                    ty: named("Self"),
+                                },
+                                ast::Param {
+                                    name: "b".to_string(),
+                                    ty: named("Self"),
+                                },
+                            ],
+                            ret: Some(named("Self")),
+                        },
+                        body: block(sym("a")),
+                    }],
+                })),
+                src.len()
+            )
+        );
+        assert_eq!(
+            Item.parse("impl Complex {}")?.0,
+            Some(ast::Item::Impl(ast::Impl {
+                generics: vec![],
+                trait_: None,
+                ty: named("Complex"),
+                fns: vec![],
+            }))
+        );
+        assert!(Item.parse("impl for Complex {}").is_err());
+        assert!(Item.parse("impl Add for {}").is_err());
+        assert!(Item.parse("impl Complex { fn f() }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn operator_overloading() -> Result<()> {
+        let named = |s: &str| TypeExpr::Named(s.to_string());
+        let src = "impl Add for Complex {
+            fn +(a: Self, b: Self) -> Self { a }
+            fn -(a: Self) -> Self { a }
+        }";
+        let Some(ast::Item::Impl(imp)) = Item.parse(src)?.0 else {
+            panic!("expected an impl")
+        };
+        let names: Vec<_> = imp.fns.iter().map(|f| f.sig.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec![
+                ast::FnName::Binary(BinaryOp::Add),
+                ast::FnName::Unary(UnaryOp::Neg)
+            ]
+        );
+        assert_eq!(imp.fns[0].sig.ret, Some(named("Self")));
+        assert_eq!(
+            Item.parse("trait Eq { fn ==(a: Self, b: Self) -> Bool }")?
+                .0,
+            Some(ast::Item::Trait(ast::Trait {
+                name: "Eq".to_string(),
+                generics: vec![],
+                fns: vec![ast::TraitFn {
+                    sig: ast::Signature {
+                        name: ast::FnName::Binary(BinaryOp::Eq),
+                        is_const: false,
+                        is_co: false,
+                        generics: vec![],
+                        params: vec![
+                            ast::Param {
+                                name: "a".to_string(),
+                                ty: named("Self"),
+                            },
+                            ast::Param {
+                                name: "b".to_string(),
+                                ty: named("Self"),
+                            },
+                        ],
+                        ret: Some(named("Bool")),
+                    },
+                    default: None,
+                }],
+            }))
+        );
+        assert!(Item.parse("fn *(a: Self) {}").is_err());
+        assert!(Item.parse("fn :=(a: Self, b: Self) {}").is_err());
+        assert!(Item.parse("fn !(a: Self, b: Self) {}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn constants() -> Result<()> {
+        let src = "const G: Real = 9.81; const fn tau() -> Real { 2 * G }";
+        let (program, _) = Program.parse(src)?;
+        assert_eq!(
+            program.items[0],
+            ast::Item::Const(ast::Const {
+                name: "G".to_string(),
+                ty: TypeExpr::Named("Real".to_string()),
+                value: Expr::Real(9.81),
+            })
+        );
+        let ast::Item::Fn(tau) = &program.items[1] else {
+            panic!("expected a function")
+        };
+        assert!(tau.sig.is_const);
+        let Some(ast::Item::Impl(imp)) = Item.parse("impl R { const fn a() {} fn b() {} }")?.0
+        else {
+            panic!("expected an impl")
+        };
+        let consts: Vec<_> = imp.fns.iter().map(|f| f.sig.is_const).collect();
+        assert_eq!(consts, vec![true, false]);
+        assert!(Item.parse("const PI = 3;").is_err());
+        assert!(Item.parse("const PI: Real 3;").is_err());
+        assert!(Item.parse("const PI: Real = 3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn references() -> Result<()> {
+        let src = "fn first<'a, T>(xs: &'a mut Vec<T>) -> &'a T { xs[0] }";
+        let Some(ast::Item::Fn(f)) = Item.parse(src)?.0 else {
+            panic!("expected a function")
+        };
+        assert_eq!(
+            f.sig.generics[0],
+            ast::GenericParam {
+                name: "a".to_string(),
+                lifetime: true,
+                bounds: vec![],
+            }
+        );
+        assert_eq!(
+            f.sig.params[0].ty,
+            TypeExpr::Ref {
+                lifetime: Some("a".to_string()),
+                mutable: true,
+                inner: Box::new(TypeExpr::Generic {
+                    name: "Vec".to_string(),
+                    args: vec![TypeExpr::Named("T".to_string())],
+                }),
+            }
+        );
+        let shared = |inner| TypeExpr::Ref {
+            lifetime: None,
+            mutable: false,
+            inner: Box::new(inner),
+        };
+        assert_eq!(
+            Type.parse("&&Real")?,
+            (Some(shared(shared(TypeExpr::Named("Real".to_string())))), 6)
+        );
+        assert!(Type.parse("&'a").is_err());
+        assert!(Item.parse("fn f<'a: T>() {}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn coroutines() -> Result<()> {
+        let Some(ast::Item::Fn(f)) = Item.parse("co fn gen() { yield 1; x := yield; yield }")?.0
+        else {
+            panic!("expected a function")
+        };
+        assert!(f.sig.is_co);
+        assert_eq!(
+            f.body,
+            stmts(
+                vec![
+                    Stmt::Expr(Expr::Yield(Some(Arc::new(Expr::Integer(1))))),
+                    Stmt::Let {
+                        name: "x".to_string(),
+                        value: Expr::Yield(None),
+                    },
+                ],
+                Some(Expr::Yield(None)),
+            )
+        );
+        assert_eq!(
+            Expression.parse("yield a + b")?.0,
+            Some(Expr::Yield(Some(Arc::new(bin(
+                sym("a"),
+                BinaryOp::Add,
+                sym("b")
+            )))))
+        );
+        assert!(Item.parse("co gen() {}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn interpolation() -> Result<()> {
+        let text = |s: &str| ast::StrPart::Text(s.to_string());
+        assert_eq!(
+            Expression.parse(r#""{a} + 1 = { a + 1 }!""#)?.0,
+            Some(Expr::Interpolate {
+                parts: vec![
+                    ast::StrPart::Expr(sym("a")),
+                    text(" + 1 = "),
+                    ast::StrPart::Expr(bin(sym("a"), BinaryOp::Add, Expr::Integer(1))),
+                    text("!"),
+                ]
+            })
+        );
+        assert_eq!(
+            Expression.parse(r#""\{a\}""#)?.0,
+            Some(Expr::String("{a}".to_string()))
+        );
+        assert!(Expression.parse(r#""{}""#).is_err());
+        assert!(Expression.parse(r#""{a b}""#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pipeline() -> Result<()> {
+        let call = |f: &str, arg| Expr::Call(Arc::new(sym(f)), vec![arg]);
+        assert_eq!(
+            Expression.parse("data |> normalize |> sum")?,
+            (Some(call("sum", call("normalize", sym("data")))), 24)
+        );
+        assert_eq!(
+            Expression.parse("a + 1 |> f")?.0,
+            Some(call("f", bin(sym("a"), BinaryOp::Add, Expr::Integer(1))))
+        );
+        assert!(matches!(
+            Expression.parse("0..n |> sum")?.0,
+            Some(Expr::Call(_, args)) if matches!(args[..], [Expr::Range { .. }])
+        ));
+        assert!(Expression.parse("data |>").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn assignment() -> Result<()> {
+        assert_eq!(
+            Statement.parse("x = x + 1")?.0,
+            Some(Stmt::Assign {
+                place: sym("x"),
+                op: None,
+                value: bin(sym("x"), BinaryOp::Add, Expr::Integer(1)),
+            })
+        );
+        assert_eq!(
+            Statement.parse("a[i] += 1")?.0,
+            Some(Stmt::Assign {
+                place: Expr::Index(Arc::new(sym("a")), Arc::new(sym("i"))),
+                op: Some(BinaryOp::Add),
+                value: Expr::Integer(1),
+            })
+        );
+        assert_eq!(
+            Statement.parse("p.x = 0")?.0,
+            Some(Stmt::Assign {
+                place: Expr::Field(Arc::new(sym("p")), "x".to_string()),
+                op: None,
+                value: Expr::Integer(0),
+            })
+        );
+        assert_eq!(
+            Statement.parse("a == b")?.0,
+            Some(Stmt::Expr(bin(sym("a"), BinaryOp::Eq, sym("b"))))
+        );
+        assert!(matches!(
+            Statement.parse("a <= b")?.0,
+            Some(Stmt::Expr(Expr::Binary(_, BinaryOp::Le, _)))
+        ));
+        assert!(Statement.parse("f() = 1").is_err());
+        assert!(Statement.parse("x =").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn logical_operators() -> Result<()> {
+        let logical = |a, op, b| Expr::Logical(Arc::new(a), op, Arc::new(b));
+        assert_eq!(
+            Expression.parse("a || b && c == d")?.0,
+            Some(logical(
+                sym("a"),
+                LogicalOp::Or,
+                logical(
+                    sym("b"),
+                    LogicalOp::And,
+                    bin(sym("c"), BinaryOp::Eq, sym("d"))
+                )
+            ))
+        );
+        assert_eq!(
+            Expression.parse("a & b && c | d")?.0,
+            Some(logical(
+                bin(sym("a"), BinaryOp::BitAnd, sym("b")),
+                LogicalOp::And,
+                bin(sym("c"), BinaryOp::BitOr, sym("d"))
+            ))
+        );
+        assert_eq!(
+            Expression.parse("a || b || c")?.0,
+            Some(logical(
+                logical(sym("a"), LogicalOp::Or, sym("b")),
+                LogicalOp::Or,
+                sym("c")
+            ))
+        );
+        assert!(Expression.parse("a &&").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn imaginary_numbers() -> Result<()> {
+        assert_eq!(
+            Expression.parse("3 + 4i")?.0,
+            Some(bin(Expr::Integer(3), BinaryOp::Add, Expr::Imaginary(4.)))
+        );
+        assert_eq!(
+            Expression.parse("0.5i * i")?.0,
+            Some(bin(Expr::Imaginary(0.5), BinaryOp::Mul, sym("i")))
+        );
+        assert_eq!(
+            Expression.parse("1.5f * 2f")?.0,
+            Some(bin(Expr::FReal(1.5), BinaryOp::Mul, Expr::FReal(2.)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn regions() -> Result<()> {
+        assert_eq!(
+            Expression.parse("region 'r { x }")?.0,
+            Some(Expr::Region {
+                lifetime: Some("r".to_string()),
+                body: block(sym("x")),
+            })
+        );
+        assert_eq!(
+            Statement.parse("region { x } y")?,
+            (
+                Some(Stmt::Expr(Expr::Region {
+                    lifetime: None,
+                    body: block(sym("x")),
+                })),
+                12
+            )
+        );
+        assert!(Expression.parse("region 'r x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tasks() -> Result<()> {
+        assert_eq!(
+            Statement.parse("task { x } y")?,
+            (Some(Stmt::Expr(Expr::Task(block(sym("x"))))), 10)
+        );
+        assert!(Expression.parse("task x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spans() -> Result<()> {
+        let src = "fn f() {\n    x := \"{ { 1 } }\";\n    x\n}\nfn g() {}";
+        let (program, _) = Program.parse(src)?;
+        assert_eq!(program.spans, vec![0..38, 39..48]);
+        let ast::Item::Fn(f) = &program.items[0] else {
+            panic!("{:?} isn't a function", program.items[0]);
+        };
+        assert_eq!(f.body.spans, vec![13..29, 35..36]);
+        assert_eq!(&src[f.body.spans[1].clone()], "x");
+        // Code in a string is where the string is.
+        let Stmt::Let {
+            value: Expr::Interpolate { parts },
+            ..
+        } = &f.body.stmts[0]
+        else {
+            panic!("{:?} isn't a let", f.body.stmts[0]);
+        };
+        let ast::StrPart::Expr(Expr::Block(inner)) = &parts[0] else {
+            panic!("{parts:?} isn't a block in a string");
+        };
+        assert_eq!(inner.spans, vec![18..29]);
+        let err = Program.parse("fn f() {\n    x := 1\n    x\n}").unwrap_err();
+        assert_eq!(ast::Located::span(&err), Some(24..27));
+        Ok(())
+    }
+}
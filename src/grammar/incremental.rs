@@ -0,0 +1,192 @@
+//! Reparsing a file after an edit, for editors that reparse on every keystroke.
+//!
+//! A [`Document`] remembers where each of its items is. After an edit it reuses the items
+//! before the edit, and parses from the start of the first item the edit touches. Once that
+//! parse gets past the edit, to where an item started before the edit, the rest of the items are
+//! the same as before, so they're reused too. Items end at a `}` or a `;`, which means how they
+//! parse doesn't depend on the text after them, so the items are reused as they are, with the
+//! spans of the ones after the edit shifted. Usually only the item being edited is parsed.
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::ast;
+use crate::ast::visit::VisitMut;
+use crate::grammar::{error_from_start, from_start, syntax_error, Item, Respan};
+use crate::parser::*;
+use anyhow::*;
+use core::ops::Range;
+
+/// The source and syntax tree of a file, which are kept up to date by [`Document::edit`].
+#[derive(Clone, Debug)]
+pub struct Document {
+    src: String,
+    items: Vec<ast::Item>,
+    /// The first byte of each item, after the whitespace before it.
+    starts: Vec<usize>,
+    /// The byte after the end of each item.
+    ends: Vec<usize>,
+}
+
+impl Document {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut doc = Document {
+            src: String::new(),
+            items: vec![],
+            starts: vec![],
+            ends: vec![],
+        };
+        doc.edit(0..0, src)?;
+        Ok(doc)
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// The items of the source, or none if the last edit made it fail to parse.
+    pub fn items(&self) -> &[ast::Item] {
+        &self.items
+    }
+
+    /// The program, which is the same as parsing the whole source with
+    /// [`grammar::Program`](crate::grammar::Program).
+    pub fn program(&self) -> ast::Program {
+        ast::Program {
+            items: self.items.clone(),
+            spans: self
+                .starts
+                .iter()
+                .zip(&self.ends)
+                .map(|(&s, &e)| s..e)
+                .collect(),
+        }
+    }
+
+    /// Replaces the bytes `range` of the source with `text`, and reparses it, returning how many
+    /// items were parsed again.
+    ///
+    /// When the new source fails to parse, it's still the source of the document, but there are
+    /// no items until an edit fixes it, and that edit parses the whole source again.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<usize> {
+        ensure!(
+            range.start <= range.end && self.src.get(range.clone()).is_some(),
+            "edit of bytes {range:?} isn't within the {} bytes of the source",
+            self.src.len()
+        );
+        self.src.replace_range(range.clone(), text);
+        let reparsed = self.reparse(range, text.len());
+        if reparsed.is_err() {
+            self.items.clear();
+            self.starts.clear();
+            self.ends.clear();
+        }
+        reparsed
+    }
+
+    /// Reparses after the bytes `range` of the old source were replaced with `len` bytes.
+    fn reparse(&mut self, range: Range<usize>, len: usize) -> Result<usize> {
+        // Items ending right where the edit starts are parsed again, since the edit might
+        // continue them.
+        let first = self.ends.partition_point(|&end| end < range.start);
+        let mut rem = if first == 0 { 0 } else { self.ends[first - 1] };
+        let (mut items, mut spans) = (vec![], vec![]);
+        let mut rest = self.items.len();
+        loop {
+            let next = rem + whitespace(&self.src[rem..]);
+            if next >= range.start + len {
+                // Where `next` was before the edit, which is after the edit.
+                let old = next - (range.start + len) + range.end;
+                let n = self.starts.partition_point(|&start| start < old);
+                if n < self.items.len() && self.starts[n] == old {
+                    rest = n;
+                    break;
+                }
+            }
+            let parsed = Item.parse(&self.src[next..]);
+            match parsed.map_err(|e| error_from_start(e, self.src.len()))? {
+                (Some(mut item), n) => {
+                    Respan(from_start(self.src.len())).visit_item_mut(&mut item);
+                    items.push(item);
+                    spans.push((next, next + n));
+                    rem = next + n;
+                }
+                (None, _) if next == self.src.len() => break,
+                (None, _) => {
+                    let error = syntax_error(&self.src[next..], "expected item".into());
+                    return Err(error_from_start(error, self.src.len()));
+                }
+            }
+        }
+        let reparsed = items.len();
+        let shift = |n: usize| n + len + range.start - range.end;
+        let (starts, ends): (Vec<_>, Vec<_>) = spans.into_iter().unzip();
+        self.items.splice(first..rest, items);
+        self.starts.splice(first..rest, starts);
+        self.ends.splice(first..rest, ends);
+        let moved = first + reparsed..self.items.len();
+        let mut respan =
+            Respan(|span: &mut Range<usize>| *span = shift(span.start)..shift(span.end));
+        for item in &mut self.items[moved.clone()] {
+            respan.visit_item_mut(item);
+        }
+        for n in &mut self.starts[moved.clone()] {
+            *n = shift(*n);
+        }
+        for n in &mut self.ends[moved] {
+            *n = shift(*n);
+        }
+        Ok(reparsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::incremental::*;
+    use crate::grammar::Program;
+
+    /// Makes the edit, and checks that the document is the same as when it's parsed anew, down
+    /// to the spans, which equality leaves out.
+    fn edit(doc: &mut Document, range: Range<usize>, text: &str) -> Result<usize> {
+        let reparsed = doc.edit(range, text)?;
+        let program = Program.parse(doc.src())?.0;
+        assert_eq!(format!("{:?}", doc.program()), format!("{program:?}"));
+        Ok(reparsed)
+    }
+
+    #[test]
+    fn reuses_items() -> Result<()> {
+        let src = "fn a() { 1 }\nfn b() { 2 }\nstruct C { x: Real }\nfn d() { 4 }\n";
+        let mut doc = Document::parse(src)?;
+        assert_eq!(doc.items().len(), 4);
+        // Changing the body of `b` reparses only `b`.
+        let two = doc.src().find('2').unwrap();
+        assert_eq!(edit(&mut doc, two..two + 1, "2 + 20")?, 1);
+        // Inserting an item between two others parses just it, and the whitespace between them
+        // doesn't matter.
+        let c = doc.src().find("struct").unwrap();
+        assert_eq!(edit(&mut doc, c..c, "fn bc() {}\n")?, 1);
+        // Deleting the last item only parses the one the edit starts right after.
+        let (d, len) = (doc.src().find("\nfn d").unwrap(), doc.src().len());
+        assert_eq!(edit(&mut doc, d..len, "")?, 1);
+        assert_eq!(doc.items().len(), 4);
+        // An edit joining two items parses the one they become.
+        let joined = "}\nfn bc() {}";
+        let end = doc.src().find(joined).unwrap();
+        assert_eq!(edit(&mut doc, end..end + joined.len(), "; 3 }")?, 1);
+        assert_eq!(doc.items().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let mut doc = Document::parse("fn a() { 1 }\nfn b() { 2 }")?;
+        let brace = doc.src().rfind('}').unwrap();
+        assert!(doc.edit(brace..brace + 1, "").is_err());
+        assert_eq!(doc.src(), "fn a() { 1 }\nfn b() { 2 ");
+        assert!(doc.items().is_empty());
+        let end = doc.src().len();
+        assert_eq!(edit(&mut doc, end..end, "}")?, 2);
+        assert!(doc.edit(0..100, "").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,622 @@
+//! JSON for external tools, as emitted by `chantrs --emit tokens-json` and
+//! `chantrs --emit ast-json`, and read back with [`FromJson`].
+//!
+//! # Shape
+//! Every enum is an object with a `"kind"`, naming the variant, and the fields of the variant.
+//! Structs are objects with a key for each field, and tokens carry their `"span"` as
+//! `[start, end]` byte offsets into the source. So `x + 1` is the tokens
+//!
+//! ```json
+//! [{"kind":"Symbol","value":"x","span":[0,1]},
+//!  {"kind":"Operator","value":"+","span":[2,3]},
+//!  {"kind":"Integer","value":1,"span":[4,5]}]
+//! ```
+//!
+//! and the expression
+//!
+//! ```json
+//! {"kind":"Binary","op":"+","lhs":{"kind":"Symbol","name":"x"},"rhs":{"kind":"Integer","value":1}}
+//! ```
+//!
+//! Optional fields are `null` when absent, and numbers that aren't finite are `null` too, so
+//! they can't be read back. See [`crate::ast::json`] for the AST.
+//!
+//! # Versions
+//! The shape is version [`VERSION`], which a program carries as its `"version"`, and which tokens
+//! have too, though they don't carry it. The version goes up with every change that tools
+//! reading the JSON could trip over, like renaming or removing a key or a kind, but not with new
+//! keys. [`FromJson`] only reads the current version.
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::parser::{Segment, Token};
+use crate::tokenizer::Spanned;
+use alloc::sync::Arc;
+use anyhow::*;
+use core::fmt;
+use core::ops::Range;
+
+/// The version of the shape of the JSON.
+pub const VERSION: i64 = 1;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Integers are kept apart from other numbers, so that they aren't rounded.
+    Integer(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys are kept in order, so that the output is stable.
+    Object(Vec<(String, Json)>),
+}
+
+/// Conversion into the JSON shape documented in [`crate::json`].
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+/// Conversion back from the JSON shape documented in [`crate::json`], so that
+/// `T::from_json(&t.to_json())` is `t`.
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self>;
+}
+
+impl Json {
+    /// Parses JSON text.
+    pub fn parse(src: &str) -> Result<Json> {
+        let mut reader = Reader { src, at: 0 };
+        let json = reader.value()?;
+        reader.whitespace();
+        if reader.at != src.len() {
+            bail!("expected the end of the JSON at byte {}", reader.at)
+        }
+        Ok(json)
+    }
+
+    /// The value of the key `key` of an object.
+    pub fn get(&self, key: &str) -> Result<&Json> {
+        let Json::Object(fields) = self else {
+            bail!("expected an object with {key:?}, got {self}")
+        };
+        match fields.iter().find(|(k, _)| k == key) {
+            Some((_, value)) => Ok(value),
+            None => bail!("expected an object with {key:?}, got {self}"),
+        }
+    }
+
+    /// Reads the value of the key `key` of an object.
+    pub fn field<T: FromJson>(&self, key: &str) -> Result<T> {
+        T::from_json(self.get(key)?).with_context(|| format!("in {key:?}"))
+    }
+
+    /// The `"kind"` of an object for an enum variant.
+    pub fn kind(&self) -> Result<&str> {
+        self.get("kind")?.as_str()
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => bail!("expected a string, got {self}"),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Json::Bool(b) => Ok(*b),
+            _ => bail!("expected a boolean, got {self}"),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64> {
+        match self {
+            Json::Integer(n) => Ok(*n),
+            _ => bail!("expected an integer, got {self}"),
+        }
+    }
+
+    /// The number, which may have been written as an integer, like `2` for `2.0`.
+    pub fn as_f64(&self) -> Result<f64> {
+        match self {
+            Json::Integer(n) => Ok(*n as f64),
+            Json::Number(x) => Ok(*x),
+            _ => bail!("expected a number, got {self}"),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Json]> {
+        match self {
+            Json::Array(items) => Ok(items),
+            _ => bail!("expected an array, got {self}"),
+        }
+    }
+}
+
+/// Reads JSON text from `at`.
+struct Reader<'s> {
+    src: &'s str,
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn whitespace(&mut self) {
+        let rest = &self.src[self.at..];
+        self.at += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// Consumes `s`, if it's next.
+    fn eat(&mut self, s: &str) -> bool {
+        self.whitespace();
+        let found = self.src[self.at..].starts_with(s);
+        if found {
+            self.at += s.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.whitespace();
+        let rest = &self.src[self.at..];
+        for (word, json) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if self.eat(word) {
+                return Ok(json);
+            }
+        }
+        match rest.chars().next() {
+            Some('"') => self.string().map(Json::String),
+            Some('[') => {
+                self.at += 1;
+                let mut items = vec![];
+                if self.eat("]") {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat("]") {
+                        return Ok(Json::Array(items));
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Some('{') => {
+                self.at += 1;
+                let mut fields = vec![];
+                if self.eat("}") {
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    if self.eat("}") {
+                        return Ok(Json::Object(fields));
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => bail!("expected a JSON value at byte {}", self.at),
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<()> {
+        match self.eat(s) {
+            true => Ok(()),
+            false => bail!("expected `{s}` at byte {}", self.at),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let rest = &self.src[self.at..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let text = &rest[..len];
+        self.at += len;
+        if let Result::Ok(n) = text.parse() {
+            return Ok(Json::Integer(n));
+        }
+        match text.parse() {
+            Result::Ok(x) => Ok(Json::Number(x)),
+            Err(_) => bail!("invalid number {text:?} in JSON"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if !self.src[self.at..].starts_with('"') {
+            bail!("expected a string at byte {}", self.at)
+        }
+        self.at += 1;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.src[self.at..].chars().next() else {
+                bail!("unterminated string in JSON")
+            };
+            self.at += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(c) = self.src[self.at..].chars().next() else {
+                        bail!("unterminated string in JSON")
+                    };
+                    self.at += 1;
+                    s.push(match c {
+                        '"' | '\\' | '/' => c,
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => self.unicode()?,
+                        _ => bail!("unknown escape `\\{c}` in JSON"),
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which is two of them for one outside of the BMP.
+    fn unicode(&mut self) -> Result<char> {
+        let mut n = self.hex()?;
+        if (0xd800..0xdc00).contains(&n) {
+            if !self.src[self.at..].starts_with("\\u") {
+                bail!("expected the rest of a surrogate pair in JSON")
+            }
+            self.at += 2;
+            let low = self.hex()?;
+            n = 0x10000 + ((n - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+        }
+        match char::from_u32(n) {
+            Some(c) => Ok(c),
+            None => bail!("`\\u` escape of {n:#x} isn't a character"),
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32> {
+        let digits = self.src.get(self.at..self.at + 4);
+        let n = digits.and_then(|d| u32::from_str_radix(d, 16).ok());
+        self.at += 4;
+        n.ok_or_else(|| anyhow!("expected 4 hex digits after `\\u` in JSON"))
+    }
+}
+
+/// An object for the enum variant `kind`, with `fields`.
+pub fn node(kind: &str, fields: Vec<(&str, Json)>) -> Json {
+    let mut object = vec![("kind".to_string(), Json::from(kind))];
+    object.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+    Json::Object(object)
+}
+
+/// An object for a struct with `fields`.
+pub fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(x: f64) -> Self {
+        Json::Number(x)
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Arc<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::from(self.as_str())
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self> {
+        let items = json.as_array()?.iter().enumerate();
+        items
+            .map(|(n, item)| T::from_json(item).with_context(|| format!("in item {n}")))
+            .collect()
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self> {
+        match json {
+            Json::Null => Ok(None),
+            json => T::from_json(json).map(Some),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(json: &Json) -> Result<Self> {
+        T::from_json(json).map(Box::new)
+    }
+}
+
+impl<T: FromJson> FromJson for Arc<T> {
+    fn from_json(json: &Json) -> Result<Self> {
+        T::from_json(json).map(Arc::new)
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<Self> {
+        json.as_str().map(String::from)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self> {
+        json.as_bool()
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(json: &Json) -> Result<Self> {
+        json.as_f64()
+    }
+}
+
+impl FromJson for i64 {
+    fn from_json(json: &Json) -> Result<Self> {
+        json.as_i64()
+    }
+}
+
+impl FromJson for usize {
+    fn from_json(json: &Json) -> Result<Self> {
+        let n = json.as_i64()?;
+        n.try_into()
+            .map_err(|_| anyhow!("expected a size, got {n}"))
+    }
+}
+
+/// A span, as `[start, end]`.
+pub fn span(span: &Range<usize>) -> Json {
+    Json::Array(vec![
+        Json::Integer(span.start as i64),
+        Json::Integer(span.end as i64),
+    ])
+}
+
+/// Reads a span written by [`span`].
+pub fn span_from_json(json: &Json) -> Result<Range<usize>> {
+    match json.as_array()? {
+        [start, end] => Ok(usize::from_json(start)?..usize::from_json(end)?),
+        _ => bail!("expected a span of [start, end], got {json}"),
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        match self {
+            Token::Symbol(s) => node("Symbol", vec![("value", s.to_json())]),
+            Token::Integer(n) => node("Integer", vec![("value", Json::Integer(*n))]),
+            Token::Real(x) => node("Real", vec![("value", Json::from(*x))]),
+            Token::Imaginary(x) => node("Imaginary", vec![("value", Json::from(*x))]),
+            Token::FReal(x) => node("FReal", vec![("value", Json::from(*x))]),
+            Token::String(s) => node("String", vec![("value", s.to_json())]),
+            Token::Interpolated(segments) => {
+                node("Interpolated", vec![("segments", segments.to_json())])
+            }
+            Token::Operator(s) => node("Operator", vec![("value", s.to_json())]),
+            Token::Separator(c) => node("Separator", vec![("value", c.to_string().to_json())]),
+            Token::Lifetime(s) => node("Lifetime", vec![("value", s.to_json())]),
+            Token::Blank => node("Blank", vec![]),
+        }
+    }
+}
+
+impl FromJson for Token {
+    fn from_json(json: &Json) -> Result<Self> {
+        let value = || json.get("value");
+        Ok(match json.kind()? {
+            "Symbol" => Token::Symbol(json.field("value")?),
+            "Integer" => Token::Integer(json.field("value")?),
+            "Real" => Token::Real(json.field("value")?),
+            "Imaginary" => Token::Imaginary(json.field("value")?),
+            "FReal" => Token::FReal(json.field("value")?),
+            "String" => Token::String(json.field("value")?),
+            "Interpolated" => Token::Interpolated(json.field("segments")?),
+            "Operator" => Token::Operator(json.field("value")?),
+            "Separator" => {
+                let mut chars = value()?.as_str()?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Token::Separator(c),
+                    _ => bail!("expected a separator of one character, got {}", value()?),
+                }
+            }
+            "Lifetime" => Token::Lifetime(json.field("value")?),
+            "Blank" => Token::Blank,
+            kind => bail!("unknown kind of token {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Segment {
+    fn to_json(&self) -> Json {
+        match self {
+            Segment::Text(s) => node("Text", vec![("value", s.to_json())]),
+            Segment::Code(s) => node("Code", vec![("value", s.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Segment {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(match json.kind()? {
+            "Text" => Segment::Text(json.field("value")?),
+            "Code" => Segment::Code(json.field("value")?),
+            kind => bail!("unknown kind of segment {kind:?}"),
+        })
+    }
+}
+
+impl ToJson for Spanned {
+    fn to_json(&self) -> Json {
+        let Json::Object(mut fields) = self.token.to_json() else {
+            unreachable!()
+        };
+        fields.push(("span".to_string(), span(&self.span)));
+        Json::Object(fields)
+    }
+}
+
+impl FromJson for Spanned {
+    fn from_json(json: &Json) -> Result<Self> {
+        Ok(Spanned {
+            token: Token::from_json(json)?,
+            span: span_from_json(json.get("span")?)?,
+        })
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Integer(n) => write!(f, "{n}"),
+            // Debug keeps the `.0` of whole numbers, so that they read back as numbers.
+            Json::Number(x) if x.is_finite() => write!(f, "{x:?}"),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (n, item) in items.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in fields.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{value}", Json::from(key.as_str()))?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::*;
+    use crate::tokenizer::tokenize;
+    use anyhow::Result;
+
+    #[test]
+    fn tokens() -> Result<()> {
+        assert_eq!(
+            tokenize("x + 1")?.to_json().to_string(),
+            r#"[{"kind":"Symbol","value":"x","span":[0,1]},{"kind":"Operator","value":"+","span":[2,3]},{"kind":"Integer","value":1,"span":[4,5]}]"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tokens_round_trip() -> Result<()> {
+        let tokens = tokenize("x.0 += \"a{b}\\n\" 'r 1.5f 2i 3.25 {}")?;
+        let json = Json::parse(&tokens.to_json().to_string())?;
+        assert_eq!(Vec::<Spanned>::from_json(&json)?, tokens);
+        Ok(())
+    }
+
+    #[test]
+    fn parses() -> Result<()> {
+        let src = r#" {"a": [1, -2.5e3, true, null], "b\u00e9\ud83d\ude00\n": {}} "#;
+        assert_eq!(
+            Json::parse(src)?,
+            Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Integer(1),
+                        Json::Number(-2500.0),
+                        Json::Bool(true),
+                        Json::Null,
+                    ])
+                ),
+                ("bé😀\n".to_string(), Json::Object(vec![])),
+            ])
+        );
+        // What's written reads back the same.
+        let json = Json::parse(src)?;
+        assert_eq!(Json::parse(&json.to_string())?, json);
+        for bad in ["[1,]", "{\"a\" 1}", "\"a", "[] []", "\"\\x\"", "1e"] {
+            assert!(Json::parse(bad).is_err(), "{bad:?} parses");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(Json::from("a\"\\\n\u{1}").to_string(), r#""a\"\\\n\u0001""#);
+        assert_eq!(Json::Number(0.5).to_string(), "0.5");
+        assert_eq!(Json::Number(2.0).to_string(), "2.0");
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+    }
+}
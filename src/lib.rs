@@ -0,0 +1,149 @@
+//! # Chant
+//! The chant programming language, as a library for running chant programs from Rust.
+//!
+//! ```
+//! use chant::prelude::*;
+//!
+//! # fn main() -> Result<(), Diagnostic> {
+//! let session = Compiler::new().compile("fn main() { 1 + 2 }")?;
+//! assert_eq!(session.run()?.to_string(), "3");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Stability
+//! The items of [`prelude`] are the stable API. [`Compiler`] compiles source into a [`Session`],
+//! whose functions take and return [`Value`]s, and everything that fails is a [`Diagnostic`].
+//! They follow semver: while chant is 0.x, only a new minor version breaks them, and enums that
+//! will get more variants, like [`Value`], are `#[non_exhaustive]`, so adding one isn't a break.
+//! The modules of the stages below are public for tools that need more, like editors, but
+//! change with the implementation, in any release.
+//!
+//! [`parse`], [`compile`] and [`eval()`] go from source code to an AST, a compiled module and the
+//! value of `main`. Their errors are [`anyhow::Error`]s, with context saying where they come
+//! from, and the runtime errors a host may want to handle, like running out of memory, can be
+//! downcast to [`eval::RuntimeError`]. The stages are exposed on their own too:
+//! - [`tokenizer`] splits source code into the tokens of [`parser`].
+//! - [`grammar`] parses tokens into the syntax tree of [`ast`], and [`loader`] parses programs
+//!   spread over multiple files. [`grammar::incremental`] reparses a file after an edit.
+//! - [`check`] checks syntax trees.
+//! - [`eval`](mod@eval) compiles and runs them, with [`eval::Machine`], on the values of
+//!   [`eval::value`].
+//!   [`eval::convert`] converts between those values and Rust data, and [`eval::host`] makes Rust
+//!   functions callable from programs.
+//!
+//! With the `wasm` feature, the module `wasm` exports functions for compiling and running
+//! programs from JavaScript. The `chant-capi` crate, in `capi/`, is a C interface for embedding
+//! chant in hosts that aren't written in Rust.
+//!
+//! # `no_std`
+//! Without the default `std` feature, the crate is `no_std`, and only has the front end, from
+//! [`tokenizer`] to [`check`] and [`json`], which only need [`alloc`]. That's enough to use chant
+//! as a configuration language in embedded and kernel code, which can evaluate the syntax trees
+//! itself.
+//!
+//! # Strong mathematical numerical type system
+//! - Natural numbers  (unsigned int)
+//! - Integer          (signed int)
+//! - Real numbers     (float)
+//! - Complex
+//! - Fast floats
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+pub mod ast;
+pub mod check;
+#[cfg(feature = "std")]
+pub mod eval;
+// Only public for the fuzz targets and `examples/fuzz.rs`, and not part of the API.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod fuzz;
+pub mod grammar;
+pub mod json;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod num;
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod runtime;
+#[cfg(feature = "std")]
+pub mod session;
+pub mod tokenizer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use eval::value::Value;
+#[cfg(feature = "std")]
+pub use session::{Compiler, Diagnostic, Session, Stage};
+
+/// The stable API, see [the crate docs](crate#stability).
+#[cfg(feature = "std")]
+pub mod prelude {
+    pub use crate::eval::convert::{FromChant, ToChant};
+    pub use crate::eval::host::HostModule;
+    pub use crate::eval::{Limits, RuntimeError};
+    pub use crate::host_module;
+    pub use crate::num::natural::SubPolicy;
+    pub use crate::{Compiler, Diagnostic, Session, Stage, Value};
+}
+
+extern crate alloc;
+
+/// What the std prelude has, and `alloc` has too, for the modules of the front end when they're
+/// built without `std`.
+#[cfg(not(feature = "std"))]
+mod alloc_prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
+
+use anyhow::*;
+use parser::Parser;
+
+// Hosts choose the global allocator, but tests of heap limits need this one.
+#[cfg(all(test, feature = "std"))]
+#[global_allocator]
+static ALLOCATOR: eval::heap::Counting = eval::heap::Counting;
+
+/// Parses `src` as a program, and checks it.
+pub fn parse(src: &str) -> Result<ast::Program> {
+    let (program, _) = grammar::Program.parse(src)?;
+    check::regions(&program)?;
+    Ok(program)
+}
+
+/// Parses `src` as a program, and compiles it to be run by an [`eval::Machine`].
+#[cfg(feature = "std")]
+pub fn compile(src: &str) -> Result<eval::compile::Module> {
+    eval::compile::Module::compile(&parse(src)?)
+}
+
+/// Parses `src` as a program, and returns the result of calling its `main` function.
+#[cfg(feature = "std")]
+pub fn eval(src: &str) -> Result<eval::value::Value> {
+    eval::run(&parse(src)?)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::eval::value::Value;
+    use crate::eval::{Limits, Machine};
+    use crate::*;
+
+    #[test]
+    fn embedding() -> Result<()> {
+        let module = compile("fn square(x: Integer) -> Integer { x * x }")?;
+        let mut machine = Machine::new(&module, Limits::default())?;
+        let three = Value::Integer(num::integer::Integer::new(3));
+        assert_eq!(machine.call("square", vec![three])?.to_string(), "9");
+        assert_eq!(eval("fn main() { 1 + 2 }")?.to_string(), "3");
+        let err = eval("fn main() { region 'r { x: &'r Int } }").unwrap_err();
+        assert_eq!(err.to_string(), "a reference into region 'r escapes it");
+        Ok(())
+    }
+}
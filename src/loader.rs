@@ -0,0 +1,259 @@
+//! Loads chant programs spread over multiple files.
+//!
+//! A module declared as `mod name;` in `dir/main.chant` is loaded from `dir/name.chant`. Modules
+//! declared in `dir/name.chant` are in turn loaded from `dir/name/`. Large files are mapped into
+//! memory rather than read, see [`source`].
+
+pub mod source;
+
+use crate::ast::{Item, Program};
+use crate::check;
+use crate::grammar;
+use crate::parser::Parser;
+use anyhow::*;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// File extension of chant source files.
+pub const EXTENSION: &str = "chant";
+
+/// Parses the file at `path`, and all the modules it declares.
+///
+/// Files are parsed and checked on a pool of threads, each as soon as the file declaring it has
+/// been parsed. When files fail to load, the errors are in the order their modules are declared
+/// in, rather than the order they happened to be parsed in.
+pub fn load(path: &Path) -> Result<Program> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let loader = Loader::default();
+    loader.push(path.to_path_buf(), dir.to_path_buf());
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loader.work());
+        }
+    });
+    let mut files = loader.state.into_inner().unwrap().files;
+    let mut errors = vec![];
+    let program = assemble(&mut files, 0, &mut errors);
+    match errors.len() {
+        0 => Ok(program),
+        1 => Err(errors.remove(0)),
+        n => {
+            let errors: Vec<_> = errors.iter().map(|e| format!("{e:#}")).collect();
+            bail!("{n} files failed to load:\n{}", errors.join("\n"))
+        }
+    }
+}
+
+/// A parsed file.
+struct File {
+    program: Result<Program>,
+    /// The files of the modules the file declares, in the order they're declared in.
+    modules: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Loader {
+    state: Mutex<State>,
+    /// Notified when there are files in the queue, or none are left to parse.
+    work: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Files to parse, with the directories to load their modules from.
+    queue: VecDeque<(usize, PathBuf, PathBuf)>,
+    /// Files by id, which are `None` until they're parsed.
+    files: Vec<Option<File>>,
+    /// Files that are queued, or being parsed.
+    pending: usize,
+}
+
+impl Loader {
+    /// Queues the file at `path`, loading its modules from `dir`, and returns its id.
+    fn push(&self, path: PathBuf, dir: PathBuf) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let id = state.files.len();
+        state.files.push(None);
+        state.queue.push_back((id, path, dir));
+        state.pending += 1;
+        self.work.notify_one();
+        id
+    }
+
+    /// Parses files until there are none left.
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let Some((id, path, dir)) = state.queue.pop_front() else {
+                if state.pending == 0 {
+                    return;
+                }
+                state = self.work.wait(state).unwrap();
+                continue;
+            };
+            drop(state);
+            let file = self.parse(&path, &dir);
+            state = self.state.lock().unwrap();
+            state.files[id] = Some(file);
+            // Modules of the file were queued while parsing it, so this only reaches 0 once
+            // every file has been parsed.
+            state.pending -= 1;
+            if state.pending == 0 {
+                self.work.notify_all();
+            }
+        }
+    }
+
+    /// Parses and checks the file at `path`, and queues the files of its modules.
+    fn parse(&self, path: &Path, dir: &Path) -> File {
+        let mut modules = vec![];
+        let program = parse_file(path).inspect(|p| self.declare(&p.items, dir, &mut modules));
+        File { program, modules }
+    }
+
+    /// Queues the file of every `mod name;` in `items`, loading it from `dir`.
+    fn declare(&self, items: &[Item], dir: &Path, modules: &mut Vec<usize>) {
+        for item in items {
+            if let Item::Mod { name, items } = item {
+                let dir = dir.join(name);
+                match items {
+                    Some(items) => self.declare(items, &dir, modules),
+                    None => modules.push(self.push(dir.with_extension(EXTENSION), dir)),
+                }
+            }
+        }
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Program> {
+    let src = source::Source::open(path)?;
+    let (program, _) = grammar::Program
+        .parse(&src)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    check::regions(&program).with_context(|| format!("checking {}", path.display()))?;
+    Ok(program)
+}
+
+/// The file `id`, with the contents of its modules filled in, or no items if it failed to load,
+/// adding its errors and those of its modules to `errors`. The spans of the items are in the
+/// file, and the items filled in are in files of their own, so they have none.
+fn assemble(files: &mut [Option<File>], id: usize, errors: &mut Vec<Error>) -> Program {
+    let file = files[id].take().unwrap();
+    match file.program {
+        Result::Ok(mut program) => {
+            fill(
+                &mut program.items,
+                &mut file.modules.into_iter(),
+                files,
+                errors,
+            );
+            program
+        }
+        Err(error) => {
+            errors.push(error);
+            Program {
+                items: vec![],
+                spans: vec![],
+            }
+        }
+    }
+}
+
+/// Fills in the contents of every `mod name;` in `items`, from the files `modules`.
+fn fill(
+    items: &mut [Item],
+    modules: &mut impl Iterator<Item = usize>,
+    files: &mut [Option<File>],
+    errors: &mut Vec<Error>,
+) {
+    for item in items {
+        if let Item::Mod { items, .. } = item {
+            match items {
+                Some(items) => fill(items, modules, files, errors),
+                None => *items = Some(assemble(files, modules.next().unwrap(), errors).items),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Writes `files` to a fresh temporary directory.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("chant-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, src) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, src)?;
+        }
+        Ok(dir)
+    }
+
+    fn module<'a>(items: &'a [Item], name: &str) -> Option<&'a [Item]> {
+        items.iter().find_map(|item| match item {
+            Item::Mod { name: n, items } if n == name => items.as_deref(),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn nested_modules() -> Result<()> {
+        let dir = write_files(
+            "nested-modules",
+            &[
+                ("main.chant", "mod geometry; use geometry.area;"),
+                ("geometry.chant", "mod shapes; mod util { mod fmt; }"),
+                ("geometry/shapes.chant", "fn circle() {}"),
+                ("geometry/util/fmt.chant", "fn show() {}"),
+            ],
+        )?;
+        let program = load(&dir.join("main.chant"))?;
+        let geometry = module(&program.items, "geometry").unwrap();
+        assert!(matches!(module(geometry, "shapes"), Some([Item::Fn(_)])));
+        let util = module(geometry, "util").unwrap();
+        assert!(matches!(module(util, "fmt"), Some([Item::Fn(_)])));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn errors_in_order() -> Result<()> {
+        let dir = write_files(
+            "errors-in-order",
+            &[
+                ("main.chant", "mod a; mod b { mod c; } mod d;"),
+                ("a.chant", "fn f() { region 'r { x: &'r Int } }"),
+                ("b/c.chant", "fn ("),
+            ],
+        )?;
+        let main = dir.join("main.chant");
+        for _ in 0..10 {
+            let err = format!("{:#}", load(&main).unwrap_err());
+            let lines: Vec<_> = err.lines().collect();
+            assert_eq!(lines.len(), 4);
+            assert_eq!(lines[0], "3 files failed to load:");
+            assert!(lines[1].starts_with("checking ") && lines[1].contains("a.chant"));
+            assert!(lines[2].starts_with("parsing ") && lines[2].contains("c.chant"));
+            assert!(lines[3].starts_with("reading ") && lines[3].contains("d.chant"));
+        }
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn missing_module() -> Result<()> {
+        let dir = write_files("missing-module", &[("main.chant", "mod nope;")])?;
+        let err = load(&dir.join("main.chant")).unwrap_err();
+        assert!(format!("{err:#}").contains("nope.chant"));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
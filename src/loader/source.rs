@@ -0,0 +1,162 @@
+//! Reading source files, by mapping the large ones into memory rather than copying them.
+//!
+//! Generated sources can be hundreds of megabytes, which reading into a `String` copies, and
+//! which the lexer only ever reads once, front to back. Mapping them lets the lexer borrow slices
+//! of the page cache directly. Checking that the file is UTF-8 still reads all of it, but doesn't
+//! need the memory for a copy.
+//!
+//! The map is private and read-only, but the file can still change under it, which would change
+//! the source while it's being lexed, or truncating the file would crash the process. That's
+//! what every tool that maps files has to live with, so files shouldn't be edited while they're
+//! being loaded.
+
+use anyhow::*;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Files smaller than this are read, since mapping them costs more than copying them.
+pub const MAP_THRESHOLD: u64 = 1 << 20;
+
+/// The contents of a source file, which derefs to a `str`.
+pub struct Source(Contents);
+
+enum Contents {
+    Read(String),
+    #[cfg(unix)]
+    Mapped(map::Map),
+}
+
+impl Source {
+    /// Reads the file at `path`, mapping it if it's at least [`MAP_THRESHOLD`] bytes.
+    pub fn open(path: &Path) -> Result<Self> {
+        let read = || format!("reading {}", path.display());
+        let mut file = File::open(path).with_context(read)?;
+        #[cfg(unix)]
+        {
+            let len = file.metadata().with_context(read)?.len();
+            if len >= MAP_THRESHOLD {
+                let map = map::Map::new(&file, len).with_context(read)?;
+                std::str::from_utf8(map.bytes())
+                    .with_context(|| format!("{} isn't UTF-8", path.display()))?;
+                return Ok(Source(Contents::Mapped(map)));
+            }
+        }
+        let mut src = String::new();
+        file.read_to_string(&mut src).with_context(read)?;
+        Ok(Source(Contents::Read(src)))
+    }
+
+    /// Whether the file was mapped, rather than read.
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self.0, Contents::Read(_))
+    }
+}
+
+impl Deref for Source {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &self.0 {
+            Contents::Read(src) => src,
+            // The bytes were checked to be UTF-8 when the file was mapped.
+            #[cfg(unix)]
+            Contents::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map.bytes()) },
+        }
+    }
+}
+
+#[cfg(unix)]
+mod map {
+    use anyhow::*;
+    use std::ffi::{c_int, c_void};
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+    const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    /// A read-only map of a whole file, which is unmapped when dropped.
+    pub struct Map {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    // The map is never written to, so it can be shared like a `&[u8]`.
+    unsafe impl Send for Map {}
+    unsafe impl Sync for Map {}
+
+    impl Map {
+        /// Maps the `len` bytes of `file`, which can't be 0.
+        pub fn new(file: &File, len: u64) -> Result<Self> {
+            let len = usize::try_from(len)?;
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ,
+                    MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == MAP_FAILED {
+                return Err(std::io::Error::last_os_error()).context("mapping the file");
+            }
+            Ok(Map { ptr, len })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Map {
+        fn drop(&mut self) {
+            unsafe {
+                munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::source::*;
+    use std::fs;
+
+    #[test]
+    fn open() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("chant-{name}-{}.chant", std::process::id()));
+        let (small, large) = (path("small"), path("large"));
+        let item = "fn answer() { 42 }\n";
+        let src = item.repeat(MAP_THRESHOLD as usize / item.len() + 1);
+        fs::write(&small, item)?;
+        fs::write(&large, &src)?;
+        let (small_src, large_src) = (Source::open(&small)?, Source::open(&large)?);
+        assert_eq!((&*small_src, small_src.is_mapped()), (item, false));
+        assert_eq!(&*large_src, src);
+        assert_eq!(large_src.is_mapped(), cfg!(unix));
+        drop(large_src);
+        fs::write(&large, [0xff].repeat(MAP_THRESHOLD as usize))?;
+        assert!(Source::open(&large).is_err());
+        fs::remove_file(small)?;
+        fs::remove_file(large)?;
+        Ok(())
+    }
+}
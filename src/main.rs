@@ -1,13 +1,64 @@
-//! # Strong mathematical numerical type system
-//! - Natural numbers  (unsigned int)
-//! - Integer          (signed int)
-//! - Real numbers     (float)
-//! - Complex
-//! - Fast floats
-
-//mod tokenizer;
-mod parser;
-
-fn main() {
-    println!("Hello, world!");
+//! The `chantrs` command, for running chant programs and inspecting how they're parsed.
+
+use anyhow::*;
+use chant::eval::Limits;
+use chant::json::ToJson;
+use chant::{eval, loader, tokenizer};
+use std::path::Path;
+
+#[global_allocator]
+static ALLOCATOR: eval::heap::Counting = eval::heap::Counting;
+
+const USAGE: &str = concat!(
+    "usage: chantrs --emit <tokens-json|ast-json> <file>, ",
+    "or chantrs --run [--deterministic] [--max-heap <bytes>] <file>"
+);
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (emit, path) = match args.split_first() {
+        Some((flag, [emit, path])) if flag == "--emit" => (emit, path),
+        Some((flag, options)) if flag == "--run" => {
+            let (path, limits) = run_options(options)?;
+            return run(path, limits);
+        }
+        _ => bail!(USAGE),
+    };
+    let json = match emit.as_str() {
+        "tokens-json" => {
+            let src = loader::source::Source::open(Path::new(path))?;
+            tokenizer::tokenize(&src)?.to_json()
+        }
+        "ast-json" => loader::load(Path::new(path))?.to_json(),
+        _ => bail!("can't emit {emit:?}, {USAGE}"),
+    };
+    println!("{json}");
+    Ok(())
+}
+
+/// The file to run, after the options, and the limits the options set.
+fn run_options(args: &[String]) -> Result<(&str, Limits)> {
+    let mut limits = Limits::default();
+    let mut args = args.iter().map(String::as_str);
+    loop {
+        match args.next() {
+            Some("--deterministic") => limits = limits.deterministic(true),
+            Some("--max-heap") => {
+                let bytes = args.next().context(USAGE)?;
+                let bytes = bytes.parse().with_context(|| {
+                    format!("--max-heap takes a number of bytes, not {bytes:?}")
+                })?;
+                limits = limits.max_heap(Some(bytes));
+            }
+            Some(path) if args.len() == 0 => return Ok((path, limits)),
+            _ => bail!(USAGE),
+        }
+    }
+}
+
+fn run(path: &str, limits: Limits) -> Result<()> {
+    eval::interrupt::install()?;
+    let value = eval::run_with(&loader::load(Path::new(path))?, limits)?;
+    println!("{value}");
+    Ok(())
 }
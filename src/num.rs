@@ -0,0 +1,139 @@
+//! Numeric types of chant, from the strong mathematical numerical type system in the crate
+//! docs.
+
+use crate::ast::Expr;
+
+pub mod complex;
+pub mod freal;
+pub mod integer;
+pub mod natural;
+pub mod real;
+
+/// The numeric types, as the type checker sees them.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum NumType {
+    /// Never negative, see [`natural::Natural`].
+    Natural,
+    /// See [`integer::Integer`].
+    Integer,
+    /// See [`real::Real`].
+    Real,
+    /// See [`complex::Complex`].
+    Complex,
+    /// Fast floats, see [`freal::FReal`]. Never widened to or from.
+    FReal,
+}
+
+impl NumType {
+    /// The type named `name` in the source, like `Natural` in `x: Natural`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Natural" => NumType::Natural,
+            "Integer" => NumType::Integer,
+            "Real" => NumType::Real,
+            "Complex" => NumType::Complex,
+            "FReal" => NumType::FReal,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NumType::Natural => "Natural",
+            NumType::Integer => "Integer",
+            NumType::Real => "Real",
+            NumType::Complex => "Complex",
+            NumType::FReal => "FReal",
+        }
+    }
+
+    /// Whether a value of this type can be used where `to` is expected, without an explicit
+    /// conversion. This is the only place implicit widening is defined: `Natural -> Integer ->
+    /// Real -> Complex`. Narrowing, like `Integer -> Natural`, is always explicit, and `FReal`
+    /// is outside the chain, so mixing it with `Real` is a type error.
+    pub fn widens_to(self, to: NumType) -> bool {
+        match (self.rank(), to.rank()) {
+            (Some(from), Some(to)) => from <= to,
+            _ => self == to,
+        }
+    }
+
+    /// The type both operands of a binary operator are widened to, like `Real` for `1 + 0.5`,
+    /// or `None` when neither widens to the other.
+    pub fn join(self, other: NumType) -> Option<NumType> {
+        if self.widens_to(other) {
+            Some(other)
+        } else if other.widens_to(self) {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    /// Position in the chain of widenings, if the type is in it.
+    fn rank(self) -> Option<u8> {
+        Some(match self {
+            NumType::Natural => 0,
+            NumType::Integer => 1,
+            NumType::Real => 2,
+            NumType::Complex => 3,
+            NumType::FReal => return None,
+        })
+    }
+
+    /// The type of a numeric literal. Literals without a fractional part are integers, and
+    /// widen to whatever type they are used as.
+    pub fn of_literal(e: &Expr) -> Option<NumType> {
+        match e {
+            Expr::Integer(_) => Some(NumType::Integer),
+            Expr::Real(_) => Some(NumType::Real),
+            Expr::Imaginary(_) => Some(NumType::Complex),
+            Expr::FReal(_) => Some(NumType::FReal),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::*;
+
+    #[test]
+    fn names() {
+        for ty in [
+            NumType::Natural,
+            NumType::Integer,
+            NumType::Real,
+            NumType::Complex,
+            NumType::FReal,
+        ] {
+            assert_eq!(NumType::from_name(ty.name()), Some(ty));
+        }
+        assert_ne!(NumType::from_name("Natural"), NumType::from_name("Integer"));
+        assert_eq!(NumType::from_name("Vec"), None);
+    }
+
+    #[test]
+    fn widening() {
+        use NumType::*;
+        assert!(Natural.widens_to(Integer));
+        assert!(Integer.widens_to(Real));
+        assert!(Natural.widens_to(Real));
+        assert!(Real.widens_to(Complex));
+        assert!(!Complex.widens_to(Real));
+        assert!(!Real.widens_to(Integer));
+        assert!(!Integer.widens_to(Natural));
+        assert_eq!(Integer.join(Real), Some(Real));
+        assert_eq!(Real.join(Natural), Some(Real));
+        assert_eq!(Natural.join(Natural), Some(Natural));
+        assert!(FReal.widens_to(FReal));
+        assert!(!Real.widens_to(FReal));
+        assert!(!FReal.widens_to(Real));
+        assert!(!Integer.widens_to(FReal));
+        assert_eq!(Real.join(FReal), None);
+        assert_eq!(NumType::of_literal(&Expr::Integer(1)), Some(Integer));
+        assert_eq!(NumType::of_literal(&Expr::Real(0.5)), Some(Real));
+        assert_eq!(NumType::of_literal(&Expr::Imaginary(4.)), Some(Complex));
+        assert_eq!(NumType::of_literal(&Expr::FReal(1.5)), Some(FReal));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::num::integer::Integer;
+use crate::num::natural::Natural;
+use crate::num::real::Real;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A complex number, with real and imaginary parts. Imaginary literals, like `4i`, are complex
+/// numbers without a real part.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Complex {
+    re: Real,
+    im: Real,
+}
+
+impl Complex {
+    pub fn new(re: Real, im: Real) -> Self {
+        Complex { re, im }
+    }
+
+    /// The value of an imaginary literal, like `4i`.
+    pub fn imaginary(im: Real) -> Self {
+        Complex::new(Real::default(), im)
+    }
+
+    pub fn re(self) -> Real {
+        self.re
+    }
+
+    pub fn im(self) -> Real {
+        self.im
+    }
+
+    /// The magnitude, or distance from zero.
+    pub fn abs(self) -> Real {
+        Real::new(self.re.get().hypot(self.im.get()))
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// The angle from the positive real axis, in radians.
+    pub fn arg(self) -> Real {
+        Real::new(self.im.get().atan2(self.re.get()))
+    }
+
+    /// The principal value of `self ^ exp`, which is zero when `self` is zero. Integer exponents
+    /// are exact, by multiplying, rather than through the polar form, which rounds.
+    pub fn pow(self, exp: Complex) -> Complex {
+        if self == Complex::default() {
+            return Complex::default();
+        }
+        let n = exp.re.get();
+        if exp.im.get() == 0. && n.fract() == 0. && n.abs() <= u32::MAX as f64 {
+            let power = self.powi(n.abs() as u32);
+            return match n < 0. {
+                true => Complex::from(Real::new(1.)) / power,
+                false => power,
+            };
+        }
+        let (ln_abs, arg) = (self.abs().get().ln(), self.arg().get());
+        let (re, im) = (exp.re.get(), exp.im.get());
+        let abs = (re * ln_abs - im * arg).exp();
+        let angle = im * ln_abs + re * arg;
+        Complex::new(Real::new(abs * angle.cos()), Real::new(abs * angle.sin()))
+    }
+
+    /// `self ^ n`, by squaring.
+    fn powi(self, mut n: u32) -> Complex {
+        let (mut base, mut power) = (self, Complex::from(Real::new(1.)));
+        while n > 0 {
+            if n & 1 == 1 {
+                power = power * base;
+            }
+            base = base * base;
+            n >>= 1;
+        }
+        power
+    }
+}
+
+/// A real is a complex number without an imaginary part.
+impl From<Real> for Complex {
+    fn from(re: Real) -> Self {
+        Complex::new(re, Real::default())
+    }
+}
+
+impl From<Integer> for Complex {
+    fn from(n: Integer) -> Self {
+        Complex::from(Real::from(n))
+    }
+}
+
+impl From<Natural> for Complex {
+    fn from(n: Natural) -> Self {
+        Complex::from(Real::from(n))
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.re * rhs.re + rhs.im * rhs.im;
+        let n = self * rhs.conj();
+        Complex::new(n.re / d, n.im / d)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.im.get().is_sign_negative() {
+            write!(f, "{} - {}i", self.re, -self.im)
+        } else {
+            write!(f, "{} + {}i", self.re, self.im)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::complex::*;
+
+    fn c(re: f64, im: f64) -> Complex {
+        Complex::new(Real::new(re), Real::new(im))
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(c(1., 2.) + c(3., -1.), c(4., 1.));
+        assert_eq!(c(1., 2.) - c(3., -1.), c(-2., 3.));
+        assert_eq!(c(1., 2.) * c(3., -1.), c(5., 5.));
+        assert_eq!(c(5., 5.) / c(3., -1.), c(1., 2.));
+        assert_eq!(-c(1., -2.), c(-1., 2.));
+        assert_eq!(c(3., 4.).abs(), Real::new(5.));
+        assert_eq!(c(3., 4.).conj(), c(3., -4.));
+    }
+
+    #[test]
+    fn pow() {
+        let close = |a: Complex, b: Complex| (a - b).abs().get() < 1e-12;
+        let i = Complex::imaginary(Real::new(1.));
+        assert!(close(i.pow(c(2., 0.)), c(-1., 0.)));
+        assert_eq!(c(1., 1.).pow(c(2., 0.)), c(0., 2.));
+        assert_eq!(i.pow(c(-3., 0.)), i);
+        assert_eq!(c(2., 0.).pow(c(0., 0.)), c(1., 0.));
+        assert!(close(c(-1., 0.).pow(c(0.5, 0.)), i));
+        assert_eq!(Complex::default().pow(i), Complex::default());
+    }
+
+    #[test]
+    fn widening() {
+        assert_eq!(Complex::from(Real::new(1.5)), c(1.5, 0.));
+        assert_eq!(Complex::from(Integer::new(-3)), c(-3., 0.));
+        assert_eq!(Complex::from(Natural::new(3)), c(3., 0.));
+        assert_eq!(c(1., -2.).to_string(), "1 - 2i");
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A fast float, from `f` suffixed literals like `1.5f`, or values annotated as `FReal`.
+///
+/// Unlike [`crate::num::real::Real`], arithmetic on fast floats doesn't have to be evaluated
+/// exactly as written. The compiler and interpreter may reassociate sums and products, fuse
+/// `a * b + c` into a single rounding, and assume no value is NaN or infinite, so results can
+/// differ in the last bits between builds. Converting between the two is always explicit.
+#[derive(PartialEq, PartialOrd, Clone, Copy, Debug, Default)]
+pub struct FReal(f64);
+
+impl FReal {
+    pub fn new(x: f64) -> Self {
+        FReal(x)
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// `self * a + b`, with a single rounding.
+    pub fn mul_add(self, a: FReal, b: FReal) -> FReal {
+        FReal(self.0.mul_add(a.0, b.0))
+    }
+
+    /// The sum of `xs`, reassociated into independent accumulators.
+    pub fn sum(xs: &[FReal]) -> FReal {
+        let mut acc = [0.; 4];
+        let mut chunks = xs.chunks_exact(4);
+        for chunk in &mut chunks {
+            for (a, x) in acc.iter_mut().zip(chunk) {
+                *a += x.0;
+            }
+        }
+        let rest: f64 = chunks.remainder().iter().map(|x| x.0).sum();
+        FReal((acc[0] + acc[1]) + (acc[2] + acc[3]) + rest)
+    }
+
+    /// The smaller of `self` and `rhs`, without the NaN handling of `f64::min`.
+    pub fn min(self, rhs: FReal) -> FReal {
+        if self.0 < rhs.0 {
+            self
+        } else {
+            rhs
+        }
+    }
+
+    /// The larger of `self` and `rhs`, without the NaN handling of `f64::max`.
+    pub fn max(self, rhs: FReal) -> FReal {
+        if self.0 > rhs.0 {
+            self
+        } else {
+            rhs
+        }
+    }
+
+    pub fn pow(self, exp: FReal) -> FReal {
+        FReal(self.0.powf(exp.0))
+    }
+
+    pub fn sqrt(self) -> FReal {
+        FReal(self.0.sqrt())
+    }
+}
+
+impl Add for FReal {
+    type Output = FReal;
+
+    fn add(self, rhs: FReal) -> FReal {
+        FReal(self.0 + rhs.0)
+    }
+}
+
+impl Sub for FReal {
+    type Output = FReal;
+
+    fn sub(self, rhs: FReal) -> FReal {
+        FReal(self.0 - rhs.0)
+    }
+}
+
+impl Mul for FReal {
+    type Output = FReal;
+
+    fn mul(self, rhs: FReal) -> FReal {
+        FReal(self.0 * rhs.0)
+    }
+}
+
+impl Div for FReal {
+    type Output = FReal;
+
+    fn div(self, rhs: FReal) -> FReal {
+        FReal(self.0 / rhs.0)
+    }
+}
+
+impl Neg for FReal {
+    type Output = FReal;
+
+    fn neg(self) -> FReal {
+        FReal(-self.0)
+    }
+}
+
+impl fmt::Display for FReal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}f", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::freal::*;
+
+    #[test]
+    fn arithmetic() {
+        let r = FReal::new;
+        assert_eq!(r(1.5) + r(2.), r(3.5));
+        assert_eq!(r(1.5) - r(2.), r(-0.5));
+        assert_eq!(r(1.5) * r(2.), r(3.));
+        assert_eq!(r(1.) / r(4.), r(0.25));
+        assert_eq!(r(2.).mul_add(r(3.), r(1.)), r(7.));
+        assert_eq!(r(1.).min(r(2.)), r(1.));
+        assert_eq!(r(1.).max(r(2.)), r(2.));
+        assert_eq!(r(2.5).to_string(), "2.5f");
+    }
+
+    #[test]
+    fn sum() {
+        let xs: Vec<_> = (1..=10).map(|n| FReal::new(n as f64)).collect();
+        assert_eq!(FReal::sum(&xs), FReal::new(55.));
+        assert_eq!(FReal::sum(&[]), FReal::new(0.));
+    }
+}
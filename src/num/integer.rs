@@ -0,0 +1,159 @@
+use crate::num::natural::Natural;
+use anyhow::*;
+use std::fmt;
+
+/// A signed integer, backed by an `i64`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default, Hash)]
+pub struct Integer(i64);
+
+/// What happens when the result of integer arithmetic doesn't fit in an `i64`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Overflow {
+    /// It's an error.
+    #[default]
+    Checked,
+    /// The result wraps around, in two's complement.
+    Wrapping,
+}
+
+impl Integer {
+    pub fn new(n: i64) -> Self {
+        Integer(n)
+    }
+
+    pub fn get(self) -> i64 {
+        self.0
+    }
+
+    /// Applies `checked` or `wrapping`, depending on `overflow`, where `op` is the operator, for
+    /// error messages.
+    fn arith(
+        self,
+        rhs: Self,
+        op: &str,
+        overflow: Overflow,
+        checked: fn(i64, i64) -> Option<i64>,
+        wrapping: fn(i64, i64) -> i64,
+    ) -> Result<Self> {
+        match overflow {
+            Overflow::Checked => checked(self.0, rhs.0)
+                .map(Integer)
+                .ok_or_else(|| anyhow!("`{self} {op} {rhs}` overflows an Integer")),
+            Overflow::Wrapping => Ok(Integer(wrapping(self.0, rhs.0))),
+        }
+    }
+
+    pub fn try_add(self, rhs: Self, overflow: Overflow) -> Result<Self> {
+        self.arith(rhs, "+", overflow, i64::checked_add, i64::wrapping_add)
+    }
+
+    pub fn try_sub(self, rhs: Self, overflow: Overflow) -> Result<Self> {
+        self.arith(rhs, "-", overflow, i64::checked_sub, i64::wrapping_sub)
+    }
+
+    pub fn try_mul(self, rhs: Self, overflow: Overflow) -> Result<Self> {
+        self.arith(rhs, "*", overflow, i64::checked_mul, i64::wrapping_mul)
+    }
+
+    /// Integer division, rounding towards zero. Dividing by zero is an error regardless of
+    /// `overflow`.
+    pub fn try_div(self, rhs: Self, overflow: Overflow) -> Result<Self> {
+        if rhs.0 == 0 {
+            bail!("`{self} / {rhs}` divides by zero")
+        }
+        self.arith(rhs, "/", overflow, i64::checked_div, i64::wrapping_div)
+    }
+
+    pub fn try_rem(self, rhs: Self, overflow: Overflow) -> Result<Self> {
+        if rhs.0 == 0 {
+            bail!("`{self} % {rhs}` divides by zero")
+        }
+        self.arith(rhs, "%", overflow, i64::checked_rem, i64::wrapping_rem)
+    }
+
+    /// `self ^ exp`, where the exponent is a natural number, so the result is always an integer.
+    pub fn try_pow(self, exp: Natural, overflow: Overflow) -> Result<Self> {
+        let Some(e) = u32::try_from(exp.get()).ok() else {
+            bail!("`{self} ^ {exp}` overflows an Integer")
+        };
+        match overflow {
+            Overflow::Checked => self
+                .0
+                .checked_pow(e)
+                .map(Integer)
+                .ok_or_else(|| anyhow!("`{self} ^ {exp}` overflows an Integer")),
+            Overflow::Wrapping => Ok(Integer(self.0.wrapping_pow(e))),
+        }
+    }
+
+    pub fn try_neg(self, overflow: Overflow) -> Result<Self> {
+        Integer(0).try_sub(self, overflow)
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(n: i64) -> Self {
+        Integer(n)
+    }
+}
+
+impl TryFrom<Natural> for Integer {
+    type Error = Error;
+
+    fn try_from(n: Natural) -> Result<Self> {
+        i64::try_from(n.get())
+            .map(Integer)
+            .map_err(|_| anyhow!("{n} is too large for an Integer"))
+    }
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::integer::*;
+
+    fn i(n: i64) -> Integer {
+        Integer::new(n)
+    }
+
+    #[test]
+    fn checked() -> Result<()> {
+        let o = Overflow::Checked;
+        assert_eq!(i(2).try_add(i(-3), o)?, i(-1));
+        assert_eq!(i(2).try_sub(i(3), o)?, i(-1));
+        assert_eq!(i(-2).try_mul(i(3), o)?, i(-6));
+        assert_eq!(i(-7).try_div(i(2), o)?, i(-3));
+        assert_eq!(i(-7).try_rem(i(2), o)?, i(-1));
+        assert_eq!(i(-2).try_pow(Natural::new(3), o)?, i(-8));
+        assert_eq!(i(5).try_neg(o)?, i(-5));
+        assert!(i(i64::MAX).try_add(i(1), o).is_err());
+        assert!(i(i64::MIN).try_sub(i(1), o).is_err());
+        assert!(i(i64::MIN).try_div(i(-1), o).is_err());
+        assert!(i(i64::MIN).try_neg(o).is_err());
+        assert!(i(2).try_pow(Natural::new(63), o).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wrapping() -> Result<()> {
+        let o = Overflow::Wrapping;
+        assert_eq!(i(i64::MAX).try_add(i(1), o)?, i(i64::MIN));
+        assert_eq!(i(i64::MIN).try_div(i(-1), o)?, i(i64::MIN));
+        assert_eq!(i(2).try_pow(Natural::new(64), o)?, i(0));
+        assert!(i(1).try_div(i(0), o).is_err());
+        assert!(i(1).try_rem(i(0), o).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn from_natural() -> Result<()> {
+        assert_eq!(Integer::try_from(Natural::new(3))?, i(3));
+        assert!(Integer::try_from(Natural::new(u64::MAX)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::*;
+use std::fmt;
+
+/// A natural number, which is guaranteed to never be negative. Arithmetic is checked, and errors
+/// instead of wrapping around on overflow.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default, Hash)]
+pub struct Natural(u64);
+
+/// What subtracting a natural number from a smaller one results in.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum SubPolicy {
+    /// It's an error, since the result would be negative.
+    #[default]
+    Error,
+    /// The result is 0.
+    Saturate,
+}
+
+impl Natural {
+    pub const ZERO: Natural = Natural(0);
+
+    pub fn new(n: u64) -> Self {
+        Natural(n)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn try_add(self, rhs: Self) -> Result<Self> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Natural)
+            .ok_or_else(|| anyhow!("`{self} + {rhs}` overflows a Natural"))
+    }
+
+    pub fn try_sub(self, rhs: Self, policy: SubPolicy) -> Result<Self> {
+        match (self.0.checked_sub(rhs.0), policy) {
+            (Some(n), _) => Ok(Natural(n)),
+            (None, SubPolicy::Saturate) => Ok(Natural::ZERO),
+            (None, SubPolicy::Error) => bail!("`{self} - {rhs}` is negative, so not a Natural"),
+        }
+    }
+
+    pub fn try_mul(self, rhs: Self) -> Result<Self> {
+        self.0
+            .checked_mul(rhs.0)
+            .map(Natural)
+            .ok_or_else(|| anyhow!("`{self} * {rhs}` overflows a Natural"))
+    }
+
+    /// Integer division, rounding down.
+    pub fn try_div(self, rhs: Self) -> Result<Self> {
+        self.0
+            .checked_div(rhs.0)
+            .map(Natural)
+            .ok_or_else(|| anyhow!("`{self} / {rhs}` divides by zero"))
+    }
+
+    pub fn try_rem(self, rhs: Self) -> Result<Self> {
+        self.0
+            .checked_rem(rhs.0)
+            .map(Natural)
+            .ok_or_else(|| anyhow!("`{self} % {rhs}` divides by zero"))
+    }
+
+    pub fn try_pow(self, rhs: Self) -> Result<Self> {
+        u32::try_from(rhs.0)
+            .ok()
+            .and_then(|exp| self.0.checked_pow(exp))
+            .map(Natural)
+            .ok_or_else(|| anyhow!("`{self} ^ {rhs}` overflows a Natural"))
+    }
+}
+
+impl TryFrom<i64> for Natural {
+    type Error = Error;
+
+    fn try_from(n: i64) -> Result<Self> {
+        u64::try_from(n)
+            .map(Natural)
+            .map_err(|_| anyhow!("{n} is negative, so not a Natural"))
+    }
+}
+
+impl fmt::Display for Natural {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::natural::*;
+
+    fn n(n: u64) -> Natural {
+        Natural::new(n)
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        assert_eq!(n(2).try_add(n(3))?, n(5));
+        assert_eq!(n(5).try_sub(n(3), SubPolicy::Error)?, n(2));
+        assert_eq!(n(2).try_mul(n(3))?, n(6));
+        assert_eq!(n(7).try_div(n(2))?, n(3));
+        assert_eq!(n(7).try_rem(n(2))?, n(1));
+        assert_eq!(n(2).try_pow(n(10))?, n(1024));
+        assert!(n(u64::MAX).try_add(n(1)).is_err());
+        assert!(n(u64::MAX).try_mul(n(2)).is_err());
+        assert!(n(2).try_pow(n(64)).is_err());
+        assert!(n(1).try_div(n(0)).is_err());
+        assert!(n(1).try_rem(n(0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn subtraction() -> Result<()> {
+        assert!(n(2).try_sub(n(3), SubPolicy::Error).is_err());
+        assert_eq!(n(2).try_sub(n(3), SubPolicy::Saturate)?, Natural::ZERO);
+        assert_eq!(SubPolicy::default(), SubPolicy::Error);
+        Ok(())
+    }
+
+    #[test]
+    fn from_integer() -> Result<()> {
+        assert_eq!(Natural::try_from(3)?, n(3));
+        assert!(Natural::try_from(-3).is_err());
+        Ok(())
+    }
+}
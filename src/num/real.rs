@@ -0,0 +1,120 @@
+use crate::num::integer::Integer;
+use crate::num::natural::Natural;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+
+/// A real number, as an IEEE 754 double.
+#[derive(PartialEq, PartialOrd, Clone, Copy, Debug, Default)]
+pub struct Real(f64);
+
+impl Real {
+    pub fn new(x: f64) -> Self {
+        Real(x)
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    pub fn pow(self, exp: Real) -> Real {
+        Real(self.0.powf(exp.0))
+    }
+
+    pub fn abs(self) -> Real {
+        Real(self.0.abs())
+    }
+
+    pub fn sqrt(self) -> Real {
+        Real(self.0.sqrt())
+    }
+}
+
+/// An integer widens to the nearest real, which loses precision for integers larger than 2^53.
+impl From<Integer> for Real {
+    fn from(n: Integer) -> Self {
+        Real(n.get() as f64)
+    }
+}
+
+impl From<Natural> for Real {
+    fn from(n: Natural) -> Self {
+        Real(n.get() as f64)
+    }
+}
+
+impl Add for Real {
+    type Output = Real;
+
+    fn add(self, rhs: Real) -> Real {
+        Real(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Real {
+    type Output = Real;
+
+    fn sub(self, rhs: Real) -> Real {
+        Real(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Real {
+    type Output = Real;
+
+    fn mul(self, rhs: Real) -> Real {
+        Real(self.0 * rhs.0)
+    }
+}
+
+impl Div for Real {
+    type Output = Real;
+
+    fn div(self, rhs: Real) -> Real {
+        Real(self.0 / rhs.0)
+    }
+}
+
+impl Rem for Real {
+    type Output = Real;
+
+    fn rem(self, rhs: Real) -> Real {
+        Real(self.0 % rhs.0)
+    }
+}
+
+impl Neg for Real {
+    type Output = Real;
+
+    fn neg(self) -> Real {
+        Real(-self.0)
+    }
+}
+
+impl fmt::Display for Real {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::num::real::*;
+
+    #[test]
+    fn arithmetic() {
+        let r = Real::new;
+        assert_eq!(r(1.5) + r(2.), r(3.5));
+        assert_eq!(r(1.5) - r(2.), r(-0.5));
+        assert_eq!(r(1.5) * r(2.), r(3.));
+        assert_eq!(r(1.) / r(4.), r(0.25));
+        assert_eq!(r(7.) % r(2.), r(1.));
+        assert_eq!(-r(1.), r(-1.));
+        assert_eq!(r(2.).pow(r(0.5)), r(2f64.sqrt()));
+    }
+
+    #[test]
+    fn widening() {
+        assert_eq!(Real::from(Integer::new(-3)), Real::new(-3.));
+        assert_eq!(Real::from(Natural::new(3)), Real::new(3.));
+    }
+}
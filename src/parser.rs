@@ -1,33 +1,32 @@
 //! Parser combinator, implemented in rust, for the chant programming language
 
-use anyhow::*;
-use std::marker::PhantomData;
+pub mod scan;
 
-const OPERATOR_CHARS: &str = ":=+-/*^&%|<>!";
-const SEPARATOR_CHARS: &str = ",.(){}[]";
-const WHITESPACE_CHARS: &str = " \t\n";
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use anyhow::*;
 
 /// A basic token type.
 #[derive(PartialEq, Clone, Debug)]
 pub enum Token {
     Symbol(String),
-    Number(f64),
+    /// A number without a fractional part, like `123`.
+    Integer(i64),
+    /// A number with a fractional part, like `1.5`.
+    Real(f64),
+    /// An imaginary number, like `4i`.
+    Imaginary(f64),
+    /// A fast float, like `1.5f`.
+    FReal(f64),
     String(String),
+    Interpolated(Vec<Segment>),
     Operator(String),
     Separator(char),
+    /// A lifetime, like `'a`, without the leading `'`.
+    Lifetime(String),
     Blank,
 }
 
-impl Token {
-    fn number(&mut self) -> &mut f64 {
-        if let Token::Number(n) = self {
-            n
-        } else {
-            panic!("{self:?} is not a number")
-        }
-    }
-}
-
 /// Parser specialized for a specific use case. For example this could be a parser that only parses math expressions.
 ///
 /// # Results
@@ -68,19 +67,18 @@ impl Parser for NaturalNumber {
     type Token = Token;
 
     fn parse(&self, i: &str) -> Result<(Token, usize)> {
-        let mut num = 0.;
-        let mut rem = 0;
-        for c in i.chars() {
-            match format!("{c}").parse::<u8>() {
-                std::result::Result::Ok(n) => num = num * 10. + n as f64,
-                Err(_) => break,
-            }
-            rem += 1;
-        }
-        if rem == 0 {
+        let digits = &i.as_bytes()[..scan::run(i.as_bytes(), scan::DIGIT)];
+        if digits.is_empty() {
             return Ok((Token::Blank, 0));
         }
-        Ok((Token::Number(num), rem))
+        let mut num = 0i64;
+        for &b in digits {
+            num = num
+                .checked_mul(10)
+                .and_then(|num| num.checked_add((b - b'0') as i64))
+                .ok_or_else(|| anyhow!("integer literal is too large"))?;
+        }
+        Ok((Token::Integer(num), digits.len()))
     }
 }
 
@@ -91,10 +89,10 @@ impl Parser for Integer {
     type Token = Token;
 
     fn parse(&self, i: &str) -> Result<(Token, usize)> {
-        if i.chars().nth(0) == Some('-') {
+        if i.as_bytes().first() == Some(&b'-') {
             let mut n = NaturalNumber.parse(&i[1..])?;
-            if let Token::Number(n) = &mut n.0 {
-                *n *= -1.;
+            if let Token::Integer(n) = &mut n.0 {
+                *n = -*n;
             } else {
                 return Ok((Token::Blank, 0));
             }
@@ -106,97 +104,210 @@ impl Parser for Integer {
     }
 }
 
+/// Parser for numbers with an optional fractional part, like `-1`, `1.5` or `.5`. Numbers
+/// without a fractional part are `Token::Integer`, and others are `Token::Real`. Numbers with an
+/// `i` suffix, like `4i`, are `Token::Imaginary`, and numbers with an `f` suffix, like `1.5f`,
+/// are fast floats, `Token::FReal`.
 pub struct Float;
 
 impl Parser for Float {
     type Token = Token;
 
     fn parse(&self, i: &str) -> Result<(Token, usize)> {
-        let mut num = Integer.parse(i)?;
-        if i.chars().nth(num.1) != Some('.') {
-            return Ok(num);
-        }
-        if num.0 == Token::Blank {
-            num.0 = Token::Number(0.)
+        let (num, len) = unsuffixed(i)?;
+        let x = match num {
+            Token::Integer(n) => n as f64,
+            Token::Real(x) => x,
+            _ => return Ok((num, len)),
+        };
+        if suffix(&i[len..], 'i') {
+            return Ok((Token::Imaginary(x), len + 1));
         }
-        let mut decimalps = NaturalNumber.parse(&i[num.1 + 1..])?;
-        if decimalps.0 == Token::Blank {
-            return Ok(num);
+        if suffix(&i[len..], 'f') {
+            return Ok((Token::FReal(x), len + 1));
         }
-        *num.0.number() += *decimalps.0.number() / (10usize.pow(decimalps.1 as u32)) as f64
-            * num.0.number().signum();
-        num.1 += 1 + decimalps.1;
-        Ok(num)
+        Ok((num, len))
+    }
+}
+
+/// A number without a suffix. The digits are scanned before deciding, since only integers are
+/// accumulated into an `i64`, and reals can have more digits than fit in one, like
+/// `0.10000000000000000000` or `100000000000000000000.0`.
+fn unsuffixed(i: &str) -> Result<(Token, usize)> {
+    let bytes = i.as_bytes();
+    let sign = (bytes.first() == Some(&b'-')) as usize;
+    let dot = sign + scan::run(&bytes[sign..], scan::DIGIT);
+    let decimals = match bytes.get(dot) {
+        Some(b'.') => scan::run(&bytes[dot + 1..], scan::DIGIT),
+        _ => 0,
+    };
+    if decimals == 0 {
+        return Integer.parse(i);
+    }
+    if dot == 1 && sign == 1 {
+        // A `-` without digits, like in `-.5`, is the operator.
+        return Ok((Token::Blank, 0));
     }
+    let len = dot + 1 + decimals;
+    // Reals have to be rounded correctly, which `str::parse` does. Its error isn't converted
+    // with `?`, since without `std`, anyhow can only convert errors of its own.
+    let x = i[..len]
+        .parse()
+        .map_err(|e| anyhow!("invalid number {:?}: {e}", &i[..len]))?;
+    Ok((Token::Real(x), len))
 }
 
+/// Whether `i` starts with the suffix `c`, which isn't the start of a symbol, like in `4if`.
+fn suffix(i: &str, c: char) -> bool {
+    let mut chars = i.chars();
+    chars.next() == Some(c)
+        && !chars
+            .next()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parser for symbols, a letter or `_` followed by letters, digits and `_`s.
 pub struct Symbol;
 
 impl Parser for Symbol {
     type Token = Token;
 
     fn parse(&self, i: &str) -> Result<(Token, usize)> {
-        let mut buffer = vec![];
-        let mut i = i.chars();
-
-        // check that first charecter is alphabetical og '_'
-        let fc = i.next().unwrap() as u8;
-        if fc == 95 || (fc > 64 && fc < 91) || (fc > 96 && fc < 123) {
-            buffer.push(fc as char)
-        } else {
+        let bytes = i.as_bytes();
+        if !bytes
+            .first()
+            .is_some_and(|&b| scan::is(b, scan::SYMBOL_START))
+        {
             return Ok((Token::Blank, 0));
         }
-
-        // all other charecters can also be numbers...
-        let mut rem = 1;
-        for c in i {
-            let c = c as u8;
-            if c == 95 || (c > 64 && c < 91) || (c > 96 && c < 123) || (c > 47 && c < 58) {
-                buffer.push(c as char)
-            } else {
-                break;
-            }
-            rem += 1;
-        }
-
-        Ok((Token::Symbol(buffer.iter().collect()), rem))
+        let n = 1 + scan::run(&bytes[1..], scan::SYMBOL);
+        Ok((Token::Symbol(i[..n].to_string()), n))
     }
 }
 
+/// Parser for operators, which lexes the longest operator of [`scan::OPERATORS`].
 pub struct Operator;
 
 impl Parser for Operator {
     type Token = Token;
 
     fn parse(&self, i: &str) -> Result<(Token, usize)> {
-        let mut rem = 0;
+        match scan::operator(i.as_bytes()) {
+            0 => Ok((Token::Blank, 0)),
+            n => Ok((Token::Operator(i[..n].to_string()), n)),
+        }
+    }
+}
 
-        for c in i.chars() {
-            if !OPERATOR_CHARS.contains(c) {
-                break;
-            }
-            rem += 1
+pub struct Separator;
+
+impl Parser for Separator {
+    type Token = Token;
+
+    fn parse(&self, i: &str) -> Result<(Self::Token, usize)> {
+        match i.as_bytes().first() {
+            Some(&b) if scan::is(b, scan::SEPARATOR) => Ok((Token::Separator(b as char), 1)),
+            _ => Ok((Token::Blank, 0)),
         }
+    }
+}
 
-        if rem == 0 {
+/// Parser for string literals, delimited by `"`. Supports the escapes `\"`, `\\`, `\n`, `\t`,
+/// `\{` and `\}`.
+///
+/// Strings containing `{expr}` are interpolated, and result in `Token::Interpolated`, where the
+/// source of each embedded expression is left for the grammar to parse.
+pub struct StringLiteral;
+
+impl Parser for StringLiteral {
+    type Token = Token;
+
+    fn parse(&self, i: &str) -> Result<(Token, usize)> {
+        let mut chars = i.char_indices();
+        if chars.next() != Some((0, '"')) {
             return Ok((Token::Blank, 0));
         }
+        let mut segments = vec![];
+        let mut buffer = String::new();
+        while let Some((n, c)) = chars.next() {
+            match c {
+                '"' if segments.is_empty() => return Ok((Token::String(buffer), n + 1)),
+                '"' => {
+                    if !buffer.is_empty() {
+                        segments.push(Segment::Text(buffer));
+                    }
+                    return Ok((Token::Interpolated(segments), n + 1));
+                }
+                '\\' => match chars.next() {
+                    Some((_, '"')) => buffer.push('"'),
+                    Some((_, '\\')) => buffer.push('\\'),
+                    Some((_, 'n')) => buffer.push('\n'),
+                    Some((_, 't')) => buffer.push('\t'),
+                    Some((_, '{')) => buffer.push('{'),
+                    Some((_, '}')) => buffer.push('}'),
+                    Some((_, c)) => bail!("unknown escape `\\{c}` in string literal"),
+                    None => break,
+                },
+                '{' => {
+                    if !buffer.is_empty() {
+                        segments.push(Segment::Text(core::mem::take(&mut buffer)));
+                    }
+                    let len = embedded(&i[n + 1..])?;
+                    segments.push(Segment::Code(i[n + 1..n + 1 + len].to_string()));
+                    // Skip the embedded expression and the closing `}`.
+                    let end = n + 1 + len;
+                    while chars.next().is_some_and(|(m, _)| m < end) {}
+                }
+                '}' => bail!("unmatched `}}` in string literal, use `\\}}` for a literal `}}`"),
+                c => buffer.push(c),
+            }
+        }
+        bail!("unterminated string literal")
+    }
+}
 
-        Ok((Token::Operator((&i[0..rem]).to_string()), rem))
+/// A part of an interpolated string.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Segment {
+    Text(String),
+    /// The source of an embedded expression, without the surrounding braces.
+    Code(String),
+}
+
+/// Length of an expression embedded in a string, up to the `}` closing it. Braces and strings
+/// inside the expression are skipped, so `"{f({a})}"` and `"{g("\}")}"` work.
+fn embedded(i: &str) -> Result<usize> {
+    let mut depth = 0;
+    let mut n = 0;
+    while let Some(c) = i[n..].chars().next() {
+        match c {
+            '"' => {
+                n += StringLiteral.parse(&i[n..])?.1;
+                continue;
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => return Ok(n),
+            '}' => depth -= 1,
+            _ => {}
+        }
+        n += c.len_utf8();
     }
+    bail!("unterminated interpolation in string literal")
 }
 
-pub struct Separator;
+/// Parser for lifetimes, a `'` followed by a symbol.
+pub struct Lifetime;
 
-impl Parser for Separator {
+impl Parser for Lifetime {
     type Token = Token;
 
-    fn parse(&self, i: &str) -> Result<(Self::Token, usize)> {
-        let c = i.chars().nth(0).unwrap();
-        if !SEPARATOR_CHARS.contains(c) {
-            Ok((Token::Blank, 0))
-        } else {
-            Ok((Token::Separator(c), 1))
+    fn parse(&self, i: &str) -> Result<(Token, usize)> {
+        let Some(rest) = i.strip_prefix('\'') else {
+            return Ok((Token::Blank, 0));
+        };
+        match Symbol.parse(rest)? {
+            (Token::Symbol(name), n) => Ok((Token::Lifetime(name), n + 1)),
+            _ => bail!("expected lifetime name after `'`"),
         }
     }
 }
@@ -219,20 +330,18 @@ impl<A: Parser> Parser for EatPrecedingWhitespace<A> {
     type Token = A::Token;
 
     fn parse(&self, i: &str) -> Result<(A::Token, usize)> {
-        let mut rem = 0;
-        for c in i.chars() {
-            if !WHITESPACE_CHARS.contains(c) {
-                break;
-            }
-            rem += 1;
-        }
-
+        let rem = whitespace(i);
         let mut tmp = self.0.parse(&i[rem..])?;
         tmp.1 += rem;
         Ok(tmp)
     }
 }
 
+/// Length of the whitespace at the start of `i`.
+pub(crate) fn whitespace(i: &str) -> usize {
+    scan::run(i.as_bytes(), scan::WHITESPACE)
+}
+
 pub struct IfLiteral<A: Parser>(A, String);
 
 impl<A: Parser> Parser for IfLiteral<A> {
@@ -254,11 +363,17 @@ mod tests {
 
     #[test]
     fn int() -> Result<()> {
-        assert_eq!(NaturalNumber.parse("123")?, (Token::Number(123.), 3));
+        assert_eq!(NaturalNumber.parse("123")?, (Token::Integer(123), 3));
         assert_eq!(NaturalNumber.parse("-123")?.0, Token::Blank);
-        assert_eq!(Integer.parse("-123")?, (Token::Number(-123.), 4));
-        assert_eq!(Integer.parse("123")?, (Token::Number(123.), 3));
-        assert_eq!(Integer.parse("123abc")?, (Token::Number(123.), 3));
+        assert_eq!(Integer.parse("-123")?, (Token::Integer(-123), 4));
+        assert_eq!(Integer.parse("123")?, (Token::Integer(123), 3));
+        assert_eq!(Integer.parse("123abc")?, (Token::Integer(123), 3));
+        assert_eq!(Integer.parse("007é")?, (Token::Integer(7), 3));
+        assert_eq!(
+            Integer.parse("-9223372036854775807")?,
+            (Token::Integer(-i64::MAX), 20)
+        );
+        assert!(NaturalNumber.parse("9223372036854775808").is_err());
         Ok(())
     }
 
@@ -269,6 +384,7 @@ mod tests {
             (Token::Symbol("_oki123".to_string()), 7)
         );
         assert_eq!(Symbol.parse("1_oki123")?.0, Token::Blank);
+        assert_eq!(Symbol.parse("")?.0, Token::Blank);
         Ok(())
     }
 
@@ -278,6 +394,11 @@ mod tests {
             Operator.parse("+=")?,
             (Token::Operator("+=".to_string()), 2)
         );
+        assert_eq!(
+            Operator.parse("*-1")?,
+            (Token::Operator("*".to_string()), 1)
+        );
+        assert_eq!(Separator.parse(".5")?, (Token::Separator('.'), 1));
         Ok(())
     }
 
@@ -285,7 +406,7 @@ mod tests {
     fn num_then_symbol() -> Result<()> {
         assert_eq!(
             Then(Integer, Symbol).parse("123abc")?,
-            ((Token::Number(123.), Token::Symbol("abc".to_string())), 6)
+            ((Token::Integer(123), Token::Symbol("abc".to_string())), 6)
         );
 
         Ok(())
@@ -295,7 +416,7 @@ mod tests {
     fn symbol_then_num() -> Result<()> {
         assert_eq!(
             Symbol.then(Integer.after_whitespace()).parse("abc 123")?,
-            ((Token::Symbol("abc".to_string()), Token::Number(123.)), 7)
+            ((Token::Symbol("abc".to_string()), Token::Integer(123)), 7)
         );
 
         Ok(())
@@ -304,6 +425,47 @@ mod tests {
     #[test]
     fn sep() -> Result<()> {
         assert_eq!(Separator.parse("(())")?, (Token::Separator('('), 1));
+        assert_eq!(Separator.parse("")?, (Token::Blank, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn string() -> Result<()> {
+        assert_eq!(
+            StringLiteral.parse(r#""two" 3"#)?,
+            (Token::String("two".to_string()), 5)
+        );
+        assert_eq!(
+            StringLiteral.parse(r#""a\"b\\\n" "#)?,
+            (Token::String("a\"b\\\n".to_string()), 10)
+        );
+        assert_eq!(StringLiteral.parse("two")?, (Token::Blank, 0));
+        assert!(StringLiteral.parse(r#""two"#).is_err());
+        assert!(StringLiteral.parse(r#""\q""#).is_err());
+        assert_eq!(
+            StringLiteral.parse(r#""x = {f("\}", {a})}\{""#)?,
+            (
+                Token::Interpolated(vec![
+                    Segment::Text("x = ".to_string()),
+                    Segment::Code(r#"f("\}", {a})"#.to_string()),
+                    Segment::Text("{".to_string()),
+                ]),
+                22
+            )
+        );
+        assert!(StringLiteral.parse(r#""{a""#).is_err());
+        assert!(StringLiteral.parse(r#""a}""#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lifetime() -> Result<()> {
+        assert_eq!(
+            Lifetime.parse("'a T")?,
+            (Token::Lifetime("a".to_string()), 2)
+        );
+        assert_eq!(Lifetime.parse("a")?, (Token::Blank, 0));
+        assert!(Lifetime.parse("'1").is_err());
         Ok(())
     }
 
@@ -311,21 +473,51 @@ mod tests {
     fn oneline_float_parser() -> Result<()> {
         let float = Integer.then(NaturalNumber.if_literal("."));
 
-        assert_eq!(float.parse("123")?, ((Token::Number(123.), None), 3));
+        assert_eq!(float.parse("123")?, ((Token::Integer(123), None), 3));
         assert_eq!(
             float.parse("-123.456")?,
-            ((Token::Number(-123.), Some(Token::Number(456.))), 8)
+            ((Token::Integer(-123), Some(Token::Integer(456))), 8)
         );
         Ok(())
     }
 
     #[test]
     fn floats() -> Result<()> {
-        assert_eq!(Float.parse("-123.456")?, (Token::Number(-123.456), 8));
-        assert_eq!(Float.parse("123")?, (Token::Number(123.), 3));
-        assert_eq!(Float.parse("123.")?, (Token::Number(123.), 3));
-        assert_eq!(Float.parse(".456")?, (Token::Number(0.456), 4));
+        assert_eq!(Float.parse("-123.456")?, (Token::Real(-123.456), 8));
+        assert_eq!(Float.parse("123")?, (Token::Integer(123), 3));
+        assert_eq!(Float.parse("123.")?, (Token::Integer(123), 3));
+        assert_eq!(Float.parse(".456")?, (Token::Real(0.456), 4));
         assert_eq!(Float.parse("-.456")?, (Token::Blank, 0));
+        assert_eq!(Float.parse(".")?, (Token::Blank, 0));
+        assert_eq!(Float.parse("4i")?, (Token::Imaginary(4.), 2));
+        assert_eq!(Float.parse("-.5i+")?, (Token::Blank, 0));
+        assert_eq!(Float.parse("0.5i+")?, (Token::Imaginary(0.5), 4));
+        assert_eq!(Float.parse("4if")?, (Token::Integer(4), 1));
+        assert_eq!(Float.parse("1.5f")?, (Token::FReal(1.5), 4));
+        assert_eq!(Float.parse("2f")?, (Token::FReal(2.), 2));
+        assert_eq!(Float.parse("2fn")?, (Token::Integer(2), 1));
+        Ok(())
+    }
+
+    #[test]
+    fn long_floats() -> Result<()> {
+        assert_eq!(
+            Float.parse("3.14159265358979323846")?,
+            (Token::Real(core::f64::consts::PI), 22)
+        );
+        assert_eq!(
+            Float.parse("0.10000000000000000000")?,
+            (Token::Real(0.1), 22)
+        );
+        assert_eq!(
+            Float.parse("100000000000000000000.0")?,
+            (Token::Real(1e20), 23)
+        );
+        assert_eq!(
+            Float.parse("-100000000000000000000.5i")?,
+            (Token::Imaginary(-1e20), 25)
+        );
+        assert!(Float.parse("100000000000000000000").is_err());
         Ok(())
     }
 }
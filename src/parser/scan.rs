@@ -0,0 +1,262 @@
+//! Byte classes for the hot loops of the lexer, which skip whitespace and scan symbols and numbers,
+//! and a trie of the operators.
+//!
+//! Every byte has a set of classes, in a table, so checking a byte is one lookup rather than a
+//! search through a list of characters. [`run`] tests 8 bytes at a time instead, as the bytes of a
+//! `u64`, with a few comparisons of ranges and of single bytes done on the whole word at once. That
+//! makes a mask of which bytes are in the class, so long runs of whitespace and long names cost
+//! one branch per 8 bytes. Bytes of non-ASCII characters aren't in any class.
+//!
+//! [`operator`] walks the trie one byte at a time, and stops at the first byte that no operator
+//! continues with, so `a+-b` is `+` and then `-`, rather than an operator `+-`.
+
+pub const WHITESPACE: u8 = 1;
+pub const DIGIT: u8 = 1 << 1;
+/// Bytes that can start a symbol.
+pub const SYMBOL_START: u8 = 1 << 2;
+/// Bytes that can continue a symbol.
+pub const SYMBOL: u8 = 1 << 3;
+pub const OPERATOR: u8 = 1 << 4;
+pub const SEPARATOR: u8 = 1 << 5;
+
+/// Every operator, which the longest of is lexed, like `<=` rather than `<` in `a <= b`.
+pub const OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "^", "&", "|", "!", "<", ">", "=", ":", "==", "!=", "<=", ">=", "&&",
+    "||", "|>", ":=", "::", "->", "=>", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=", "+%", "-%",
+    "*%",
+];
+
+const OPERATOR_CHARS: &[u8] = b":=+-/*^&%|<>!";
+const SEPARATOR_CHARS: &[u8] = b",.;(){}[]";
+
+static CLASSES: [u8; 256] = classes();
+
+const fn classes() -> [u8; 256] {
+    let mut classes = [0; 256];
+    let mut b = 0;
+    while b < 128 {
+        let c = b as u8;
+        let mut class = 0;
+        if matches!(c, b' ' | b'\t' | b'\n') {
+            class |= WHITESPACE;
+        }
+        if c.is_ascii_digit() {
+            class |= DIGIT | SYMBOL;
+        }
+        if c.is_ascii_alphabetic() || c == b'_' {
+            class |= SYMBOL_START | SYMBOL;
+        }
+        if contains(OPERATOR_CHARS, c) {
+            class |= OPERATOR;
+        }
+        if contains(SEPARATOR_CHARS, c) {
+            class |= SEPARATOR;
+        }
+        classes[b] = class;
+        b += 1;
+    }
+    classes
+}
+
+const fn contains(bytes: &[u8], c: u8) -> bool {
+    let mut n = 0;
+    while n < bytes.len() {
+        if bytes[n] == c {
+            return true;
+        }
+        n += 1;
+    }
+    false
+}
+
+/// Whether `b` is in any of the classes of `class`.
+pub fn is(b: u8, class: u8) -> bool {
+    CLASSES[b as usize] & class != 0
+}
+
+/// The classes [`run`] tests for with arithmetic on whole words, rather than a lookup per byte.
+const WORD_CLASSES: u8 = WHITESPACE | DIGIT | SYMBOL_START | SYMBOL;
+
+/// The length of the run of bytes at the start of `i` that are in `class`. Operators and
+/// separators, which are never in long runs, are looked up a byte at a time.
+pub fn run(i: &[u8], class: u8) -> usize {
+    if class & !WORD_CLASSES != 0 {
+        return i.iter().take_while(|&&b| is(b, class)).count();
+    }
+    let misses = |word: [u8; 8]| !in_class(u64::from_le_bytes(word), class) & HIGH;
+    let mut n = 0;
+    while let Some(word) = i.get(n..n + 8) {
+        let misses = misses(word.try_into().unwrap());
+        if misses != 0 {
+            return n + misses.trailing_zeros() as usize / 8;
+        }
+        n += 8;
+    }
+    // The last bytes are padded with zeros, which aren't in any class.
+    let mut word = [0; 8];
+    word[..i.len() - n].copy_from_slice(&i[n..]);
+    n + misses(word).trailing_zeros() as usize / 8
+}
+
+/// A byte of ones, in each byte of a word.
+const ONES: u64 = u64::MAX / 0xff;
+/// The high bit of each byte of a word.
+const HIGH: u64 = ONES * 0x80;
+
+/// A word with the high bit of each byte of `x` that's in `class` set, and nothing else, for the
+/// classes in `WORD_CLASSES`. Each test keeps to the bytes of the word, with nothing carried or
+/// borrowed from one byte to the next.
+#[inline]
+fn in_class(x: u64, class: u8) -> u64 {
+    // The high bit of the bytes that aren't 0.
+    let nonzero = |y: u64| (((y & !HIGH) + !HIGH) | y) & HIGH;
+    let eq = |c: u8| !nonzero(x ^ (ONES * c as u64)) & HIGH;
+    // The high bit of the ASCII bytes that are at least `c`, which is at most 0x80.
+    let at_least = |x: u64, c: u8| ((x | HIGH) - ONES * c as u64) & HIGH;
+    let between = |x: u64, lo: u8, hi: u8| at_least(x, lo) & !at_least(x, hi + 1);
+    let mut mask = 0;
+    if class & WHITESPACE != 0 {
+        mask |= eq(b' ') | eq(b'\t') | eq(b'\n');
+    }
+    if class & (DIGIT | SYMBOL) != 0 {
+        mask |= between(x, b'0', b'9');
+    }
+    if class & (SYMBOL_START | SYMBOL) != 0 {
+        // Setting 0x20 makes upper case letters lower case, and no other byte a letter.
+        mask |= between(x | (ONES * 0x20), b'a', b'z') | eq(b'_');
+    }
+    mask & !x & HIGH
+}
+
+/// A node of the operator trie, with the nodes after it, by the index of the next byte in
+/// `OPERATOR_CHARS`, where 0 is none, since no operator goes back to the root.
+#[derive(Clone, Copy)]
+struct Node {
+    next: [u8; OPERATOR_CHARS.len()],
+    /// Whether the bytes up to this node are an operator.
+    end: bool,
+}
+
+/// The trie has a node for every prefix of an operator, which is at most one per byte.
+const NODES: usize = {
+    let (mut n, mut sum) = (0, 1);
+    while n < OPERATORS.len() {
+        sum += OPERATORS[n].len();
+        n += 1;
+    }
+    sum
+};
+
+static TRIE: [Node; NODES] = trie();
+
+/// The index of each byte in `OPERATOR_CHARS`, if it's in it.
+static OPERATOR_INDEX: [u8; 256] = {
+    let mut index = [u8::MAX; 256];
+    let mut n = 0;
+    while n < OPERATOR_CHARS.len() {
+        index[OPERATOR_CHARS[n] as usize] = n as u8;
+        n += 1;
+    }
+    index
+};
+
+const fn trie() -> [Node; NODES] {
+    let empty = Node {
+        next: [0; OPERATOR_CHARS.len()],
+        end: false,
+    };
+    let mut trie = [empty; NODES];
+    let mut len = 1;
+    let mut n = 0;
+    while n < OPERATORS.len() {
+        let op = OPERATORS[n].as_bytes();
+        let (mut node, mut k) = (0, 0);
+        while k < op.len() {
+            assert!(
+                contains(OPERATOR_CHARS, op[k]),
+                "operators are made of operator chars"
+            );
+            let c = OPERATOR_INDEX[op[k] as usize] as usize;
+            if trie[node].next[c] == 0 {
+                trie[node].next[c] = len as u8;
+                len += 1;
+            }
+            node = trie[node].next[c] as usize;
+            k += 1;
+        }
+        trie[node].end = true;
+        n += 1;
+    }
+    trie
+}
+
+/// The length of the longest operator at the start of `i`, or 0 if there is none.
+pub fn operator(i: &[u8]) -> usize {
+    let (mut node, mut len) = (0, 0);
+    for (n, &b) in i.iter().enumerate() {
+        let c = OPERATOR_INDEX[b as usize];
+        if c == u8::MAX {
+            break;
+        }
+        match TRIE[node].next[c as usize] {
+            0 => break,
+            next => node = next as usize,
+        }
+        if TRIE[node].end {
+            len = n + 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::scan::*;
+
+    #[test]
+    fn runs() {
+        assert_eq!(run(b"", WHITESPACE), 0);
+        assert_eq!(run(b"  \t\nx", WHITESPACE), 4);
+        assert_eq!(run(b"                 x", WHITESPACE), 17);
+        assert_eq!(run(b"abc_123_long_name_ ", SYMBOL), 18);
+        assert_eq!(run(b"12345678", DIGIT), 8);
+        assert_eq!(run("\u{a0} ".as_bytes(), WHITESPACE), 0);
+        assert!(is(b'_', SYMBOL_START) && !is(b'1', SYMBOL_START));
+        assert!(is(b'|', OPERATOR) && is(b'.', SEPARATOR) && !is(b'.', OPERATOR));
+    }
+
+    #[test]
+    fn words() {
+        // Every byte, next to every other, at the start and in the middle of a word.
+        let classes = [WHITESPACE, DIGIT, SYMBOL_START, SYMBOL, WHITESPACE | SYMBOL];
+        for (a, b) in (0..=255u8).flat_map(|a| (0..=255u8).map(move |b| (a, b))) {
+            let bytes = [a, b, b'x', b' ', 0xff, b, a, b'9'];
+            for class in classes {
+                let expected = bytes.iter().take_while(|&&b| is(b, class)).count();
+                assert_eq!(run(&bytes, class), expected, "{bytes:?} {class}");
+                let shifted = [b'x', a, b, b'_', b'\t', b'0', b'Z', b'.'];
+                let expected = shifted.iter().map(|&b| (is(b, class) as u64) << 7);
+                let x = u64::from_le_bytes(shifted);
+                let expected = expected
+                    .enumerate()
+                    .map(|(n, bit)| bit << (8 * n))
+                    .sum::<u64>();
+                assert_eq!(in_class(x, class), expected, "{shifted:?} {class}");
+            }
+        }
+        assert_eq!(run(b"  \t\n  \n        \n  x", WHITESPACE), 18);
+        assert_eq!(run(b"+-*/ ", OPERATOR), 4);
+    }
+
+    #[test]
+    fn operators() {
+        for op in OPERATORS {
+            assert_eq!(operator(op.as_bytes()), op.len(), "{op}");
+        }
+        assert_eq!(operator(b"+-b"), 1);
+        assert_eq!(operator(b"<=>"), 2);
+        assert_eq!(operator(b"|>x"), 2);
+        assert_eq!(operator(b"::="), 2);
+        assert_eq!(operator(b"x"), 0);
+    }
+}
@@ -0,0 +1,465 @@
+//! Runtime support for `co fn`, a cooperative scheduler running many coroutines on one thread.
+//!
+//! Each coroutine runs until it suspends, and says what it's waiting for: another turn, a
+//! timeout, or an IO source becoming ready. The scheduler resumes it once that has happened, so
+//! programs can have many coroutines in flight without manually resuming each one.
+//!
+//! A [`Scheduler::deterministic`] one runs them the same way every time: in the order they become
+//! ready, on a clock that only moves when they're all asleep, so sleeping takes no real time and
+//! [`Context::now`] doesn't depend on how fast the machine is.
+
+pub mod channel;
+pub mod task;
+pub mod threaded;
+
+use std::collections::{BinaryHeap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Why a coroutine gave up control.
+pub enum Suspend {
+    /// Resume after the coroutines that are already ready, like a bare `yield`.
+    Yield,
+    /// Resume once the duration has passed.
+    Sleep(Duration),
+    /// Resume once the source is ready.
+    Io(Box<dyn Ready>),
+    /// The coroutine has returned, and is never resumed again.
+    Done,
+}
+
+/// A source of IO, like a socket or a channel, that a coroutine can wait on.
+pub trait Ready {
+    /// Whether resuming the coroutine waiting on this would make progress. This is polled, so it
+    /// shouldn't block.
+    fn is_ready(&mut self) -> bool;
+}
+
+/// A coroutine, which is resumed by the [`Scheduler`] until it's done. It can borrow what lives
+/// for `'a`, like the scheduler does.
+pub trait Coroutine<'a> {
+    fn resume(&mut self, cx: &mut Context<'a>) -> Suspend;
+}
+
+impl<'a, F: FnMut(&mut Context<'a>) -> Suspend> Coroutine<'a> for F {
+    fn resume(&mut self, cx: &mut Context<'a>) -> Suspend {
+        self(cx)
+    }
+}
+
+/// Identifies a coroutine spawned on a [`Scheduler`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+pub struct TaskId(usize);
+
+/// What a coroutine can do to the scheduler while it's running.
+pub struct Context<'a> {
+    id: TaskId,
+    /// The id of the next coroutine to be spawned.
+    next: usize,
+    spawned: Vec<Box<dyn Coroutine<'a> + 'a>>,
+    cancelled: Vec<TaskId>,
+    now: Duration,
+}
+
+impl<'a> Context<'a> {
+    /// The coroutine that's running.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// The time since the scheduler started, by its clock.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Starts `co` once the running coroutine suspends.
+    pub fn spawn(&mut self, co: impl Coroutine<'a> + 'a) -> TaskId {
+        self.spawned.push(Box::new(co));
+        TaskId(self.next + self.spawned.len() - 1)
+    }
+
+    /// Drops the coroutine `id` once the running coroutine suspends, without resuming it again.
+    pub fn cancel(&mut self, id: TaskId) {
+        self.cancelled.push(id);
+    }
+}
+
+/// A sleeping coroutine, ordered so that the earliest deadline is at the top of the heap.
+struct Sleeper {
+    deadline: Duration,
+    id: TaskId,
+}
+
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.id) == (other.deadline, other.id)
+    }
+}
+
+impl Eq for Sleeper {}
+
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sleeper {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (other.deadline, other.id).cmp(&(self.deadline, self.id))
+    }
+}
+
+/// How long to wait between polls, when every coroutine is waiting on IO.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Where a [`Scheduler`] gets the time from.
+enum Clock {
+    /// The time since the scheduler was made.
+    Real(Instant),
+    /// Time that only passes when every coroutine is asleep, by skipping to the next deadline.
+    Virtual(Duration),
+}
+
+impl Clock {
+    fn now(&self) -> Duration {
+        match self {
+            Clock::Real(start) => start.elapsed(),
+            Clock::Virtual(now) => *now,
+        }
+    }
+}
+
+/// Runs coroutines until all of them are done.
+pub struct Scheduler<'a> {
+    /// Coroutines by id, which are `None` once done.
+    tasks: Vec<Option<Box<dyn Coroutine<'a> + 'a>>>,
+    ready: VecDeque<TaskId>,
+    sleeping: BinaryHeap<Sleeper>,
+    waiting: Vec<(TaskId, Box<dyn Ready>)>,
+    clock: Clock,
+}
+
+impl Default for Scheduler<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Scheduler<'a> {
+    /// A scheduler with the real clock. On `wasm32-unknown-unknown`, where there is no clock to
+    /// read, and the thread can't sleep, it's the virtual clock of [`Scheduler::deterministic`].
+    pub fn new() -> Self {
+        if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+            return Self::deterministic();
+        }
+        Self::with_clock(Clock::Real(Instant::now()))
+    }
+
+    /// A scheduler with a virtual clock, so that runs are reproducible, as long as the
+    /// coroutines only wait on IO from each other, and get the time with [`Context::now`].
+    pub fn deterministic() -> Self {
+        Self::with_clock(Clock::Virtual(Duration::ZERO))
+    }
+
+    fn with_clock(clock: Clock) -> Self {
+        Scheduler {
+            tasks: vec![],
+            ready: VecDeque::new(),
+            sleeping: BinaryHeap::new(),
+            waiting: vec![],
+            clock,
+        }
+    }
+
+    /// Adds `co`, which first runs after the coroutines that are already ready.
+    pub fn spawn(&mut self, co: impl Coroutine<'a> + 'a) -> TaskId {
+        self.spawn_boxed(Box::new(co))
+    }
+
+    fn spawn_boxed(&mut self, co: Box<dyn Coroutine<'a> + 'a>) -> TaskId {
+        let id = TaskId(self.tasks.len());
+        self.tasks.push(Some(co));
+        self.ready.push_back(id);
+        id
+    }
+
+    /// Whether every coroutine is done.
+    pub fn is_idle(&self) -> bool {
+        self.ready.is_empty() && self.sleeping.is_empty() && self.waiting.is_empty()
+    }
+
+    /// Resumes coroutines until all of them are done, blocking the thread while they're all
+    /// asleep or waiting on IO.
+    pub fn run(&mut self) {
+        self.run_until(|| false)
+    }
+
+    /// Like [`Scheduler::run`], but stops once `stop` returns true, which is checked after each
+    /// resume, and leaves the coroutines that aren't done yet.
+    pub fn run_until(&mut self, mut stop: impl FnMut() -> bool) {
+        while !self.is_idle() && !stop() {
+            if !self.step() {
+                self.idle();
+            }
+        }
+    }
+
+    /// Like [`Scheduler::run_until`], for coroutines that only wait on IO from each other, like
+    /// channels and task groups. Once none of them can make progress, and none are asleep,
+    /// nothing will wake them, so it stops there too, and returns false.
+    pub fn run_until_stuck(&mut self, mut stop: impl FnMut() -> bool) -> bool {
+        while !self.is_idle() && !stop() {
+            if !self.step() {
+                if self.sleeping.is_empty() {
+                    return false;
+                }
+                self.idle();
+            }
+        }
+        true
+    }
+
+    /// Resumes the next coroutine that can make progress, if there is one.
+    fn step(&mut self) -> bool {
+        self.wake();
+        let Some(id) = self.ready.pop_front() else {
+            return false;
+        };
+        self.resume(id);
+        true
+    }
+
+    /// Moves the coroutines that can make progress to the ready queue.
+    fn wake(&mut self) {
+        let now = self.clock.now();
+        while self.sleeping.peek().is_some_and(|s| s.deadline <= now) {
+            let sleeper = self.sleeping.pop().unwrap();
+            self.ready.push_back(sleeper.id);
+        }
+        let mut n = 0;
+        while n < self.waiting.len() {
+            if self.waiting[n].1.is_ready() {
+                let (id, _) = self.waiting.remove(n);
+                self.ready.push_back(id);
+            } else {
+                n += 1;
+            }
+        }
+    }
+
+    /// Waits until a coroutine could wake up.
+    fn idle(&mut self) {
+        let next = self.sleeping.peek().map(|s| s.deadline);
+        match (&mut self.clock, next) {
+            // Coroutines waiting on IO are waiting for the ones that are asleep.
+            (Clock::Virtual(now), Some(deadline)) => *now = deadline,
+            _ => std::thread::sleep(self.idle_time()),
+        }
+    }
+
+    /// How long nothing can wake up for.
+    fn idle_time(&self) -> Duration {
+        let sleep = self
+            .sleeping
+            .peek()
+            .map(|s| s.deadline.saturating_sub(self.clock.now()));
+        match sleep {
+            Some(sleep) if self.waiting.is_empty() => sleep,
+            Some(sleep) => sleep.min(POLL_INTERVAL),
+            None => POLL_INTERVAL,
+        }
+    }
+
+    fn resume(&mut self, id: TaskId) {
+        let Some(mut co) = self.tasks[id.0].take() else {
+            return;
+        };
+        let mut cx = self.context(id);
+        let done = match co.resume(&mut cx) {
+            Suspend::Yield => {
+                self.ready.push_back(id);
+                false
+            }
+            Suspend::Sleep(time) => {
+                let deadline = self.clock.now() + time;
+                self.sleeping.push(Sleeper { deadline, id });
+                false
+            }
+            Suspend::Io(source) => {
+                self.waiting.push((id, source));
+                false
+            }
+            Suspend::Done => true,
+        };
+        if !done {
+            self.tasks[id.0] = Some(co);
+        }
+        self.apply(cx);
+    }
+
+    /// A context for running the coroutine `id`.
+    fn context(&self, id: TaskId) -> Context<'a> {
+        Context {
+            id,
+            next: self.tasks.len(),
+            spawned: vec![],
+            cancelled: vec![],
+            now: self.clock.now(),
+        }
+    }
+
+    /// Spawns and cancels what a coroutine asked to while it was running.
+    fn apply(&mut self, cx: Context<'a>) {
+        for co in cx.spawned {
+            self.spawn_boxed(co);
+        }
+        for id in cx.cancelled {
+            self.cancel(id);
+        }
+    }
+
+    /// Drops the coroutine `id`, if it isn't done yet.
+    pub fn cancel(&mut self, id: TaskId) {
+        if self.tasks.get_mut(id.0).and_then(Option::take).is_some() {
+            self.ready.retain(|&r| r != id);
+            self.sleeping.retain(|s| s.id != id);
+            self.waiting.retain(|(w, _)| *w != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// A coroutine that logs `name` `n` times, suspending with `suspend` in between.
+    fn logger(
+        log: &Rc<RefCell<Vec<String>>>,
+        name: &str,
+        n: usize,
+        suspend: impl Fn() -> Suspend + 'static,
+    ) -> impl Coroutine<'static> {
+        let (log, name) = (log.clone(), name.to_string());
+        let mut count = 0;
+        move |_: &mut Context| {
+            log.borrow_mut().push(format!("{name}{count}"));
+            count += 1;
+            if count == n {
+                Suspend::Done
+            } else {
+                suspend()
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.spawn(logger(&log, "a", 3, || Suspend::Yield));
+        scheduler.spawn(logger(&log, "b", 2, || Suspend::Yield));
+        scheduler.run();
+        assert_eq!(*log.borrow(), ["a0", "b0", "a1", "b1", "a2"]);
+        assert!(scheduler.is_idle());
+    }
+
+    #[test]
+    fn spawn_and_sleep() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        let slow = logger(&log, "slow", 2, || {
+            Suspend::Sleep(Duration::from_millis(20))
+        });
+        let fast = logger(&log, "fast", 2, || Suspend::Sleep(Duration::from_millis(1)));
+        let mut parts = Some((slow, fast));
+        scheduler.spawn(move |cx: &mut Context<'static>| {
+            let (slow, fast) = parts.take().unwrap();
+            cx.spawn(slow);
+            cx.spawn(fast);
+            Suspend::Done
+        });
+        let start = Instant::now();
+        scheduler.run();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(*log.borrow(), ["slow0", "fast0", "fast1", "slow1"]);
+    }
+
+    #[test]
+    fn deterministic() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::deterministic();
+        for (name, secs) in [("a", 30), ("b", 10), ("c", 20)] {
+            let (log, mut slept) = (log.clone(), false);
+            scheduler.spawn(move |cx: &mut Context| {
+                if !std::mem::replace(&mut slept, true) {
+                    return Suspend::Sleep(Duration::from_secs(secs));
+                }
+                log.borrow_mut().push(format!("{name} {:?}", cx.now()));
+                Suspend::Done
+            });
+        }
+        let start = Instant::now();
+        scheduler.run();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(*log.borrow(), ["b 10s", "c 20s", "a 30s"]);
+    }
+
+    struct Flag(Rc<Cell<bool>>);
+
+    impl Ready for Flag {
+        fn is_ready(&mut self) -> bool {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn io() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let flag = Rc::new(Cell::new(false));
+        let mut scheduler = Scheduler::new();
+        let waiting = flag.clone();
+        scheduler.spawn(logger(&log, "reader", 2, move || {
+            Suspend::Io(Box::new(Flag(waiting.clone())))
+        }));
+        let mut turns = 0;
+        let (writer_log, writer_flag) = (log.clone(), flag.clone());
+        scheduler.spawn(move |_: &mut Context| {
+            turns += 1;
+            if turns < 3 {
+                return Suspend::Yield;
+            }
+            writer_log.borrow_mut().push("ready".to_string());
+            writer_flag.set(true);
+            Suspend::Done
+        });
+        scheduler.run();
+        assert_eq!(*log.borrow(), ["reader0", "ready", "reader1"]);
+    }
+
+    #[test]
+    fn stuck() {
+        let flag = Rc::new(Cell::new(false));
+        let mut scheduler = Scheduler::deterministic();
+        let waiting = flag.clone();
+        scheduler.spawn(move |_: &mut Context| match waiting.get() {
+            true => Suspend::Done,
+            false => Suspend::Io(Box::new(Flag(waiting.clone()))),
+        });
+        let (sleeping, mut slept) = (flag.clone(), false);
+        scheduler.spawn(move |_: &mut Context| {
+            if !std::mem::replace(&mut slept, true) {
+                return Suspend::Sleep(Duration::from_secs(1));
+            }
+            sleeping.set(true);
+            Suspend::Done
+        });
+        assert!(scheduler.run_until_stuck(|| false));
+        flag.set(false);
+        let waiting = flag.clone();
+        scheduler.spawn(move |_: &mut Context| Suspend::Io(Box::new(Flag(waiting.clone()))));
+        assert!(!scheduler.run_until_stuck(|| false));
+        assert!(!scheduler.is_idle());
+    }
+}
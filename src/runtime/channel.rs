@@ -0,0 +1,302 @@
+//! Channels, for sending values between coroutines on the same [`Scheduler`].
+//!
+//! Sending and receiving never block the thread. When a channel is full or empty, the coroutine
+//! keeps hold of what it was doing and suspends with [`Sender::ready`] or [`Receiver::ready`],
+//! so the scheduler resumes it once it can try again.
+//!
+//! [`Scheduler`]: crate::runtime::Scheduler
+
+use crate::runtime::{Ready, Suspend};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+struct Shared<T> {
+    queue: VecDeque<T>,
+    /// The most values the queue holds, or `None` when unbounded.
+    capacity: Option<usize>,
+    senders: usize,
+    receiver: bool,
+}
+
+impl<T> Shared<T> {
+    fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|c| self.queue.len() >= c)
+    }
+}
+
+/// The sending half of a channel, which can be cloned to have many senders.
+pub struct Sender<T>(Rc<RefCell<Shared<T>>>);
+
+/// The receiving half of a channel.
+pub struct Receiver<T>(Rc<RefCell<Shared<T>>>);
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum SendError<T> {
+    /// The channel is full, so wait on [`Sender::ready`] and send the value again.
+    Full(T),
+    /// The receiver is gone, so the value can never be received.
+    Closed(T),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum RecvError {
+    /// The channel is empty, so wait on [`Receiver::ready`] and try again.
+    Empty,
+    /// The channel is empty and every sender is gone.
+    Closed,
+}
+
+/// A channel that holds at most `capacity` values. Senders have to wait for the receiver once
+/// it's full. With a capacity of 0, each send waits until the receiver has taken the value
+/// before it, as in CSP.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    channel(Some(capacity))
+}
+
+/// A channel that can hold any number of values, so sending never waits.
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(RefCell::new(Shared {
+        queue: VecDeque::new(),
+        capacity,
+        senders: 1,
+        receiver: true,
+    }));
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut shared = self.0.borrow_mut();
+        if !shared.receiver {
+            return Err(SendError::Closed(value));
+        }
+        // A rendezvous channel takes one value at a time, once the receiver has taken the last.
+        if shared.is_full() && !(shared.capacity == Some(0) && shared.queue.is_empty()) {
+            return Err(SendError::Full(value));
+        }
+        shared.queue.push_back(value);
+        Ok(())
+    }
+
+    /// Suspends until there's room in the channel, or the receiver is gone.
+    pub fn ready(&self) -> Suspend
+    where
+        T: 'static,
+    {
+        Suspend::Io(Box::new(Writable(self.0.clone())))
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut shared = self.0.borrow_mut();
+        match shared.queue.pop_front() {
+            Some(value) => Ok(value),
+            None if shared.senders == 0 => Err(RecvError::Closed),
+            None => Err(RecvError::Empty),
+        }
+    }
+
+    /// Suspends until there's a value in the channel, or every sender is gone.
+    pub fn ready(&self) -> Suspend
+    where
+        T: 'static,
+    {
+        Suspend::Io(Box::new(Readable(self.0.clone())))
+    }
+}
+
+/// A channel that isn't kept open by this, for a collector to find the channels that only the
+/// values queued in channels refer to.
+pub struct Tracked<T>(Weak<RefCell<Shared<T>>>);
+
+impl<T> Sender<T> {
+    /// Identifies the channel, among the ones that haven't been freed.
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+
+    pub fn track(&self) -> Tracked<T> {
+        Tracked(Rc::downgrade(&self.0))
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Identifies the channel, like [`Sender::id`].
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl<T> Tracked<T> {
+    /// Identifies the channel, like [`Sender::id`].
+    pub fn id(&self) -> usize {
+        self.0.as_ptr() as *const () as usize
+    }
+
+    /// Whether the channel hasn't been freed.
+    pub fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    /// How many senders and receivers refer to the channel, together with the coroutines that
+    /// are waiting on it.
+    pub fn handles(&self) -> usize {
+        self.0.strong_count()
+    }
+
+    /// Calls `f` with each value in the queue.
+    pub fn for_each(&self, f: impl FnMut(&T)) {
+        if let Some(shared) = self.0.upgrade() {
+            shared.borrow().queue.iter().for_each(f);
+        }
+    }
+
+    /// Takes the values out of the queue.
+    pub fn drain(&self) -> Vec<T> {
+        match self.0.upgrade() {
+            Some(shared) => shared.borrow_mut().queue.drain(..).collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// Senders are equal when they send to the same channel.
+impl<T> PartialEq for Sender<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> PartialEq for Receiver<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.0.borrow_mut().senders += 1;
+        Sender(self.0.clone())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().senders -= 1;
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().receiver = false;
+    }
+}
+
+struct Writable<T>(Rc<RefCell<Shared<T>>>);
+
+impl<T> Ready for Writable<T> {
+    fn is_ready(&mut self) -> bool {
+        let shared = self.0.borrow();
+        !shared.receiver
+            || !shared.is_full()
+            || shared.capacity == Some(0) && shared.queue.is_empty()
+    }
+}
+
+struct Readable<T>(Rc<RefCell<Shared<T>>>);
+
+impl<T> Ready for Readable<T> {
+    fn is_ready(&mut self) -> bool {
+        let shared = self.0.borrow();
+        !shared.queue.is_empty() || shared.senders == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::channel::*;
+    use crate::runtime::{Context, Scheduler};
+
+    #[test]
+    fn bounded_capacity() {
+        let (tx, rx) = bounded(1);
+        assert_eq!(tx.send(1), Ok(()));
+        assert_eq!(tx.send(2), Err(SendError::Full(2)));
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Err(RecvError::Empty));
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn closing() {
+        let (tx, rx) = unbounded();
+        let tx2 = tx.clone();
+        for n in 0..100 {
+            assert_eq!(tx.send(n), Ok(()));
+        }
+        drop(tx);
+        assert_eq!(tx2.send(100), Ok(()));
+        drop(rx);
+        assert_eq!(tx2.send(101), Err(SendError::Closed(101)));
+    }
+
+    #[test]
+    fn producer_consumer() {
+        let (tx, rx) = bounded(0);
+        let received = std::rc::Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        let (mut next, mut pending) = (0, None);
+        let mut tx = Some(tx);
+        scheduler.spawn(move |_: &mut Context| {
+            let sender = tx.as_ref().unwrap();
+            loop {
+                let value = pending.take().unwrap_or_else(|| {
+                    next += 1;
+                    next
+                });
+                match sender.send(value) {
+                    Ok(()) if value == 5 => {
+                        tx = None;
+                        return Suspend::Done;
+                    }
+                    Ok(()) => {}
+                    Err(SendError::Full(value)) => {
+                        pending = Some(value);
+                        return sender.ready();
+                    }
+                    Err(SendError::Closed(_)) => return Suspend::Done,
+                }
+            }
+        });
+        let log = received.clone();
+        scheduler.spawn(move |_: &mut Context| loop {
+            match rx.recv() {
+                Ok(value) => log.borrow_mut().push(value),
+                Err(RecvError::Empty) => return rx.ready(),
+                Err(RecvError::Closed) => return Suspend::Done,
+            }
+        });
+        scheduler.run();
+        assert_eq!(*received.borrow(), [1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Structured concurrency for `task { ... }` blocks, on top of the [`Scheduler`](super::Scheduler).
+//!
+//! A [`Group`] owns the child tasks spawned in a block. The parent waits on the group until all of
+//! them are done, and gets their results in the order they were spawned. The first child to fail
+//! cancels the rest, and dropping the group cancels whichever children are left, so no child
+//! outlives the block that spawned it.
+
+use super::{Context, Coroutine, Ready, Suspend, TaskId};
+use anyhow::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How far a child task has got.
+pub enum Poll<T> {
+    /// The task has suspended, and isn't done yet. Suspending with [`Suspend::Done`] ends the
+    /// task with an error, since it has no result.
+    Pending(Suspend),
+    /// The task is done.
+    Ready(Result<T>),
+}
+
+/// A coroutine with a result, which can be spawned in a [`Group`].
+pub trait Child<'a, T> {
+    fn resume(&mut self, cx: &mut Context<'a>) -> Poll<T>;
+}
+
+impl<'a, T, F: FnMut(&mut Context<'a>) -> Poll<T>> Child<'a, T> for F {
+    fn resume(&mut self, cx: &mut Context<'a>) -> Poll<T> {
+        self(cx)
+    }
+}
+
+struct State<T> {
+    /// The children, and their results once they're done.
+    children: Vec<(TaskId, Option<T>)>,
+    /// How many children are still running.
+    running: usize,
+    error: Option<Error>,
+    /// Whether the group has been dropped, or has failed, so the children left should stop.
+    cancelled: bool,
+}
+
+/// The child tasks of a `task { ... }` block.
+pub struct Group<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+impl<T: 'static> Default for Group<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Group<T> {
+    pub fn new() -> Self {
+        let state = State {
+            children: vec![],
+            running: 0,
+            error: None,
+            cancelled: false,
+        };
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Starts `child` as a member of the group, once the running coroutine suspends. Does nothing
+    /// if a child has already failed.
+    pub fn spawn<'a>(&self, cx: &mut Context<'a>, mut child: impl Child<'a, T> + 'a) {
+        if self.state.borrow().cancelled {
+            return;
+        }
+        let state = self.state.clone();
+        let n = state.borrow().children.len();
+        let id = cx.spawn(move |cx: &mut Context<'a>| {
+            if state.borrow().cancelled {
+                return Suspend::Done;
+            }
+            let result = match child.resume(cx) {
+                Poll::Pending(Suspend::Done) => Err(anyhow!("task ended without a result")),
+                Poll::Pending(suspend) => return suspend,
+                Poll::Ready(result) => result,
+            };
+            let mut state = state.borrow_mut();
+            state.running -= 1;
+            match result {
+                Result::Ok(value) => state.children[n].1 = Some(value),
+                Err(error) => {
+                    state.error = Some(error);
+                    state.cancelled = true;
+                    state.running = 0;
+                    let id = cx.id();
+                    for &(sibling, _) in state.children.iter().filter(|(s, _)| *s != id) {
+                        cx.cancel(sibling);
+                    }
+                }
+            }
+            Suspend::Done
+        });
+        let mut state = self.state.borrow_mut();
+        state.children.push((id, None));
+        state.running += 1;
+    }
+
+    /// Suspends until every child is done, or one of them has failed.
+    pub fn ready(&self) -> Suspend {
+        Suspend::Io(Box::new(Joined(self.state.clone())))
+    }
+
+    /// The results of the children in the order they were spawned, or the error of the first to
+    /// fail. Pending while children are still running.
+    pub fn join(&self) -> Poll<Vec<T>> {
+        let mut state = self.state.borrow_mut();
+        if state.running > 0 {
+            drop(state);
+            return Poll::Pending(self.ready());
+        }
+        if let Some(error) = state.error.take() {
+            return Poll::Ready(Err(error));
+        }
+        let children = std::mem::take(&mut state.children);
+        Poll::Ready(Ok(children.into_iter().filter_map(|(_, r)| r).collect()))
+    }
+}
+
+impl<T> Drop for Group<T> {
+    fn drop(&mut self) {
+        // There's no context to cancel the children with here, so they stop the next time
+        // they're resumed instead.
+        self.state.borrow_mut().cancelled = true;
+    }
+}
+
+/// Whether the children of a group are done.
+struct Joined<T>(Rc<RefCell<State<T>>>);
+
+impl<T> Ready for Joined<T> {
+    fn is_ready(&mut self) -> bool {
+        self.0.borrow().running == 0
+    }
+}
+
+/// Runs `child` as a coroutine, without a parent to give its result to.
+pub fn detach<'a, T>(mut child: impl Child<'a, T>) -> impl Coroutine<'a> {
+    move |cx: &mut Context<'a>| match child.resume(cx) {
+        Poll::Pending(suspend) => suspend,
+        Poll::Ready(_) => Suspend::Done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::task::*;
+    use crate::runtime::Scheduler;
+    use std::time::{Duration, Instant};
+
+    /// A child that sleeps for `ms` milliseconds, then logs `name` and returns `result`.
+    fn sleeper(
+        log: &Rc<RefCell<Vec<String>>>,
+        name: &str,
+        ms: u64,
+        result: impl Fn() -> Result<usize> + 'static,
+    ) -> impl Child<'static, usize> {
+        let (log, name) = (log.clone(), name.to_string());
+        let mut slept = false;
+        move |_: &mut Context| {
+            if !std::mem::replace(&mut slept, true) {
+                return Poll::Pending(Suspend::Sleep(Duration::from_millis(ms)));
+            }
+            log.borrow_mut().push(name.clone());
+            Poll::Ready(result())
+        }
+    }
+
+    /// A parent that spawns `children` in a group, and logs how it ended.
+    fn parent(
+        log: &Rc<RefCell<Vec<String>>>,
+        mut children: Vec<Box<dyn Child<'static, usize>>>,
+    ) -> impl Coroutine<'static> {
+        let log = log.clone();
+        let group = Group::new();
+        detach(move |cx: &mut Context<'static>| {
+            for mut child in children.drain(..) {
+                group.spawn(cx, move |cx: &mut Context<'static>| child.resume(cx));
+            }
+            let joined = group.join();
+            if let Poll::Ready(result) = &joined {
+                log.borrow_mut().push(match result {
+                    Result::Ok(values) => format!("{values:?}"),
+                    Err(error) => error.to_string(),
+                });
+            }
+            joined
+        })
+    }
+
+    #[test]
+    fn joined() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.spawn(parent(
+            &log,
+            vec![
+                Box::new(sleeper(&log, "a", 10, || Ok(1))),
+                Box::new(sleeper(&log, "b", 1, || Ok(2))),
+                Box::new(|_: &mut Context| Poll::Ready(Ok(3))),
+            ],
+        ));
+        scheduler.run();
+        assert_eq!(*log.borrow(), ["b", "a", "[1, 2, 3]"]);
+    }
+
+    #[test]
+    fn cancellation() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.spawn(parent(
+            &log,
+            vec![
+                Box::new(sleeper(&log, "slow", 10_000, || Ok(1))),
+                Box::new(sleeper(&log, "failing", 1, || bail!("failed"))),
+                Box::new(|_: &mut Context| Poll::Pending(Suspend::Yield)),
+            ],
+        ));
+        let start = Instant::now();
+        scheduler.run();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(*log.borrow(), ["failing", "failed"]);
+
+        // Children of a cancelled child are cancelled along with it.
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        let group = Group::new();
+        let mut nested = Some(sleeper(&log, "nested", 5, || Ok(1)));
+        scheduler.spawn(parent(
+            &log,
+            vec![
+                Box::new(move |cx: &mut Context<'static>| {
+                    if let Some(nested) = nested.take() {
+                        group.spawn(cx, nested);
+                    }
+                    Poll::Pending(Suspend::Sleep(Duration::from_secs(10)))
+                }),
+                Box::new(|_: &mut Context| Poll::Pending(Suspend::Done)),
+            ],
+        ));
+        scheduler.run();
+        assert_eq!(*log.borrow(), ["task ended without a result"]);
+    }
+}
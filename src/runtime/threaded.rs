@@ -0,0 +1,301 @@
+//! An executor running coroutines on a pool of threads, as an alternative to the one-threaded
+//! [`Scheduler`] for programs with more work than one thread gets through.
+//!
+//! Every worker thread has a queue of coroutines that are ready. It runs those first, and when
+//! they run out, takes half of the coroutines waiting to be started, or steals half of another
+//! worker's queue. Only coroutines that are `Send` move between threads, which the types of
+//! [`Executor::spawn`] and [`Spawner::spawn`] ensure. Coroutines spawned through a
+//! [`Context`](super::Context) needn't be `Send`, so they're pinned to the worker they were
+//! spawned on, which runs them on a `Scheduler` of its own. Neither can values that aren't `Sync`
+//! be shared between threads, since the coroutines holding them would have to be `Send`.
+//!
+//! This is for coroutines written in Rust. The ones chant programs spawn don't run on it: their
+//! values are reference counted with `Rc`, and they borrow the [`Machine`](crate::eval::Machine)
+//! they run on, so none of them is `Send`, and [`Machine::call`](crate::eval::Machine::call)
+//! always runs them on one thread, on a `Scheduler`.
+
+use super::{Coroutine, Ready, Scheduler, Suspend, TaskId, POLL_INTERVAL};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+type Task = Box<dyn Coroutine<'static> + Send>;
+
+struct Shared {
+    /// The ready coroutines of each worker.
+    queues: Vec<Mutex<VecDeque<Task>>>,
+    /// Coroutines that haven't been started by any worker yet.
+    injector: Mutex<VecDeque<Task>>,
+    /// How many of the coroutines that can move between threads aren't done.
+    live: AtomicUsize,
+    /// Notified when there's more work, to wake up idle workers.
+    work: Condvar,
+    idle: Mutex<()>,
+}
+
+impl Shared {
+    fn spawn(&self, co: Task) {
+        self.live.fetch_add(1, Ordering::SeqCst);
+        self.injector.lock().unwrap().push_back(co);
+        self.work.notify_one();
+    }
+}
+
+/// Runs coroutines on a pool of threads until all of them are done.
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Executor {
+    /// An executor with `threads` worker threads, or one if `threads` is 0.
+    pub fn new(threads: usize) -> Self {
+        let shared = Shared {
+            queues: (0..threads.max(1)).map(|_| Mutex::default()).collect(),
+            injector: Mutex::default(),
+            live: AtomicUsize::new(0),
+            work: Condvar::new(),
+            idle: Mutex::new(()),
+        };
+        Self {
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Adds `co`, which is started by the first worker with nothing else to do.
+    pub fn spawn(&self, co: impl Coroutine<'static> + Send + 'static) {
+        self.spawner().spawn(co)
+    }
+
+    /// A handle for spawning coroutines from the ones that are running.
+    pub fn spawner(&self) -> Spawner {
+        Spawner(self.shared.clone())
+    }
+
+    /// Runs the coroutines, and the ones they spawn, until all of them are done.
+    pub fn run(self) {
+        std::thread::scope(|scope| {
+            for index in 0..self.shared.queues.len() {
+                let shared = &self.shared;
+                scope.spawn(move || Worker::new(index, shared).run());
+            }
+        })
+    }
+}
+
+/// Spawns coroutines on an [`Executor`], from any thread.
+#[derive(Clone)]
+pub struct Spawner(Arc<Shared>);
+
+impl Spawner {
+    pub fn spawn(&self, co: impl Coroutine<'static> + Send + 'static) {
+        self.0.spawn(Box::new(co))
+    }
+}
+
+struct Worker<'a> {
+    index: usize,
+    shared: &'a Shared,
+    /// Runs the coroutines pinned to this thread.
+    pinned: Scheduler<'static>,
+    /// The id that coroutines from the queues run under, while they're on this thread.
+    id: TaskId,
+    sleeping: Vec<(Instant, Task)>,
+    waiting: Vec<(Task, Box<dyn Ready>)>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(index: usize, shared: &'a Shared) -> Self {
+        let mut pinned = Scheduler::new();
+        // Reserves an id that never refers to a pinned coroutine, so cancelling it does nothing.
+        let id = TaskId(pinned.tasks.len());
+        pinned.tasks.push(None);
+        Self {
+            index,
+            shared,
+            pinned,
+            id,
+            sleeping: vec![],
+            waiting: vec![],
+        }
+    }
+
+    fn run(mut self) {
+        loop {
+            self.wake();
+            let mut progress = false;
+            if let Some(task) = self.next() {
+                self.resume(task);
+                progress = true;
+            }
+            progress |= self.pinned.step();
+            if progress {
+                continue;
+            }
+            if self.shared.live.load(Ordering::SeqCst) == 0 && self.pinned.is_idle() {
+                return;
+            }
+            let idle = self.shared.idle.lock().unwrap();
+            let timeout = self.pinned.idle_time().min(POLL_INTERVAL);
+            drop(self.shared.work.wait_timeout(idle, timeout).unwrap());
+        }
+    }
+
+    fn queue(&self) -> std::sync::MutexGuard<'_, VecDeque<Task>> {
+        self.shared.queues[self.index].lock().unwrap()
+    }
+
+    /// Takes the next coroutine to run, from this worker's queue, the injector, or another
+    /// worker's queue, in that order.
+    fn next(&mut self) -> Option<Task> {
+        if let Some(task) = self.queue().pop_front() {
+            return Some(task);
+        }
+        let mut batch = {
+            let mut injector = self.shared.injector.lock().unwrap();
+            let n = injector.len().div_ceil(2);
+            injector.drain(..n).collect::<VecDeque<_>>()
+        };
+        let n = self.shared.queues.len();
+        for victim in (1..n).map(|k| (self.index + k) % n) {
+            if !batch.is_empty() {
+                break;
+            }
+            // Only one queue is locked at a time, so workers stealing from each other can't
+            // deadlock.
+            let mut queue = self.shared.queues[victim].lock().unwrap();
+            let half = queue.len() / 2;
+            batch = queue.split_off(half);
+        }
+        let task = batch.pop_front();
+        if !batch.is_empty() {
+            self.queue().extend(batch);
+            self.shared.work.notify_one();
+        }
+        task
+    }
+
+    fn resume(&mut self, mut task: Task) {
+        let mut cx = self.pinned.context(self.id);
+        let suspend = task.resume(&mut cx);
+        self.pinned.apply(cx);
+        match suspend {
+            Suspend::Yield => self.queue().push_back(task),
+            Suspend::Sleep(time) => self.sleeping.push((Instant::now() + time, task)),
+            Suspend::Io(source) => self.waiting.push((task, source)),
+            Suspend::Done => {
+                self.shared.live.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Moves this worker's coroutines that can make progress to its queue.
+    fn wake(&mut self) {
+        let now = Instant::now();
+        let mut woken = vec![];
+        let mut n = 0;
+        while n < self.sleeping.len() {
+            if self.sleeping[n].0 <= now {
+                woken.push(self.sleeping.swap_remove(n).1);
+            } else {
+                n += 1;
+            }
+        }
+        let mut n = 0;
+        while n < self.waiting.len() {
+            if self.waiting[n].1.is_ready() {
+                woken.push(self.waiting.swap_remove(n).0);
+            } else {
+                n += 1;
+            }
+        }
+        if !woken.is_empty() {
+            self.queue().extend(woken);
+            self.shared.work.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::threaded::*;
+    use crate::runtime::Context;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    #[test]
+    fn stealing() {
+        // The first worker to start takes two of the coroutines, and each of them blocks its
+        // thread until all four are running, so the second has to be stolen.
+        let barrier = Arc::new(Barrier::new(4));
+        let executor = Executor::new(4);
+        for _ in 0..4 {
+            let barrier = barrier.clone();
+            executor.spawn(move |_: &mut Context| {
+                barrier.wait();
+                Suspend::Done
+            });
+        }
+        executor.run();
+    }
+
+    #[test]
+    fn spawner() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(4);
+        let (spawner, spawned) = (executor.spawner(), done.clone());
+        executor.spawn(move |_: &mut Context| {
+            for _ in 0..64 {
+                let (done, mut turns) = (spawned.clone(), 0);
+                spawner.spawn(move |_: &mut Context| {
+                    turns += 1;
+                    if turns < 4 {
+                        return Suspend::Sleep(Duration::from_millis(1));
+                    }
+                    done.fetch_add(1, Ordering::SeqCst);
+                    Suspend::Done
+                });
+            }
+            Suspend::Done
+        });
+        executor.run();
+        assert_eq!(done.load(Ordering::SeqCst), 64);
+    }
+
+    #[test]
+    fn pinned() {
+        let threads = Arc::new(Mutex::new(vec![]));
+        let executor = Executor::new(4);
+        for _ in 0..8 {
+            let (threads, mut turns) = (threads.clone(), 0);
+            executor.spawn(move |cx: &mut Context| {
+                turns += 1;
+                if turns < 4 {
+                    return Suspend::Sleep(Duration::from_millis(1));
+                }
+                // The child holds an `Rc`, so it can't move to another thread.
+                let (threads, parent) = (threads.clone(), std::thread::current().id());
+                let count = Rc::new(Cell::new(0));
+                cx.spawn(move |_: &mut Context| {
+                    threads
+                        .lock()
+                        .unwrap()
+                        .push((parent, std::thread::current().id()));
+                    count.set(count.get() + 1);
+                    if count.get() == 3 {
+                        Suspend::Done
+                    } else {
+                        Suspend::Sleep(Duration::from_millis(1))
+                    }
+                });
+                Suspend::Done
+            });
+        }
+        executor.run();
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 24);
+        assert!(threads.iter().all(|(parent, child)| parent == child));
+    }
+}
@@ -0,0 +1,245 @@
+//! The stable way to compile and run programs, which the [`prelude`](crate::prelude) exports.
+//!
+//! A [`Compiler`] is set up with the host modules programs can use, the limits they run with and
+//! how their arithmetic behaves, and compiles source into a [`Session`], from which functions
+//! are called. Everything that goes wrong, from parsing to running, is a [`Diagnostic`], which
+//! says which [`Stage`] failed.
+
+use crate::ast::{self, Located};
+use crate::eval::compile::Module;
+use crate::eval::host::HostModule;
+use crate::eval::value::Value;
+use crate::eval::{Limits, Machine, RuntimeError};
+use crate::num::natural::SubPolicy;
+use crate::parser::Parser;
+use crate::{check, grammar};
+use std::fmt;
+use std::ops::Range;
+
+/// The stage of going from source to a result, where a [`Diagnostic`] comes from.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum Stage {
+    /// Lexing and parsing the source.
+    Parse,
+    /// Checking the syntax tree, like that references don't escape their regions.
+    Check,
+    /// Compiling the syntax tree, like resolving names.
+    Compile,
+    /// Running a function, or evaluating the constants it can use.
+    Run,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Parse => "parsing",
+            Stage::Check => "checking",
+            Stage::Compile => "compiling",
+            Stage::Run => "running",
+        })
+    }
+}
+
+/// An error from compiling or running a program.
+#[derive(Debug)]
+pub struct Diagnostic {
+    stage: Stage,
+    error: anyhow::Error,
+    span: Option<Range<usize>>,
+    line: Option<usize>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(stage: Stage) -> impl FnOnce(anyhow::Error) -> Self {
+        move |error| Diagnostic {
+            stage,
+            error,
+            span: None,
+            line: None,
+        }
+    }
+
+    /// Like [`Diagnostic::new`], for an error that may be [`Located`] in `src`.
+    pub(crate) fn in_source(stage: Stage, src: &str) -> impl FnOnce(anyhow::Error) -> Self + '_ {
+        move |error| {
+            let span = Located::span(&error);
+            let line = span.as_ref().map(|span| ast::line(src, span.start));
+            Diagnostic {
+                stage,
+                error,
+                span,
+                line,
+            }
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// The bytes of the source the error is about, if it says. Errors from parsing say, and so
+    /// do errors from checking and compiling, with the statement or the item they're in.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
+    /// The line of the source the error is about, counting from 1, if it says, like
+    /// [`Diagnostic::span`].
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// The message, after the context of the error, like "in `main`: interrupted".
+    pub fn message(&self) -> String {
+        format!("{:#}", self.error)
+    }
+
+    /// The error from running the program, if it's one that hosts may want to handle.
+    pub fn runtime_error(&self) -> Option<&RuntimeError> {
+        self.error.downcast_ref()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} failed: {:#}", self.stage, self.error)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Compiles programs, with the host modules and limits they get.
+#[derive(Default)]
+pub struct Compiler {
+    hosts: Vec<HostModule>,
+    limits: Limits,
+    natural_sub: SubPolicy,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `module` usable by the programs compiled, by its name.
+    pub fn host(mut self, module: HostModule) -> Self {
+        self.hosts.push(module);
+        self
+    }
+
+    /// The limits every call of the sessions runs with.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// What subtracting a natural number from a smaller one results in, in the programs compiled,
+    /// which is an error by default.
+    pub fn natural_sub(mut self, policy: SubPolicy) -> Self {
+        self.natural_sub = policy;
+        self
+    }
+
+    /// Parses, checks and compiles `src`. The compiler's host modules go to the session, so a
+    /// compiler compiles one program.
+    pub fn compile(self, src: &str) -> Result<Session, Diagnostic> {
+        let (program, _) = grammar::Program
+            .parse(src)
+            .map_err(Diagnostic::in_source(Stage::Parse, src))?;
+        check::regions(&program).map_err(Diagnostic::in_source(Stage::Check, src))?;
+        let mut module = Module::compile_with(&program, self.hosts)
+            .map_err(Diagnostic::in_source(Stage::Compile, src))?;
+        module.natural_sub = self.natural_sub;
+        Ok(Session {
+            module,
+            limits: self.limits,
+        })
+    }
+}
+
+/// A compiled program, whose functions can be called.
+pub struct Session {
+    module: Module,
+    limits: Limits,
+}
+
+impl Session {
+    /// Calls the function `name` with `args`, on a machine of its own, which evaluates the
+    /// constants of the program again.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, Diagnostic> {
+        Machine::new(&self.module, self.limits.clone())
+            .and_then(|mut machine| machine.call(name, args))
+            .map_err(Diagnostic::new(Stage::Run))
+    }
+
+    /// Calls `main`.
+    pub fn run(&self) -> Result<Value, Diagnostic> {
+        self.call("main", vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn stages() -> anyhow::Result<()> {
+        let math = HostModule::new("math").function("sqrt", |x: f64| x.sqrt());
+        let session = Compiler::new()
+            .host(math)
+            .compile("fn main() { math.sqrt(16.0) } fn twice(x: Integer) { 2 * x }")?;
+        assert_eq!(session.run()?.to_string(), "4");
+        assert_eq!(
+            session.call("twice", vec![21.to_chant()])?.to_string(),
+            "42"
+        );
+        for (src, stage, line) in [
+            ("fn main() {\n    x := 1\n    x\n}", Stage::Parse, 3),
+            (
+                "fn main() {\n    region 'r { x: &'r Int }\n}",
+                Stage::Check,
+                2,
+            ),
+            ("fn main() {\n    1;\n    y\n}", Stage::Compile, 3),
+            ("fn f() {}\nfn f() {}", Stage::Compile, 2),
+        ] {
+            let Err(e) = Compiler::new().compile(src) else {
+                panic!("{src:?} compiles");
+            };
+            assert_eq!((e.stage(), e.line()), (stage, Some(line)), "{e}");
+        }
+        let limits = Limits::default().max_depth(10);
+        let e = Compiler::new()
+            .limits(limits)
+            .compile("fn main() { main() }")?
+            .run()
+            .unwrap_err();
+        assert_eq!(e.stage(), Stage::Run);
+        let overflow = RuntimeError::StackOverflow { depth: 10 };
+        assert_eq!(e.runtime_error(), Some(&overflow));
+        assert_eq!(e.message(), format!("in `main`: {overflow}"));
+        Ok(())
+    }
+
+    #[test]
+    fn natural_sub() -> anyhow::Result<()> {
+        let src = "fn main() {
+            x := 2 : Natural;
+            x -= 3 : Natural;
+            four := 4 : Natural;
+            five := 5 : Natural;
+            (x, four - five, x + four)
+        }";
+        let e = Compiler::new().compile(src)?.run().unwrap_err();
+        assert_eq!(
+            e.message(),
+            "in `main`: `2 - 3` is negative, so not a Natural"
+        );
+        let session = Compiler::new()
+            .natural_sub(SubPolicy::Saturate)
+            .compile(src)?;
+        assert_eq!(session.run()?.to_string(), "(0, 0, 4)");
+        Ok(())
+    }
+}
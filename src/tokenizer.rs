@@ -0,0 +1,281 @@
+//! Splits chant source into tokens, for tools that want the tokens rather than the AST.
+//!
+//! [`Lexer`] lexes tokens as they're needed, and [`tokenize`] and [`lex`] lex all of them.
+//!
+//! The grammar doesn't go through this, and lexes as it parses, using the same parsers from
+//! [`crate::parser`], so parsing already stops lexing at the first syntax error. Lexing on
+//! demand is for the tools that consume tokens: the parser doesn't pull them from a [`Lexer`],
+//! and [`Tokens`] isn't lazy, since it's made by lexing the whole source.
+
+#[cfg(not(feature = "std"))]
+use crate::alloc_prelude::*;
+use crate::ast::Located;
+use crate::parser::*;
+use anyhow::*;
+use core::ops::Range;
+
+/// A token, and the range of bytes it was lexed from.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Spanned {
+    pub token: Token,
+    pub span: Range<usize>,
+}
+
+/// Lexes all of `src`, skipping whitespace.
+pub fn tokenize(src: &str) -> Result<Vec<Spanned>> {
+    Lexer::new(src).collect()
+}
+
+/// Lexes all of `src`, like [`tokenize`], into [`Tokens`].
+pub fn lex(src: &str) -> Result<Tokens<'_>> {
+    ensure!(
+        u32::try_from(src.len()).is_ok(),
+        "source of {} bytes is too large to lex",
+        src.len()
+    );
+    let mut tokens = Tokens {
+        src,
+        kinds: vec![],
+        starts: vec![],
+        ends: vec![],
+    };
+    for spanned in Lexer::new(src) {
+        let Spanned { token, span } = spanned?;
+        tokens.kinds.push(Kind::of(&token));
+        tokens.starts.push(span.start as u32);
+        tokens.ends.push(span.end as u32);
+    }
+    Ok(tokens)
+}
+
+/// Lexes tokens as they're pulled from it, so tools that stop at the first token they don't
+/// expect never lex the rest of the source. Errors are [`Located`] at the character the token
+/// starts with, and after one, there are no more tokens.
+#[derive(Clone, Debug)]
+pub struct Lexer<'s> {
+    src: &'s str,
+    /// The start of the rest of the source, which is `src.len()` once lexing is done or failed.
+    rem: usize,
+    /// Whether the last token was a `.`.
+    after_dot: bool,
+}
+
+impl<'s> Lexer<'s> {
+    pub fn new(src: &'s str) -> Self {
+        Lexer {
+            src,
+            rem: whitespace(src),
+            after_dot: false,
+        }
+    }
+
+    // Without inlining it into `next`, `tokenize` is about a tenth slower in `cargo bench`.
+    #[inline(always)]
+    fn lex(&mut self) -> Result<Spanned> {
+        let (rem, i) = (self.rem, &self.src[self.rem..]);
+        let (token, n) = match i.chars().next() {
+            // `t.0.1` indexes a tuple twice, rather than by `0.1`.
+            Some(c) if c.is_ascii_digit() && self.after_dot => NaturalNumber.parse(i)?,
+            // A leading `-` is an operator, and not part of the number.
+            Some(c) if c.is_ascii_digit() => Float.parse(i)?,
+            _ => first_token(i)?,
+        };
+        if token == Token::Blank {
+            bail!(
+                "unexpected character {:?} at byte {rem}",
+                i.chars().next().unwrap()
+            )
+        }
+        self.after_dot = matches!(token, Token::Separator('.'));
+        self.rem += n;
+        self.rem += whitespace(&self.src[self.rem..]);
+        Ok(Spanned {
+            token,
+            span: rem..rem + n,
+        })
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Spanned>;
+
+    fn next(&mut self) -> Option<Result<Spanned>> {
+        if self.rem == self.src.len() {
+            return None;
+        }
+        let start = self.rem;
+        let spanned = self.lex().map_err(|e| {
+            let end = start + self.src[start..].chars().next().map_or(0, char::len_utf8);
+            Located::wrap(e, Some(start..end))
+        });
+        if spanned.is_err() {
+            self.rem = self.src.len();
+        }
+        Some(spanned)
+    }
+}
+
+impl core::iter::FusedIterator for Lexer<'_> {}
+
+/// The kind of a [`Token`], without its value.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kind {
+    Symbol,
+    Integer,
+    Real,
+    Imaginary,
+    FReal,
+    String,
+    Interpolated,
+    Operator,
+    Separator,
+    Lifetime,
+}
+
+impl Kind {
+    pub fn of(token: &Token) -> Kind {
+        match token {
+            Token::Symbol(_) => Kind::Symbol,
+            Token::Integer(_) => Kind::Integer,
+            Token::Real(_) => Kind::Real,
+            Token::Imaginary(_) => Kind::Imaginary,
+            Token::FReal(_) => Kind::FReal,
+            Token::String(_) => Kind::String,
+            Token::Interpolated(_) => Kind::Interpolated,
+            Token::Operator(_) => Kind::Operator,
+            Token::Separator(_) => Kind::Separator,
+            Token::Lifetime(_) => Kind::Lifetime,
+            Token::Blank => unreachable!("blank tokens aren't lexed"),
+        }
+    }
+}
+
+/// The tokens of a source, stored compactly, for tools that keep the tokens of whole projects
+/// around.
+///
+/// Rather than a [`Spanned`] per token, which is 48 bytes and often allocates, the kinds and the
+/// bounds of the spans are kept in arrays of their own, which is 9 bytes per token. The values
+/// of tokens are in the source, so they're lexed again from their text by [`Tokens::token`],
+/// when they're needed. The source is lexed all at once, see [`Lexer`] to lex it on demand.
+#[derive(Clone, Debug)]
+pub struct Tokens<'s> {
+    src: &'s str,
+    kinds: Vec<Kind>,
+    starts: Vec<u32>,
+    ends: Vec<u32>,
+}
+
+impl<'s> Tokens<'s> {
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn kind(&self, n: usize) -> Kind {
+        self.kinds[n]
+    }
+
+    pub fn span(&self, n: usize) -> Range<usize> {
+        self.starts[n] as usize..self.ends[n] as usize
+    }
+
+    /// The source text of the token `n`.
+    pub fn text(&self, n: usize) -> &'s str {
+        &self.src[self.span(n)]
+    }
+
+    /// The token `n`, with its value.
+    pub fn token(&self, n: usize) -> Token {
+        let text = self.text(n);
+        // A token lexes the same way on its own, since it was lexed from the same text. Numbers
+        // after a `.` stop at the next `.`, which isn't part of the text.
+        let token = match text.as_bytes()[0] {
+            b'0'..=b'9' => Float.parse(text),
+            _ => first_token(text),
+        };
+        token.expect("tokens lex again from their text").0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Spanned> + '_ {
+        (0..self.len()).map(|n| Spanned {
+            token: self.token(n),
+            span: self.span(n),
+        })
+    }
+}
+
+/// The first token of `i`, which doesn't start with a digit.
+fn first_token(i: &str) -> Result<(Token, usize)> {
+    for parser in [
+        &StringLiteral as &dyn Parser<Token = Token>,
+        &Lifetime,
+        &Symbol,
+        &Operator,
+        &Separator,
+    ] {
+        let (token, n) = parser.parse(i)?;
+        if token != Token::Blank {
+            return Ok((token, n));
+        }
+    }
+    Ok((Token::Blank, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tokenizer::*;
+
+    #[test]
+    fn spans() -> Result<()> {
+        let src = "x := t.0.1 - 2.5; \"s\" &'a";
+        let tokens: Vec<_> = tokenize(src)?
+            .into_iter()
+            .map(|t| (t.token, t.span))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Symbol("x".to_string()), 0..1),
+                (Token::Operator(":=".to_string()), 2..4),
+                (Token::Symbol("t".to_string()), 5..6),
+                (Token::Separator('.'), 6..7),
+                (Token::Integer(0), 7..8),
+                (Token::Separator('.'), 8..9),
+                (Token::Integer(1), 9..10),
+                (Token::Operator("-".to_string()), 11..12),
+                (Token::Real(2.5), 13..16),
+                (Token::Separator(';'), 16..17),
+                (Token::String("s".to_string()), 18..21),
+                (Token::Operator("&".to_string()), 22..23),
+                (Token::Lifetime("a".to_string()), 23..25),
+            ]
+        );
+        assert!(tokenize("a # b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn compact() -> Result<()> {
+        let src = "fn f(x: &'a Real) { t.0.1 + 4i * 1.5f - \"{x}\" }";
+        let tokens = lex(src)?;
+        assert_eq!(tokens.iter().collect::<Vec<_>>(), tokenize(src)?);
+        assert_eq!(tokens.kind(7), Kind::Symbol);
+        assert_eq!(tokens.text(7), "Real");
+        assert!(lex("a # b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lazy() -> Result<()> {
+        // The `#` is never reached, so it isn't an error.
+        let mut lexer = Lexer::new("fn f() # b");
+        let first: Vec<_> = lexer.by_ref().take(4).collect::<Result<_>>()?;
+        assert_eq!(first.last().unwrap().token, Token::Separator(')'));
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Bindings for running chant in a browser, with the `wasm` feature, so a playground can compile
+//! and run programs client-side.
+//!
+//! The functions are exported to JavaScript with `wasm-bindgen`, and take the source as a string
+//! and return JSON, which is a string too. The module is built with
+//!
+//! ```text
+//! cargo rustc --lib --release --features wasm --target wasm32-unknown-unknown --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/chant.wasm
+//! ```
+//!
+//! and then
+//!
+//! ```js
+//! import init, { run, tokens_json } from "./pkg/chant.js";
+//! await init();
+//! JSON.parse(run("fn main() { 1 + 2 }")); // {"value": "3", "diagnostics": []}
+//! ```
+//!
+//! Every result has the `"diagnostics"` of the source, each with the `"stage"` that failed, a
+//! `"message"` and the `"line"` it's about, or null if it doesn't say, and is otherwise null
+//! where that failed.
+//!
+//! There's no clock to read in a browser without JavaScript, so programs run on the virtual clock
+//! of [`Scheduler::deterministic`](crate::runtime::Scheduler::deterministic), where sleeping
+//! takes no time.
+
+use crate::json::{object, Json, ToJson};
+use crate::session::{Compiler, Diagnostic, Stage};
+use crate::tokenizer;
+use wasm_bindgen::prelude::*;
+
+/// Compiles `src`, with the result `{"diagnostics": [...]}`.
+#[wasm_bindgen]
+pub fn compile(src: &str) -> String {
+    let diagnostics = Compiler::new().compile(src).err();
+    object(vec![("diagnostics", diagnostics.to_json_array())]).to_string()
+}
+
+/// Compiles `src`, and runs its `main`, with the result `{"value": "...", "diagnostics":
+/// [...]}`, where the value is printed like `chantrs --run` prints it.
+#[wasm_bindgen]
+pub fn run(src: &str) -> String {
+    let result = Compiler::new().compile(src).and_then(|s| s.run());
+    let (value, diagnostic) = match result {
+        Ok(v) => (Json::from(v.to_string().as_str()), None),
+        Err(e) => (Json::Null, Some(e)),
+    };
+    object(vec![
+        ("value", value),
+        ("diagnostics", diagnostic.to_json_array()),
+    ])
+    .to_string()
+}
+
+/// Lexes `src`, with the result `{"tokens": [...], "diagnostics": [...]}`, where the tokens are
+/// like those of `chantrs --emit tokens-json`.
+#[wasm_bindgen]
+pub fn tokens_json(src: &str) -> String {
+    let (tokens, diagnostic) = match tokenizer::tokenize(src) {
+        Ok(tokens) => (tokens.to_json(), None),
+        Err(e) => (
+            Json::Null,
+            Some(Diagnostic::in_source(Stage::Parse, src)(e)),
+        ),
+    };
+    object(vec![
+        ("tokens", tokens),
+        ("diagnostics", diagnostic.to_json_array()),
+    ])
+    .to_string()
+}
+
+/// The diagnostics of a result, which has at most one.
+trait Diagnostics {
+    fn to_json_array(&self) -> Json;
+}
+
+impl Diagnostics for Option<Diagnostic> {
+    fn to_json_array(&self) -> Json {
+        let diagnostics = self.iter().map(|d| {
+            object(vec![
+                ("stage", Json::from(d.stage().to_string().as_str())),
+                ("message", Json::from(d.message().as_str())),
+                (
+                    "line",
+                    d.line().map_or(Json::Null, |n| Json::Integer(n as i64)),
+                ),
+            ])
+        });
+        Json::Array(diagnostics.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::*;
+
+    #[test]
+    fn exports() {
+        assert_eq!(
+            run("fn main() { 1 + 2 }"),
+            r#"{"value":"3","diagnostics":[]}"#
+        );
+        assert_eq!(
+            compile("fn main() { y }"),
+            concat!(
+                r#"{"diagnostics":[{"stage":"compiling","message":"`y` isn't defined","#,
+                r#""line":1}]}"#
+            )
+        );
+        assert_eq!(
+            tokens_json("x"),
+            r#"{"tokens":[{"kind":"Symbol","value":"x","span":[0,1]}],"diagnostics":[]}"#
+        );
+        assert_eq!(
+            tokens_json("\"x"),
+            concat!(
+                r#"{"tokens":null,"diagnostics":"#,
+                r#"[{"stage":"parsing","message":"unterminated string literal","line":1}]}"#
+            )
+        );
+    }
+}
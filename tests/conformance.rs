@@ -0,0 +1,120 @@
+//! Checks that the programs in `tests/conformance/` compile with exactly the errors they are
+//! annotated with. An annotation is a `//~` comment at the end of a line:
+//!
+//! ```text
+//! fn main() { y } //~ ERROR `y` isn't defined
+//! ```
+//!
+//! says that compiling the program fails with an error containing "`y` isn't defined", at that
+//! line, and a program without annotations has to compile. The compiler stops at the first error,
+//! so a program has at most one. Its line is the one the [`Diagnostic`] says it's on, so an error
+//! that doesn't say where it is fails every annotation.
+//!
+//! [`Diagnostic`]: chant::Diagnostic
+//!
+//! Chant has no comments, so the annotations are blanked out before the program is compiled.
+//! `//~ TYPE` annotations, for inferred types, are rejected until types are inferred.
+
+use chant::Compiler;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+struct Annotation {
+    line: usize,
+    kind: String,
+    text: String,
+}
+
+/// The annotations of `src`, and `src` with them replaced by spaces, which keeps the lines and
+/// offsets of everything else.
+fn annotations(src: &str) -> (Vec<Annotation>, String) {
+    let mut annotations = vec![];
+    let mut blanked = String::new();
+    for (n, line) in src.split_inclusive('\n').enumerate() {
+        let Some(at) = line.find("//~") else {
+            blanked.push_str(line);
+            continue;
+        };
+        let annotation = line[at + 3..].trim();
+        let (kind, text) = annotation.split_once(' ').unwrap_or((annotation, ""));
+        annotations.push(Annotation {
+            line: n + 1,
+            kind: kind.to_string(),
+            text: text.trim().to_string(),
+        });
+        blanked.push_str(&line[..at]);
+        blanked.extend(line[at..].chars().map(|c| if c == '\n' { c } else { ' ' }));
+    }
+    (annotations, blanked)
+}
+
+/// The failures of the program `src`.
+fn check(src: &str) -> Vec<String> {
+    let (annotations, src) = annotations(src);
+    let mut failures = vec![];
+    let mut errors = vec![];
+    for a in annotations {
+        match a.kind.as_str() {
+            "ERROR" => errors.push(a),
+            "TYPE" => failures.push(format!(
+                "line {}: types aren't inferred yet, so `//~ TYPE` can't be checked",
+                a.line
+            )),
+            kind => failures.push(format!("line {}: unknown annotation {kind:?}", a.line)),
+        }
+    }
+    match Compiler::new().compile(&src) {
+        Ok(_) => {
+            for a in errors {
+                failures.push(format!("line {}: expected an error, {:?}", a.line, a.text));
+            }
+        }
+        Err(e) => {
+            let message = e.message();
+            let matches = |a: &Annotation| message.contains(&a.text) && e.line() == Some(a.line);
+            let at = match e.line() {
+                Some(line) => format!(" at line {line}"),
+                None => " at no line".to_string(),
+            };
+            match errors.iter().position(matches) {
+                Some(n) => {
+                    errors.remove(n);
+                }
+                None => failures.push(format!("unexpected error{at}: {message}")),
+            }
+            for a in errors {
+                failures.push(format!(
+                    "line {}: expected an error, {:?}, but the error was{at}: {message}",
+                    a.line, a.text
+                ));
+            }
+        }
+    }
+    failures
+}
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut programs: Vec<_> = fs::read_dir(&dir)
+        .expect("tests/conformance exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "chant"))
+        .collect();
+    programs.sort();
+    let mut failed = 0;
+    for path in &programs {
+        let failures = check(&fs::read_to_string(path).unwrap());
+        if !failures.is_empty() {
+            failed += 1;
+            let name = path.file_name().unwrap().to_string_lossy();
+            for failure in failures {
+                println!("{name}: {failure}");
+            }
+        }
+    }
+    println!("{} programs, {failed} failed", programs.len());
+    if failed > 0 {
+        exit(1);
+    }
+}
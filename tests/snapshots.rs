@@ -0,0 +1,111 @@
+//! Golden tests of the whole front end. Each `tests/cases/<name>.chant` is lexed, parsed and
+//! checked, and what comes out is compared to the files next to it:
+//! - `<name>.tokens`, the tokens with their spans, one per line.
+//! - `<name>.ast`, the syntax tree, if it parses.
+//! - `<name>.diagnostics`, the errors of tokenizing and of parsing, if there are any.
+//!
+//! A file that isn't expected is missing. When a change to the front end is meant to change the
+//! output, run
+//!
+//! ```text
+//! cargo test --test snapshots -- --bless
+//! ```
+//!
+//! to write the new output into the files, and review the changes to them with the change.
+
+use chant::tokenizer;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+/// The outputs for the source `src`, by the extension of their file.
+fn outputs(src: &str) -> Vec<(&'static str, Option<String>)> {
+    let mut diagnostics = vec![];
+    let tokens = match tokenizer::tokenize(src) {
+        Ok(tokens) => Some(
+            tokens
+                .iter()
+                .map(|t| format!("{:?} {:?}\n", t.span, t.token))
+                .collect(),
+        ),
+        Err(e) => {
+            diagnostics.push(format!("tokenizing: {e:#}\n"));
+            None
+        }
+    };
+    let ast = match chant::parse(src) {
+        Ok(program) => Some(format!("{program:#?}\n")),
+        Err(e) => {
+            diagnostics.push(format!("parsing: {e:#}\n"));
+            None
+        }
+    };
+    let diagnostics = (!diagnostics.is_empty()).then(|| diagnostics.concat());
+    vec![
+        ("tokens", tokens),
+        ("ast", ast),
+        ("diagnostics", diagnostics),
+    ]
+}
+
+/// Where `expected` and `actual` first differ, as the line in each.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let (mut expected, mut actual) = (expected.lines(), actual.lines());
+    for line in 1.. {
+        match (expected.next(), actual.next()) {
+            (Some(e), Some(a)) if e == a => continue,
+            (e, a) => {
+                return format!(
+                    "line {line} is {:?}, but {:?} was expected",
+                    a.unwrap_or("the end"),
+                    e.unwrap_or("the end")
+                )
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn main() {
+    let bless = std::env::args().any(|arg| arg == "--bless");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut cases: Vec<_> = fs::read_dir(&dir)
+        .expect("tests/cases exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "chant"))
+        .collect();
+    cases.sort();
+    let mut failures = 0;
+    for case in &cases {
+        let src = fs::read_to_string(case).unwrap();
+        for (extension, actual) in outputs(&src) {
+            let path = case.with_extension(extension);
+            let expected = fs::read_to_string(&path).ok();
+            if expected == actual {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_string_lossy();
+            if bless {
+                match actual {
+                    Some(actual) => fs::write(&path, actual).unwrap(),
+                    None => fs::remove_file(&path).unwrap(),
+                }
+                println!("blessed {name}");
+                continue;
+            }
+            failures += 1;
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => {
+                    println!("{name}: {}", first_difference(&expected, &actual))
+                }
+                (Some(_), None) => println!("{name} is expected, but there's no such output"),
+                (None, _) => println!("{name} is missing"),
+            }
+        }
+    }
+    println!("{} cases, {failures} failures", cases.len());
+    if failures > 0 {
+        println!("run `cargo test --test snapshots -- --bless` if the changes are right");
+        exit(1);
+    }
+}